@@ -0,0 +1,107 @@
+/*!
+ * Entry Attachments
+ * Files encrypted under their own key, which is wrapped by the vault data key
+ */
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use zeroize::Zeroizing;
+
+use crate::crypto::{self, base64_bytes, VaultKey};
+
+/// Attachments up to this size are stored inside the vault itself; larger
+/// ones go to a sidecar file so every vault save stays cheap
+pub const INLINE_LIMIT: usize = 64 * 1024;
+
+/// Default for `Settings::max_attachment_size`
+pub const DEFAULT_MAX_SIZE: u64 = 10 * 1024 * 1024;
+
+/// An encrypted file attached to an entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attachment {
+    pub id: Uuid,
+    pub file_name: String,
+    /// Size of the original file in bytes
+    pub size: u64,
+    pub added_at: DateTime<Utc>,
+    /// Per-attachment key, wrapped by the vault data key
+    #[serde(with = "base64_bytes")]
+    pub wrapped_key: Vec<u8>,
+    pub storage: AttachmentStorage,
+}
+
+/// Where the encrypted contents live
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AttachmentStorage {
+    /// `nonce || ciphertext` stored in the vault
+    Inline {
+        #[serde(with = "base64_bytes")]
+        data: Vec<u8>,
+    },
+    /// `nonce || ciphertext` stored in `storage::attachment_path`
+    Sidecar,
+}
+
+/// What the UI lists for an attachment
+#[derive(Debug, Clone, Serialize)]
+pub struct AttachmentInfo {
+    pub id: Uuid,
+    pub file_name: String,
+    pub size: u64,
+    pub added_at: DateTime<Utc>,
+}
+
+impl From<&Attachment> for AttachmentInfo {
+    fn from(attachment: &Attachment) -> Self {
+        AttachmentInfo {
+            id: attachment.id,
+            file_name: attachment.file_name.clone(),
+            size: attachment.size,
+            added_at: attachment.added_at,
+        }
+    }
+}
+
+impl Attachment {
+    pub fn is_sidecar(&self) -> bool {
+        matches!(self.storage, AttachmentStorage::Sidecar)
+    }
+}
+
+/// Encrypt `contents` under a fresh key. Returns the attachment and, for
+/// large files, the bytes to write to its sidecar file.
+pub fn seal(vault_key: &VaultKey, file_name: &str, contents: &[u8]) -> Result<(Attachment, Option<Vec<u8>>), String> {
+    let id = Uuid::new_v4();
+    let key = crypto::generate_key();
+    // Bind the ciphertext to its id so sidecar files cannot be swapped
+    let sealed = crypto::encrypt(&key, contents, id.as_bytes())?;
+
+    let (storage, sidecar) = if contents.len() <= INLINE_LIMIT {
+        (AttachmentStorage::Inline { data: sealed }, None)
+    } else {
+        (AttachmentStorage::Sidecar, Some(sealed))
+    };
+
+    let attachment = Attachment {
+        id,
+        file_name: file_name.to_string(),
+        size: contents.len() as u64,
+        added_at: Utc::now(),
+        wrapped_key: crypto::wrap_key(vault_key, &key)?,
+        storage,
+    };
+    Ok((attachment, sidecar))
+}
+
+/// Decrypt an attachment; `sidecar` holds the sidecar file's bytes when the
+/// attachment is not stored inline
+pub fn open(vault_key: &VaultKey, attachment: &Attachment, sidecar: Option<&[u8]>) -> Result<Zeroizing<Vec<u8>>, String> {
+    let sealed = match &attachment.storage {
+        AttachmentStorage::Inline { data } => data.as_slice(),
+        AttachmentStorage::Sidecar => sidecar.ok_or_else(|| "Attachment file is missing".to_string())?,
+    };
+    let key = crypto::unwrap_key(vault_key, &attachment.wrapped_key)?;
+    crypto::decrypt(&key, sealed, attachment.id.as_bytes())
+}
@@ -0,0 +1,146 @@
+/*!
+ * Security Audit Log
+ * Append-only record of changes to security settings and keychain
+ * material, kept outside the vaults
+ */
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::PathBuf;
+use tauri::AppHandle;
+
+use crate::error::VaultError;
+use crate::keychain::{KeychainBackend, VaultSecret};
+use crate::storage;
+
+const AUDIT_FILE_NAME: &str = "security_audit.jsonl";
+/// Past this size the older half of the log is dropped
+const MAX_LOG_BYTES: usize = 1024 * 1024;
+
+/// One security setting changed from an unlocked session, or by SafeNode
+/// itself, e.g. when biometric unlock is invalidated. Values are plain
+/// settings such as timeouts and switches; secrets like PINs are never
+/// recorded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecurityChange {
+    pub at: DateTime<Utc>,
+    /// Vault whose session made the change
+    pub vault: String,
+    pub setting: String,
+    pub before: Value,
+    pub after: Value,
+}
+
+impl SecurityChange {
+    pub fn new(vault: &str, setting: &str, before: impl Serialize, after: impl Serialize) -> Self {
+        SecurityChange {
+            at: Utc::now(),
+            vault: vault.to_string(),
+            setting: setting.to_string(),
+            before: serde_json::to_value(before).unwrap_or(Value::Null),
+            after: serde_json::to_value(after).unwrap_or(Value::Null),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeychainOperation {
+    Write,
+    Delete,
+}
+
+/// SafeNode wrote or deleted an OS keychain entry, or one in the file that
+/// stands in for the keychain. Records where, never the secret.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeychainChange {
+    pub at: DateTime<Utc>,
+    pub operation: KeychainOperation,
+    /// Keychain service without the `com.safenode.` prefix
+    pub service: String,
+    /// `storage::keychain_id` of the vault, for secrets SafeNode keeps per
+    /// vault
+    pub vault_id: Option<String>,
+    pub secret: Option<VaultSecret>,
+    pub backend: KeychainBackend,
+    /// Command the change was made for; `None` for SafeNode's own upkeep,
+    /// e.g. migrations at startup
+    pub command: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditCategory {
+    SecurityChange,
+    Keychain,
+}
+
+/// One line of the log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "category", rename_all = "snake_case")]
+pub enum AuditEvent {
+    SecurityChange(SecurityChange),
+    Keychain(KeychainChange),
+}
+
+impl AuditEvent {
+    pub fn category(&self) -> AuditCategory {
+        match self {
+            AuditEvent::SecurityChange(_) => AuditCategory::SecurityChange,
+            AuditEvent::Keychain(_) => AuditCategory::Keychain,
+        }
+    }
+
+    /// Lines written before there were categories are security changes
+    fn parse(line: &[u8]) -> Option<AuditEvent> {
+        serde_json::from_slice(line)
+            .or_else(|_| serde_json::from_slice(line).map(AuditEvent::SecurityChange))
+            .ok()
+    }
+}
+
+fn audit_path(app: &AppHandle) -> Result<PathBuf, VaultError> {
+    Ok(storage::data_dir(app)?.join(AUDIT_FILE_NAME))
+}
+
+pub fn record(app: &AppHandle, change: &SecurityChange) -> Result<(), VaultError> {
+    append(app, &AuditEvent::SecurityChange(change.clone()))
+}
+
+pub fn record_keychain_change(app: &AppHandle, change: &KeychainChange) -> Result<(), VaultError> {
+    append(app, &AuditEvent::Keychain(change.clone()))
+}
+
+/// Up to `limit` events, newest first, only those in `category` when given.
+/// Lines that do not parse are skipped.
+pub fn read(app: &AppHandle, category: Option<AuditCategory>, limit: usize) -> Result<Vec<AuditEvent>, VaultError> {
+    let log = storage::read_file(&audit_path(app)?)?.unwrap_or_default();
+    Ok(log
+        .split(|&b| b == b'\n')
+        .rev()
+        .filter_map(AuditEvent::parse)
+        .filter(|event| category.is_none_or(|category| event.category() == category))
+        .take(limit)
+        .collect())
+}
+
+fn append(app: &AppHandle, event: &AuditEvent) -> Result<(), VaultError> {
+    let path = audit_path(app)?;
+    let mut line = serde_json::to_vec(event).map_err(|e| VaultError::Io(e.to_string()))?;
+    line.push(b'\n');
+
+    let size = std::fs::metadata(&path).map_or(0, |metadata| metadata.len() as usize);
+    if size + line.len() <= MAX_LOG_BYTES {
+        return storage::append_private(&path, &line);
+    }
+    let log = storage::read_file(&path)?.unwrap_or_default();
+    // Keep the newer half, starting at a line boundary
+    let keep_from = log[log.len() / 2..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map_or(log.len(), |offset| log.len() / 2 + offset + 1);
+    let mut trimmed = log[keep_from..].to_vec();
+    trimmed.extend_from_slice(&line);
+    storage::write_atomic(&path, &trimmed)
+}
@@ -0,0 +1,155 @@
+/*!
+ * Biometric Prompt Text
+ * The reason shown in the platform's biometric or password dialog, built
+ * from what the user is about to do
+ */
+
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Longest entry title put into a prompt; system dialogs are small
+const MAX_TITLE_CHARS: usize = 48;
+
+/// What a prompt is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PromptOperation {
+    UnlockVault,
+    RevealPassword,
+    CopyPassword,
+    ApproveExport,
+    /// Confirming it's the user before a sensitive change
+    Reauthenticate,
+}
+
+impl PromptOperation {
+    /// Key of this operation's template in `BiometricPromptContext::strings`
+    fn key(self) -> &'static str {
+        match self {
+            PromptOperation::UnlockVault => "unlock_vault",
+            PromptOperation::RevealPassword => "reveal_password",
+            PromptOperation::CopyPassword => "copy_password",
+            PromptOperation::ApproveExport => "approve_export",
+            PromptOperation::Reauthenticate => "reauthenticate",
+        }
+    }
+
+    fn english(self) -> &'static str {
+        match self {
+            PromptOperation::UnlockVault => "Unlock SafeNode",
+            PromptOperation::RevealPassword => "Reveal the password for {entry}",
+            PromptOperation::CopyPassword => "Copy the password for {entry}",
+            PromptOperation::ApproveExport => "Approve exporting your vault",
+            PromptOperation::Reauthenticate => "Confirm it's you to continue",
+        }
+    }
+}
+
+/// Key of the phrase used for `{entry}` when there is no entry title
+const THIS_ENTRY_KEY: &str = "this_entry";
+const THIS_ENTRY_ENGLISH: &str = "this entry";
+
+/// What the frontend knows about a prompt: the operation, the entry it
+/// concerns and templates in the user's language. Templates are keyed by
+/// operation (`"reveal_password"`) and may contain `{entry}`; missing keys
+/// fall back to English.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BiometricPromptContext {
+    pub operation: PromptOperation,
+    #[serde(default)]
+    pub entry_title: Option<String>,
+    #[serde(default)]
+    pub strings: HashMap<String, String>,
+}
+
+impl BiometricPromptContext {
+    /// Context in English for `operation`
+    pub fn new(operation: PromptOperation) -> Self {
+        BiometricPromptContext {
+            operation,
+            entry_title: None,
+            strings: HashMap::new(),
+        }
+    }
+
+    pub fn with_entry(mut self, title: &str) -> Self {
+        self.entry_title = Some(title.to_string());
+        self
+    }
+
+    fn string<'a>(&'a self, key: &str, english: &'a str) -> &'a str {
+        self.strings
+            .get(key)
+            .map(String::as_str)
+            .filter(|s| !s.trim().is_empty())
+            .unwrap_or(english)
+    }
+
+    /// The text for the platform dialog
+    pub fn text(&self) -> String {
+        let template = self.string(self.operation.key(), self.operation.english());
+        if !template.contains("{entry}") {
+            return template.to_string();
+        }
+        let entry = match self.entry_title.as_deref().map(str::trim).filter(|t| !t.is_empty()) {
+            Some(title) => format!("\u{201c}{}\u{201d}", short_title(title)),
+            None => self.string(THIS_ENTRY_KEY, THIS_ENTRY_ENGLISH).to_string(),
+        };
+        template.replace("{entry}", &entry)
+    }
+}
+
+/// `title` cut to `MAX_TITLE_CHARS`, with control characters removed so a
+/// title cannot break the dialog's layout
+fn short_title(title: &str) -> String {
+    let mut chars = title.chars().filter(|c| !c.is_control());
+    let mut short: String = chars.by_ref().take(MAX_TITLE_CHARS).collect();
+    if chars.next().is_some() {
+        short.push('\u{2026}');
+    }
+    short
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn localized(operation: PromptOperation, strings: &[(&str, &str)]) -> BiometricPromptContext {
+        BiometricPromptContext {
+            strings: strings.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect(),
+            ..BiometricPromptContext::new(operation)
+        }
+    }
+
+    #[test]
+    fn missing_or_blank_strings_fall_back_to_english() {
+        let german = [("unlock_vault", "SafeNode entsperren"), ("copy_password", "  ")];
+        assert_eq!(localized(PromptOperation::UnlockVault, &german).text(), "SafeNode entsperren");
+        assert_eq!(localized(PromptOperation::ApproveExport, &german).text(), "Approve exporting your vault");
+        assert_eq!(
+            localized(PromptOperation::CopyPassword, &german).with_entry("Bank").text(),
+            "Copy the password for \u{201c}Bank\u{201d}"
+        );
+    }
+
+    #[test]
+    fn entry_is_substituted_or_named_generically() {
+        let french = [("reveal_password", "Afficher le mot de passe de {entry}"), ("this_entry", "cet élément")];
+        let reveal = localized(PromptOperation::RevealPassword, &french);
+        assert_eq!(reveal.clone().with_entry(" Banque ").text(), "Afficher le mot de passe de \u{201c}Banque\u{201d}");
+        assert_eq!(reveal.clone().text(), "Afficher le mot de passe de cet élément");
+        assert_eq!(reveal.with_entry("   ").text(), "Afficher le mot de passe de cet élément");
+        assert_eq!(
+            BiometricPromptContext::new(PromptOperation::RevealPassword).text(),
+            "Reveal the password for this entry"
+        );
+    }
+
+    #[test]
+    fn titles_are_cut_and_stripped_of_control_characters() {
+        assert_eq!(short_title("Bank\n\u{7}\tlogin"), "Banklogin");
+        assert_eq!(short_title(&"a".repeat(MAX_TITLE_CHARS)), "a".repeat(MAX_TITLE_CHARS));
+        let long = format!("{}\u{1b}b", "é".repeat(MAX_TITLE_CHARS));
+        assert_eq!(short_title(&long), format!("{}\u{2026}", "é".repeat(MAX_TITLE_CHARS)));
+    }
+}
@@ -0,0 +1,148 @@
+/*!
+ * Biometric Unlock
+ * The vault data key, sealed by a `SecureKeyStore` and released after a
+ * successful biometric or system password check
+ */
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::ErrorKind;
+use tauri::AppHandle;
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
+
+use crate::biometrics::{AuthMethod, AuthenticatorSource, BiometricAuthenticator};
+use crate::crypto::{base64_bytes, VaultKey};
+use crate::error::VaultError;
+use crate::secure_key;
+use crate::storage;
+
+/// The sealed data key, stored next to the vault. Files written before
+/// hardware stores existed hold the software store's wrapping key under
+/// `wrapping_key`.
+#[derive(Serialize, Deserialize, Zeroize, ZeroizeOnDrop)]
+struct BiometricSlot {
+    #[serde(with = "base64_bytes", alias = "wrapping_key")]
+    sealed: Vec<u8>,
+    #[serde(default)]
+    hardware_backed: bool,
+    /// Digest of the platform's enrollment state when the slot was made
+    #[serde(default)]
+    enrollment: Option<String>,
+    /// How the user proves it's them before the key is released
+    #[serde(default)]
+    #[zeroize(skip)]
+    method: AuthMethod,
+}
+
+pub fn is_enabled(app: &AppHandle, vault_name: &str) -> bool {
+    storage::biometric_path(app, vault_name).is_ok_and(|path| path.exists())
+}
+
+fn load(app: &AppHandle, vault_name: &str) -> Result<BiometricSlot, VaultError> {
+    let json = Zeroizing::new(
+        storage::read_file(&storage::biometric_path(app, vault_name)?)?.ok_or(VaultError::BiometricUnlockNotEnabled)?,
+    );
+    serde_json::from_slice(&json).map_err(|e| VaultError::Io(format!("Biometric unlock file is malformed: {}", e)))
+}
+
+/// Whether the data key of `vault_name` is bound to hardware. Hardware
+/// stores prompt for biometrics themselves in `release`.
+pub fn is_hardware_backed(app: &AppHandle, vault_name: &str) -> bool {
+    load(app, vault_name).is_ok_and(|slot| slot.hardware_backed)
+}
+
+/// The method that protects the data key of `vault_name`
+pub fn method(app: &AppHandle, vault_name: &str) -> Result<AuthMethod, VaultError> {
+    load(app, vault_name).map(|slot| slot.method)
+}
+
+/// Digest of the current enrollment of `authenticator`, if the platform
+/// reports one
+fn current_enrollment(authenticator: &dyn BiometricAuthenticator) -> Result<Option<String>, VaultError> {
+    let state = authenticator.enrollment_state().map_err(VaultError::BiometricFailed)?;
+    Ok(state.map(|state| Sha256::digest(&state).iter().map(|b| format!("{:02x}", b)).collect()))
+}
+
+/// Fail with `BiometricInvalidated`, deleting the stored key, when
+/// biometrics were enrolled or removed since `enable`
+pub fn check_enrollment(app: &AppHandle, vault_name: &str, source: &dyn AuthenticatorSource) -> Result<(), VaultError> {
+    let slot = load(app, vault_name)?;
+    let Some(enrolled) = slot.enrollment.as_deref() else {
+        return Ok(());
+    };
+    let authenticator = source.authenticator(slot.method).ok_or(VaultError::BiometricUnavailable)?;
+    match current_enrollment(&*authenticator)? {
+        Some(current) if current != enrolled => {
+            disable(app, vault_name)?;
+            Err(VaultError::BiometricInvalidated)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Seal `data_key` for release after `authenticator`, with the strongest
+/// store available, replacing any earlier material. Falls back to the
+/// software store when the hardware one cannot be used, e.g. in an unsigned
+/// build; hardware stores only ask for biometrics. Returns whether the key
+/// is hardware backed.
+pub fn enable(
+    app: &AppHandle,
+    vault_name: &str,
+    data_key: &VaultKey,
+    authenticator: &dyn BiometricAuthenticator,
+) -> Result<bool, VaultError> {
+    let method = authenticator.method();
+    let enrollment = current_enrollment(authenticator)?;
+    let keychain_id = storage::keychain_id(app, vault_name)?;
+    let mut store = match method {
+        AuthMethod::Biometric => secure_key::platform_store(),
+        AuthMethod::SystemPassword => secure_key::software_store(),
+    };
+    let sealed = match store.seal(&keychain_id, data_key) {
+        Ok(sealed) => sealed,
+        // The user turning the prompt down is not a reason to fall back
+        Err(e) if store.hardware_backed() && !matches!(e, VaultError::BiometricFailed(_)) => {
+            eprintln!("Hardware key store unavailable, using the keychain: {}", e);
+            store = secure_key::software_store();
+            store.seal(&keychain_id, data_key)?
+        }
+        Err(e) => return Err(e),
+    };
+
+    let slot = BiometricSlot {
+        sealed: sealed.to_vec(),
+        hardware_backed: store.hardware_backed(),
+        enrollment,
+        method,
+    };
+    let json = Zeroizing::new(serde_json::to_vec(&slot).map_err(|e| VaultError::Io(e.to_string()))?);
+    storage::write_atomic(&storage::biometric_path(app, vault_name)?, &json)?;
+
+    // Material left in the other kind of store must not outlive the switch
+    if let Ok(other) = secure_key::store_for(!slot.hardware_backed) {
+        if let Err(e) = other.delete(&keychain_id) {
+            eprintln!("Failed to delete old biometric key: {}", e);
+        }
+    }
+    Ok(slot.hardware_backed)
+}
+
+/// The data key stored by `enable`. With the software store, call only
+/// after the slot's `method` succeeded; a hardware store shows its own prompt and
+/// blocks until it is answered.
+pub fn release(app: &AppHandle, vault_name: &str) -> Result<VaultKey, VaultError> {
+    let slot = load(app, vault_name)?;
+    let keychain_id = storage::keychain_id(app, vault_name)?;
+    secure_key::store_for(slot.hardware_backed)?.open(&keychain_id, &slot.sealed)
+}
+
+/// Remove the file and every stored copy of the data key
+pub fn disable(app: &AppHandle, vault_name: &str) -> Result<(), VaultError> {
+    if let Err(e) = fs::remove_file(storage::biometric_path(app, vault_name)?) {
+        if e.kind() != ErrorKind::NotFound {
+            return Err(e.into());
+        }
+    }
+    secure_key::delete_all(&storage::keychain_id(app, vault_name)?)
+}
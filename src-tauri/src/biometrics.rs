@@ -1,25 +1,321 @@
-/**
+/*!
  * Biometric Authentication Module
  * Cross-platform biometric authentication abstraction
  */
 
+use serde::ser::{Serialize, SerializeMap, Serializer};
 use serde_json::Value;
+use std::fmt;
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long a prompt may wait for the user before it is dismissed
+pub const PROMPT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long a platform gets to return after its prompt was cancelled
+/// before the prompt is abandoned
+const CANCEL_GRACE: Duration = Duration::from_secs(5);
 
 /// Biometric authentication result
 #[derive(Debug, Clone)]
 pub struct BiometricResult {
     pub success: bool,
-    pub error: Option<String>,
-    pub method: Option<String>,
+    /// Why the user was not verified, when `success` is false
+    pub error: Option<BiometricError>,
+    /// What satisfied the prompt, or what was tried when it failed
+    pub method: Option<AuthMechanism>,
+    pub outcome: PromptOutcome,
+}
+
+impl BiometricResult {
+    pub fn verified(method: AuthMechanism) -> Self {
+        BiometricResult {
+            success: true,
+            error: None,
+            method: Some(method),
+            outcome: PromptOutcome::Success,
+        }
+    }
+
+    pub fn failed(error: BiometricError, method: AuthMechanism) -> Self {
+        BiometricResult {
+            success: false,
+            error: Some(error),
+            method: Some(method),
+            outcome: PromptOutcome::Failure,
+        }
+    }
+
+    /// A prompt dismissed by `CancelToken` rather than by the user
+    fn ended(outcome: PromptOutcome) -> Self {
+        let error = match outcome {
+            PromptOutcome::Timeout => BiometricError::Timeout,
+            _ => BiometricError::Cancelled,
+        };
+        BiometricResult {
+            success: false,
+            error: Some(error),
+            method: None,
+            outcome,
+        }
+    }
+}
+
+/// The mechanism behind a prompt. Security settings tell a biometric pass
+/// apart from a password, so each has its own name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+#[allow(dead_code)] // Each platform constructs only its own mechanisms
+pub enum AuthMechanism {
+    TouchId,
+    FaceId,
+    OpticId,
+    /// Approved on a paired Apple Watch
+    AppleWatch,
+    /// The Mac's login password, through `LAPolicyDeviceOwnerAuthentication`
+    DevicePassword,
+    /// Windows Hello, which may accept its PIN as well as biometrics
+    WindowsHello,
+    /// A reader driven by fprintd
+    Fingerprint,
+    /// The login password, through polkit
+    SystemPassword,
+}
+
+/// How a prompt ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PromptOutcome {
+    Success,
+    /// The user was not verified
+    Failure,
+    Timeout,
+    /// Aborted by `cancel_biometric_prompt` or a newer prompt
+    Cancelled,
+}
+
+/// Why biometrics could not be used or did not verify the user.
+///
+/// Serialized like `VaultError`, as `{ "kind": "...", "message": "...",
+/// "details": {...}? }`, so the UI can branch on `kind`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BiometricError {
+    /// No biometric hardware, or the platform service is missing
+    NotAvailable,
+    /// The hardware is there but the user has not enrolled
+    NotEnrolled,
+    /// The biometric did not match
+    NotRecognized,
+    /// The user dismissed the prompt
+    UserCancelled,
+    /// The user asked to enter a password instead
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    FallbackRequested,
+    /// The system or app dismissed the prompt
+    Cancelled,
+    /// Too many failed attempts; the OS requires its password first
+    LockedOut,
+    /// The sensor is in use by something else
+    DeviceBusy,
+    /// No biometric was presented before the prompt gave up
+    Timeout,
+    /// Anything else the platform reported. `code` is the platform's own
+    /// error code, or 0 where it has none.
+    PlatformError { code: i64, message: String },
+}
+
+impl BiometricError {
+    pub fn platform(message: impl Into<String>) -> Self {
+        BiometricError::PlatformError {
+            code: 0,
+            message: message.into(),
+        }
+    }
+
+    /// Stable discriminator sent to the frontend
+    pub fn kind(&self) -> &'static str {
+        match self {
+            BiometricError::NotAvailable => "not_available",
+            BiometricError::NotEnrolled => "not_enrolled",
+            BiometricError::NotRecognized => "not_recognized",
+            BiometricError::UserCancelled => "user_cancelled",
+            BiometricError::FallbackRequested => "fallback_requested",
+            BiometricError::Cancelled => "cancelled",
+            BiometricError::LockedOut => "locked_out",
+            BiometricError::DeviceBusy => "device_busy",
+            BiometricError::Timeout => "timeout",
+            BiometricError::PlatformError { .. } => "platform_error",
+        }
+    }
+}
+
+impl fmt::Display for BiometricError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BiometricError::NotAvailable => write!(f, "Biometric authentication is not available on this device"),
+            BiometricError::NotEnrolled => write!(f, "No biometrics are enrolled on this device"),
+            BiometricError::NotRecognized => write!(f, "Biometric not recognized"),
+            BiometricError::UserCancelled => write!(f, "Verification was cancelled"),
+            BiometricError::FallbackRequested => write!(f, "Use your master password instead"),
+            BiometricError::Cancelled => write!(f, "The biometric prompt was dismissed"),
+            BiometricError::LockedOut => write!(f, "Too many failed attempts; unlock your device with its password first"),
+            BiometricError::DeviceBusy => write!(f, "The biometric sensor is in use by another application"),
+            BiometricError::Timeout => write!(f, "The biometric prompt timed out"),
+            BiometricError::PlatformError { message, .. } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for BiometricError {}
+
+impl Serialize for BiometricError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let code = match self {
+            BiometricError::PlatformError { code, .. } => Some(code),
+            _ => None,
+        };
+        let mut map = serializer.serialize_map(Some(if code.is_some() { 3 } else { 2 }))?;
+        map.serialize_entry("kind", self.kind())?;
+        map.serialize_entry("message", &self.to_string())?;
+        if let Some(code) = code {
+            map.serialize_entry("details", &serde_json::json!({ "code": code }))?;
+        }
+        map.end()
+    }
+}
+
+#[derive(Default)]
+struct CancelInner {
+    ended: Option<PromptOutcome>,
+    finished: bool,
+    dismiss: Option<Box<dyn FnOnce() + Send>>,
+}
+
+/// Lets another thread end a prompt in flight. Platforms register a hook
+/// that dismisses their dialog; it runs under the token's lock, so clearing
+/// it with `clear_on_cancel` guarantees it is not running afterwards.
+#[derive(Clone, Default)]
+pub struct CancelToken(Arc<CancelShared>);
+
+#[derive(Default)]
+struct CancelShared {
+    inner: Mutex<CancelInner>,
+    changed: Condvar,
+}
+
+impl CancelToken {
+    fn inner(&self) -> std::sync::MutexGuard<'_, CancelInner> {
+        self.0.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn cancel(&self) {
+        self.end(PromptOutcome::Cancelled);
+    }
+
+    fn end(&self, outcome: PromptOutcome) {
+        let mut inner = self.inner();
+        if inner.finished || inner.ended.is_some() {
+            return;
+        }
+        inner.ended = Some(outcome);
+        if let Some(dismiss) = inner.dismiss.take() {
+            dismiss();
+        }
+        self.0.changed.notify_all();
+    }
+
+    /// How the prompt was ended from outside, if it was
+    pub fn ended(&self) -> Option<PromptOutcome> {
+        self.inner().ended
+    }
+
+    /// Run `dismiss` on cancellation, or right away if already cancelled
+    pub fn on_cancel(&self, dismiss: impl FnOnce() + Send + 'static) {
+        let mut inner = self.inner();
+        if inner.ended.is_some() {
+            dismiss();
+        } else {
+            inner.dismiss = Some(Box::new(dismiss));
+        }
+    }
+
+    /// Drop the hook before the resources it uses are freed
+    pub fn clear_on_cancel(&self) {
+        self.inner().dismiss = None;
+    }
+
+    pub fn same_as(&self, other: &CancelToken) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+
+    /// Block until the prompt finishes, ending it as timed out once
+    /// `timeout` passes. Returns `true` if it is still running
+    /// `CANCEL_GRACE` after it was ended, i.e. the platform ignored the
+    /// cancellation.
+    fn watch(&self, timeout: Duration) -> bool {
+        let inner = self.inner();
+        let (inner, wait) = self
+            .0
+            .changed
+            .wait_timeout_while(inner, timeout, |inner| !inner.finished && inner.ended.is_none())
+            .unwrap_or_else(|e| e.into_inner());
+        drop(inner);
+        if wait.timed_out() {
+            self.end(PromptOutcome::Timeout);
+        }
+
+        let inner = self.inner();
+        let (inner, _) = self
+            .0
+            .changed
+            .wait_timeout_while(inner, CANCEL_GRACE, |inner| !inner.finished)
+            .unwrap_or_else(|e| e.into_inner());
+        !inner.finished
+    }
+
+    fn finish(&self) {
+        self.inner().finished = true;
+        self.0.changed.notify_all();
+    }
 }
 
 /// Trait for platform-specific biometric authentication
-pub trait BiometricAuthenticator {
+pub trait BiometricAuthenticator: Send {
     /// Check if biometric authentication is available
-    fn is_available(&self) -> Result<BiometricAvailability, String>;
+    fn is_available(&self) -> Result<BiometricAvailability, BiometricError>;
     
-    /// Authenticate using biometrics
-    fn authenticate(&self, prompt: &str) -> Result<BiometricResult, String>;
+    /// Authenticate using biometrics. Blocks until the prompt ends;
+    /// implementations dismiss it through `cancel.on_cancel` where the
+    /// platform allows.
+    fn authenticate(&self, prompt: &str, cancel: &CancelToken) -> Result<BiometricResult, BiometricError>;
+
+    /// Opaque value that changes whenever biometrics are enrolled or
+    /// removed. `None` where the platform offers no such signal, as with
+    /// Windows Hello, or cannot tell right now.
+    fn enrollment_state(&self) -> Result<Option<Vec<u8>>, BiometricError> {
+        Ok(None)
+    }
+
+    /// What the user presents to this authenticator
+    fn method(&self) -> AuthMethod {
+        AuthMethod::Biometric
+    }
+}
+
+/// What the user presents to confirm it's them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMethod {
+    /// The platform's fingerprint, face or other biometric prompt
+    #[default]
+    Biometric,
+    /// The user's login password, asked for by the OS
+    SystemPassword,
+}
+
+impl AuthMethod {
+    /// Every method, most preferred first
+    pub const ALL: [AuthMethod; 2] = [AuthMethod::Biometric, AuthMethod::SystemPassword];
 }
 
 /// Biometric availability information
@@ -28,121 +324,951 @@ pub struct BiometricAvailability {
     pub available: bool,
     pub biometric_type: BiometricType,
     pub enrolled: bool,
+    /// The OS refuses biometrics until its password is entered
+    pub locked_out: bool,
+    /// Attempts left before a lockout, where the platform says
+    pub retries_remaining: Option<u32>,
 }
 
 /// Types of biometric authentication
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(dead_code)] // Each platform reports only the sensors it knows
 pub enum BiometricType {
     Fingerprint,
     Face,
+    /// A sensor that is neither, such as an iris scanner
+    IrisOrOther,
     Unknown,
 }
 
+impl BiometricType {
+    /// Name sent to the frontend, which picks icons and wording by it
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BiometricType::Fingerprint => "fingerprint",
+            BiometricType::Face => "face",
+            BiometricType::IrisOrOther => "iris_or_other",
+            BiometricType::Unknown => "unknown",
+        }
+    }
+}
+
 /// Platform-specific biometric authenticator implementations
 
+/// LocalAuthentication: `LAContext` with the
+/// `deviceOwnerAuthenticationWithBiometrics` policy
 #[cfg(target_os = "macos")]
 pub mod macos {
     use super::*;
-    
+    use block::ConcreteBlock;
+    use objc::rc::autoreleasepool;
+    use objc::runtime::{Object, BOOL, NO, YES};
+    use objc::{class, msg_send, sel, sel_impl};
+    use std::ffi::{CStr, CString};
+    use std::os::raw::c_char;
+    use std::ptr;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc;
+
+    #[link(name = "LocalAuthentication", kind = "framework")]
+    extern "C" {}
+
+    /// `LAPolicyDeviceOwnerAuthenticationWithBiometrics`
+    const POLICY_BIOMETRICS: isize = 1;
+    /// `LAPolicyDeviceOwnerAuthentication`: biometrics, Apple Watch or the
+    /// login password
+    const POLICY_DEVICE_OWNER: isize = 2;
+    /// `LAPolicyDeviceOwnerAuthenticationWithWatch`
+    const POLICY_WATCH: isize = 3;
+
+    // `LAError` codes
+    const LA_AUTHENTICATION_FAILED: isize = -1;
+    const LA_USER_CANCEL: isize = -2;
+    const LA_USER_FALLBACK: isize = -3;
+    const LA_SYSTEM_CANCEL: isize = -4;
+    const LA_BIOMETRY_NOT_AVAILABLE: isize = -6;
+    const LA_BIOMETRY_NOT_ENROLLED: isize = -7;
+    const LA_BIOMETRY_LOCKOUT: isize = -8;
+    const LA_APP_CANCEL: isize = -9;
+
+    // `LABiometryType`
+    const BIOMETRY_TOUCH_ID: isize = 1;
+    const BIOMETRY_FACE_ID: isize = 2;
+    const BIOMETRY_OPTIC_ID: isize = 4;
+
     pub struct MacOSBiometricAuthenticator;
-    
+
+    /// `Settings::allow_device_credential_fallback`
+    static DEVICE_CREDENTIAL_FALLBACK: AtomicBool = AtomicBool::new(false);
+
+    pub fn set_device_credential_fallback(allowed: bool) {
+        DEVICE_CREDENTIAL_FALLBACK.store(allowed, Ordering::Relaxed);
+    }
+
+    /// The `LAContext` of a prompt, handed to its cancel hook. `invalidate`
+    /// may be sent from any thread, and the hook is cleared before the
+    /// context is released.
+    struct ContextHandle(*mut Object);
+
+    unsafe impl Send for ContextHandle {}
+
+    impl ContextHandle {
+        /// Dismiss the prompt; its reply then reports `LAErrorAppCancel`
+        fn invalidate(&self) {
+            unsafe {
+                let _: () = msg_send![self.0, invalidate];
+            }
+        }
+    }
+
+    unsafe fn ns_string(value: &str) -> *mut Object {
+        // An interior NUL would truncate the prompt; drop them instead
+        let value = CString::new(value.replace('\0', "")).unwrap_or_default();
+        msg_send![class!(NSString), stringWithUTF8String: value.as_ptr()]
+    }
+
+    /// `code` and `localizedDescription` of an `NSError`
+    unsafe fn error_details(error: *mut Object) -> (isize, String) {
+        if error.is_null() {
+            return (0, String::new());
+        }
+        let code: isize = msg_send![error, code];
+        let description: *mut Object = msg_send![error, localizedDescription];
+        let utf8: *const c_char = if description.is_null() {
+            ptr::null()
+        } else {
+            msg_send![description, UTF8String]
+        };
+        let message = if utf8.is_null() {
+            format!("LocalAuthentication error {}", code)
+        } else {
+            CStr::from_ptr(utf8).to_string_lossy().into_owned()
+        };
+        (code, message)
+    }
+
+    fn error_for(code: isize, message: String) -> BiometricError {
+        match code {
+            LA_AUTHENTICATION_FAILED => BiometricError::NotRecognized,
+            LA_USER_CANCEL => BiometricError::UserCancelled,
+            LA_USER_FALLBACK => BiometricError::FallbackRequested,
+            LA_SYSTEM_CANCEL | LA_APP_CANCEL => BiometricError::Cancelled,
+            LA_BIOMETRY_NOT_AVAILABLE => BiometricError::NotAvailable,
+            LA_BIOMETRY_NOT_ENROLLED => BiometricError::NotEnrolled,
+            LA_BIOMETRY_LOCKOUT => BiometricError::LockedOut,
+            _ => BiometricError::PlatformError {
+                code: code as i64,
+                message,
+            },
+        }
+    }
+
+    fn biometric_type(biometry_type: isize) -> BiometricType {
+        match biometry_type {
+            BIOMETRY_TOUCH_ID => BiometricType::Fingerprint,
+            BIOMETRY_FACE_ID => BiometricType::Face,
+            BIOMETRY_OPTIC_ID => BiometricType::IrisOrOther,
+            _ => BiometricType::Unknown,
+        }
+    }
+
     impl super::BiometricAuthenticator for MacOSBiometricAuthenticator {
-        fn is_available(&self) -> Result<BiometricAvailability, String> {
-            // macOS: Check for LocalAuthentication framework availability
-            // In production, this would use Objective-C bindings or a crate like `localauth`
-            // For now, return a placeholder that indicates availability
-            
+        fn enrollment_state(&self) -> Result<Option<Vec<u8>>, BiometricError> {
+            autoreleasepool(|| unsafe {
+                let context: *mut Object = msg_send![class!(LAContext), new];
+                if context.is_null() {
+                    return Err(BiometricError::NotAvailable);
+                }
+                let mut error: *mut Object = ptr::null_mut();
+                let can_evaluate: BOOL = msg_send![context, canEvaluatePolicy: POLICY_BIOMETRICS error: &mut error];
+                // Set by a successful `canEvaluatePolicy`
+                let domain_state: *mut Object = msg_send![context, evaluatedPolicyDomainState];
+                let state = if can_evaluate == YES && !domain_state.is_null() {
+                    let bytes: *const u8 = msg_send![domain_state, bytes];
+                    let length: usize = msg_send![domain_state, length];
+                    if bytes.is_null() {
+                        Some(Vec::new())
+                    } else {
+                        Some(std::slice::from_raw_parts(bytes, length).to_vec())
+                    }
+                } else {
+                    None
+                };
+                let _: () = msg_send![context, release];
+                Ok(state)
+            })
+        }
+
+        fn is_available(&self) -> Result<BiometricAvailability, BiometricError> {
+            let (can_evaluate, code, biometry_type) = check_policy(POLICY_BIOMETRICS)?;
+            // With the fallback, the login password stands in for a sensor
+            // that is out of reach, e.g. with the lid closed
+            let fallback = DEVICE_CREDENTIAL_FALLBACK.load(Ordering::Relaxed);
+            if !can_evaluate && fallback && check_policy(POLICY_DEVICE_OWNER)?.0 {
+                return Ok(BiometricAvailability {
+                    available: true,
+                    biometric_type: biometric_type(biometry_type),
+                    enrolled: true,
+                    locked_out: false,
+                    retries_remaining: None,
+                });
+            }
+
+            // A locked-out sensor is still there and enrolled; the prompt
+            // reports the lockout
+            let (available, enrolled) = match code {
+                _ if can_evaluate => (true, true),
+                LA_BIOMETRY_LOCKOUT => (true, true),
+                LA_BIOMETRY_NOT_ENROLLED => (true, false),
+                _ => (false, false),
+            };
             Ok(BiometricAvailability {
-                available: true,
-                biometric_type: BiometricType::Fingerprint, // Could be Face for Face ID
-                enrolled: true,
+                available,
+                biometric_type: biometric_type(biometry_type),
+                enrolled,
+                locked_out: !can_evaluate && code == LA_BIOMETRY_LOCKOUT,
+                // LocalAuthentication does not expose its retry count
+                retries_remaining: None,
             })
         }
-        
-        fn authenticate(&self, prompt: &str) -> Result<BiometricResult, String> {
-            // macOS: Use LocalAuthentication framework
-            // In production, this would:
-            // 1. Create an LAContext
-            // 2. Evaluate policy with LAPolicy.deviceOwnerAuthenticationWithBiometrics
-            // 3. Handle success/failure callbacks
-            
-            // Placeholder implementation
-            Ok(BiometricResult {
-                success: true,
-                error: None,
-                method: Some("Touch ID or Face ID".to_string()),
+
+        /// Biometrics alone, unless the device credential fallback is on.
+        /// Then the Apple Watch is tried when the sensor cannot be used,
+        /// and the login password when neither can or the user asks for
+        /// it. That last prompt may still offer Touch ID; a pass there is
+        /// reported as `DevicePassword`, since LocalAuthentication does not
+        /// say which one was used.
+        fn authenticate(&self, prompt: &str, cancel: &CancelToken) -> Result<BiometricResult, BiometricError> {
+            let (biometrics_ready, _, biometry_type) = check_policy(POLICY_BIOMETRICS)?;
+            let biometrics = match biometric_type(biometry_type) {
+                BiometricType::Face => AuthMechanism::FaceId,
+                BiometricType::IrisOrOther => AuthMechanism::OpticId,
+                _ => AuthMechanism::TouchId,
+            };
+            let fallback = DEVICE_CREDENTIAL_FALLBACK.load(Ordering::Relaxed);
+
+            let mut password_requested = false;
+            if biometrics_ready || !fallback {
+                match evaluate(POLICY_BIOMETRICS, prompt, cancel)? {
+                    None => return Ok(BiometricResult::verified(biometrics)),
+                    Some((LA_USER_FALLBACK, _)) if fallback => password_requested = true,
+                    Some((code, message)) => return Ok(BiometricResult::failed(error_for(code, message), biometrics)),
+                }
+            }
+            if !password_requested && check_policy(POLICY_WATCH)?.0 {
+                match evaluate(POLICY_WATCH, prompt, cancel)? {
+                    None => return Ok(BiometricResult::verified(AuthMechanism::AppleWatch)),
+                    Some((LA_USER_FALLBACK, _)) => {}
+                    Some((code, message)) => {
+                        return Ok(BiometricResult::failed(error_for(code, message), AuthMechanism::AppleWatch))
+                    }
+                }
+            }
+            match evaluate(POLICY_DEVICE_OWNER, prompt, cancel)? {
+                None => Ok(BiometricResult::verified(AuthMechanism::DevicePassword)),
+                Some((code, message)) => {
+                    Ok(BiometricResult::failed(error_for(code, message), AuthMechanism::DevicePassword))
+                }
+            }
+        }
+    }
+
+    /// Whether `policy` can be evaluated now, the `LAError` code if not,
+    /// and the sensor's `LABiometryType`
+    fn check_policy(policy: isize) -> Result<(bool, isize, isize), BiometricError> {
+        autoreleasepool(|| unsafe {
+            let context: *mut Object = msg_send![class!(LAContext), new];
+            if context.is_null() {
+                return Err(BiometricError::NotAvailable);
+            }
+            let mut error: *mut Object = ptr::null_mut();
+            let can_evaluate: BOOL = msg_send![context, canEvaluatePolicy: policy error: &mut error];
+            // Only meaningful after `canEvaluatePolicy`
+            let biometry_type: isize = msg_send![context, biometryType];
+            let (code, _) = error_details(error);
+            let _: () = msg_send![context, release];
+            Ok((can_evaluate == YES, code, biometry_type))
+        })
+    }
+
+    /// Show the prompt for `policy` and wait for it. `None` when the user
+    /// passed, else the `LAError` code and message.
+    fn evaluate(policy: isize, prompt: &str, cancel: &CancelToken) -> Result<Option<(isize, String)>, BiometricError> {
+        let (sender, receiver) = mpsc::channel();
+        let context = autoreleasepool(|| unsafe {
+            let context: *mut Object = msg_send![class!(LAContext), new];
+            if context.is_null() {
+                return Err(BiometricError::NotAvailable);
+            }
+
+            // The reply runs on a private queue, so blocking this thread on
+            // the channel cannot deadlock it
+            let reply = ConcreteBlock::new(move |success: BOOL, error: *mut Object| {
+                let outcome = if success != NO { None } else { Some(error_details(error)) };
+                let _ = sender.send(outcome);
             })
+            .copy();
+            let _: () = msg_send![context,
+                evaluatePolicy: policy
+                localizedReason: ns_string(prompt)
+                reply: &*reply];
+            Ok(context)
+        })?;
+
+        // Runs at once if the prompt was already cancelled
+        let handle = ContextHandle(context);
+        cancel.on_cancel(move || handle.invalidate());
+        let outcome = receiver.recv();
+        cancel.clear_on_cancel();
+        unsafe {
+            let _: () = msg_send![context, release];
         }
+        outcome.map_err(|_| BiometricError::platform("Biometric prompt ended without a result"))
     }
 }
 
+/// Windows Hello through `UserConsentVerifier`
 #[cfg(target_os = "windows")]
 pub mod windows {
     use super::*;
-    
+    use ::windows::core::HSTRING;
+    use ::windows::Security::Credentials::UI::{
+        UserConsentVerificationResult, UserConsentVerifier, UserConsentVerifierAvailability,
+    };
+    use ::windows::Win32::Devices::BiometricFramework::{WinBioEnumBiometricUnits, WinBioFree, WINBIO_UNIT_SCHEMA};
+    use std::ptr;
+
+    // `WINBIO_BIOMETRIC_TYPE` factors
+    const WINBIO_TYPE_FACIAL_FEATURES: u32 = 0x0000_0002;
+    const WINBIO_TYPE_FINGERPRINT: u32 = 0x0000_0008;
+    const WINBIO_TYPE_ANY: u32 = 0x00FF_FFFF;
+
     pub struct WindowsBiometricAuthenticator;
-    
+
+    /// Whether the Windows Biometric Framework has a sensor for `factor`
+    fn has_sensor(factor: u32) -> bool {
+        let mut units: *mut WINBIO_UNIT_SCHEMA = ptr::null_mut();
+        let mut count = 0usize;
+        unsafe {
+            let found = WinBioEnumBiometricUnits(factor, &mut units, &mut count).is_ok() && count > 0;
+            if !units.is_null() {
+                let _ = WinBioFree(units as *const _);
+            }
+            found
+        }
+    }
+
+    /// UserConsentVerifier does not say which sensor Hello will use, so
+    /// ask the biometric framework. Face wins when both are present, as
+    /// Hello offers it first. Without any sensor Hello falls back to its PIN.
+    fn hello_modality() -> BiometricType {
+        if has_sensor(WINBIO_TYPE_FACIAL_FEATURES) {
+            BiometricType::Face
+        } else if has_sensor(WINBIO_TYPE_FINGERPRINT) {
+            BiometricType::Fingerprint
+        } else if has_sensor(WINBIO_TYPE_ANY) {
+            BiometricType::IrisOrOther
+        } else {
+            BiometricType::Unknown
+        }
+    }
+
+    /// Run a blocking WinRT wait on its own thread, so it never blocks a
+    /// thread that owns a UI or async runtime
+    fn on_worker_thread<T: Send + 'static>(
+        f: impl FnOnce() -> ::windows::core::Result<T> + Send + 'static,
+    ) -> Result<T, BiometricError> {
+        std::thread::spawn(f)
+            .join()
+            .map_err(|_| BiometricError::platform("Windows Hello worker thread panicked"))?
+            .map_err(|e| BiometricError::PlatformError {
+                code: i64::from(e.code().0),
+                message: format!("Windows Hello error: {}", e.message()),
+            })
+    }
+
     impl super::BiometricAuthenticator for WindowsBiometricAuthenticator {
-        fn is_available(&self) -> Result<BiometricAvailability, String> {
-            // Windows: Check for Windows Hello availability
-            // In production, this would use Windows.Security.Credentials.UI APIs
-            // via the `windows` crate
-            
+        fn is_available(&self) -> Result<BiometricAvailability, BiometricError> {
+            let availability = on_worker_thread(|| UserConsentVerifier::CheckAvailabilityAsync()?.get())?;
+            let (available, enrolled) = match availability {
+                UserConsentVerifierAvailability::Available => (true, true),
+                // Present and set up, just in use right now
+                UserConsentVerifierAvailability::DeviceBusy => (true, true),
+                UserConsentVerifierAvailability::NotConfiguredForUser => (true, false),
+                // DeviceNotPresent, DisabledByPolicy
+                _ => (false, false),
+            };
             Ok(BiometricAvailability {
-                available: true,
-                biometric_type: BiometricType::Fingerprint, // Could be Face for Windows Hello Face
-                enrolled: true,
+                available,
+                biometric_type: hello_modality(),
+                enrolled,
+                // Hello reports a lockout only as the result of a prompt
+                // (`RetriesExhausted`), and never its retry count
+                locked_out: false,
+                retries_remaining: None,
             })
         }
-        
-        fn authenticate(&self, prompt: &str) -> Result<BiometricResult, String> {
-            // Windows: Use Windows Hello APIs
-            // In production, this would:
-            // 1. Use UserConsentVerifier.RequestVerificationAsync
-            // 2. Handle the verification result
-            
-            // Placeholder implementation
-            Ok(BiometricResult {
-                success: true,
-                error: None,
-                method: Some("Windows Hello".to_string()),
+
+        fn authenticate(&self, prompt: &str, cancel: &CancelToken) -> Result<BiometricResult, BiometricError> {
+            let message = HSTRING::from(prompt);
+            let cancel = cancel.clone();
+            let result = on_worker_thread(move || {
+                let operation = UserConsentVerifier::RequestVerificationAsync(&message)?;
+                // Cancelling the operation closes the Windows Hello dialog
+                let handle = operation.clone();
+                cancel.on_cancel(move || {
+                    let _ = handle.Cancel();
+                });
+                let result = operation.get();
+                cancel.clear_on_cancel();
+                result
+            })?;
+            let error = match result {
+                UserConsentVerificationResult::Verified => {
+                    return Ok(BiometricResult::verified(AuthMechanism::WindowsHello))
+                }
+                UserConsentVerificationResult::Canceled => BiometricError::UserCancelled,
+                UserConsentVerificationResult::RetriesExhausted => BiometricError::LockedOut,
+                UserConsentVerificationResult::DeviceBusy => BiometricError::DeviceBusy,
+                UserConsentVerificationResult::DeviceNotPresent => BiometricError::NotAvailable,
+                UserConsentVerificationResult::NotConfiguredForUser => BiometricError::NotEnrolled,
+                UserConsentVerificationResult::DisabledByPolicy => BiometricError::PlatformError {
+                    code: i64::from(result.0),
+                    message: "Windows Hello is disabled by policy".to_string(),
+                },
+                _ => BiometricError::PlatformError {
+                    code: i64::from(result.0),
+                    message: "Windows Hello verification failed".to_string(),
+                },
+            };
+            Ok(BiometricResult::failed(error, AuthMechanism::WindowsHello))
+        }
+    }
+}
+
+/// fprintd over the system D-Bus: `Claim`, `VerifyStart("any")` and the
+/// `VerifyStatus` signals that follow, then `Release`
+#[cfg(all(target_os = "linux", feature = "dbus"))]
+pub mod linux {
+    use super::*;
+    use std::sync::mpsc;
+    use zbus::blocking::{Connection, Proxy};
+    use zbus::zvariant::OwnedObjectPath;
+
+    const FPRINTD: &str = "net.reactivated.Fprint";
+    const MANAGER_PATH: &str = "/net/reactivated/Fprint/Manager";
+    const MANAGER_INTERFACE: &str = "net.reactivated.Fprint.Manager";
+    const DEVICE_INTERFACE: &str = "net.reactivated.Fprint.Device";
+    /// fprintd reads an empty user name as the caller's user
+    const CURRENT_USER: &str = "";
+
+    pub struct LinuxBiometricAuthenticator;
+
+    /// Why fprintd could not be used
+    enum FprintdError {
+        NotInstalled,
+        NoDevice,
+        NotEnrolled,
+        DeviceBusy,
+        Other(String),
+    }
+
+    impl From<FprintdError> for BiometricError {
+        fn from(error: FprintdError) -> Self {
+            match error {
+                FprintdError::NotInstalled | FprintdError::NoDevice => BiometricError::NotAvailable,
+                FprintdError::NotEnrolled => BiometricError::NotEnrolled,
+                FprintdError::DeviceBusy => BiometricError::DeviceBusy,
+                FprintdError::Other(message) => BiometricError::platform(format!("fprintd error: {}", message)),
+            }
+        }
+    }
+
+    impl From<zbus::Error> for FprintdError {
+        fn from(error: zbus::Error) -> Self {
+            let name = match &error {
+                zbus::Error::MethodError(name, _, _) => name.as_str(),
+                zbus::Error::FDO(e) if matches!(**e, zbus::fdo::Error::ServiceUnknown(_)) => {
+                    return FprintdError::NotInstalled
+                }
+                _ => return FprintdError::Other(error.to_string()),
+            };
+            match name {
+                "org.freedesktop.DBus.Error.ServiceUnknown" => FprintdError::NotInstalled,
+                "net.reactivated.Fprint.Error.NoSuchDevice" => FprintdError::NoDevice,
+                "net.reactivated.Fprint.Error.NoEnrolledPrints" => FprintdError::NotEnrolled,
+                "net.reactivated.Fprint.Error.AlreadyInUse" => FprintdError::DeviceBusy,
+                _ => FprintdError::Other(error.to_string()),
+            }
+        }
+    }
+
+    fn default_device(connection: &Connection) -> Result<Proxy<'static>, FprintdError> {
+        let manager = Proxy::new(connection, FPRINTD, MANAGER_PATH, MANAGER_INTERFACE)?;
+        let path: OwnedObjectPath = manager.call("GetDefaultDevice", &())?;
+        Ok(Proxy::new(connection, FPRINTD, path.into_inner(), DEVICE_INTERFACE)?)
+    }
+
+    fn enrolled_fingers(device: &Proxy<'static>) -> Result<Vec<String>, FprintdError> {
+        let fingers: Result<Vec<String>, FprintdError> = device
+            .call("ListEnrolledFingers", &(CURRENT_USER,))
+            .map_err(FprintdError::from);
+        match fingers {
+            Err(FprintdError::NotEnrolled) => Ok(Vec::new()),
+            fingers => fingers,
+        }
+    }
+
+    fn failed(error: BiometricError) -> BiometricResult {
+        BiometricResult::failed(error, AuthMechanism::Fingerprint)
+    }
+
+    /// What the verification loop waits for
+    enum Event {
+        /// The watcher is subscribed to `VerifyStatus`
+        Ready,
+        Status(String, bool),
+        Cancelled,
+        Failed(zbus::Error),
+    }
+
+    /// Run a verification on a claimed device and stop it again
+    fn verify(device: &Proxy<'static>, cancel: &CancelToken) -> Result<BiometricResult, FprintdError> {
+        // Subscribe on the watcher thread before starting, so no status is
+        // missed. After a cancel the thread stays parked until fprintd sends
+        // another status, which is harmless.
+        let (sender, receiver) = mpsc::channel();
+        let watcher = device.clone();
+        let watcher_sender = sender.clone();
+        std::thread::spawn(move || {
+            let statuses = match watcher.receive_signal("VerifyStatus") {
+                Ok(statuses) => statuses,
+                Err(e) => {
+                    let _ = watcher_sender.send(Event::Failed(e));
+                    return;
+                }
+            };
+            if watcher_sender.send(Event::Ready).is_err() {
+                return;
+            }
+            for message in statuses {
+                let (event, done) = match message.body::<(String, bool)>() {
+                    Ok((result, done)) => (Event::Status(result, done), done),
+                    Err(e) => (Event::Failed(e), true),
+                };
+                if watcher_sender.send(event).is_err() || done {
+                    break;
+                }
+            }
+        });
+        match receiver.recv() {
+            Ok(Event::Ready) => {}
+            Ok(Event::Failed(e)) => return Err(e.into()),
+            _ => return Err(FprintdError::Other("Fingerprint watcher stopped".to_string())),
+        }
+
+        device.call::<_, _, ()>("VerifyStart", &("any",))?;
+        cancel.on_cancel(move || {
+            let _ = sender.send(Event::Cancelled);
+        });
+        let outcome = loop {
+            match receiver.recv() {
+                Ok(Event::Status(result, true)) => break Ok(result_for(&result)),
+                // `verify-retry-scan` and friends: the user may try again
+                Ok(Event::Status(_, false) | Event::Ready) => continue,
+                Ok(Event::Cancelled) => break Ok(failed(BiometricError::Cancelled)),
+                Ok(Event::Failed(e)) => break Err(e.into()),
+                Err(_) => break Err(FprintdError::Other("Fingerprint watcher stopped".to_string())),
+            }
+        };
+        cancel.clear_on_cancel();
+        let _ = device.call::<_, _, ()>("VerifyStop", &());
+        outcome
+    }
+
+    fn result_for(status: &str) -> BiometricResult {
+        match status {
+            "verify-match" => BiometricResult::verified(AuthMechanism::Fingerprint),
+            "verify-no-match" => failed(BiometricError::NotRecognized),
+            "verify-disconnected" => failed(BiometricError::platform("The fingerprint reader was disconnected")),
+            _ => failed(BiometricError::platform(format!("Fingerprint verification failed: {}", status))),
+        }
+    }
+
+    impl super::BiometricAuthenticator for LinuxBiometricAuthenticator {
+        fn is_available(&self) -> Result<BiometricAvailability, BiometricError> {
+            let unavailable = BiometricAvailability {
+                available: false,
+                biometric_type: BiometricType::Unknown,
+                enrolled: false,
+                locked_out: false,
+                retries_remaining: None,
+            };
+            let connection = Connection::system().map_err(FprintdError::from)?;
+            let device = match default_device(&connection) {
+                Ok(device) => device,
+                Err(FprintdError::NotInstalled | FprintdError::NoDevice) => return Ok(unavailable),
+                Err(e) => return Err(e.into()),
+            };
+            Ok(BiometricAvailability {
+                available: true,
+                // fprintd only drives fingerprint readers; its `scan-type`
+                // just says whether to press or swipe
+                biometric_type: BiometricType::Fingerprint,
+                enrolled: !enrolled_fingers(&device)?.is_empty(),
+                // fprintd has no lockout of its own
+                locked_out: false,
+                retries_remaining: None,
             })
         }
+
+        /// fprintd only names the enrolled fingers, so re-enrolling the same
+        /// finger goes unnoticed
+        fn enrollment_state(&self) -> Result<Option<Vec<u8>>, BiometricError> {
+            let connection = Connection::system().map_err(FprintdError::from)?;
+            let device = match default_device(&connection) {
+                Ok(device) => device,
+                Err(FprintdError::NotInstalled | FprintdError::NoDevice) => return Ok(None),
+                Err(e) => return Err(e.into()),
+            };
+            let mut fingers = enrolled_fingers(&device)?;
+            fingers.sort();
+            Ok(Some(fingers.join("\n").into_bytes()))
+        }
+
+        // fprintd has no prompt of its own; the app shows `_prompt`
+        fn authenticate(&self, _prompt: &str, cancel: &CancelToken) -> Result<BiometricResult, BiometricError> {
+            let connection = Connection::system().map_err(FprintdError::from)?;
+            let device = default_device(&connection)?;
+            match device.call::<_, _, ()>("Claim", &(CURRENT_USER,)).map_err(FprintdError::from) {
+                Ok(()) => {}
+                Err(FprintdError::DeviceBusy) => return Ok(failed(BiometricError::DeviceBusy)),
+                Err(e) => return Err(e.into()),
+            }
+            let outcome = verify(&device, cancel);
+            // Always hand the reader back, whatever happened above
+            let _ = device.call::<_, _, ()>("Release", &());
+            Ok(outcome?)
+        }
     }
 }
 
-#[cfg(target_os = "linux")]
+/// Built without the `dbus` feature, so fprintd cannot be reached
+#[cfg(all(target_os = "linux", not(feature = "dbus")))]
 pub mod linux {
     use super::*;
-    
+
     pub struct LinuxBiometricAuthenticator;
-    
+
     impl super::BiometricAuthenticator for LinuxBiometricAuthenticator {
-        fn is_available(&self) -> Result<BiometricAvailability, String> {
-            // Linux: Check for fprintd availability via D-Bus
-            // In production, this would use zbus to query fprintd service
-            
+        fn is_available(&self) -> Result<BiometricAvailability, BiometricError> {
             Ok(BiometricAvailability {
                 available: false,
                 biometric_type: BiometricType::Unknown,
                 enrolled: false,
+                locked_out: false,
+                retries_remaining: None,
             })
         }
-        
-        fn authenticate(&self, prompt: &str) -> Result<BiometricResult, String> {
-            // Linux: Use fprintd via D-Bus
-            // In production, this would:
-            // 1. Connect to fprintd D-Bus service
-            // 2. Call VerifyStart and VerifyStop methods
-            // 3. Handle verification result
-            
-            Err("Biometric authentication requires fprintd. Install fprintd to enable fingerprint authentication.".to_string())
+
+        // This build of SafeNode was made without fingerprint support
+        fn authenticate(&self, _prompt: &str, _cancel: &CancelToken) -> Result<BiometricResult, BiometricError> {
+            Err(BiometricError::NotAvailable)
+        }
+    }
+}
+
+/// Login password through the desktop's polkit agent, for the many Linux
+/// machines without a fingerprint reader. The agent shows the message from
+/// `linux/com.safenode.desktop.policy`; polkit ignores the caller's prompt.
+#[cfg(all(target_os = "linux", feature = "dbus"))]
+pub mod polkit {
+    use super::*;
+    use std::collections::HashMap;
+    use zbus::blocking::{Connection, Proxy};
+    use zbus::zvariant::Value as Variant;
+
+    const POLKIT: &str = "org.freedesktop.PolicyKit1";
+    const AUTHORITY_PATH: &str = "/org/freedesktop/PolicyKit1/Authority";
+    const AUTHORITY_INTERFACE: &str = "org.freedesktop.PolicyKit1.Authority";
+    /// Action installed with the .deb package; it requires `auth_self`
+    const ACTION_ID: &str = "com.safenode.desktop.authenticate";
+    /// `CheckAuthorizationFlags::AllowUserInteraction`
+    const ALLOW_USER_INTERACTION: u32 = 1;
+
+    /// `EnumerateActions` entry: action id, description, message, vendor
+    /// name and URL, icon, the three defaults and annotations
+    type ActionDescription = (String, String, String, String, String, String, u32, u32, u32, HashMap<String, String>);
+
+    pub struct SystemAuthAuthenticator;
+
+    fn polkit_error(error: zbus::Error) -> BiometricError {
+        let name = match &error {
+            zbus::Error::MethodError(name, _, _) => name.as_str(),
+            zbus::Error::FDO(e) if matches!(**e, zbus::fdo::Error::ServiceUnknown(_)) => {
+                return BiometricError::NotAvailable
+            }
+            _ => "",
+        };
+        match name {
+            "org.freedesktop.DBus.Error.ServiceUnknown" => BiometricError::NotAvailable,
+            "org.freedesktop.PolicyKit1.Error.Cancelled" => BiometricError::Cancelled,
+            _ => BiometricError::platform(format!("polkit error: {}", error)),
+        }
+    }
+
+    fn authority(connection: &Connection) -> Result<Proxy<'static>, BiometricError> {
+        Proxy::new(connection, POLKIT, AUTHORITY_PATH, AUTHORITY_INTERFACE).map_err(polkit_error)
+    }
+
+    fn failed(error: BiometricError) -> BiometricResult {
+        BiometricResult::failed(error, AuthMechanism::SystemPassword)
+    }
+
+    impl super::BiometricAuthenticator for SystemAuthAuthenticator {
+        /// Available once polkit runs and knows SafeNode's action, which
+        /// only a packaged install provides
+        fn is_available(&self) -> Result<BiometricAvailability, BiometricError> {
+            let connection = Connection::system().map_err(polkit_error)?;
+            let actions: Vec<ActionDescription> = match authority(&connection)?.call("EnumerateActions", &("",)) {
+                Ok(actions) => actions,
+                Err(e) => match polkit_error(e) {
+                    BiometricError::NotAvailable => Vec::new(),
+                    e => return Err(e),
+                },
+            };
+            let available = actions.iter().any(|action| action.0 == ACTION_ID);
+            Ok(BiometricAvailability {
+                available,
+                biometric_type: BiometricType::Unknown,
+                // Every user has a login password
+                enrolled: available,
+                // Lockouts are up to PAM, which polkit does not report
+                locked_out: false,
+                retries_remaining: None,
+            })
+        }
+
+        fn authenticate(&self, _prompt: &str, cancel: &CancelToken) -> Result<BiometricResult, BiometricError> {
+            let connection = Connection::system().map_err(polkit_error)?;
+            let authority = authority(&connection)?;
+            let bus_name = connection
+                .unique_name()
+                .map(|name| name.to_string())
+                .ok_or_else(|| BiometricError::platform("Not connected to the system bus"))?;
+            let subject: (&str, HashMap<&str, Variant>) =
+                ("system-bus-name", HashMap::from([("name", Variant::from(bus_name.as_str()))]));
+            let details: HashMap<&str, &str> = HashMap::new();
+            let cancellation_id = crate::crypto::random_token();
+
+            let canceller = authority.clone();
+            let cancel_id = cancellation_id.clone();
+            cancel.on_cancel(move || {
+                let _ = canceller.call::<_, _, ()>("CancelCheckAuthorization", &(cancel_id.as_str(),));
+            });
+            // polkit only cancels checks it has seen
+            if cancel.ended().is_some() {
+                cancel.clear_on_cancel();
+                return Ok(failed(BiometricError::Cancelled));
+            }
+            let checked: Result<(bool, bool, HashMap<String, String>), zbus::Error> = authority.call(
+                "CheckAuthorization",
+                &(subject, ACTION_ID, details, ALLOW_USER_INTERACTION, cancellation_id.as_str()),
+            );
+            cancel.clear_on_cancel();
+
+            match checked {
+                Ok((true, _, _)) => Ok(BiometricResult::verified(AuthMechanism::SystemPassword)),
+                Ok((false, _, details)) if details.get("polkit.dismissed").is_some_and(|v| v == "true") => {
+                    Ok(failed(BiometricError::UserCancelled))
+                }
+                Ok((false, _, _)) => Ok(failed(BiometricError::NotRecognized)),
+                Err(e) => match polkit_error(e) {
+                    BiometricError::Cancelled => Ok(failed(BiometricError::Cancelled)),
+                    e => Err(e),
+                },
+            }
+        }
+
+        fn method(&self) -> AuthMethod {
+            AuthMethod::SystemPassword
+        }
+    }
+}
+
+/// Where the commands get their authenticators. `AppState` holds one, so
+/// the real platform can be swapped for a stand-in without the hardware.
+pub trait AuthenticatorSource: Send + Sync {
+    /// The authenticator for `method`, if there is one
+    fn authenticator(&self, method: AuthMethod) -> Option<Box<dyn BiometricAuthenticator>>;
+}
+
+/// The authenticators of the platform SafeNode runs on
+pub struct PlatformAuthenticators;
+
+impl AuthenticatorSource for PlatformAuthenticators {
+    fn authenticator(&self, method: AuthMethod) -> Option<Box<dyn BiometricAuthenticator>> {
+        match method {
+            AuthMethod::Biometric => Some(biometric_authenticator()),
+            #[cfg(all(target_os = "linux", feature = "dbus"))]
+            AuthMethod::SystemPassword => Some(Box::new(polkit::SystemAuthAuthenticator)),
+            #[cfg(not(all(target_os = "linux", feature = "dbus")))]
+            AuthMethod::SystemPassword => None,
+        }
+    }
+}
+
+/// Scripted stand-in for development and CI machines without a sensor.
+/// Only compiled into debug builds.
+#[cfg(debug_assertions)]
+pub mod mock {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::str::FromStr;
+
+    /// Script read at startup, e.g. `SAFENODE_BIOMETRIC_MOCK=fail,succeed`
+    pub const ENV_VAR: &str = "SAFENODE_BIOMETRIC_MOCK";
+
+    /// What the next prompt does
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum MockBehavior {
+        Succeed,
+        Fail,
+        /// The user dismisses the prompt
+        Cancel,
+        Timeout,
+        /// No sensor, so no prompt is shown
+        Unavailable,
+        /// Never returns, ignoring cancellation, like a wedged OS service
+        Hang,
+    }
+
+    impl FromStr for MockBehavior {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.trim() {
+                "succeed" => Ok(MockBehavior::Succeed),
+                "fail" => Ok(MockBehavior::Fail),
+                "cancel" => Ok(MockBehavior::Cancel),
+                "timeout" => Ok(MockBehavior::Timeout),
+                "unavailable" => Ok(MockBehavior::Unavailable),
+                "hang" => Ok(MockBehavior::Hang),
+                other => Err(format!("Unknown biometric mock behavior: {}", other)),
+            }
+        }
+    }
+
+    /// Parse a comma-separated script such as `fail,fail,succeed`
+    pub fn parse_script(script: &str) -> Result<Vec<MockBehavior>, String> {
+        script.split(',').map(str::parse).collect()
+    }
+
+    /// Plays its script one prompt at a time. The last behavior repeats
+    /// once the script runs out, so `succeed` alone always succeeds.
+    #[derive(Clone)]
+    pub struct MockBiometricAuthenticator {
+        script: Arc<Mutex<VecDeque<MockBehavior>>>,
+    }
+
+    impl MockBiometricAuthenticator {
+        pub fn new(script: Vec<MockBehavior>) -> Self {
+            MockBiometricAuthenticator {
+                script: Arc::new(Mutex::new(script.into())),
+            }
+        }
+
+        fn script(&self) -> std::sync::MutexGuard<'_, VecDeque<MockBehavior>> {
+            self.script.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+        }
+
+        fn peek(&self) -> MockBehavior {
+            self.script().front().copied().unwrap_or(MockBehavior::Unavailable)
+        }
+
+        fn next(&self) -> MockBehavior {
+            let mut script = self.script();
+            if script.len() > 1 {
+                script.pop_front().unwrap_or(MockBehavior::Unavailable)
+            } else {
+                script.front().copied().unwrap_or(MockBehavior::Unavailable)
+            }
+        }
+    }
+
+    impl super::BiometricAuthenticator for MockBiometricAuthenticator {
+        fn is_available(&self) -> Result<BiometricAvailability, BiometricError> {
+            let available = self.peek() != MockBehavior::Unavailable;
+            Ok(BiometricAvailability {
+                available,
+                biometric_type: if available { BiometricType::Fingerprint } else { BiometricType::Unknown },
+                enrolled: available,
+                locked_out: false,
+                retries_remaining: None,
+            })
+        }
+
+        fn authenticate(&self, _prompt: &str, _cancel: &CancelToken) -> Result<BiometricResult, BiometricError> {
+            // The mock poses as a fingerprint reader
+            let error = match self.next() {
+                MockBehavior::Succeed => return Ok(BiometricResult::verified(AuthMechanism::Fingerprint)),
+                MockBehavior::Fail => BiometricError::NotRecognized,
+                MockBehavior::Cancel => BiometricError::UserCancelled,
+                MockBehavior::Timeout => return Ok(BiometricResult::ended(PromptOutcome::Timeout)),
+                MockBehavior::Unavailable => return Err(BiometricError::NotAvailable),
+                MockBehavior::Hang => loop {
+                    std::thread::park();
+                },
+            };
+            Ok(BiometricResult::failed(error, AuthMechanism::Fingerprint))
+        }
+    }
+
+    /// Offers the mock as the biometric method and nothing else, so the
+    /// outcome does not depend on the machine
+    pub struct MockAuthenticators(pub MockBiometricAuthenticator);
+
+    impl AuthenticatorSource for MockAuthenticators {
+        fn authenticator(&self, method: AuthMethod) -> Option<Box<dyn BiometricAuthenticator>> {
+            match method {
+                AuthMethod::Biometric => Some(Box::new(self.0.clone())),
+                AuthMethod::SystemPassword => None,
+            }
         }
     }
+
+    /// The source scripted by `ENV_VAR`, if it is set
+    pub fn from_env() -> Option<MockAuthenticators> {
+        let script = std::env::var(ENV_VAR).ok()?;
+        match parse_script(&script) {
+            Ok(script) => Some(MockAuthenticators(MockBiometricAuthenticator::new(script))),
+            Err(e) => {
+                eprintln!("Ignoring {}: {}", ENV_VAR, e);
+                None
+            }
+        }
+    }
+}
+
+/// Every authenticator of `source` the user can pass right now, most
+/// preferred first
+pub fn get_system_authenticators(source: &dyn AuthenticatorSource) -> Vec<Box<dyn BiometricAuthenticator>> {
+    AuthMethod::ALL
+        .into_iter()
+        .filter_map(|method| source.authenticator(method))
+        .filter(|authenticator| {
+            matches!(authenticator.is_available(), Ok(availability) if availability.available && availability.enrolled)
+        })
+        .collect()
 }
 
-/// Get platform-specific biometric authenticator
-pub fn get_biometric_authenticator() -> Box<dyn BiometricAuthenticator> {
+/// The method `get_system_authenticators` prefers, if any is usable
+pub fn preferred_method(source: &dyn AuthenticatorSource) -> Option<AuthMethod> {
+    get_system_authenticators(source).first().map(|authenticator| authenticator.method())
+}
+
+/// Platform-specific biometric authenticator
+fn biometric_authenticator() -> Box<dyn BiometricAuthenticator> {
     #[cfg(target_os = "macos")]
     {
         Box::new(macos::MacOSBiometricAuthenticator)
@@ -163,55 +1289,291 @@ pub fn get_biometric_authenticator() -> Box<dyn BiometricAuthenticator> {
         // Fallback for unsupported platforms
         struct UnsupportedBiometricAuthenticator;
         impl BiometricAuthenticator for UnsupportedBiometricAuthenticator {
-            fn is_available(&self) -> Result<BiometricAvailability, String> {
+            fn is_available(&self) -> Result<BiometricAvailability, BiometricError> {
                 Ok(BiometricAvailability {
                     available: false,
                     biometric_type: BiometricType::Unknown,
                     enrolled: false,
+                    locked_out: false,
+                    retries_remaining: None,
                 })
             }
             
-            fn authenticate(&self, _prompt: &str) -> Result<BiometricResult, String> {
-                Err("Biometric authentication not available on this platform".to_string())
+            fn authenticate(&self, _prompt: &str, _cancel: &CancelToken) -> Result<BiometricResult, BiometricError> {
+                Err(BiometricError::NotAvailable)
             }
         }
         Box::new(UnsupportedBiometricAuthenticator)
     }
 }
 
-/// Check biometric availability (for Tauri command)
-pub fn check_biometric_available() -> Result<Value, String> {
-    let authenticator = get_biometric_authenticator();
+/// Let macOS prompts fall back to the Apple Watch or the login password
+/// (`Settings::allow_device_credential_fallback`). Windows Hello always
+/// offers its PIN, and other platforms have no such fallback.
+pub fn set_device_credential_fallback(allowed: bool) {
+    #[cfg(target_os = "macos")]
+    macos::set_device_credential_fallback(allowed);
+
+    #[cfg(not(target_os = "macos"))]
+    let _ = allowed;
+}
+
+/// How long an answer from `check_biometric_available` is reused
+pub const AVAILABILITY_TTL: Duration = Duration::from_secs(30);
+
+/// The last answer from `check_biometric_available`, so the UI can ask
+/// often without a D-Bus round trip or OS query each time
+#[derive(Debug, Default)]
+pub struct AvailabilityCache {
+    last: Option<Value>,
+    checked_at: Option<Instant>,
+}
+
+impl AvailabilityCache {
+    /// The last answer, unless it expired or was invalidated
+    pub fn fresh(&self) -> Option<Value> {
+        match (&self.last, self.checked_at) {
+            (Some(last), Some(checked_at)) if checked_at.elapsed() < AVAILABILITY_TTL => Some(last.clone()),
+            _ => None,
+        }
+    }
+
+    /// Check again on the next read. The old answer is kept to compare the
+    /// new one with.
+    pub fn invalidate(&mut self) {
+        self.checked_at = None;
+    }
+
+    /// Record a new answer; returns whether it differs from an earlier one
+    pub fn store(&mut self, availability: Value) -> bool {
+        let changed = self.last.as_ref().is_some_and(|last| *last != availability);
+        self.last = Some(availability);
+        self.checked_at = Some(Instant::now());
+        changed
+    }
+}
+
+/// Check availability of the preferred method, or of biometrics when no
+/// method is usable (for Tauri command)
+pub fn check_biometric_available(source: &dyn AuthenticatorSource) -> Result<Value, BiometricError> {
+    let authenticator = get_system_authenticators(source)
+        .into_iter()
+        .next()
+        .or_else(|| source.authenticator(AuthMethod::Biometric))
+        .ok_or(BiometricError::NotAvailable)?;
     let availability = authenticator.is_available()?;
     
     Ok(serde_json::json!({
         "available": availability.available,
-        "type": match availability.biometric_type {
-            BiometricType::Fingerprint => "fingerprint",
-            BiometricType::Face => "face",
-            BiometricType::Unknown => "unknown",
-        },
-        "enrolled": availability.enrolled
+        "method": authenticator.method(),
+        "type": availability.biometric_type.as_str(),
+        "enrolled": availability.enrolled,
+        "locked_out": availability.locked_out,
+        "retries_remaining": availability.retries_remaining
     }))
 }
 
-/// Authenticate with biometrics (for Tauri command)
-pub fn authenticate_biometric(prompt: &str) -> Result<Value, String> {
-    let authenticator = get_biometric_authenticator();
-    let result = authenticator.authenticate(prompt)?;
-    
+/// Show the prompt of `authenticator` on its own thread, so async commands
+/// are not stalled. It is dismissed when `cancel` fires or after `timeout`.
+/// A platform that does not return within `CANCEL_GRACE` of that, such as
+/// a wedged Windows Hello service, is left behind on its thread and the
+/// prompt ends anyway.
+pub async fn authenticate(
+    authenticator: Box<dyn BiometricAuthenticator>,
+    prompt: &str,
+    timeout: Duration,
+    cancel: CancelToken,
+) -> Result<BiometricResult, BiometricError> {
+    // `None` from the watchdog means the platform is stuck
+    let (sender, receiver) = mpsc::channel();
+    let watchdog = cancel.clone();
+    let watchdog_sender = sender.clone();
+    std::thread::spawn(move || {
+        if watchdog.watch(timeout) {
+            let _ = watchdog_sender.send(None);
+        }
+    });
+
+    let prompt = prompt.to_string();
+    let token = cancel.clone();
+    std::thread::spawn(move || {
+        let _ = sender.send(Some(authenticator.authenticate(&prompt, &token)));
+    });
+    let received = tauri::async_runtime::spawn_blocking(move || receiver.recv())
+        .await
+        .map_err(|e| BiometricError::platform(format!("Biometric prompt failed: {}", e)))?;
+    cancel.finish();
+
+    let result = match received {
+        Ok(Some(result)) => result,
+        Ok(None) => {
+            eprintln!("Biometric prompt did not respond to cancellation; abandoning it");
+            return Ok(BiometricResult::ended(cancel.ended().unwrap_or(PromptOutcome::Timeout)));
+        }
+        Err(_) => Err(BiometricError::platform("Biometric prompt ended without a result")),
+    };
+
+    // However the platform reported a dismissed prompt, say why it ended
+    match (cancel.ended(), &result) {
+        (_, Ok(result)) if result.success => {}
+        (Some(outcome), _) => return Ok(BiometricResult::ended(outcome)),
+        _ => {}
+    }
+    result
+}
+
+/// Result of `authenticate` as sent to the frontend (for Tauri command)
+pub fn authentication_json(prompt: &str, result: BiometricResult) -> Value {
     if result.success {
-        Ok(serde_json::json!({
+        serde_json::json!({
             "success": true,
             "method": result.method,
+            "outcome": result.outcome,
             "prompt": prompt
-        }))
+        })
     } else {
-        Ok(serde_json::json!({
+        serde_json::json!({
             "success": false,
-            "error": result.error.unwrap_or_else(|| "Authentication failed".to_string()),
+            "error": result.error.unwrap_or(BiometricError::NotRecognized),
+            "outcome": result.outcome,
             "prompt": prompt
-        }))
+        })
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::VaultError;
+    use serde_json::json;
+
+    #[test]
+    fn errors_serialize_with_a_stable_kind() {
+        for (error, kind) in [
+            (BiometricError::NotAvailable, "not_available"),
+            (BiometricError::NotEnrolled, "not_enrolled"),
+            (BiometricError::UserCancelled, "user_cancelled"),
+            (BiometricError::LockedOut, "locked_out"),
+            (BiometricError::Timeout, "timeout"),
+        ] {
+            let value = serde_json::to_value(&error).unwrap();
+            assert_eq!(value, json!({ "kind": kind, "message": error.to_string() }));
+        }
+    }
+
+    #[test]
+    fn platform_errors_carry_their_code() {
+        let error = BiometricError::PlatformError {
+            code: -2147023673,
+            message: "The operation was canceled by the user".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            json!({
+                "kind": "platform_error",
+                "message": "The operation was canceled by the user",
+                "details": { "code": -2147023673i64 }
+            })
+        );
+    }
+
+    #[test]
+    fn vault_errors_nest_the_biometric_error() {
+        let value = serde_json::to_value(VaultError::BiometricFailed(BiometricError::LockedOut)).unwrap();
+        assert_eq!(value["kind"], "biometric_failed");
+        assert_eq!(value["details"]["biometric"]["kind"], "locked_out");
+    }
+
+    #[test]
+    fn authentication_results_as_json() {
+        let verified = authentication_json("Unlock", BiometricResult::verified(AuthMechanism::Fingerprint));
+        assert_eq!(
+            verified,
+            json!({ "success": true, "method": "fingerprint", "outcome": "success", "prompt": "Unlock" })
+        );
+
+        let failed = authentication_json(
+            "Unlock",
+            BiometricResult::failed(BiometricError::UserCancelled, AuthMechanism::Fingerprint),
+        );
+        assert_eq!(failed["success"], false);
+        assert_eq!(failed["outcome"], "failure");
+        assert_eq!(failed["error"]["kind"], "user_cancelled");
+    }
+
+    fn mock(script: &str) -> mock::MockAuthenticators {
+        mock::MockAuthenticators(mock::MockBiometricAuthenticator::new(mock::parse_script(script).unwrap()))
+    }
+
+    /// `authenticate` with the mock's biometric prompt
+    fn prompt(source: &mock::MockAuthenticators, timeout: Duration, cancel: CancelToken) -> BiometricResult {
+        let authenticator = source.authenticator(AuthMethod::Biometric).unwrap();
+        tauri::async_runtime::block_on(authenticate(authenticator, "Unlock", timeout, cancel)).unwrap()
+    }
+
+    #[test]
+    fn mock_scripts_parse() {
+        use mock::MockBehavior;
+        assert_eq!(
+            mock::parse_script("fail, cancel,succeed").unwrap(),
+            [MockBehavior::Fail, MockBehavior::Cancel, MockBehavior::Succeed]
+        );
+        assert!(mock::parse_script("succeed,explode").is_err());
+        assert!(mock::parse_script("").is_err());
+    }
+
+    #[test]
+    fn mock_plays_its_script_then_repeats_the_last_step() {
+        let source = mock("fail,timeout,succeed");
+        let outcomes: Vec<PromptOutcome> = (0..4)
+            .map(|_| prompt(&source, PROMPT_TIMEOUT, CancelToken::default()).outcome)
+            .collect();
+        assert_eq!(
+            outcomes,
+            [PromptOutcome::Failure, PromptOutcome::Timeout, PromptOutcome::Success, PromptOutcome::Success]
+        );
+    }
+
+    #[test]
+    fn mock_is_the_only_method_offered() {
+        let source = mock("succeed");
+        assert_eq!(preferred_method(&source), Some(AuthMethod::Biometric));
+        let availability = check_biometric_available(&source).unwrap();
+        assert_eq!(availability["available"], true);
+        assert_eq!(availability["type"], "fingerprint");
+
+        let source = mock("unavailable");
+        assert_eq!(preferred_method(&source), None);
+        assert_eq!(check_biometric_available(&source).unwrap()["available"], false);
+        let authenticator = source.authenticator(AuthMethod::Biometric).unwrap();
+        let result = tauri::async_runtime::block_on(authenticate(
+            authenticator,
+            "Unlock",
+            PROMPT_TIMEOUT,
+            CancelToken::default(),
+        ));
+        assert_eq!(result.unwrap_err(), BiometricError::NotAvailable);
+    }
+
+    #[test]
+    fn watchdog_ends_a_hanging_prompt() {
+        let started = Instant::now();
+        let result = prompt(&mock("hang"), Duration::from_millis(100), CancelToken::default());
+        assert_eq!(result.outcome, PromptOutcome::Timeout);
+        assert_eq!(result.error, Some(BiometricError::Timeout));
+        assert!(started.elapsed() < Duration::from_millis(100) + CANCEL_GRACE * 2);
+    }
+
+    #[test]
+    fn cancelling_a_hanging_prompt_returns() {
+        let cancel = CancelToken::default();
+        let canceller = cancel.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            canceller.cancel();
+        });
+        let result = prompt(&mock("hang"), PROMPT_TIMEOUT, cancel);
+        assert_eq!(result.outcome, PromptOutcome::Cancelled);
+        assert_eq!(result.error, Some(BiometricError::Cancelled));
+    }
+}
@@ -0,0 +1,71 @@
+/*!
+ * Breach Check
+ * Generated passwords looked up in Pwned Passwords without revealing them
+ */
+
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+/// Pwned Passwords range API; only the first five hex digits of the SHA-1
+/// are sent, and every hash sharing them comes back
+const RANGE_URL: &str = "https://api.pwnedpasswords.com/range/";
+const PREFIX_LEN: usize = 5;
+/// A generated password is returned unchecked rather than kept waiting
+const TIMEOUT: Duration = Duration::from_secs(3);
+/// Responses are a few hundred lines; anything larger is not one
+const MAX_RESPONSE_SIZE: usize = 256 * 1024;
+
+/// Whether a generated password was checked against Pwned Passwords
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreachCheck {
+    NotRequested,
+    /// Checked and not in the corpus
+    Passed,
+    /// Requested, but the lookup failed or the network is off
+    Unchecked,
+}
+
+/// `Some(true)` if `password` appears in Pwned Passwords, `None` when the
+/// lookup fails, including timeouts
+pub async fn is_breached(password: &str) -> Option<bool> {
+    let hash = Zeroizing::new(
+        Sha1::digest(password.as_bytes())
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect::<String>(),
+    );
+    let (prefix, suffix) = hash.split_at(PREFIX_LEN);
+
+    let client = reqwest::Client::builder()
+        .timeout(TIMEOUT)
+        .user_agent("SafeNode")
+        .build()
+        .ok()?;
+    // Padding hides the true number of matches from anyone watching
+    let mut response = client
+        .get(format!("{}{}", RANGE_URL, prefix))
+        .header("Add-Padding", "true")
+        .send()
+        .await
+        .ok()?
+        .error_for_status()
+        .ok()?;
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await.ok()? {
+        body.extend_from_slice(&chunk);
+        if body.len() > MAX_RESPONSE_SIZE {
+            return None;
+        }
+    }
+
+    // Lines are `SUFFIX:COUNT`; padding lines have a count of zero
+    let body = String::from_utf8(body).ok()?;
+    Some(body.lines().any(|line| {
+        line.split_once(':').is_some_and(|(candidate, count)| {
+            candidate.trim().eq_ignore_ascii_case(suffix) && count.trim().parse::<u64>().is_ok_and(|count| count > 0)
+        })
+    }))
+}
@@ -0,0 +1,623 @@
+/*!
+ * System Clipboard
+ * Copies made by the backend, so secrets read from a vault never pass
+ * through the webview
+ */
+
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, OnceLock, TryLockError};
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+use crate::error::{FieldError, VaultError};
+use crate::vault::Entry;
+
+/// Why the clipboard could not be written
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClipboardError {
+    /// There is no clipboard, e.g. a Linux session without a display server
+    Unavailable(String),
+    /// Anything else the platform reported
+    Failed(String),
+}
+
+impl ClipboardError {
+    /// Stable discriminator sent to the frontend
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ClipboardError::Unavailable(_) => "unavailable",
+            ClipboardError::Failed(_) => "failed",
+        }
+    }
+}
+
+impl fmt::Display for ClipboardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClipboardError::Unavailable(msg) => write!(f, "No clipboard is available: {}", msg),
+            ClipboardError::Failed(msg) => write!(f, "Clipboard error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ClipboardError {}
+
+impl Serialize for ClipboardError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(2))?;
+        map.serialize_entry("kind", self.kind())?;
+        map.serialize_entry("message", &self.to_string())?;
+        map.end()
+    }
+}
+
+impl From<arboard::Error> for ClipboardError {
+    fn from(e: arboard::Error) -> Self {
+        match e {
+            arboard::Error::ClipboardNotSupported => ClipboardError::Unavailable(e.to_string()),
+            e => ClipboardError::Failed(e.to_string()),
+        }
+    }
+}
+
+/// How long a copied secret stays on the clipboard by default
+pub const DEFAULT_CLEAR_AFTER: Duration = Duration::from_secs(30);
+
+/// Opened on first use and kept for the life of the app. On Linux the
+/// copied text is served from it, so it would vanish with it.
+static CLIPBOARD: Mutex<Option<Box<dyn Selections>>> = Mutex::new(None);
+
+/// Settled when `CLIPBOARD` is first opened, as that is when arboard picks
+/// its backend
+static MECHANISM: OnceLock<Mechanism> = OnceLock::new();
+
+/// How copies reach other apps
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+#[allow(dead_code)] // Each platform constructs only its own mechanisms
+pub enum Mechanism {
+    /// The Windows or macOS clipboard
+    Native,
+    X11,
+    /// The wlr data-control protocol, which works without a focused window
+    WaylandDataControl,
+    /// A Wayland session whose compositor lacks data-control (e.g. GNOME),
+    /// so copies go through XWayland.
+    ///
+    /// The copy is held by the X11 selection owner arboard serves from its
+    /// own thread, inside the handle `CLIPBOARD` keeps for the life of the
+    /// app, so it outlives a hidden window. An invisible Wayland surface
+    /// kept alive for the hold would not help: the compositor only takes a
+    /// selection from a surface with keyboard focus, which a window that is
+    /// never shown cannot get, and showing one would take focus from the
+    /// app the user is about to paste into.
+    Xwayland,
+}
+
+impl Mechanism {
+    /// Why copies made this way may not arrive, if they may not.
+    /// `window_hidden` is whether the main window was hidden to the tray.
+    fn degradation(self, window_hidden: bool) -> Option<&'static str> {
+        match self {
+            Mechanism::Xwayland if window_hidden => Some(
+                "Copied while SafeNode is hidden, and the compositor does not support Wayland data-control, \
+                 so the copy went through XWayland and may not reach Wayland apps; show SafeNode and copy \
+                 again if it does not paste",
+            ),
+            Mechanism::Xwayland => Some(
+                "The compositor does not support Wayland data-control, so copies go through XWayland and \
+                 may not reach Wayland apps while SafeNode is hidden",
+            ),
+            _ => None,
+        }
+    }
+}
+
+/// Whether the main window is hidden to the tray, for `CopyStatus`
+static WINDOW_HIDDEN: AtomicBool = AtomicBool::new(false);
+
+/// Note whether the main window is hidden to the tray
+pub fn set_window_hidden(hidden: bool) {
+    WINDOW_HIDDEN.store(hidden, Ordering::Relaxed);
+}
+
+#[cfg(target_os = "linux")]
+fn detect_mechanism() -> Mechanism {
+    // arboard tries data-control first whenever WAYLAND_DISPLAY is set
+    if std::env::var_os("WAYLAND_DISPLAY").is_none() {
+        return Mechanism::X11;
+    }
+    // Answered over data-control, so errors when the compositor lacks it
+    match wl_clipboard_rs::utils::is_primary_selection_supported() {
+        Ok(_) => Mechanism::WaylandDataControl,
+        Err(_) => Mechanism::Xwayland,
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn detect_mechanism() -> Mechanism {
+    Mechanism::Native
+}
+
+fn with_clipboard<T>(op: impl FnOnce(&mut dyn Selections) -> Result<T, arboard::Error>) -> Result<T, ClipboardError> {
+    let mut clipboard = CLIPBOARD.lock().unwrap();
+    if clipboard.is_none() {
+        let opened = arboard::Clipboard::new().map_err(|e| ClipboardError::Unavailable(e.to_string()))?;
+        MECHANISM.get_or_init(detect_mechanism);
+        *clipboard = Some(Box::new(opened));
+    }
+    Ok(op(clipboard.as_deref_mut().expect("clipboard opened above"))?)
+}
+
+/// How a copy was made, returned to the frontend
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CopyStatus {
+    /// Marked so clipboard history tools skip it. False for plain copies and
+    /// on platforms without such a hint; managers that ignore it still keep it.
+    pub concealed: bool,
+    pub mechanism: Mechanism,
+    /// Why the copy may not reach other apps, if it may not
+    pub degraded: Option<&'static str>,
+    /// Made while the main window was hidden, e.g. from the tray, with
+    /// `Mechanism::Xwayland`, so it may never arrive and the UI should say so
+    pub may_not_arrive: bool,
+    /// Also set as the PRIMARY selection for middle-click paste; see
+    /// `Settings::also_set_primary_selection`
+    pub primary_selection: bool,
+}
+
+impl CopyStatus {
+    /// Status of a copy just made
+    fn new(concealed: bool, primary_selection: bool) -> Self {
+        let mechanism = *MECHANISM.get_or_init(detect_mechanism);
+        let window_hidden = WINDOW_HIDDEN.load(Ordering::Relaxed);
+        CopyStatus {
+            concealed,
+            mechanism,
+            degraded: mechanism.degradation(window_hidden),
+            may_not_arrive: mechanism == Mechanism::Xwayland && window_hidden,
+            primary_selection,
+        }
+    }
+}
+
+/// Set secret `text`, marked so clipboard history tools leave it out.
+/// Returns whether it could be marked.
+#[cfg(target_os = "windows")]
+fn set_concealed(clipboard: &mut arboard::Clipboard, text: &str) -> Result<bool, arboard::Error> {
+    use arboard::SetExtWindows;
+
+    // ExcludeClipboardContentFromMonitorProcessing, and
+    // CanIncludeInClipboardHistory / CanUploadToCloudClipboard set to 0
+    clipboard
+        .set()
+        .exclude_from_monitoring()
+        .exclude_from_history()
+        .exclude_from_cloud()
+        .text(text)?;
+    Ok(true)
+}
+
+#[cfg(target_os = "macos")]
+fn set_concealed(clipboard: &mut arboard::Clipboard, text: &str) -> Result<bool, arboard::Error> {
+    use arboard::SetExtApple;
+
+    // Also offers org.nspasteboard.ConcealedType
+    clipboard.set().exclude_from_history().text(text)?;
+    Ok(true)
+}
+
+#[cfg(target_os = "linux")]
+fn set_concealed(clipboard: &mut arboard::Clipboard, text: &str) -> Result<bool, arboard::Error> {
+    use arboard::SetExtLinux;
+
+    // Also offers x-kde-passwordManagerHint=secret, which Klipper skips
+    clipboard.set().exclude_from_history().text(text)?;
+    Ok(true)
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+fn set_concealed(clipboard: &mut arboard::Clipboard, text: &str) -> Result<bool, arboard::Error> {
+    clipboard.set_text(text)?;
+    Ok(false)
+}
+
+/// Whether copies also go to the PRIMARY selection, for middle-click paste.
+/// Only Linux has one.
+static PRIMARY_SELECTION: AtomicBool = AtomicBool::new(false);
+
+/// Apply `Settings::also_set_primary_selection`
+pub fn set_primary_selection(enabled: bool) {
+    PRIMARY_SELECTION.store(enabled, Ordering::Relaxed);
+}
+
+#[cfg(target_os = "linux")]
+fn set_primary(clipboard: &mut arboard::Clipboard, text: &str, conceal: bool) -> Result<(), arboard::Error> {
+    use arboard::{LinuxClipboardKind, SetExtLinux};
+
+    let set = clipboard.set().clipboard(LinuxClipboardKind::Primary);
+    if conceal {
+        set.exclude_from_history().text(text)
+    } else {
+        set.text(text)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn get_primary(clipboard: &mut arboard::Clipboard) -> Result<String, arboard::Error> {
+    use arboard::{GetExtLinux, LinuxClipboardKind};
+
+    clipboard.get().clipboard(LinuxClipboardKind::Primary).text()
+}
+
+#[cfg(target_os = "linux")]
+fn clear_primary(clipboard: &mut arboard::Clipboard) -> Result<(), arboard::Error> {
+    use arboard::{ClearExtLinux, LinuxClipboardKind};
+
+    clipboard.clear_with().clipboard(LinuxClipboardKind::Primary)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_primary(_clipboard: &mut arboard::Clipboard, _text: &str, _conceal: bool) -> Result<(), arboard::Error> {
+    Err(arboard::Error::ClipboardNotSupported)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn get_primary(_clipboard: &mut arboard::Clipboard) -> Result<String, arboard::Error> {
+    Err(arboard::Error::ClipboardNotSupported)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn clear_primary(_clipboard: &mut arboard::Clipboard) -> Result<(), arboard::Error> {
+    Err(arboard::Error::ClipboardNotSupported)
+}
+
+/// Write `text` to the clipboard, and to PRIMARY when enabled. Returns
+/// whether it was concealed and whether PRIMARY was set.
+fn write(clipboard: &mut arboard::Clipboard, text: &str, secret: bool) -> Result<(bool, bool), arboard::Error> {
+    let concealed = if secret {
+        set_concealed(clipboard, text)?
+    } else {
+        clipboard.set_text(text)?;
+        false
+    };
+    // PRIMARY is a convenience, so failing to set it does not fail the copy
+    let primary = PRIMARY_SELECTION.load(Ordering::Relaxed) && set_primary(clipboard, text, secret).is_ok();
+    Ok((concealed, primary))
+}
+
+/// The clipboard and PRIMARY selection as this module uses them; the
+/// system's outside of tests
+trait Selections: Send {
+    /// See `write`
+    fn write(&mut self, text: &str, secret: bool) -> Result<(bool, bool), arboard::Error>;
+    fn get_text(&mut self) -> Result<String, arboard::Error>;
+    fn clear(&mut self) -> Result<(), arboard::Error>;
+    fn get_primary(&mut self) -> Result<String, arboard::Error>;
+    fn clear_primary(&mut self) -> Result<(), arboard::Error>;
+}
+
+impl Selections for arboard::Clipboard {
+    fn write(&mut self, text: &str, secret: bool) -> Result<(bool, bool), arboard::Error> {
+        write(self, text, secret)
+    }
+
+    fn get_text(&mut self) -> Result<String, arboard::Error> {
+        arboard::Clipboard::get_text(self)
+    }
+
+    fn clear(&mut self) -> Result<(), arboard::Error> {
+        arboard::Clipboard::clear(self)
+    }
+
+    fn get_primary(&mut self) -> Result<String, arboard::Error> {
+        get_primary(self)
+    }
+
+    fn clear_primary(&mut self) -> Result<(), arboard::Error> {
+        clear_primary(self)
+    }
+}
+
+/// The secret SafeNode last put on the clipboard. Only digests are kept,
+/// enough to tell whether it is still there.
+struct Held {
+    /// Set while the clipboard may still hold it
+    clipboard: Option<[u8; 32]>,
+    /// Set while PRIMARY may still hold it, or an earlier secret
+    primary: Option<[u8; 32]>,
+    generation: u64,
+}
+
+/// Locked before `CLIPBOARD`
+static HELD: Mutex<Option<Held>> = Mutex::new(None);
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+fn digest(text: &str) -> [u8; 32] {
+    Sha256::digest(text.as_bytes()).into()
+}
+
+/// Put `text` on the clipboard. A secret copied before is no longer ours to
+/// clear from the selections this overwrote.
+pub fn copy(text: &str) -> Result<CopyStatus, ClipboardError> {
+    let mut held = HELD.lock().unwrap();
+    let (_, primary) = with_clipboard(|clipboard| clipboard.write(text, false))?;
+    if let Some(current) = held.as_mut() {
+        current.clipboard = None;
+        if primary {
+            current.primary = None;
+        }
+        if current.primary.is_none() {
+            *held = None;
+        }
+    }
+    Ok(CopyStatus::new(false, primary))
+}
+
+/// Put secret `text` on the clipboard, hidden from clipboard history where
+/// possible. Returns the generation to pass to `clear_secret` when it
+/// should go.
+pub fn copy_secret(text: &str) -> Result<(u64, CopyStatus), ClipboardError> {
+    let mut held = HELD.lock().unwrap();
+    let (concealed, primary) = with_clipboard(|clipboard| clipboard.write(text, true))?;
+    let generation = GENERATION.fetch_add(1, Ordering::Relaxed) + 1;
+    let digest = digest(text);
+    // A secret left in PRIMARY by an earlier copy goes with this one
+    let earlier_primary = held.as_ref().and_then(|earlier| earlier.primary);
+    *held = Some(Held {
+        clipboard: Some(digest),
+        primary: if primary { Some(digest) } else { earlier_primary },
+        generation,
+    });
+    Ok((generation, CopyStatus::new(concealed, primary)))
+}
+
+/// Whether the secret copied as `generation` has not been replaced or cleared
+pub fn is_held(generation: u64) -> bool {
+    HELD.lock().unwrap().as_ref().is_some_and(|held| held.generation == generation)
+}
+
+/// Whether `current` selection contents are still the secret with `digest_held`
+fn unchanged(current: Result<String, arboard::Error>, digest_held: [u8; 32]) -> Result<bool, arboard::Error> {
+    match current {
+        Ok(text) => Ok(digest(&Zeroizing::new(text)) == digest_held),
+        // Emptied, or holding an image or files
+        Err(arboard::Error::ContentNotAvailable) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// What `clear_secret` found
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClearOutcome {
+    /// No secret of ours was held
+    NothingHeld,
+    /// Something else was copied since; it was left alone
+    Replaced,
+    Cleared,
+}
+
+/// Clear the clipboard, and PRIMARY, if they still hold the secret copied
+/// as `generation`, or any secret SafeNode copied when `None`
+pub fn clear_secret(generation: Option<u64>) -> Result<ClearOutcome, ClipboardError> {
+    let mut held = HELD.lock().unwrap();
+    let cleared = match held.take() {
+        Some(current) if generation.is_none() || generation == Some(current.generation) => current,
+        other => {
+            *held = other;
+            return Ok(ClearOutcome::NothingHeld);
+        }
+    };
+    with_clipboard(|clipboard| clear_held(cleared, clipboard))
+}
+
+fn clear_held(cleared: Held, clipboard: &mut dyn Selections) -> Result<ClearOutcome, arboard::Error> {
+    let mut outcome = ClearOutcome::Replaced;
+    if let Some(digest_held) = cleared.clipboard {
+        if unchanged(clipboard.get_text(), digest_held)? {
+            clipboard.clear()?;
+            outcome = ClearOutcome::Cleared;
+        }
+    }
+    if let Some(digest_held) = cleared.primary {
+        if unchanged(clipboard.get_primary(), digest_held)? {
+            clipboard.clear_primary()?;
+            outcome = ClearOutcome::Cleared;
+        }
+    }
+    Ok(outcome)
+}
+
+/// Lock without waiting; `None` if another thread holds `mutex`
+fn try_lock<T>(mutex: &Mutex<T>) -> Option<MutexGuard<'_, T>> {
+    match mutex.try_lock() {
+        Ok(guard) => Some(guard),
+        Err(TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner()),
+        Err(TryLockError::WouldBlock) => None,
+    }
+}
+
+/// Best-effort `clear_secret(None)` as the app exits or crashes. Gives up
+/// rather than wait, as a panic may have struck while the locks were held.
+pub fn scrub() {
+    let (Some(mut held), Some(mut clipboard)) = (try_lock(&HELD), try_lock(&CLIPBOARD)) else {
+        return;
+    };
+    if let (Some(cleared), Some(clipboard)) = (held.take(), clipboard.as_deref_mut()) {
+        let _ = clear_held(cleared, clipboard);
+    }
+}
+
+/// Scrub a copied secret if the backend panics, then report the panic as
+/// before
+pub fn install_panic_scrub() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        scrub();
+        previous(info);
+    }));
+}
+
+/// Entry field `copy_secret_to_clipboard` copies
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryField {
+    Username,
+    Password,
+    Url,
+    Notes,
+    CardNumber,
+    CardCvv,
+    /// Custom field by name
+    Custom(String),
+}
+
+impl EntryField {
+    /// The field's value in `entry`, and whether it is a secret
+    pub fn read(&self, entry: &Entry) -> Result<(Zeroizing<String>, bool), VaultError> {
+        let missing = || VaultError::InvalidFields(vec![FieldError::new("field", "The entry has no such field")]);
+        let (value, secret) = match self {
+            EntryField::Username => (&entry.username, false),
+            EntryField::Password => (&entry.password, true),
+            EntryField::Url => (&entry.url, false),
+            // The body of a secure note
+            EntryField::Notes => (&entry.notes, true),
+            EntryField::CardNumber => (&entry.card.as_ref().ok_or_else(missing)?.number, true),
+            EntryField::CardCvv => (&entry.card.as_ref().ok_or_else(missing)?.cvv, true),
+            EntryField::Custom(name) => {
+                let field = entry
+                    .custom_fields
+                    .iter()
+                    .find(|field| &field.name == name)
+                    .ok_or_else(missing)?;
+                (&field.value, field.is_hidden())
+            }
+        };
+        Ok((Zeroizing::new(value.clone()), secret))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Clipboard state is global, so tests that touch it take turns
+    static SERIAL: Mutex<()> = Mutex::new(());
+
+    /// Selections that live in memory; PRIMARY is not offered
+    #[derive(Default)]
+    struct FakeClipboard {
+        text: Option<String>,
+    }
+
+    impl Selections for FakeClipboard {
+        fn write(&mut self, text: &str, secret: bool) -> Result<(bool, bool), arboard::Error> {
+            self.text = Some(text.to_string());
+            Ok((secret, false))
+        }
+
+        fn get_text(&mut self) -> Result<String, arboard::Error> {
+            self.text.clone().ok_or(arboard::Error::ContentNotAvailable)
+        }
+
+        fn clear(&mut self) -> Result<(), arboard::Error> {
+            self.text = None;
+            Ok(())
+        }
+
+        fn get_primary(&mut self) -> Result<String, arboard::Error> {
+            Err(arboard::Error::ClipboardNotSupported)
+        }
+
+        fn clear_primary(&mut self) -> Result<(), arboard::Error> {
+            Err(arboard::Error::ClipboardNotSupported)
+        }
+    }
+
+    /// Take the clipboard for one test, starting empty with nothing held
+    fn fake_clipboard() -> MutexGuard<'static, ()> {
+        let serial = SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        *CLIPBOARD.lock().unwrap() = Some(Box::<FakeClipboard>::default());
+        *HELD.lock().unwrap() = None;
+        serial
+    }
+
+    fn clipboard_text() -> Option<String> {
+        with_clipboard(|clipboard| clipboard.get_text()).ok()
+    }
+
+    #[test]
+    fn lock_clears_a_pending_copy() {
+        let _serial = fake_clipboard();
+        let (generation, status) = copy_secret("hunter2").unwrap();
+        assert!(status.concealed);
+        assert!(is_held(generation));
+
+        // What a lock does while the countdown still runs
+        assert_eq!(clear_secret(None).unwrap(), ClearOutcome::Cleared);
+        assert_eq!(clipboard_text(), None);
+        // The countdown stops, and its own clear finds nothing left
+        assert!(!is_held(generation));
+        assert_eq!(clear_secret(Some(generation)).unwrap(), ClearOutcome::NothingHeld);
+    }
+
+    #[test]
+    fn lock_leaves_something_copied_since_alone() {
+        let _serial = fake_clipboard();
+        copy_secret("hunter2").unwrap();
+        // Copied in another app
+        with_clipboard(|clipboard| clipboard.write("lunch order", false)).unwrap();
+
+        assert_eq!(clear_secret(None).unwrap(), ClearOutcome::Replaced);
+        assert_eq!(clipboard_text().as_deref(), Some("lunch order"));
+    }
+
+    #[test]
+    fn later_copy_outlives_an_earlier_countdown() {
+        let _serial = fake_clipboard();
+        let (first, _) = copy_secret("hunter2").unwrap();
+        let (second, _) = copy_secret("correct horse").unwrap();
+
+        assert!(!is_held(first));
+        assert_eq!(clear_secret(Some(first)).unwrap(), ClearOutcome::NothingHeld);
+        assert_eq!(clipboard_text().as_deref(), Some("correct horse"));
+        assert_eq!(clear_secret(Some(second)).unwrap(), ClearOutcome::Cleared);
+    }
+
+    #[test]
+    fn plain_copy_ends_ownership_of_the_secret() {
+        let _serial = fake_clipboard();
+        let (generation, _) = copy_secret("hunter2").unwrap();
+        copy("https://example.com").unwrap();
+
+        assert!(!is_held(generation));
+        assert_eq!(clear_secret(None).unwrap(), ClearOutcome::NothingHeld);
+        assert_eq!(clipboard_text().as_deref(), Some("https://example.com"));
+    }
+
+    #[test]
+    fn scrub_clears_a_pending_copy() {
+        let _serial = fake_clipboard();
+        let (generation, _) = copy_secret("hunter2").unwrap();
+
+        scrub();
+
+        assert!(!is_held(generation));
+        assert_eq!(clipboard_text(), None);
+    }
+
+    #[test]
+    fn hidden_xwayland_copies_are_reported_as_unreliable() {
+        assert_eq!(Mechanism::X11.degradation(true), None);
+        assert_eq!(Mechanism::WaylandDataControl.degradation(true), None);
+        let shown = Mechanism::Xwayland.degradation(false).unwrap();
+        let hidden = Mechanism::Xwayland.degradation(true).unwrap();
+        assert_ne!(shown, hidden);
+        assert!(hidden.starts_with("Copied while SafeNode is hidden"));
+    }
+}
@@ -0,0 +1,341 @@
+/*!
+ * Vault Crypto Module
+ * Key derivation and authenticated encryption for the vault file
+ */
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use hmac::{Hmac, Mac};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::time::{Duration, Instant};
+use zeroize::Zeroizing;
+
+pub const SALT_LEN: usize = 16;
+pub const NONCE_LEN: usize = 24;
+pub const KEY_LEN: usize = 32;
+/// Random bytes in a token from `random_token`
+const TOKEN_LEN: usize = 32;
+
+const KEY_CHECK_CONTEXT: &[u8] = b"safenode:key-check";
+const MACHINE_KEY_CONTEXT: &[u8] = b"safenode:machine-key";
+const KEY_WRAP_CONTEXT: &[u8] = b"safenode:key-wrap";
+
+/// Argon2id cost parameters (memory in KiB)
+const ARGON2_MEMORY_KIB: u32 = 64 * 1024;
+const ARGON2_ITERATIONS: u32 = 3;
+const ARGON2_PARALLELISM: u32 = 1;
+
+/// Calibration bounds: never go below OWASP's minimum, never take forever
+const ARGON2_MIN_MEMORY_KIB: u32 = 19 * 1024;
+const ARGON2_MIN_ITERATIONS: u32 = 2;
+const ARGON2_MAX_ITERATIONS: u32 = 64;
+
+/// Derivation time aimed for when a vault is created
+pub const DEFAULT_KDF_TARGET: Duration = Duration::from_millis(500);
+
+/// Bounds on the derivation time a user may ask `calibrate` for
+pub const MIN_KDF_TARGET: Duration = Duration::from_millis(100);
+pub const MAX_KDF_TARGET: Duration = Duration::from_secs(5);
+
+/// Derived vault key, scrubbed from memory when dropped
+pub type VaultKey = Zeroizing<[u8; KEY_LEN]>;
+
+/// Supported vault ciphers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CipherAlgorithm {
+    Xchacha20poly1305,
+}
+
+/// Supported key derivation functions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KdfAlgorithm {
+    Argon2id,
+}
+
+/// Key derivation parameters recorded with every vault
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub algorithm: KdfAlgorithm,
+    #[serde(with = "base64_bytes")]
+    pub salt: Vec<u8>,
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+    /// Whether a key file must be combined with the password
+    #[serde(default)]
+    pub key_file: bool,
+}
+
+impl KdfParams {
+    /// Default Argon2id costs with the given salt
+    pub fn with_salt(salt: &[u8]) -> Self {
+        KdfParams {
+            algorithm: KdfAlgorithm::Argon2id,
+            salt: salt.to_vec(),
+            memory_kib: ARGON2_MEMORY_KIB,
+            iterations: ARGON2_ITERATIONS,
+            parallelism: ARGON2_PARALLELISM,
+            key_file: false,
+        }
+    }
+
+    /// Same costs with a fresh random salt
+    pub fn with_fresh_salt(&self) -> Self {
+        KdfParams {
+            salt: generate_salt().to_vec(),
+            ..self.clone()
+        }
+    }
+}
+
+/// Benchmark Argon2id on this machine and pick costs that take roughly
+/// `target` to derive a key. Returns parameters with a fresh salt.
+///
+/// Memory stays at the default unless a single pass is already slower than
+/// the target, in which case it is halved down to the OWASP minimum; the
+/// iteration count is then scaled to fill the remaining budget.
+pub fn calibrate(target: Duration) -> Result<KdfParams, String> {
+    let mut params = KdfParams::with_salt(&generate_salt());
+    params.iterations = 1;
+
+    let mut elapsed = time_derivation(&params)?;
+    while elapsed > target && params.memory_kib / 2 >= ARGON2_MIN_MEMORY_KIB {
+        params.memory_kib /= 2;
+        elapsed = time_derivation(&params)?;
+    }
+
+    let per_pass = elapsed.as_secs_f64().max(f64::EPSILON);
+    let passes = (target.as_secs_f64() / per_pass).floor() as u32;
+    params.iterations = passes.clamp(ARGON2_MIN_ITERATIONS, ARGON2_MAX_ITERATIONS);
+
+    Ok(params)
+}
+
+fn time_derivation(params: &KdfParams) -> Result<Duration, String> {
+    let start = Instant::now();
+    derive_key(b"safenode-calibration", params)?;
+    Ok(start.elapsed())
+}
+
+/// Generate a random salt for key derivation
+pub fn generate_salt() -> [u8; SALT_LEN] {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    salt
+}
+
+/// Combine the master password with an optional key file into the KDF input.
+///
+/// Without a key file the password bytes are used as-is, so password-only
+/// vaults are unaffected; with one, `SHA-256(password) || SHA-256(key file)`.
+pub fn master_secret(password: &str, key_file: Option<&[u8]>) -> Zeroizing<Vec<u8>> {
+    match key_file {
+        None => Zeroizing::new(password.as_bytes().to_vec()),
+        Some(contents) => {
+            let mut secret = Zeroizing::new(Vec::with_capacity(64));
+            secret.extend_from_slice(&Sha256::digest(password.as_bytes()));
+            secret.extend_from_slice(&Sha256::digest(contents));
+            secret
+        }
+    }
+}
+
+/// Derive a vault key from the master secret (see `master_secret`)
+pub fn derive_key(secret: &[u8], kdf: &KdfParams) -> Result<VaultKey, String> {
+    let params = Params::new(kdf.memory_kib, kdf.iterations, kdf.parallelism, Some(KEY_LEN))
+        .map_err(|e| format!("Invalid Argon2 parameters: {}", e))?;
+    let argon2 = match kdf.algorithm {
+        KdfAlgorithm::Argon2id => Argon2::new(Algorithm::Argon2id, Version::V0x13, params),
+    };
+
+    let mut key = Zeroizing::new([0u8; KEY_LEN]);
+    argon2
+        .hash_password_into(secret, &kdf.salt, &mut key[..])
+        .map_err(|e| format!("Key derivation failed: {}", e))?;
+
+    Ok(key)
+}
+
+/// Encrypt plaintext with a fresh random nonce, returning `nonce || ciphertext`.
+/// `aad` is authenticated but not encrypted.
+pub fn encrypt(key: &VaultKey, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, String> {
+    let cipher = XChaCha20Poly1305::new(Key::from_slice(&key[..]));
+
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+
+    let ciphertext = cipher
+        .encrypt(XNonce::from_slice(&nonce), Payload { msg: plaintext, aad })
+        .map_err(|_| "Encryption failed".to_string())?;
+
+    let mut out = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&ciphertext);
+    Ok(out)
+}
+
+/// Decrypt `nonce || ciphertext`; fails if the key is wrong or either the data or `aad` was modified
+pub fn decrypt(key: &VaultKey, data: &[u8], aad: &[u8]) -> Result<Zeroizing<Vec<u8>>, String> {
+    if data.len() < NONCE_LEN {
+        return Err("Ciphertext too short".to_string());
+    }
+
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    let cipher = XChaCha20Poly1305::new(Key::from_slice(&key[..]));
+
+    cipher
+        .decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad })
+        .map(Zeroizing::new)
+        .map_err(|_| "Decryption failed".to_string())
+}
+
+/// Generate a random data key
+pub fn generate_key() -> VaultKey {
+    let mut key = Zeroizing::new([0u8; KEY_LEN]);
+    OsRng.fill_bytes(&mut key[..]);
+    key
+}
+
+/// Random hex token for authorizing IPC calls
+pub fn random_token() -> String {
+    let mut bytes = [0u8; TOKEN_LEN];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Compare tokens in time independent of where they differ
+pub fn tokens_equal(a: &str, b: &str) -> bool {
+    bytes_equal(a.as_bytes(), b.as_bytes())
+}
+
+/// Compare secrets in time independent of where they differ
+pub fn bytes_equal(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Encrypt `key` under `wrapping_key`
+pub fn wrap_key(wrapping_key: &VaultKey, key: &VaultKey) -> Result<Vec<u8>, String> {
+    encrypt(wrapping_key, &key[..], KEY_WRAP_CONTEXT)
+}
+
+/// Recover a key produced by `wrap_key`
+pub fn unwrap_key(wrapping_key: &VaultKey, wrapped: &[u8]) -> Result<VaultKey, String> {
+    let plaintext = decrypt(wrapping_key, wrapped, KEY_WRAP_CONTEXT)?;
+    if plaintext.len() != KEY_LEN {
+        return Err("Wrapped key has the wrong length".to_string());
+    }
+    let mut key = Zeroizing::new([0u8; KEY_LEN]);
+    key.copy_from_slice(&plaintext);
+    Ok(key)
+}
+
+/// Keyed fingerprint of the vault key, stored in the header so a wrong
+/// password can be told apart from a damaged ciphertext.
+pub fn key_check(key: &VaultKey) -> Vec<u8> {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&key[..]).expect("HMAC accepts any key length");
+    mac.update(KEY_CHECK_CONTEXT);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Constant-time comparison of a key against a stored key check
+pub fn verify_key_check(key: &VaultKey, expected: &[u8]) -> bool {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&key[..]).expect("HMAC accepts any key length");
+    mac.update(KEY_CHECK_CONTEXT);
+    mac.verify_slice(expected).is_ok()
+}
+
+/// Key for a file that should only open on this machine, from a random
+/// `pepper` stored beside it and the machine's id
+pub fn machine_bound_key(pepper: &[u8], machine_id: &[u8]) -> VaultKey {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(pepper).expect("HMAC accepts any key length");
+    mac.update(MACHINE_KEY_CONTEXT);
+    mac.update(machine_id);
+    let mut key = Zeroizing::new([0u8; KEY_LEN]);
+    key.copy_from_slice(&mac.finalize().into_bytes());
+    key
+}
+
+/// Serde helper storing byte strings as standard base64
+pub mod base64_bytes {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD.decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encryption_round_trips() {
+        let key = generate_key();
+        let sealed = encrypt(&key, b"hunter2", b"header").unwrap();
+        assert_eq!(&decrypt(&key, &sealed, b"header").unwrap()[..], b"hunter2");
+        // A fresh nonce every time
+        assert_ne!(sealed, encrypt(&key, b"hunter2", b"header").unwrap());
+    }
+
+    #[test]
+    fn tampering_is_detected() {
+        let key = generate_key();
+        let sealed = encrypt(&key, b"hunter2", b"header").unwrap();
+        for i in [0, NONCE_LEN, sealed.len() - 1] {
+            let mut tampered = sealed.clone();
+            tampered[i] ^= 1;
+            assert!(decrypt(&key, &tampered, b"header").is_err(), "byte {}", i);
+        }
+        assert!(decrypt(&key, &sealed, b"other header").is_err());
+        assert!(decrypt(&key, &sealed[..NONCE_LEN - 1], b"header").is_err());
+    }
+
+    #[test]
+    fn wrong_key_is_rejected() {
+        let key = generate_key();
+        let sealed = encrypt(&key, b"hunter2", b"").unwrap();
+        assert!(decrypt(&generate_key(), &sealed, b"").is_err());
+
+        let wrapped = wrap_key(&key, &generate_key()).unwrap();
+        assert!(unwrap_key(&generate_key(), &wrapped).is_err());
+        assert!(!verify_key_check(&generate_key(), &key_check(&key)));
+        assert!(verify_key_check(&key, &key_check(&key)));
+    }
+
+    #[test]
+    fn derivation_depends_on_salt_and_key_file() {
+        let kdf = KdfParams {
+            memory_kib: 8,
+            iterations: 1,
+            ..KdfParams::with_salt(&generate_salt())
+        };
+        let key = derive_key(b"password", &kdf).unwrap();
+        assert_eq!(key, derive_key(b"password", &kdf).unwrap());
+        assert_ne!(key, derive_key(b"password", &kdf.with_fresh_salt()).unwrap());
+
+        assert_eq!(&master_secret("password", None)[..], b"password");
+        assert_ne!(master_secret("password", Some(b"one")), master_secret("password", Some(b"two")));
+    }
+
+    #[test]
+    fn token_comparison() {
+        let token = random_token();
+        assert_eq!(token.len(), TOKEN_LEN * 2);
+        assert!(tokens_equal(&token, &token.clone()));
+        assert!(!tokens_equal(&token, &random_token()));
+        assert!(!tokens_equal(&token, &token[1..]));
+    }
+}
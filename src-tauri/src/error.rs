@@ -0,0 +1,288 @@
+/*!
+ * Vault Error Types
+ * Structured errors returned to the frontend from vault commands
+ */
+
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use serde_json::Value;
+use std::fmt;
+use uuid::Uuid;
+
+use crate::biometrics::BiometricError;
+use crate::clipboard::ClipboardError;
+use crate::keychain::KeychainError;
+use crate::storage::RecoveryCandidate;
+use crate::strength::PasswordStrength;
+
+/// Errors surfaced by vault commands.
+///
+/// Serialized as `{ "kind": "...", "message": "...", "details": {...}? }` so the UI
+/// can branch on `kind` and show `message` directly.
+#[derive(Debug, Clone)]
+pub enum VaultError {
+    /// No vault file exists yet; the UI should offer vault creation
+    NotInitialized,
+    AlreadyExists,
+    PasswordTooShort { min_length: usize },
+    /// The master password is too guessable; `common` passwords are refused
+    /// even when weak passwords are accepted
+    WeakPassword(Box<PasswordStrength>),
+    InvalidVaultName(String),
+    /// The vault was written by a newer SafeNode
+    UnsupportedVersion { found: u32, supported: u32 },
+    /// The vault file is damaged; `candidates` lists copies it may be restored from
+    VaultCorrupted { candidates: Vec<RecoveryCandidate> },
+    /// The supplied master password did not match
+    InvalidPassword,
+    /// The command needs an unlocked vault
+    VaultLocked,
+    /// The vault was opened with its recovery code; a new master password
+    /// must be set before it can be used
+    PasswordChangeRequired,
+    /// The vault was switched or rekeyed while a key was being derived for it
+    VaultChanged,
+    EntryNotFound(Uuid),
+    /// Archived entries are read-only until unarchived
+    EntryArchived(Uuid),
+    FolderNotFound(Uuid),
+    InvalidFolder(String),
+    /// Entry input failed validation; the UI highlights each listed field
+    InvalidFields(Vec<FieldError>),
+    /// Some entries of a bulk update failed; nothing was changed
+    BulkUpdateFailed(Vec<BulkFailure>),
+    AttachmentNotFound(Uuid),
+    TemplateNotFound(Uuid),
+    AttachmentTooLarge { max_size: u64 },
+    /// Icon fetching is switched off in settings
+    IconFetchDisabled,
+    /// Too many requests; try again after `retry_after_secs`
+    RateLimited { retry_after_secs: u64 },
+    /// The command needs a token from `reauthenticate`
+    ReauthenticationRequired,
+    /// The session token is missing, stale, or from another vault
+    InvalidSession,
+    BiometricFailed(BiometricError),
+    /// PIN unlock is not set up, or was switched off after too many wrong
+    /// PINs; the master password is needed
+    PinUnlockUnavailable,
+    InvalidPin { attempts_left: u32 },
+    /// No biometric hardware, or nothing enrolled
+    BiometricUnavailable,
+    BiometricUnlockNotEnabled,
+    /// Biometrics were enrolled or removed since biometric unlock was set
+    /// up, so it was switched off
+    BiometricInvalidated,
+    /// The entry is protected by biometrics, which cannot be used here
+    BiometricRequired,
+    /// Unlocking is paused after repeated failures
+    TooManyAttempts { retry_after_secs: u64 },
+    Keychain(KeychainError),
+    Clipboard(ClipboardError),
+    Io(String),
+    Crypto(String),
+}
+
+impl VaultError {
+    /// Stable discriminator sent to the frontend
+    pub fn kind(&self) -> &'static str {
+        match self {
+            VaultError::NotInitialized => "not_initialized",
+            VaultError::AlreadyExists => "already_exists",
+            VaultError::PasswordTooShort { .. } => "password_too_short",
+            VaultError::WeakPassword(_) => "weak_password",
+            VaultError::InvalidVaultName(_) => "invalid_vault_name",
+            VaultError::UnsupportedVersion { .. } => "unsupported_version",
+            VaultError::VaultCorrupted { .. } => "vault_corrupted",
+            VaultError::InvalidPassword => "invalid_password",
+            VaultError::VaultLocked => "vault_locked",
+            VaultError::PasswordChangeRequired => "password_change_required",
+            VaultError::VaultChanged => "vault_changed",
+            VaultError::EntryNotFound(_) => "entry_not_found",
+            VaultError::EntryArchived(_) => "entry_archived",
+            VaultError::FolderNotFound(_) => "folder_not_found",
+            VaultError::InvalidFolder(_) => "invalid_folder",
+            VaultError::InvalidFields(_) => "invalid_fields",
+            VaultError::BulkUpdateFailed(_) => "bulk_update_failed",
+            VaultError::AttachmentNotFound(_) => "attachment_not_found",
+            VaultError::TemplateNotFound(_) => "template_not_found",
+            VaultError::AttachmentTooLarge { .. } => "attachment_too_large",
+            VaultError::IconFetchDisabled => "icon_fetch_disabled",
+            VaultError::RateLimited { .. } => "rate_limited",
+            VaultError::TooManyAttempts { .. } => "too_many_attempts",
+            VaultError::ReauthenticationRequired => "reauthentication_required",
+            VaultError::InvalidSession => "invalid_session",
+            VaultError::BiometricFailed(_) => "biometric_failed",
+            VaultError::PinUnlockUnavailable => "pin_unlock_unavailable",
+            VaultError::InvalidPin { .. } => "invalid_pin",
+            VaultError::BiometricUnavailable => "biometric_unavailable",
+            VaultError::BiometricUnlockNotEnabled => "biometric_unlock_not_enabled",
+            VaultError::BiometricInvalidated => "biometric_invalidated",
+            VaultError::BiometricRequired => "biometric_required",
+            VaultError::Keychain(_) => "keychain",
+            VaultError::Clipboard(_) => "clipboard",
+            VaultError::Io(_) => "io",
+            VaultError::Crypto(_) => "crypto",
+        }
+    }
+
+    /// Machine-readable extras for variants that carry data
+    fn details(&self) -> Option<Value> {
+        match self {
+            VaultError::PasswordTooShort { min_length } => {
+                Some(serde_json::json!({ "min_length": min_length }))
+            }
+            VaultError::WeakPassword(strength) => Some(serde_json::json!({ "strength": strength })),
+            VaultError::EntryNotFound(id) | VaultError::EntryArchived(id) => Some(serde_json::json!({ "id": id })),
+            VaultError::FolderNotFound(id) => Some(serde_json::json!({ "id": id })),
+            VaultError::InvalidFields(fields) => Some(serde_json::json!({ "fields": fields })),
+            VaultError::BulkUpdateFailed(failures) => Some(serde_json::json!({ "failures": failures })),
+            VaultError::AttachmentNotFound(id) | VaultError::TemplateNotFound(id) => {
+                Some(serde_json::json!({ "id": id }))
+            }
+            VaultError::AttachmentTooLarge { max_size } => Some(serde_json::json!({ "max_size": max_size })),
+            VaultError::BiometricFailed(error) => Some(serde_json::json!({ "biometric": error })),
+            VaultError::Keychain(error) => Some(serde_json::json!({ "keychain": error })),
+            VaultError::Clipboard(error) => Some(serde_json::json!({ "clipboard": error })),
+            VaultError::InvalidPin { attempts_left } => Some(serde_json::json!({ "attempts_left": attempts_left })),
+            VaultError::RateLimited { retry_after_secs } | VaultError::TooManyAttempts { retry_after_secs } => {
+                Some(serde_json::json!({ "retry_after_secs": retry_after_secs }))
+            }
+            VaultError::UnsupportedVersion { found, supported } => {
+                Some(serde_json::json!({ "found": found, "supported": supported }))
+            }
+            VaultError::VaultCorrupted { candidates } => Some(serde_json::json!({ "candidates": candidates })),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for VaultError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VaultError::NotInitialized => write!(f, "Vault has not been initialized"),
+            VaultError::AlreadyExists => write!(f, "A vault already exists"),
+            VaultError::PasswordTooShort { min_length } => {
+                write!(f, "Master password must be at least {} characters", min_length)
+            }
+            VaultError::WeakPassword(strength) if strength.common => {
+                write!(f, "This is one of the most common passwords; choose another")
+            }
+            VaultError::WeakPassword(_) => write!(f, "Master password is too easy to guess"),
+            VaultError::InvalidVaultName(name) => write!(f, "Invalid vault name: {:?}", name),
+            VaultError::UnsupportedVersion { found, supported } => write!(
+                f,
+                "This vault uses format version {} but this SafeNode only supports up to {}; please update SafeNode",
+                found, supported
+            ),
+            VaultError::VaultCorrupted { .. } => write!(f, "The vault file is damaged"),
+            VaultError::InvalidPassword => write!(f, "Incorrect master password"),
+            VaultError::VaultLocked => write!(f, "Vault is locked"),
+            VaultError::PasswordChangeRequired => write!(f, "Set a new master password to continue"),
+            VaultError::VaultChanged => write!(f, "The vault changed in the meantime; try again"),
+            VaultError::EntryNotFound(id) => write!(f, "No entry with id {}", id),
+            VaultError::EntryArchived(_) => write!(f, "This entry is archived; unarchive it to make changes"),
+            VaultError::FolderNotFound(id) => write!(f, "No folder with id {}", id),
+            VaultError::InvalidFolder(msg) => write!(f, "{}", msg),
+            VaultError::InvalidFields(fields) => match fields.as_slice() {
+                [field] => write!(f, "{}", field.message),
+                _ => write!(f, "{} fields are invalid", fields.len()),
+            },
+            VaultError::BulkUpdateFailed(failures) => {
+                write!(f, "{} entries could not be updated; no changes were made", failures.len())
+            }
+            VaultError::AttachmentNotFound(id) => write!(f, "No attachment with id {}", id),
+            VaultError::TemplateNotFound(id) => write!(f, "No template with id {}", id),
+            VaultError::AttachmentTooLarge { max_size } => {
+                write!(f, "Attachments can be at most {} bytes", max_size)
+            }
+            VaultError::IconFetchDisabled => write!(f, "Fetching site icons is turned off in settings"),
+            VaultError::RateLimited { retry_after_secs } => {
+                write!(f, "Too many requests; try again in {} seconds", retry_after_secs)
+            }
+            VaultError::TooManyAttempts { retry_after_secs } => {
+                write!(f, "Too many failed attempts; try again in {} seconds", retry_after_secs)
+            }
+            VaultError::ReauthenticationRequired => write!(f, "Confirm your master password to continue"),
+            VaultError::InvalidSession => write!(f, "Session expired; unlock the vault again"),
+            VaultError::BiometricFailed(error) => write!(f, "Biometric authentication failed: {}", error),
+            VaultError::PinUnlockUnavailable => write!(f, "PIN unlock is not available; use your master password"),
+            VaultError::InvalidPin { attempts_left } => {
+                write!(f, "Incorrect PIN; {} attempts left", attempts_left)
+            }
+            VaultError::BiometricUnavailable => write!(f, "Biometric authentication is not available on this device"),
+            VaultError::BiometricUnlockNotEnabled => {
+                write!(f, "Biometric unlock is not set up; use your master password")
+            }
+            VaultError::BiometricInvalidated => write!(
+                f,
+                "Biometrics on this device changed; unlock with your master password and set up biometric unlock again"
+            ),
+            VaultError::BiometricRequired => {
+                write!(f, "This entry needs biometric confirmation, which is not available on this device")
+            }
+            VaultError::Keychain(error) => write!(f, "{}", error),
+            VaultError::Clipboard(error) => write!(f, "{}", error),
+            VaultError::Io(msg) => write!(f, "Vault I/O error: {}", msg),
+            VaultError::Crypto(msg) => write!(f, "Vault crypto error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for VaultError {}
+
+/// A validation problem with one input field
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FieldError {
+    /// Dotted path of the field, e.g. `card.number`
+    pub field: String,
+    pub message: String,
+}
+
+/// Why one entry of a bulk update failed
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BulkFailure {
+    pub id: Uuid,
+    /// `VaultError::kind` of the failure
+    pub kind: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, message: &str) -> Self {
+        FieldError {
+            field: field.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl Serialize for VaultError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let details = self.details();
+        let mut map = serializer.serialize_map(Some(if details.is_some() { 3 } else { 2 }))?;
+        map.serialize_entry("kind", self.kind())?;
+        map.serialize_entry("message", &self.to_string())?;
+        if let Some(details) = details {
+            map.serialize_entry("details", &details)?;
+        }
+        map.end()
+    }
+}
+
+impl From<std::io::Error> for VaultError {
+    fn from(e: std::io::Error) -> Self {
+        VaultError::Io(e.to_string())
+    }
+}
+
+impl From<KeychainError> for VaultError {
+    fn from(e: KeychainError) -> Self {
+        VaultError::Keychain(e)
+    }
+}
+
+impl From<ClipboardError> for VaultError {
+    fn from(e: ClipboardError) -> Self {
+        VaultError::Clipboard(e)
+    }
+}
@@ -0,0 +1,206 @@
+/*!
+ * Vault File Format
+ * Cleartext header + AEAD payload layout of the on-disk vault
+ *
+ * Layout: MAGIC (8) || header length (u32 LE) || header JSON || nonce || ciphertext
+ *
+ * Everything before the nonce is passed to the cipher as associated data, so
+ * editing the header (e.g. weakening the KDF parameters) breaks decryption.
+ */
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use uuid::Uuid;
+use zeroize::Zeroizing;
+
+use crate::crypto::{base64_bytes, CipherAlgorithm, KdfParams};
+
+pub const MAGIC: &[u8; 8] = b"SAFENODE";
+
+/// Version of the file layout described above
+pub const FILE_FORMAT_VERSION: u32 = 1;
+
+/// Upper bound on the header size we are willing to parse
+const MAX_HEADER_LEN: usize = 64 * 1024;
+
+/// Payloads smaller than this are stored uncompressed
+const COMPRESSION_THRESHOLD: usize = 4 * 1024;
+
+/// Refuse to inflate payloads beyond this size (guards against a crafted file)
+const MAX_DECOMPRESSED_LEN: u64 = 512 * 1024 * 1024;
+
+/// Compression applied to the plaintext before encryption
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    /// Vaults written before compression existed have no field and read as `None`
+    #[default]
+    None,
+    Deflate,
+}
+
+/// Cleartext header describing how to derive the key and decrypt the payload.
+///
+/// The payload is encrypted with a random data key. `kdf`/`key_check` describe
+/// the key derived from the master password, and `wrapped_key` is the data key
+/// encrypted under it. Vaults created before key wrapping have no
+/// `wrapped_key`: their password-derived key is the data key itself.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VaultHeader {
+    pub format_version: u32,
+    pub cipher: CipherAlgorithm,
+    pub kdf: KdfParams,
+    #[serde(with = "base64_bytes")]
+    pub key_check: Vec<u8>,
+    #[serde(default)]
+    pub compression: Compression,
+    #[serde(default, skip_serializing_if = "Vec::is_empty", with = "base64_bytes")]
+    pub wrapped_key: Vec<u8>,
+    /// Copy of the data key unlockable with the recovery code
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recovery: Option<KeySlot>,
+    /// Set when the vault is created; names its keychain secrets. Vaults
+    /// created before ids have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vault_id: Option<Uuid>,
+}
+
+/// The data key wrapped under a key derived from some secret
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeySlot {
+    pub kdf: KdfParams,
+    #[serde(with = "base64_bytes")]
+    pub key_check: Vec<u8>,
+    #[serde(with = "base64_bytes")]
+    pub wrapped_key: Vec<u8>,
+}
+
+/// A vault file split into its parts
+pub struct VaultFile<'a> {
+    pub header: VaultHeader,
+    /// Magic, length and header bytes exactly as stored; used as AEAD associated data
+    pub aad: &'a [u8],
+    /// `nonce || ciphertext`
+    pub payload: &'a [u8],
+}
+
+/// Serialize the header into the prefix that precedes the payload
+pub fn encode_header(header: &VaultHeader) -> Result<Vec<u8>, String> {
+    let json = serde_json::to_vec(header).map_err(|e| format!("Failed to encode vault header: {}", e))?;
+
+    let mut out = Vec::with_capacity(MAGIC.len() + 4 + json.len());
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&(json.len() as u32).to_le_bytes());
+    out.extend_from_slice(&json);
+    Ok(out)
+}
+
+/// Split a vault file into header, associated data and payload
+pub fn decode(blob: &[u8]) -> Result<VaultFile<'_>, String> {
+    let prefix_len = MAGIC.len() + 4;
+    if blob.len() < prefix_len || &blob[..MAGIC.len()] != MAGIC {
+        return Err("Not a SafeNode vault file".to_string());
+    }
+
+    let mut len_bytes = [0u8; 4];
+    len_bytes.copy_from_slice(&blob[MAGIC.len()..prefix_len]);
+    let header_len = u32::from_le_bytes(len_bytes) as usize;
+    if header_len > MAX_HEADER_LEN || blob.len() < prefix_len + header_len {
+        return Err("Vault header is truncated".to_string());
+    }
+
+    let (aad, payload) = blob.split_at(prefix_len + header_len);
+    let header: VaultHeader = serde_json::from_slice(&aad[prefix_len..])
+        .map_err(|e| format!("Vault header is malformed: {}", e))?;
+
+    Ok(VaultFile { header, aad, payload })
+}
+
+/// Pick a compression for a plaintext of `len` bytes
+pub fn compression_for(len: usize) -> Compression {
+    if len >= COMPRESSION_THRESHOLD {
+        Compression::Deflate
+    } else {
+        Compression::None
+    }
+}
+
+pub fn compress(data: &[u8], compression: Compression) -> Result<Zeroizing<Vec<u8>>, String> {
+    match compression {
+        Compression::None => Ok(Zeroizing::new(data.to_vec())),
+        Compression::Deflate => {
+            let mut encoder = DeflateEncoder::new(Vec::with_capacity(data.len() / 2), flate2::Compression::default());
+            encoder.write_all(data).map_err(|e| format!("Compression failed: {}", e))?;
+            encoder
+                .finish()
+                .map(Zeroizing::new)
+                .map_err(|e| format!("Compression failed: {}", e))
+        }
+    }
+}
+
+pub fn decompress(data: &[u8], compression: Compression) -> Result<Zeroizing<Vec<u8>>, String> {
+    match compression {
+        Compression::None => Ok(Zeroizing::new(data.to_vec())),
+        Compression::Deflate => {
+            let mut out = Zeroizing::new(Vec::with_capacity(data.len() * 4));
+            DeflateDecoder::new(data)
+                .take(MAX_DECOMPRESSED_LEN)
+                .read_to_end(&mut out)
+                .map_err(|e| format!("Decompression failed: {}", e))?;
+            Ok(out)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compression_round_trips() {
+        let data = "entry ".repeat(10_000).into_bytes();
+        for compression in [Compression::None, Compression::Deflate] {
+            let packed = compress(&data, compression).unwrap();
+            assert_eq!(&decompress(&packed, compression).unwrap()[..], &data[..]);
+        }
+        assert!(compress(&data, Compression::Deflate).unwrap().len() < data.len() / 10);
+    }
+
+    #[test]
+    fn small_payloads_stay_uncompressed() {
+        assert_eq!(compression_for(COMPRESSION_THRESHOLD - 1), Compression::None);
+        assert_eq!(compression_for(COMPRESSION_THRESHOLD), Compression::Deflate);
+    }
+
+    #[test]
+    fn headers_without_compression_read_as_uncompressed() {
+        let header: VaultHeader = serde_json::from_value(serde_json::json!({
+            "format_version": 1,
+            "cipher": "xchacha20poly1305",
+            "kdf": { "algorithm": "argon2id", "salt": "AAAA", "memory_kib": 8, "iterations": 1, "parallelism": 1 },
+            "key_check": "AAAA"
+        }))
+        .unwrap();
+        assert_eq!(header.compression, Compression::None);
+
+        let mut blob = encode_header(&header).unwrap();
+        blob.extend_from_slice(b"payload");
+        let file = decode(&blob).unwrap();
+        assert_eq!(file.payload, b"payload");
+        assert_eq!(file.aad.len(), blob.len() - b"payload".len());
+    }
+
+    #[test]
+    fn malformed_files_are_rejected() {
+        assert!(decode(b"").is_err());
+        assert!(decode(b"NOTAVAULT\0\0\0\0").is_err());
+
+        let mut truncated = MAGIC.to_vec();
+        truncated.extend_from_slice(&100u32.to_le_bytes());
+        truncated.extend_from_slice(b"{}");
+        assert!(decode(&truncated).is_err());
+    }
+}
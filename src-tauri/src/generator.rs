@@ -0,0 +1,1002 @@
+/*!
+ * Password Generator
+ * Random passwords drawn from the OS CSPRNG
+ */
+
+use rand::rngs::OsRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use zeroize::Zeroizing;
+
+use crate::error::FieldError;
+
+const LOWERCASE: &str = "abcdefghijklmnopqrstuvwxyz";
+const UPPERCASE: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const DIGITS: &str = "0123456789";
+const SYMBOLS: &str = "!@#$%^&*()-_=+[]{};:,.<>?/~";
+/// Left out with `exclude_ambiguous`, as they are easily misread
+const AMBIGUOUS: &str = "0O1lI";
+const CONSONANTS: &str = "bcdfghjklmnpqrstvwxz";
+const VOWELS: &str = "aeiou";
+/// Pronounceable passwords containing any of these are drawn again
+const BLOCKED_WORDS: &[&str] = &[
+    "anal", "anus", "bitch", "boob", "butt", "cock", "coon", "crap", "cum", "cunt", "damn", "dick", "dyke", "fag",
+    "fuck", "fuk", "gay", "homo", "jiz", "kike", "nazi", "nig", "pedo", "penis", "piss", "poop", "porn", "puta",
+    "rape", "sex", "shit", "slut", "tit", "twat", "vagina", "wank", "whore",
+];
+/// Draws of a pronounceable password before giving up; a block is rare, so
+/// running out means the options leave almost nothing else
+const MAX_REDRAWS: usize = 1000;
+
+pub const MIN_LENGTH: usize = 4;
+pub const MAX_LENGTH: usize = 256;
+/// Highest `min_entropy_bits` accepted; far beyond any attack
+pub const MAX_ENTROPY_TARGET_BITS: u32 = 512;
+/// Bound on the states counted for positional rules, which grow with the
+/// minimum counts
+const MAX_POSITIONAL_STATES: usize = 4096;
+pub const MIN_PIN_LENGTH: usize = 4;
+pub const MAX_PIN_LENGTH: usize = 12;
+/// The 100 four-digit PINs people choose most, per analyses of leaked PINs
+/// and passwords; keypad patterns and years are well represented
+const COMMON_PINS: [&str; 100] = [
+    "1234", "1111", "0000", "1212", "7777", "1004", "2000", "4444", "2222", "6969", "9999", "3333",
+    "5555", "6666", "1122", "1313", "8888", "4321", "2001", "1010", "2580", "0852", "1379", "1470",
+    "2468", "1357", "7410", "0987", "8520", "0123", "1000", "1230", "1200", "1221", "2112", "1984",
+    "1985", "1986", "1987", "1988", "1989", "1990", "1991", "1992", "1993", "1994", "1995", "1996",
+    "1997", "1998", "1999", "2002", "2003", "2004", "2005", "2006", "2007", "2008", "2009", "2010",
+    "2011", "2012", "2020", "0001", "0007", "0069", "0420", "1024", "1101", "1112", "1123", "1201",
+    "1225", "1231", "1414", "1515", "1818", "2121", "2323", "2525", "3434", "4545", "5150", "5678",
+    "5683", "6789", "7000", "7007", "7531", "8008", "8080", "9876", "0911", "0101", "0110", "1001",
+    "1011", "2345", "3456", "4567",
+];
+const MAX_POLICY_NAME_LEN: usize = 64;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GeneratorMode {
+    /// Any mix of the enabled classes
+    #[default]
+    Random,
+    /// Alternating consonants and vowels, easy to read out. Capitals go on
+    /// consonants and digits at the end; symbols are not used.
+    Pronounceable,
+}
+
+/// A character class, as named by positional rules
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClassKind {
+    Lowercase,
+    Uppercase,
+    Digits,
+    Symbols,
+}
+
+/// What a generated password may contain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GeneratorOptions {
+    pub mode: GeneratorMode,
+    pub length: usize,
+    pub lowercase: bool,
+    pub uppercase: bool,
+    pub digits: bool,
+    pub symbols: bool,
+    /// Fewest characters of each class; ignored for classes switched off
+    pub min_lowercase: usize,
+    pub min_uppercase: usize,
+    pub min_digits: usize,
+    pub min_symbols: usize,
+    /// Leave out characters that are easily misread (`0O1lI`)
+    pub exclude_ambiguous: bool,
+    /// Characters never to use
+    pub exclude_chars: String,
+    /// Symbols to draw from instead of the usual set, e.g. `!@#`
+    pub symbol_set: Option<String>,
+    /// Ignore `length` and use the shortest one reaching this many bits
+    pub min_entropy_bits: Option<u32>,
+    /// Classes the first character must come from; empty allows any
+    pub first_char_classes: Vec<ClassKind>,
+    /// Classes the last character must come from; empty allows any
+    pub last_char_classes: Vec<ClassKind>,
+    /// Never put the same character twice in a row
+    pub no_repeated_adjacent: bool,
+    /// Draw again while the password is in Pwned Passwords
+    pub check_breached: bool,
+}
+
+impl Default for GeneratorOptions {
+    fn default() -> Self {
+        GeneratorOptions {
+            mode: GeneratorMode::default(),
+            length: 20,
+            lowercase: true,
+            uppercase: true,
+            digits: true,
+            symbols: true,
+            min_lowercase: 1,
+            min_uppercase: 1,
+            min_digits: 1,
+            min_symbols: 1,
+            exclude_ambiguous: false,
+            exclude_chars: String::new(),
+            symbol_set: None,
+            min_entropy_bits: None,
+            first_char_classes: Vec::new(),
+            last_char_classes: Vec::new(),
+            no_repeated_adjacent: false,
+            check_breached: false,
+        }
+    }
+}
+
+/// An enabled character class after exclusions
+struct CharClass {
+    kind: ClassKind,
+    chars: Vec<char>,
+    min: usize,
+}
+
+impl GeneratorOptions {
+    fn check_length(&self) -> Result<(), FieldError> {
+        if !(MIN_LENGTH..=MAX_LENGTH).contains(&self.length) {
+            return Err(FieldError::new(
+                "length",
+                &format!("Length must be between {} and {}", MIN_LENGTH, MAX_LENGTH),
+            ));
+        }
+        Ok(())
+    }
+
+    fn has_positional_rules(&self) -> bool {
+        !self.first_char_classes.is_empty() || !self.last_char_classes.is_empty() || self.no_repeated_adjacent
+    }
+
+    fn excluded(&self, c: char) -> bool {
+        (self.exclude_ambiguous && AMBIGUOUS.contains(c)) || self.exclude_chars.contains(c)
+    }
+
+    /// Distinct characters of `chars` not excluded
+    fn allowed(&self, chars: &str) -> Vec<char> {
+        let mut allowed: Vec<char> = chars.chars().filter(|&c| !self.excluded(c)).collect();
+        allowed.sort_unstable();
+        allowed.dedup();
+        allowed
+    }
+
+    fn symbols(&self) -> Result<&str, FieldError> {
+        match &self.symbol_set {
+            Some(set) if set.chars().any(|c| c.is_alphanumeric() || c.is_whitespace()) => Err(FieldError::new(
+                "symbol_set",
+                "Symbols may not include letters, digits or spaces",
+            )),
+            Some(set) => Ok(set),
+            None => Ok(SYMBOLS),
+        }
+    }
+
+    /// Character classes switched on, checked against the length
+    fn classes(&self) -> Result<Vec<CharClass>, FieldError> {
+        self.check_length()?;
+
+        let mut classes = Vec::new();
+        for (kind, enabled, chars, min, field) in [
+            (ClassKind::Lowercase, self.lowercase, LOWERCASE, self.min_lowercase, "lowercase"),
+            (ClassKind::Uppercase, self.uppercase, UPPERCASE, self.min_uppercase, "uppercase"),
+            (ClassKind::Digits, self.digits, DIGITS, self.min_digits, "digits"),
+            (ClassKind::Symbols, self.symbols, self.symbols()?, self.min_symbols, "symbols"),
+        ] {
+            if !enabled {
+                continue;
+            }
+            let chars = self.allowed(chars);
+            if chars.is_empty() {
+                return Err(FieldError::new(field, "Every character of this class is excluded"));
+            }
+            classes.push(CharClass { kind, chars, min });
+        }
+
+        if classes.is_empty() {
+            return Err(FieldError::new("options", "Enable at least one character class"));
+        }
+        let required: usize = classes.iter().map(|class| class.min).sum();
+        if required > self.length {
+            return Err(FieldError::new(
+                "length",
+                &format!(
+                    "The minimum counts add up to {} characters, more than the length of {}",
+                    required, self.length
+                ),
+            ));
+        }
+        Ok(classes)
+    }
+}
+
+/// A generated password and the entropy of the space it was drawn from
+pub struct Generated {
+    pub password: Zeroizing<String>,
+    pub entropy_bits: f64,
+}
+
+/// `ln(n!)` for `n` in `0..=max`
+fn ln_factorials(max: usize) -> Vec<f64> {
+    let mut table = vec![0.0; max + 1];
+    for n in 1..=max {
+        table[n] = table[n - 1] + (n as f64).ln();
+    }
+    table
+}
+
+/// `ln(e^a + e^b)` without overflowing
+fn ln_add(a: f64, b: f64) -> f64 {
+    let (high, low) = if a > b { (a, b) } else { (b, a) };
+    if low == f64::NEG_INFINITY {
+        return high;
+    }
+    high + (low - high).exp().ln_1p()
+}
+
+/// How many passwords meet the minimum counts, by class and length.
+/// `counts[i][n]` is the natural log of the number of `n`-character strings
+/// over the first `i` classes with at least each one's minimum.
+struct Counts {
+    counts: Vec<Vec<f64>>,
+    ln_factorials: Vec<f64>,
+}
+
+impl Counts {
+    fn new(classes: &[CharClass], length: usize) -> Self {
+        let ln_factorials = ln_factorials(length);
+        let mut counts = vec![vec![f64::NEG_INFINITY; length + 1]];
+        counts[0][0] = 0.0;
+        for (i, class) in classes.iter().enumerate() {
+            let ln_size = (class.chars.len() as f64).ln();
+            let mut next = vec![f64::NEG_INFINITY; length + 1];
+            for (n, total) in next.iter_mut().enumerate() {
+                for k in class.min..=n {
+                    *total = ln_add(*total, ln_ways(&counts[i], &ln_factorials, ln_size, n, k));
+                }
+            }
+            counts.push(next);
+        }
+        Counts { counts, ln_factorials }
+    }
+
+    fn entropy_bits(&self, length: usize) -> f64 {
+        self.counts[self.counts.len() - 1][length] / std::f64::consts::LN_2
+    }
+}
+
+/// ln of how many `n`-character strings take exactly `k` characters from a
+/// class of `e^ln_size`, with the rest counted by `below`
+fn ln_ways(below: &[f64], ln_factorials: &[f64], ln_size: f64, n: usize, k: usize) -> f64 {
+    let ln_choose = ln_factorials[n] - ln_factorials[k] - ln_factorials[n - k];
+    below[n - k] + ln_choose + k as f64 * ln_size
+}
+
+/// How many characters of each class a password gets, drawn in proportion
+/// to how many passwords have that mix, so every valid password is equally
+/// likely and nothing is generated and thrown away
+fn pick_mix(classes: &[CharClass], counts: &Counts, length: usize, rng: &mut impl Rng) -> Vec<usize> {
+    let mut mix = vec![0; classes.len()];
+    let mut remaining = length;
+    for (i, class) in classes.iter().enumerate().rev() {
+        let ln_size = (class.chars.len() as f64).ln();
+        let total = counts.counts[i + 1][remaining];
+        let mut target: f64 = rng.gen();
+        let mut chosen = None;
+        for k in class.min..=remaining {
+            let ways = ln_ways(&counts.counts[i], &counts.ln_factorials, ln_size, remaining, k);
+            if ways == f64::NEG_INFINITY {
+                continue;
+            }
+            // Rounding can leave `target` just past the last share
+            chosen = Some(k);
+            target -= (ways - total).exp();
+            if target < 0.0 {
+                break;
+            }
+        }
+        let k = chosen.expect("the minimum counts fit the length");
+        mix[i] = k;
+        remaining -= k;
+    }
+    mix
+}
+
+/// Field error unless `bits` is a usable `min_entropy_bits`
+pub fn check_entropy_target(bits: u32) -> Result<(), FieldError> {
+    if !(1..=MAX_ENTROPY_TARGET_BITS).contains(&bits) {
+        return Err(FieldError::new(
+            "min_entropy_bits",
+            &format!("Aim for between 1 and {} bits", MAX_ENTROPY_TARGET_BITS),
+        ));
+    }
+    Ok(())
+}
+
+/// `options` with `length` set from `min_entropy_bits`, if given. The
+/// entropy of every length comes from one pass over the exact counts, and
+/// more length never lowers it, so the first length reaching the target is
+/// the shortest. Positional rules need a count per length, so their length
+/// is found by binary search.
+fn sized(options: &GeneratorOptions) -> Result<Cow<'_, GeneratorOptions>, FieldError> {
+    let Some(bits) = options.min_entropy_bits else {
+        return Ok(Cow::Borrowed(options));
+    };
+    check_entropy_target(bits)?;
+    // Tolerates rounding when a length reaches the target exactly
+    let target = bits as f64 - 1e-9;
+    let at_length = |length| GeneratorOptions {
+        length,
+        ..options.clone()
+    };
+    let length = match options.mode {
+        GeneratorMode::Random if options.has_positional_rules() => {
+            let classes = at_length(MAX_LENGTH).classes()?;
+            let rules = PositionalRules::new(options, &classes)?;
+            let lengths: Vec<usize> = (MIN_LENGTH..=MAX_LENGTH).collect();
+            let index = lengths.partition_point(|&length| rules.entropy_bits(length) < target);
+            lengths.get(index).copied()
+        }
+        GeneratorMode::Random => {
+            let classes = at_length(MAX_LENGTH).classes()?;
+            let counts = Counts::new(&classes, MAX_LENGTH);
+            (MIN_LENGTH..=MAX_LENGTH).find(|&length| counts.entropy_bits(length) >= target)
+        }
+        GeneratorMode::Pronounceable => {
+            // Errors that no length avoids, e.g. every vowel excluded
+            Pronounceable::new(&at_length(MAX_LENGTH))?;
+            (MIN_LENGTH..=MAX_LENGTH).find(|&length| {
+                Pronounceable::new(&at_length(length)).is_ok_and(|plan| plan.entropy_bits() >= target)
+            })
+        }
+    };
+    match length {
+        Some(length) => Ok(Cow::Owned(at_length(length))),
+        None => Err(FieldError::new(
+            "min_entropy_bits",
+            &format!("No length up to {} reaches {} bits with these options", MAX_LENGTH, bits),
+        )),
+    }
+}
+
+/// Generate a password as `options.mode` says
+pub fn generate(options: &GeneratorOptions) -> Result<Generated, FieldError> {
+    let options = sized(options)?;
+    match options.mode {
+        GeneratorMode::Random => generate_random(&options),
+        GeneratorMode::Pronounceable => Pronounceable::new(&options)?.generate(),
+    }
+}
+
+/// A password meeting every enabled class's minimum count
+fn generate_random(options: &GeneratorOptions) -> Result<Generated, FieldError> {
+    let classes = options.classes()?;
+    if options.has_positional_rules() {
+        return PositionalRules::new(options, &classes)?.generate(options.length);
+    }
+    let counts = Counts::new(&classes, options.length);
+    let mut rng = OsRng;
+
+    // Which class each position draws from, in a random order
+    let mix = pick_mix(&classes, &counts, options.length, &mut rng);
+    let mut positions: Vec<usize> = mix.iter().enumerate().flat_map(|(i, &k)| vec![i; k]).collect();
+    positions.shuffle(&mut rng);
+
+    let chars: Zeroizing<Vec<char>> = Zeroizing::new(
+        positions
+            .iter()
+            .map(|&i| classes[i].chars[rng.gen_range(0..classes[i].chars.len())])
+            .collect(),
+    );
+    Ok(Generated {
+        password: Zeroizing::new(chars.iter().collect()),
+        entropy_bits: counts.entropy_bits(options.length),
+    })
+}
+
+/// Counts and draws passwords under `first_char_classes`,
+/// `last_char_classes` and `no_repeated_adjacent` as well as the minimum
+/// counts. A state is the class of the previous character and a tally of
+/// the characters used so far per class, capped at each class's minimum
+/// since more do not matter; tallies are mixed-radix numbers.
+struct PositionalRules<'a> {
+    classes: &'a [CharClass],
+    first: Vec<bool>,
+    last: Vec<bool>,
+    no_repeat: bool,
+    strides: Vec<usize>,
+    /// Number of distinct tallies; the last one meets every minimum
+    tallies: usize,
+}
+
+impl<'a> PositionalRules<'a> {
+    fn new(options: &GeneratorOptions, classes: &'a [CharClass]) -> Result<Self, FieldError> {
+        let allowed = |kinds: &[ClassKind], field: &str| -> Result<Vec<bool>, FieldError> {
+            if kinds.iter().any(|kind| !classes.iter().any(|class| class.kind == *kind)) {
+                return Err(FieldError::new(field, "Only enabled classes can be required here"));
+            }
+            Ok(classes.iter().map(|class| kinds.is_empty() || kinds.contains(&class.kind)).collect())
+        };
+        let first = allowed(&options.first_char_classes, "first_char_classes")?;
+        let last = allowed(&options.last_char_classes, "last_char_classes")?;
+
+        let mut strides = Vec::with_capacity(classes.len());
+        let mut tallies = 1usize;
+        for class in classes {
+            strides.push(tallies);
+            tallies = tallies.saturating_mul(class.min + 1);
+        }
+        if tallies.saturating_mul(classes.len()) > MAX_POSITIONAL_STATES {
+            return Err(FieldError::new(
+                "options",
+                "The minimum counts are too high to combine with positional rules",
+            ));
+        }
+        Ok(PositionalRules {
+            classes,
+            first,
+            last,
+            no_repeat: options.no_repeated_adjacent,
+            strides,
+            tallies,
+        })
+    }
+
+    /// `tally` after one more character of class `c`
+    fn step(&self, tally: usize, c: usize) -> usize {
+        if (tally / self.strides[c]) % (self.classes[c].min + 1) < self.classes[c].min {
+            tally + self.strides[c]
+        } else {
+            tally
+        }
+    }
+
+    /// ln of how many ways position `p` can take a character of class `c`
+    /// with the rest completed, given `ways` from `p + 1` on
+    fn weight(&self, ways: &[Vec<f64>], p: usize, prev: Option<usize>, tally: usize, c: usize) -> f64 {
+        let length = ways.len() - 1;
+        if (p == 0 && !self.first[c]) || (p == length - 1 && !self.last[c]) {
+            return f64::NEG_INFINITY;
+        }
+        let mut choices = self.classes[c].chars.len();
+        if self.no_repeat && prev == Some(c) {
+            choices -= 1;
+        }
+        (choices as f64).ln() + ways[p + 1][c * self.tallies + self.step(tally, c)]
+    }
+
+    /// ln of how many ways positions `p..` can be filled from a state
+    fn total(&self, ways: &[Vec<f64>], p: usize, prev: Option<usize>, tally: usize) -> f64 {
+        (0..self.classes.len()).fold(f64::NEG_INFINITY, |total, c| {
+            ln_add(total, self.weight(ways, p, prev, tally, c))
+        })
+    }
+
+    /// `ways[p][prev * tallies + tally]` is `total` for every state at `p`;
+    /// `ways[0][0]` counts whole passwords
+    fn ways(&self, length: usize) -> Vec<Vec<f64>> {
+        let states = self.classes.len() * self.tallies;
+        let mut ways = vec![Vec::new(); length + 1];
+        ways[length] = (0..states)
+            .map(|state| if state % self.tallies == self.tallies - 1 { 0.0 } else { f64::NEG_INFINITY })
+            .collect();
+        for p in (1..length).rev() {
+            ways[p] = (0..states)
+                .map(|state| self.total(&ways, p, Some(state / self.tallies), state % self.tallies))
+                .collect();
+        }
+        ways[0] = vec![self.total(&ways, 0, None, 0)];
+        ways
+    }
+
+    fn entropy_bits(&self, length: usize) -> f64 {
+        self.ways(length)[0][0] / std::f64::consts::LN_2
+    }
+
+    /// Draw each character's class in proportion to the passwords it leaves
+    /// possible, then the character uniformly, so every password allowed
+    /// is equally likely
+    fn generate(&self, length: usize) -> Result<Generated, FieldError> {
+        let ways = self.ways(length);
+        if ways[0][0] == f64::NEG_INFINITY {
+            return Err(FieldError::new("options", "No password of this length satisfies these rules"));
+        }
+        let mut rng = OsRng;
+        let mut chars: Zeroizing<Vec<char>> = Zeroizing::new(Vec::with_capacity(length));
+        let mut prev = None;
+        let mut tally = 0;
+        for p in 0..length {
+            let total = self.total(&ways, p, prev, tally);
+            let mut target: f64 = rng.gen();
+            let mut chosen = None;
+            for c in 0..self.classes.len() {
+                let weight = self.weight(&ways, p, prev, tally, c);
+                if weight == f64::NEG_INFINITY {
+                    continue;
+                }
+                // Rounding can leave `target` just past the last share
+                chosen = Some(c);
+                target -= (weight - total).exp();
+                if target < 0.0 {
+                    break;
+                }
+            }
+            let c = chosen.expect("every state reached can be completed");
+            let previous_char = chars.last().copied();
+            let pool: Vec<char> = self.classes[c]
+                .chars
+                .iter()
+                .copied()
+                .filter(|&ch| !self.no_repeat || Some(ch) != previous_char)
+                .collect();
+            chars.push(pool[rng.gen_range(0..pool.len())]);
+            prev = Some(c);
+            tally = self.step(tally, c);
+        }
+        Ok(Generated {
+            password: Zeroizing::new(chars.iter().collect()),
+            entropy_bits: ways[0][0] / std::f64::consts::LN_2,
+        })
+    }
+}
+
+/// What a pronounceable password is drawn from: consonant-vowel pairs with
+/// `min_uppercase` (at least one) capital consonants when uppercase is on
+/// and `min_digits` (at least one) digits at the end when digits are on
+struct Pronounceable {
+    consonants: Vec<char>,
+    vowels: Vec<char>,
+    digits: Vec<char>,
+    letters: usize,
+    capitals: usize,
+    digit_count: usize,
+}
+
+impl Pronounceable {
+    fn new(options: &GeneratorOptions) -> Result<Self, FieldError> {
+        options.check_length()?;
+        if options.has_positional_rules() {
+            return Err(FieldError::new("mode", "Positional rules apply to random passwords only"));
+        }
+        let capitals = if options.uppercase { options.min_uppercase.max(1) } else { 0 };
+        let digit_count = if options.digits { options.min_digits.max(1) } else { 0 };
+        // A consonant is only used if its capital may be too
+        let consonants: Vec<char> = options
+            .allowed(CONSONANTS)
+            .into_iter()
+            .filter(|c| !options.uppercase || !options.excluded(c.to_ascii_uppercase()))
+            .collect();
+        let vowels = options.allowed(VOWELS);
+        let digits = options.allowed(DIGITS);
+        if consonants.is_empty() || vowels.is_empty() {
+            return Err(FieldError::new(
+                "exclude_chars",
+                "Too many letters are excluded to make a pronounceable password",
+            ));
+        }
+        if digit_count > 0 && digits.is_empty() {
+            return Err(FieldError::new("digits", "Every character of this class is excluded"));
+        }
+        let letters = options.length.saturating_sub(digit_count);
+        if letters < 2 {
+            return Err(FieldError::new("min_digits", "Leave room for at least two letters"));
+        }
+        let plan = Pronounceable {
+            consonants,
+            vowels,
+            digits,
+            letters,
+            capitals,
+            digit_count,
+        };
+        if capitals > plan.consonant_slots() {
+            return Err(FieldError::new(
+                "min_uppercase",
+                &format!("At most {} capitals fit a pronounceable password this long", plan.consonant_slots()),
+            ));
+        }
+        Ok(plan)
+    }
+
+    fn consonant_slots(&self) -> usize {
+        self.letters.div_ceil(2)
+    }
+
+    /// The blocked words remove a negligible share of this space
+    fn entropy_bits(&self) -> f64 {
+        let slots = self.consonant_slots();
+        let ln_factorials = ln_factorials(slots);
+        let ln_capital_spots =
+            ln_factorials[slots] - ln_factorials[self.capitals] - ln_factorials[slots - self.capitals];
+        slots as f64 * (self.consonants.len() as f64).log2()
+            + (self.letters / 2) as f64 * (self.vowels.len() as f64).log2()
+            + ln_capital_spots / std::f64::consts::LN_2
+            + self.digit_count as f64 * (self.digits.len() as f64).log2()
+    }
+
+    fn generate(&self) -> Result<Generated, FieldError> {
+        let mut rng = OsRng;
+        for _ in 0..MAX_REDRAWS {
+            let mut chars: Zeroizing<Vec<char>> = Zeroizing::new(
+                (0..self.letters)
+                    .map(|i| {
+                        let set = if i % 2 == 0 { &self.consonants } else { &self.vowels };
+                        set[rng.gen_range(0..set.len())]
+                    })
+                    .collect(),
+            );
+            let lowercase = Zeroizing::new(chars.iter().collect::<String>());
+            if BLOCKED_WORDS.iter().any(|word| lowercase.contains(word)) {
+                continue;
+            }
+            for slot in rand::seq::index::sample(&mut rng, self.consonant_slots(), self.capitals) {
+                chars[slot * 2] = chars[slot * 2].to_ascii_uppercase();
+            }
+            for _ in 0..self.digit_count {
+                chars.push(self.digits[rng.gen_range(0..self.digits.len())]);
+            }
+            return Ok(Generated {
+                password: Zeroizing::new(chars.iter().collect()),
+                entropy_bits: self.entropy_bits(),
+            });
+        }
+        Err(FieldError::new("options", "Could not generate a password free of blocked words; try other options"))
+    }
+}
+
+/// Check `options` without generating, e.g. before saving them as a policy
+pub fn validate(options: &GeneratorOptions) -> Result<(), FieldError> {
+    let options = sized(options)?;
+    match options.mode {
+        GeneratorMode::Random => options.classes().map(drop),
+        GeneratorMode::Pronounceable => Pronounceable::new(&options).map(drop),
+    }
+}
+
+/// Named generator options, e.g. an employer's password rules. Kept in
+/// settings so every vault can use them; folders refer to them by name.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GeneratorPolicy {
+    pub name: String,
+    pub options: GeneratorOptions,
+}
+
+/// `name` trimmed, if it is usable as a policy name
+pub fn policy_name(name: &str) -> Result<String, FieldError> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_POLICY_NAME_LEN {
+        return Err(FieldError::new(
+            "name",
+            &format!("Policy names must be 1 to {} characters", MAX_POLICY_NAME_LEN),
+        ));
+    }
+    Ok(name.to_string())
+}
+
+/// Whether a guesser would try `pin` early: one digit throughout, a run up
+/// or down the digits (wrapping from 9 to 0), a shorter block repeated, or
+/// a common four-digit PIN
+fn weak_pin(pin: &str) -> bool {
+    let digits = pin.as_bytes();
+    let steps: Vec<u8> = digits.windows(2).map(|pair| (10 + pair[1] - pair[0]) % 10).collect();
+    let one_step = steps.iter().all(|&step| step == steps[0]) && matches!(steps[0], 0 | 1 | 9);
+    let repeated = (1..digits.len()).any(|period| {
+        digits.len().is_multiple_of(period) && digits[period..] == digits[..digits.len() - period]
+    });
+    one_step || repeated || COMMON_PINS.contains(&pin)
+}
+
+/// A random PIN of `length` digits, drawn again while it is weak. Also
+/// returns whether any draw was thrown away.
+pub fn generate_pin(length: usize) -> Result<(Zeroizing<String>, bool), FieldError> {
+    if !(MIN_PIN_LENGTH..=MAX_PIN_LENGTH).contains(&length) {
+        return Err(FieldError::new(
+            "length",
+            &format!("PINs must be between {} and {} digits", MIN_PIN_LENGTH, MAX_PIN_LENGTH),
+        ));
+    }
+    let mut rng = OsRng;
+    for redraws in 0..MAX_REDRAWS {
+        let pin: Zeroizing<String> =
+            Zeroizing::new((0..length).map(|_| char::from(b'0' + rng.gen_range(0..10u8))).collect());
+        if !weak_pin(&pin) {
+            return Ok((pin, redraws > 0));
+        }
+    }
+    // Under one PIN in 40 is weak, so this is never expected
+    Err(FieldError::new("length", "Could not generate a PIN that is not weak"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pronounceable() -> GeneratorOptions {
+        GeneratorOptions {
+            mode: GeneratorMode::Pronounceable,
+            ..GeneratorOptions::default()
+        }
+    }
+
+    /// Only `t`, `b` and `i` left, so many draws spell "tit"
+    fn blocked_often() -> GeneratorOptions {
+        GeneratorOptions {
+            uppercase: false,
+            digits: false,
+            exclude_chars: "acdefghjklmnopqrsuvwxyz".to_string(),
+            length: 12,
+            ..pronounceable()
+        }
+    }
+
+    #[test]
+    fn pronounceable_passwords_alternate_consonants_and_vowels() {
+        for _ in 0..500 {
+            let password = generate(&pronounceable()).unwrap().password;
+            let (letters, digits) = password.split_at(19);
+            assert!(digits.chars().all(|c| c.is_ascii_digit()), "{}", *password);
+            assert_eq!(letters.chars().filter(char::is_ascii_uppercase).count(), 1, "{}", *password);
+            for (i, c) in letters.chars().enumerate() {
+                let set = if i % 2 == 0 { CONSONANTS } else { VOWELS };
+                assert!(set.contains(c.to_ascii_lowercase()), "{}", *password);
+            }
+        }
+    }
+
+    #[test]
+    fn blocked_words_are_drawn_again() {
+        for _ in 0..500 {
+            let password = generate(&blocked_often()).unwrap().password;
+            assert!(!BLOCKED_WORDS.iter().any(|word| password.contains(word)), "{}", *password);
+        }
+    }
+
+    #[test]
+    fn gives_up_when_every_draw_is_blocked() {
+        let options = GeneratorOptions {
+            exclude_chars: "abcdefghjklmnopqrsuvwxyz".to_string(),
+            ..blocked_often()
+        };
+        assert_eq!(generate(&options).err().unwrap().field, "options");
+    }
+
+    #[test]
+    fn pronounceable_entropy_counts_the_reduced_space() {
+        // 10 consonants, 9 vowels, 1 of 10 spots capitalised and 1 digit
+        let expected = 10.0 * 20f64.log2() + 9.0 * 5f64.log2() + 10f64.log2() + 10f64.log2();
+        assert!((generate(&pronounceable()).unwrap().entropy_bits - expected).abs() < 1e-9);
+
+        let bits = generate(&blocked_often()).unwrap().entropy_bits;
+        assert!((bits - 6.0).abs() < 1e-9, "{}", bits);
+    }
+
+    #[test]
+    fn pronounceable_mode_rejects_impossible_options() {
+        let no_vowels = GeneratorOptions {
+            exclude_chars: VOWELS.to_string(),
+            ..pronounceable()
+        };
+        assert_eq!(generate(&no_vowels).err().unwrap().field, "exclude_chars");
+        let crowded = GeneratorOptions {
+            length: 6,
+            min_uppercase: 3,
+            min_digits: 2,
+            ..pronounceable()
+        };
+        assert_eq!(generate(&crowded).err().unwrap().field, "min_uppercase");
+        let positional = GeneratorOptions {
+            no_repeated_adjacent: true,
+            ..pronounceable()
+        };
+        assert_eq!(generate(&positional).err().unwrap().field, "mode");
+    }
+
+    #[test]
+    fn weak_pins_are_recognised() {
+        for pin in ["0000", "1234", "9876", "7890", "8901", "2109", "2580", "1984", "121212", "123123", "55555555"] {
+            assert!(weak_pin(pin), "{}", pin);
+        }
+        for pin in ["7392", "4815", "0317", "120120120121", "935172"] {
+            assert!(!weak_pin(pin), "{}", pin);
+        }
+    }
+
+    #[test]
+    fn generated_pins_are_never_weak() {
+        let mut redrawn = false;
+        for length in [MIN_PIN_LENGTH, 6, MAX_PIN_LENGTH] {
+            for _ in 0..2000 {
+                let (pin, redraws) = generate_pin(length).unwrap();
+                assert_eq!(pin.len(), length);
+                assert!(pin.bytes().all(|c| c.is_ascii_digit()), "{}", *pin);
+                assert!(!weak_pin(&pin), "{}", *pin);
+                redrawn |= redraws;
+            }
+        }
+        // About one four-digit draw in 40 is weak
+        assert!(redrawn);
+    }
+
+    #[test]
+    fn pin_length_is_bounded() {
+        assert_eq!(generate_pin(MIN_PIN_LENGTH - 1).err().unwrap().field, "length");
+        assert_eq!(generate_pin(MAX_PIN_LENGTH + 1).err().unwrap().field, "length");
+    }
+
+    fn positional() -> GeneratorOptions {
+        GeneratorOptions {
+            length: 10,
+            first_char_classes: vec![ClassKind::Uppercase, ClassKind::Lowercase],
+            last_char_classes: vec![ClassKind::Digits],
+            no_repeated_adjacent: true,
+            ..GeneratorOptions::default()
+        }
+    }
+
+    /// Lowercase `a` and `b` and the digit `7` only
+    fn tiny() -> GeneratorOptions {
+        GeneratorOptions {
+            length: 4,
+            uppercase: false,
+            symbols: false,
+            exclude_chars: "cdefghijklmnopqrstuvwxyz012345689".to_string(),
+            first_char_classes: vec![ClassKind::Lowercase],
+            no_repeated_adjacent: true,
+            ..GeneratorOptions::default()
+        }
+    }
+
+    fn tiny_allows(password: &str) -> bool {
+        let chars: Vec<char> = password.chars().collect();
+        chars[0].is_ascii_lowercase()
+            && chars.iter().any(char::is_ascii_lowercase)
+            && chars.iter().any(char::is_ascii_digit)
+            && chars.windows(2).all(|pair| pair[0] != pair[1])
+    }
+
+    /// Every password `tiny()` allows, by brute force
+    fn tiny_space() -> Vec<String> {
+        let alphabet = ['a', 'b', '7'];
+        (0..81)
+            .map(|mut n| {
+                (0..4)
+                    .map(|_| {
+                        let c = alphabet[n % 3];
+                        n /= 3;
+                        c
+                    })
+                    .collect::<String>()
+            })
+            .filter(|password| tiny_allows(password))
+            .collect()
+    }
+
+    #[test]
+    fn positional_rules_hold_across_many_samples() {
+        let options = positional();
+        for _ in 0..5000 {
+            let password = generate(&options).unwrap().password;
+            let chars: Vec<char> = password.chars().collect();
+            assert_eq!(chars.len(), 10);
+            assert!(chars[0].is_ascii_alphabetic(), "{}", *password);
+            assert!(chars[9].is_ascii_digit(), "{}", *password);
+            assert!(chars.windows(2).all(|pair| pair[0] != pair[1]), "{}", *password);
+            assert!(chars.iter().any(char::is_ascii_lowercase), "{}", *password);
+            assert!(chars.iter().any(char::is_ascii_uppercase), "{}", *password);
+            assert!(chars.iter().any(|c| SYMBOLS.contains(*c)), "{}", *password);
+        }
+    }
+
+    #[test]
+    fn positional_entropy_matches_a_brute_force_count() {
+        let space = tiny_space();
+        let bits = generate(&tiny()).unwrap().entropy_bits;
+        assert!((bits - (space.len() as f64).log2()).abs() < 1e-9, "{} for {}", bits, space.len());
+    }
+
+    #[test]
+    fn positional_rules_draw_every_allowed_password_evenly() {
+        let space = tiny_space();
+        let mut seen = std::collections::HashMap::new();
+        let samples = 1000 * space.len();
+        for _ in 0..samples {
+            let password = generate(&tiny()).unwrap().password;
+            assert!(space.contains(&*password), "{}", *password);
+            *seen.entry(password.to_string()).or_insert(0) += 1;
+        }
+        assert_eq!(seen.len(), space.len());
+        // Within eight standard deviations of the 1000 expected
+        assert!(seen.values().all(|&count| (750..=1250).contains(&count)), "{:?}", seen);
+    }
+
+    #[test]
+    fn impossible_positional_rules_are_an_error() {
+        let one_digit = GeneratorOptions {
+            lowercase: false,
+            uppercase: false,
+            symbols: false,
+            exclude_chars: "012345678".to_string(),
+            no_repeated_adjacent: true,
+            ..GeneratorOptions::default()
+        };
+        assert_eq!(generate(&one_digit).err().unwrap().field, "options");
+
+        let disabled = GeneratorOptions {
+            symbols: false,
+            last_char_classes: vec![ClassKind::Symbols],
+            ..GeneratorOptions::default()
+        };
+        assert_eq!(generate(&disabled).err().unwrap().field, "last_char_classes");
+
+        let crowded = GeneratorOptions {
+            length: 200,
+            min_lowercase: 20,
+            min_uppercase: 20,
+            min_digits: 20,
+            min_symbols: 20,
+            ..positional()
+        };
+        assert_eq!(generate(&crowded).err().unwrap().field, "options");
+    }
+
+    #[test]
+    fn entropy_target_picks_the_shortest_length() {
+        for options in [GeneratorOptions::default(), positional()] {
+            for bits in [40, 80, 128] {
+                let target = GeneratorOptions {
+                    min_entropy_bits: Some(bits),
+                    ..options.clone()
+                };
+                let length = sized(&target).unwrap().length;
+                let generated = generate(&target).unwrap();
+                assert_eq!(generated.password.chars().count(), length);
+                assert!(generated.entropy_bits >= bits as f64 - 1e-9);
+                let shorter = GeneratorOptions {
+                    length: length - 1,
+                    ..options.clone()
+                };
+                assert!(generate(&shorter).unwrap().entropy_bits < bits as f64, "{} bits", bits);
+            }
+        }
+        let unreachable = GeneratorOptions {
+            min_entropy_bits: Some(MAX_ENTROPY_TARGET_BITS),
+            lowercase: false,
+            uppercase: false,
+            symbols: false,
+            exclude_chars: "01234567".to_string(),
+            ..GeneratorOptions::default()
+        };
+        assert_eq!(generate(&unreachable).err().unwrap().field, "min_entropy_bits");
+    }
+
+    #[test]
+    fn minimum_counts_are_met_and_counted() {
+        let options = GeneratorOptions {
+            length: 12,
+            min_uppercase: 3,
+            min_digits: 4,
+            min_symbols: 2,
+            ..GeneratorOptions::default()
+        };
+        for _ in 0..2000 {
+            let password = generate(&options).unwrap().password;
+            assert!(password.chars().filter(char::is_ascii_uppercase).count() >= 3, "{}", *password);
+            assert!(password.chars().filter(char::is_ascii_digit).count() >= 4, "{}", *password);
+            assert!(password.chars().filter(|c| SYMBOLS.contains(*c)).count() >= 2, "{}", *password);
+        }
+        let tiny = GeneratorOptions {
+            first_char_classes: Vec::new(),
+            no_repeated_adjacent: false,
+            ..tiny()
+        };
+        // Four characters of `a`, `b` and `7` with a letter and the digit
+        let expected = 81.0 - 1.0 - 16.0;
+        assert!((generate(&tiny).unwrap().entropy_bits - f64::log2(expected)).abs() < 1e-9);
+    }
+}
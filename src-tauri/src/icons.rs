@@ -0,0 +1,179 @@
+/*!
+ * Site Icons
+ * Favicons for entry URLs, fetched on request and cached on disk by host
+ */
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tauri::AppHandle;
+use url::Url;
+
+use crate::error::VaultError;
+use crate::storage;
+
+const ICONS_DIR_NAME: &str = "icons";
+const ICON_EXTENSION: &str = "icon";
+/// Give up on a site after this long, per request
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+/// Larger responses are not icons worth showing
+const MAX_ICON_SIZE: usize = 256 * 1024;
+/// Only the start of a page is searched for `<link rel="icon">`
+const MAX_PAGE_SIZE: usize = 512 * 1024;
+/// Network fetches allowed per `RATE_WINDOW`
+const FETCHES_PER_WINDOW: usize = 20;
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Sliding-window limit on icon downloads, so a large vault cannot turn
+/// into a burst of requests
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    recent: VecDeque<Instant>,
+}
+
+impl RateLimiter {
+    /// Record a fetch if one is allowed now, otherwise return how long to wait
+    pub fn try_acquire(&mut self) -> Result<(), Duration> {
+        let now = Instant::now();
+        while self.recent.front().is_some_and(|t| now.duration_since(*t) >= RATE_WINDOW) {
+            self.recent.pop_front();
+        }
+        if self.recent.len() >= FETCHES_PER_WINDOW {
+            let oldest = self.recent[0];
+            return Err(RATE_WINDOW - now.duration_since(oldest));
+        }
+        self.recent.push_back(now);
+        Ok(())
+    }
+}
+
+fn cache_dir(app: &AppHandle) -> Result<PathBuf, VaultError> {
+    Ok(storage::data_dir(app)?.join(ICONS_DIR_NAME))
+}
+
+/// Cache files are named by a hash of the host, so the directory listing
+/// does not reveal which sites the vault holds
+fn cache_path(app: &AppHandle, host: &str) -> Result<PathBuf, VaultError> {
+    let digest = Sha256::digest(host.as_bytes());
+    let name: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+    Ok(cache_dir(app)?.join(format!("{}.{}", name, ICON_EXTENSION)))
+}
+
+/// Cached icon for `host` as a data URL
+pub fn cached(app: &AppHandle, host: &str) -> Result<Option<String>, VaultError> {
+    Ok(storage::read_file(&cache_path(app, host)?)?.and_then(|bytes| data_url(&bytes)))
+}
+
+pub fn store(app: &AppHandle, host: &str, bytes: &[u8]) -> Result<(), VaultError> {
+    storage::write_atomic(&cache_path(app, host)?, bytes)
+}
+
+pub fn clear_cache(app: &AppHandle) -> Result<(), VaultError> {
+    match fs::remove_dir_all(cache_dir(app)?) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// `data:` URL for an image, or `None` if the bytes are not a known format
+pub fn data_url(bytes: &[u8]) -> Option<String> {
+    Some(format!("data:{};base64,{}", image_type(bytes)?, STANDARD.encode(bytes)))
+}
+
+fn image_type(bytes: &[u8]) -> Option<&'static str> {
+    let head = &bytes[..bytes.len().min(256)];
+    if head.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if head.starts_with(&[0, 0, 1, 0]) {
+        Some("image/x-icon")
+    } else if head.starts_with(b"GIF87a") || head.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if head.starts_with(&[0xff, 0xd8, 0xff]) {
+        Some("image/jpeg")
+    } else if head.starts_with(b"RIFF") && head.get(8..12) == Some(b"WEBP") {
+        Some("image/webp")
+    } else if String::from_utf8_lossy(head).to_lowercase().contains("<svg") {
+        Some("image/svg+xml")
+    } else {
+        None
+    }
+}
+
+/// Download the icon of `host`: `/favicon.ico` first, then whatever the
+/// home page declares with `<link rel="icon">`. `None` on any failure,
+/// including timeouts; only recognised image formats are returned.
+pub async fn fetch(host: &str) -> Option<Vec<u8>> {
+    let client = reqwest::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .user_agent("SafeNode")
+        .build()
+        .ok()?;
+    let base = Url::parse(&format!("https://{}/", host)).ok()?;
+
+    if let Some(bytes) = download(&client, base.join("favicon.ico").ok()?, MAX_ICON_SIZE).await {
+        if image_type(&bytes).is_some() {
+            return Some(bytes);
+        }
+    }
+
+    let page = download(&client, base.clone(), MAX_PAGE_SIZE).await?;
+    for href in icon_links(&String::from_utf8_lossy(&page)) {
+        let Ok(url) = base.join(&href) else { continue };
+        if !matches!(url.scheme(), "http" | "https") {
+            continue;
+        }
+        if let Some(bytes) = download(&client, url, MAX_ICON_SIZE).await {
+            if image_type(&bytes).is_some() {
+                return Some(bytes);
+            }
+        }
+    }
+    None
+}
+
+/// Body of a successful response, cut off at `limit` bytes
+async fn download(client: &reqwest::Client, url: Url, limit: usize) -> Option<Vec<u8>> {
+    let mut response = client.get(url).send().await.ok()?.error_for_status().ok()?;
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await.ok()? {
+        body.extend_from_slice(&chunk);
+        if body.len() >= limit {
+            body.truncate(limit);
+            break;
+        }
+    }
+    Some(body)
+}
+
+/// `href`s of `<link>` tags whose `rel` includes `icon`, in page order
+fn icon_links(html: &str) -> Vec<String> {
+    let tag = Regex::new(r"(?is)<link\b[^>]*>").expect("valid regex");
+    let attribute = Regex::new(r#"(?is)\b(rel|href)\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>]+))"#).expect("valid regex");
+
+    tag.find_iter(html)
+        .filter_map(|tag| {
+            let mut rel = None;
+            let mut href = None;
+            for captures in attribute.captures_iter(tag.as_str()) {
+                let value = captures
+                    .get(2)
+                    .or_else(|| captures.get(3))
+                    .or_else(|| captures.get(4))
+                    .map(|m| m.as_str().trim().to_string());
+                match captures[1].to_lowercase().as_str() {
+                    "rel" => rel = value,
+                    _ => href = value,
+                }
+            }
+            let is_icon = rel?.to_lowercase().split_whitespace().any(|token| token == "icon");
+            href.filter(|href| is_icon && !href.is_empty())
+        })
+        .collect()
+}
@@ -0,0 +1,195 @@
+/*!
+ * Typed Item Details
+ * Fields specific to card and identity entries, and their validation
+ */
+
+use serde::{Deserialize, Serialize};
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
+
+use crate::error::FieldError;
+
+/// Payment card. `number` and `cvv` are secrets like a password: they are
+/// only returned by `get_entry`.
+/// Missing fields are empty; a container-level `#[serde(default)]` would
+/// move out of a value that zeroizes on drop.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Zeroize, ZeroizeOnDrop)]
+pub struct CardDetails {
+    #[serde(default)]
+    pub cardholder: String,
+    #[serde(default)]
+    pub number: String,
+    /// `MM/YY` or `MM/YYYY`
+    #[serde(default)]
+    pub expiry: String,
+    #[serde(default)]
+    pub cvv: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Zeroize, ZeroizeOnDrop)]
+pub struct IdentityDetails {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub address: String,
+    #[serde(default)]
+    pub phone: String,
+    #[serde(default)]
+    pub email: String,
+}
+
+impl CardDetails {
+    /// Field-level problems, with paths such as `card.number`
+    pub fn validate(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+
+        // Like the expiry and CVV, the number may be left blank
+        if !self.number.trim().is_empty() {
+            match card_digits(&self.number) {
+                None => errors.push(FieldError::new("card.number", "Card number may only contain digits")),
+                Some(digits) if !(12..=19).contains(&digits.len()) => {
+                    errors.push(FieldError::new("card.number", "Card number must have 12 to 19 digits"))
+                }
+                Some(digits) if !luhn_valid(&digits) => {
+                    errors.push(FieldError::new("card.number", "Card number is not valid"))
+                }
+                Some(_) => {}
+            }
+        }
+
+        if !self.expiry.trim().is_empty() && parse_expiry(&self.expiry).is_none() {
+            errors.push(FieldError::new("card.expiry", "Expiry must be MM/YY or MM/YYYY"));
+        }
+
+        let cvv = self.cvv.trim();
+        let cvv_valid = (cvv.len() == 3 || cvv.len() == 4) && cvv.chars().all(|c| c.is_ascii_digit());
+        if !cvv.is_empty() && !cvv_valid {
+            errors.push(FieldError::new("card.cvv", "CVV must be 3 or 4 digits"));
+        }
+
+        errors
+    }
+
+    /// The number with all but the last four digits hidden, e.g. `•••• 4242`
+    pub fn masked_number(&self) -> Option<String> {
+        let digits = card_digits(&self.number)?;
+        if digits.len() < 4 {
+            return None;
+        }
+        Some(format!("•••• {}", &digits[digits.len() - 4..]))
+    }
+}
+
+/// Digits of a card number with spaces and dashes removed; `None` if any
+/// other character appears
+fn card_digits(number: &str) -> Option<Zeroizing<String>> {
+    let mut digits = Zeroizing::new(String::with_capacity(number.len()));
+    for c in number.chars().filter(|c| !c.is_whitespace() && *c != '-') {
+        if !c.is_ascii_digit() {
+            return None;
+        }
+        digits.push(c);
+    }
+    Some(digits)
+}
+
+fn luhn_valid(digits: &str) -> bool {
+    let sum: u32 = digits
+        .bytes()
+        .rev()
+        .enumerate()
+        .map(|(i, b)| {
+            let digit = (b - b'0') as u32;
+            if i % 2 == 1 {
+                let doubled = digit * 2;
+                if doubled > 9 {
+                    doubled - 9
+                } else {
+                    doubled
+                }
+            } else {
+                digit
+            }
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+/// Month and four-digit year of an `MM/YY` or `MM/YYYY` expiry
+fn parse_expiry(expiry: &str) -> Option<(u32, u32)> {
+    let (month, year) = expiry.trim().split_once('/')?;
+    let (month, year) = (month.trim(), year.trim());
+    if month.len() != 2 || !(year.len() == 2 || year.len() == 4) {
+        return None;
+    }
+
+    let month: u32 = month.parse().ok()?;
+    let year: u32 = year.parse().ok()?;
+    if !(1..=12).contains(&month) {
+        return None;
+    }
+    Some((month, if year < 100 { 2000 + year } else { year }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card(number: &str, expiry: &str, cvv: &str) -> CardDetails {
+        CardDetails {
+            cardholder: "A N Other".to_string(),
+            number: number.to_string(),
+            expiry: expiry.to_string(),
+            cvv: cvv.to_string(),
+        }
+    }
+
+    /// Fields with errors, in order
+    fn invalid_fields(card: &CardDetails) -> Vec<String> {
+        card.validate().into_iter().map(|error| error.field).collect()
+    }
+
+    #[test]
+    fn card_numbers_are_luhn_checked() {
+        for valid in ["4242 4242 4242 4242", "4111-1111-1111-1111", "378282246310005", "6011111111111117"] {
+            assert!(invalid_fields(&card(valid, "", "")).is_empty(), "{}", valid);
+        }
+        for invalid in ["4242 4242 4242 4241", "4242 4242 42x2 4242", "42424242424", "42424242424242424242"] {
+            assert_eq!(invalid_fields(&card(invalid, "", "")), ["card.number"], "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn blank_fields_are_allowed() {
+        assert!(invalid_fields(&card("", "", "")).is_empty());
+        assert!(invalid_fields(&card("  ", " ", " ")).is_empty());
+        assert_eq!(card("", "", "").masked_number(), None);
+    }
+
+    #[test]
+    fn expiry_accepts_two_and_four_digit_years() {
+        assert_eq!(parse_expiry("07/29"), Some((7, 2029)));
+        assert_eq!(parse_expiry(" 07 / 2031 "), Some((7, 2031)));
+        for invalid in ["13/29", "00/29", "7/29", "07/029", "07-29", "07/2x"] {
+            assert_eq!(parse_expiry(invalid), None, "{}", invalid);
+            assert_eq!(invalid_fields(&card("", invalid, "")), ["card.expiry"], "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn cvv_has_three_or_four_digits() {
+        for valid in ["123", "1234"] {
+            assert!(invalid_fields(&card("", "", valid)).is_empty(), "{}", valid);
+        }
+        for invalid in ["12", "12345", "12a"] {
+            assert_eq!(invalid_fields(&card("", "", invalid)), ["card.cvv"], "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn masked_numbers_show_the_last_four_digits() {
+        assert_eq!(card("4242 4242 4242 4242", "", "").masked_number().as_deref(), Some("•••• 4242"));
+        assert_eq!(card("378282246310005", "", "").masked_number().as_deref(), Some("•••• 0005"));
+        assert_eq!(card("424", "", "").masked_number(), None);
+        assert_eq!(card("4242 x", "", "").masked_number(), None);
+    }
+}
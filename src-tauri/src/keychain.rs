@@ -0,0 +1,1261 @@
+/*!
+ * Vault Keychain Secrets
+ * OS keychain entries SafeNode itself keeps for each vault, or an encrypted
+ * file where there is no keychain
+ */
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+#[cfg(not(target_os = "macos"))]
+use keyring::Entry;
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use chrono::Utc;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::time::Duration;
+use tauri::AppHandle;
+use zeroize::Zeroizing;
+
+use crate::audit::{self, KeychainChange, KeychainOperation};
+use crate::secret_file::FileStore;
+
+/// Keychain service under which SafeNode's own secrets are stored
+pub const SERVICE: &str = "com.safenode.vault";
+/// Prefix put on every service name the frontend asks for
+const NAMESPACE: &str = "com.safenode.";
+const MAX_SERVICE_LEN: usize = 64;
+const MAX_ACCOUNT_LEN: usize = 128;
+/// Unlock secrets the frontend stored under raw names, as service and
+/// account, before names were namespaced. They belong to the default vault.
+const LEGACY_UNLOCK_SECRETS: [(&str, &str); 1] = [("safenode", "master_password")];
+/// Put before the base64 of a binary secret, so a value in another format
+/// is not mistaken for one
+const BYTES_PREFIX: &str = "safenode-b64-v1:";
+/// Account looked up by `backend_info`; never written
+const PROBE_ACCOUNT: &str = "backend-probe";
+/// Most bytes written to one entry. Windows Credential Manager holds 2560
+/// bytes of UTF-16 per credential; longer secrets are split into chunks.
+const CHUNK_LEN: usize = 1024;
+const MAX_CHUNKS: usize = 64;
+/// Value of an entry whose secret is split into chunks, followed by the
+/// chunk count
+const CHUNKS_PREFIX: &str = "safenode-chunks-v1:";
+/// How long the async operations wait for the OS, which may be showing a
+/// prompt nobody answers
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Why a keychain operation failed.
+///
+/// Serialized like `VaultError`, as `{ "kind": "...", "message": "..." }`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeychainError {
+    /// The keychain or its collection is locked
+    Locked,
+    /// The OS or the user refused SafeNode access
+    AccessDenied,
+    /// There is no keychain service, e.g. no Secret Service daemon
+    NoBackend,
+    NotFound,
+    /// A stored binary secret did not decode
+    CorruptSecret,
+    /// A service or account name from the frontend was refused
+    InvalidName(String),
+    /// What was read back after a write differs from what was written.
+    /// The entry has been deleted.
+    VerificationFailed { expected: usize, stored: usize },
+    /// The OS did not answer in time, typically because its access prompt
+    /// was ignored
+    Timeout,
+    /// Anything else the platform reported
+    PlatformError(String),
+}
+
+impl KeychainError {
+    /// Stable discriminator sent to the frontend
+    pub fn kind(&self) -> &'static str {
+        match self {
+            KeychainError::Locked => "locked",
+            KeychainError::AccessDenied => "access_denied",
+            KeychainError::NoBackend => "no_backend",
+            KeychainError::NotFound => "not_found",
+            KeychainError::CorruptSecret => "corrupt_secret",
+            KeychainError::InvalidName(_) => "invalid_name",
+            KeychainError::VerificationFailed { .. } => "verification_failed",
+            KeychainError::Timeout => "timeout",
+            KeychainError::PlatformError(_) => "platform_error",
+        }
+    }
+}
+
+impl fmt::Display for KeychainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeychainError::Locked if cfg!(target_os = "linux") => write!(
+                f,
+                "The keyring is locked; unlock it in Passwords and Keys or KWalletManager and try again"
+            ),
+            KeychainError::Locked => write!(f, "The keychain is locked; unlock it and try again"),
+            KeychainError::AccessDenied => write!(f, "Access to the keychain was denied"),
+            KeychainError::NoBackend => write!(f, "No keychain service is available on this system"),
+            KeychainError::NotFound => write!(f, "No such keychain entry"),
+            KeychainError::CorruptSecret => write!(f, "A secret stored in the keychain is damaged"),
+            KeychainError::InvalidName(msg) => write!(f, "{}", msg),
+            KeychainError::VerificationFailed { expected, stored } => write!(
+                f,
+                "The keychain did not keep the secret intact ({} of {} bytes stored)",
+                stored, expected
+            ),
+            KeychainError::Timeout => write!(f, "The keychain did not respond; answer any system prompt and try again"),
+            KeychainError::PlatformError(msg) => write!(f, "Keychain error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for KeychainError {}
+
+impl Serialize for KeychainError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(2))?;
+        map.serialize_entry("kind", self.kind())?;
+        map.serialize_entry("message", &self.to_string())?;
+        map.end()
+    }
+}
+
+/// `keyring` boxes the platform's own error, so locked and denied stores
+/// are told apart by its message
+impl From<keyring::Error> for KeychainError {
+    fn from(e: keyring::Error) -> Self {
+        match e {
+            keyring::Error::NoEntry => KeychainError::NotFound,
+            keyring::Error::NoStorageAccess(inner) => classify(&inner.to_string()).unwrap_or(KeychainError::Locked),
+            keyring::Error::PlatformFailure(inner) => {
+                let message = inner.to_string();
+                classify(&message).unwrap_or(KeychainError::PlatformError(message))
+            }
+            e => KeychainError::PlatformError(e.to_string()),
+        }
+    }
+}
+
+fn classify(message: &str) -> Option<KeychainError> {
+    let message = message.to_lowercase();
+    let mentions = |words: &[&str]| words.iter().any(|word| message.contains(word));
+    if mentions(&["org.freedesktop.secrets", "serviceunknown", "no such keychain", "unavailable"]) {
+        Some(KeychainError::NoBackend)
+    } else if mentions(&["denied", "not allowed", "dismissed", "cancel"]) {
+        Some(KeychainError::AccessDenied)
+    } else if mentions(&["locked"]) {
+        Some(KeychainError::Locked)
+    } else {
+        None
+    }
+}
+
+/// Where secrets are kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeychainBackend {
+    Keychain,
+    CredentialManager,
+    SecretService,
+    /// `secret_file::FileStore`, used where there is no OS keychain
+    #[serde(rename = "file-fallback")]
+    FileFallback,
+    None,
+}
+
+/// Whether OS keychain entries may sync to the user's other devices, as
+/// with iCloud Keychain
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncPolicy {
+    #[default]
+    LocalOnly,
+    AllowSync,
+}
+
+/// `Settings::keychain_sync_policy`; true for `SyncPolicy::AllowSync`
+static ALLOW_SYNC: AtomicBool = AtomicBool::new(false);
+
+/// Policy for entries written from now on; see `apply_sync_policy`
+pub fn set_sync_policy(policy: SyncPolicy) {
+    ALLOW_SYNC.store(policy == SyncPolicy::AllowSync, Ordering::Relaxed);
+}
+
+pub fn sync_policy() -> SyncPolicy {
+    if ALLOW_SYNC.load(Ordering::Relaxed) {
+        SyncPolicy::AllowSync
+    } else {
+        SyncPolicy::LocalOnly
+    }
+}
+
+/// A place to keep secrets. Callers go through `get`, `set` and `delete`,
+/// which pick the OS keychain or the file fallback.
+pub trait SecretStore: Send + Sync {
+    fn get(&self, service: &str, account: &str) -> Result<Option<Zeroizing<String>>, KeychainError>;
+    fn set(&self, service: &str, account: &str, secret: &str) -> Result<(), KeychainError>;
+    /// `Ok(false)` if there was no such secret
+    fn delete(&self, service: &str, account: &str) -> Result<bool, KeychainError>;
+    fn backend(&self) -> KeychainBackend;
+}
+
+/// The OS keychain, through `keyring`, or the Security framework directly
+/// on macOS, where `keyring` cannot set or find synchronizable items
+struct OsKeychain;
+
+impl SecretStore for OsKeychain {
+    #[cfg(target_os = "macos")]
+    fn get(&self, service: &str, account: &str) -> Result<Option<Zeroizing<String>>, KeychainError> {
+        macos::get(service, account)
+    }
+
+    #[cfg(target_os = "macos")]
+    fn set(&self, service: &str, account: &str, secret: &str) -> Result<(), KeychainError> {
+        macos::set(service, account, secret, sync_policy() == SyncPolicy::AllowSync)
+    }
+
+    #[cfg(target_os = "macos")]
+    fn delete(&self, service: &str, account: &str) -> Result<bool, KeychainError> {
+        macos::delete(service, account)
+    }
+
+    /// Entries not found in the collection in use are looked for in the
+    /// other one, where they were before `Settings::dedicated_keychain_collection`
+    /// last changed
+    #[cfg(not(target_os = "macos"))]
+    fn get(&self, service: &str, account: &str) -> Result<Option<Zeroizing<String>>, KeychainError> {
+        let dedicated = dedicated_collection();
+        let secret = read_entry(service, account, dedicated)?;
+        if secret.is_some() || !cfg!(target_os = "linux") {
+            return Ok(secret);
+        }
+        read_entry(service, account, !dedicated)
+    }
+
+    /// Also removes the entry from the collection not in use, so it moves
+    /// over when it is next written
+    #[cfg(not(target_os = "macos"))]
+    fn set(&self, service: &str, account: &str, secret: &str) -> Result<(), KeychainError> {
+        let dedicated = dedicated_collection();
+        #[cfg(all(target_os = "linux", feature = "dbus"))]
+        if dedicated && !secret_service::unlock(Some(COLLECTION_LABEL))? {
+            return Err(KeychainError::Locked);
+        }
+        unlocking(dedicated, || Ok(entry(service, account, dedicated)?.set_password(secret)?))?;
+        if cfg!(target_os = "linux") {
+            if let Err(e) = remove_entry(service, account, !dedicated) {
+                eprintln!("Failed to delete keychain entry from the unused collection: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn delete(&self, service: &str, account: &str) -> Result<bool, KeychainError> {
+        let dedicated = dedicated_collection();
+        let deleted = remove_entry(service, account, dedicated)?;
+        if !cfg!(target_os = "linux") {
+            return Ok(deleted);
+        }
+        Ok(remove_entry(service, account, !dedicated)? || deleted)
+    }
+
+    fn backend(&self) -> KeychainBackend {
+        if cfg!(target_os = "macos") {
+            KeychainBackend::Keychain
+        } else if cfg!(target_os = "windows") {
+            KeychainBackend::CredentialManager
+        } else if cfg!(target_os = "linux") {
+            KeychainBackend::SecretService
+        } else {
+            KeychainBackend::None
+        }
+    }
+}
+
+/// Label of the Secret Service collection used with
+/// `Settings::dedicated_keychain_collection`
+#[cfg(not(target_os = "macos"))]
+const COLLECTION_LABEL: &str = "SafeNode";
+
+/// `Settings::dedicated_keychain_collection`
+static DEDICATED_COLLECTION: AtomicBool = AtomicBool::new(false);
+
+/// Keep new entries in a Secret Service collection of their own instead of
+/// the default one. Only Linux has collections.
+pub fn set_dedicated_collection(dedicated: bool) {
+    DEDICATED_COLLECTION.store(dedicated, Ordering::Relaxed);
+}
+
+#[cfg(not(target_os = "macos"))]
+fn dedicated_collection() -> bool {
+    DEDICATED_COLLECTION.load(Ordering::Relaxed)
+}
+
+/// The `keyring` entry, in the `COLLECTION_LABEL` collection when
+/// `dedicated` on Linux
+#[cfg(not(target_os = "macos"))]
+fn entry(service: &str, account: &str, dedicated: bool) -> Result<Entry, KeychainError> {
+    if cfg!(target_os = "linux") && dedicated {
+        Ok(Entry::new_with_target(COLLECTION_LABEL, service, account)?)
+    } else {
+        Ok(Entry::new(service, account)?)
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+fn read_entry(service: &str, account: &str, dedicated: bool) -> Result<Option<Zeroizing<String>>, KeychainError> {
+    unlocking(dedicated, || match entry(service, account, dedicated)?.get_password() {
+        Ok(secret) => Ok(Some(Zeroizing::new(secret))),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(e.into()),
+    })
+}
+
+#[cfg(not(target_os = "macos"))]
+fn remove_entry(service: &str, account: &str, dedicated: bool) -> Result<bool, KeychainError> {
+    unlocking(dedicated, || match entry(service, account, dedicated)?.delete_password() {
+        Ok(()) => Ok(true),
+        Err(keyring::Error::NoEntry) => Ok(false),
+        Err(e) => Err(e.into()),
+    })
+}
+
+/// Look up `PROBE_ACCOUNT`, without offering to unlock a locked collection
+#[cfg(not(target_os = "macos"))]
+fn probe() -> Result<(), KeychainError> {
+    match entry(SERVICE, PROBE_ACCOUNT, dedicated_collection())?.get_password() {
+        Ok(_) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn probe() -> Result<(), KeychainError> {
+    macos::get(SERVICE, PROBE_ACCOUNT).map(drop)
+}
+
+/// Run `op`, and once more after the user unlocks the collection if it
+/// failed because the collection is locked. A declined unlock stays
+/// `KeychainError::Locked`.
+#[cfg(not(target_os = "macos"))]
+fn unlocking<T>(dedicated: bool, op: impl Fn() -> Result<T, KeychainError>) -> Result<T, KeychainError> {
+    let result = op();
+    #[cfg(all(target_os = "linux", feature = "dbus"))]
+    if matches!(result, Err(KeychainError::Locked)) {
+        let label = dedicated.then_some(COLLECTION_LABEL);
+        return if secret_service::unlock(label)? { op() } else { Err(KeychainError::Locked) };
+    }
+    #[cfg(not(all(target_os = "linux", feature = "dbus")))]
+    let _ = dedicated;
+    result
+}
+
+static FILE_STORE: OnceLock<FileStore> = OnceLock::new();
+
+/// Keep secrets in an encrypted file in `dir` when there is no OS keychain
+pub fn init_file_fallback(dir: PathBuf) {
+    let _ = FILE_STORE.set(FileStore::new(dir));
+}
+
+/// Run `op` against the file fallback once it holds secrets, otherwise
+/// against the OS keychain, moving to the file when there is no keychain
+/// service
+fn with_store<T>(op: impl Fn(&dyn SecretStore) -> Result<T, KeychainError>) -> Result<T, KeychainError> {
+    let file = FILE_STORE.get();
+    if let Some(file) = file.filter(|file| file.in_use()) {
+        return op(file);
+    }
+    match (op(&OsKeychain), file) {
+        (Err(KeychainError::NoBackend), Some(file)) => op(file),
+        (result, _) => result,
+    }
+}
+
+/// What `keychain_backend_info` reports
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BackendInfo {
+    pub backend: KeychainBackend,
+    /// Why the backend could not be reached, when it exists but failed
+    pub error: Option<KeychainError>,
+    /// `Settings::keychain_sync_policy` where the backend honours it, i.e.
+    /// the macOS keychain; `None` elsewhere
+    pub sync_policy: Option<SyncPolicy>,
+}
+
+impl BackendInfo {
+    fn new(backend: KeychainBackend, error: Option<KeychainError>) -> Self {
+        let sync_policy = (backend == KeychainBackend::Keychain).then(sync_policy);
+        BackendInfo { backend, error, sync_policy }
+    }
+}
+
+/// Which store is in use. The OS keychain is probed by looking up an entry
+/// that is never written.
+pub fn backend_info() -> BackendInfo {
+    let file = FILE_STORE.get();
+    if let Some(file) = file.filter(|file| file.in_use()) {
+        return BackendInfo::new(file.backend(), None);
+    }
+    match probe() {
+        Ok(()) => BackendInfo::new(OsKeychain.backend(), None),
+        Err(KeychainError::NoBackend) => {
+            BackendInfo::new(file.map_or(KeychainBackend::None, |file| file.backend()), None)
+        }
+        Err(e) => BackendInfo::new(OsKeychain.backend(), Some(e)),
+    }
+}
+
+/// A secret kept in the OS keychain on behalf of one vault
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VaultSecret {
+    /// Pepper mixed into the quick-unlock PIN
+    PinPepper,
+    /// Vault key wrapped for biometric unlock
+    BiometricKey,
+    /// Secret the frontend keeps to unlock the vault, set with
+    /// `store_unlock_secret`
+    UnlockSecret,
+}
+
+impl VaultSecret {
+    pub const ALL: [VaultSecret; 3] = [VaultSecret::PinPepper, VaultSecret::BiometricKey, VaultSecret::UnlockSecret];
+
+    fn purpose(self) -> &'static str {
+        match self {
+            VaultSecret::PinPepper => "pin-pepper",
+            VaultSecret::BiometricKey => "biometric-key",
+            VaultSecret::UnlockSecret => "unlock-secret",
+        }
+    }
+
+    /// Keychain account name of this secret for the vault with
+    /// `storage::keychain_id` `keychain_id`
+    pub fn account(self, keychain_id: &str) -> String {
+        format!("{}:{}", keychain_id, self.purpose())
+    }
+
+    /// The vault's keychain id and the secret in an `account` name
+    fn parse_account(account: &str) -> Option<(String, VaultSecret)> {
+        let (keychain_id, purpose) = account.rsplit_once(':')?;
+        let secret = VaultSecret::ALL.into_iter().find(|secret| secret.purpose() == purpose)?;
+        Some((keychain_id.to_string(), secret))
+    }
+}
+
+/// `service` from the frontend moved into SafeNode's namespace. Names
+/// with path separators or control characters are refused, as is the
+/// service SafeNode keeps its own secrets under.
+pub fn namespaced(service: &str) -> Result<String, KeychainError> {
+    check_name("service", service, MAX_SERVICE_LEN)?;
+    let service = format!("{}{}", NAMESPACE, service);
+    if service == SERVICE {
+        return Err(KeychainError::InvalidName("Keychain service is reserved".to_string()));
+    }
+    Ok(service)
+}
+
+/// Refuse an `account` from the frontend that `namespaced` would refuse
+/// as a service name
+pub fn check_account(account: &str) -> Result<(), KeychainError> {
+    check_name("account", account, MAX_ACCOUNT_LEN)
+}
+
+fn check_name(what: &str, name: &str, max_len: usize) -> Result<(), KeychainError> {
+    if name.is_empty() || name.chars().count() > max_len {
+        let message = format!("Keychain {} must be 1 to {} characters", what, max_len);
+        return Err(KeychainError::InvalidName(message));
+    }
+    if name.chars().any(|c| c == '/' || c == '\\' || c.is_control()) {
+        return Err(KeychainError::InvalidName(format!("Keychain {} contains an invalid character", what)));
+    }
+    Ok(())
+}
+
+/// Store binary `secret` for `account` under `SERVICE`
+pub fn save_secret_bytes(account: &str, secret: &[u8]) -> Result<(), KeychainError> {
+    let encoded = Zeroizing::new(format!("{}{}", BYTES_PREFIX, STANDARD.encode(secret)));
+    set(SERVICE, account, &encoded)
+}
+
+/// Binary secret stored with `save_secret_bytes`; `None` if there is none.
+/// A value from before `BYTES_PREFIX`, plain base64, is rewritten in the
+/// current format the first time it is read.
+pub fn get_secret_bytes(account: &str) -> Result<Option<Zeroizing<Vec<u8>>>, KeychainError> {
+    decode_secret_bytes(account, get(SERVICE, account)?)
+}
+
+fn decode_secret_bytes(
+    account: &str,
+    stored: Option<Zeroizing<String>>,
+) -> Result<Option<Zeroizing<Vec<u8>>>, KeychainError> {
+    let Some(stored) = stored else {
+        return Ok(None);
+    };
+    if let Some(encoded) = stored.strip_prefix(BYTES_PREFIX) {
+        let secret = STANDARD.decode(encoded).map_err(|_| KeychainError::CorruptSecret)?;
+        return Ok(Some(Zeroizing::new(secret)));
+    }
+    let secret = Zeroizing::new(STANDARD.decode(stored.as_bytes()).map_err(|_| KeychainError::CorruptSecret)?);
+    if let Err(e) = save_secret_bytes(account, &secret) {
+        eprintln!("Failed to rewrite keychain secret in the current format: {}", e);
+    }
+    Ok(Some(secret))
+}
+
+/// Text stored for `account` under `service`; `None` if there is none
+pub fn get(service: &str, account: &str) -> Result<Option<Zeroizing<String>>, KeychainError> {
+    with_store(|store| read(store, service, account))
+}
+
+/// Store text `secret` for `account` under `service`. Each entry written
+/// is read back, and one that does not match is deleted again.
+pub fn set(service: &str, account: &str, secret: &str) -> Result<(), KeychainError> {
+    let backend = with_store(|store| write(store, service, account, secret).map(|()| store.backend()))?;
+    record_change(KeychainOperation::Write, service, account, backend);
+    Ok(())
+}
+
+/// Delete a keychain entry; `Ok(false)` if there was none
+pub fn delete(service: &str, account: &str) -> Result<bool, KeychainError> {
+    let (deleted, backend) = with_store(|store| Ok((remove(store, service, account)?, store.backend())))?;
+    if deleted {
+        record_change(KeychainOperation::Delete, service, account, backend);
+    }
+    Ok(deleted)
+}
+
+/// Where `record_change` writes, set by `init_audit`
+static AUDIT_APP: OnceLock<AppHandle> = OnceLock::new();
+
+/// Record writes and deletes in the audit log of `app` from now on
+pub fn init_audit(app: AppHandle) {
+    let _ = AUDIT_APP.set(app);
+}
+
+thread_local! {
+    /// Command the keychain work on this thread is done for
+    static COMMAND: Cell<Option<&'static str>> = const { Cell::new(None) };
+}
+
+/// Run `op`, attributing its keychain changes to `command` in the audit log
+pub fn triggered_by<T>(command: &'static str, op: impl FnOnce() -> T) -> T {
+    let previous = COMMAND.with(|current| current.replace(Some(command)));
+    let result = op();
+    COMMAND.with(|current| current.set(previous));
+    result
+}
+
+fn record_change(operation: KeychainOperation, service: &str, account: &str, backend: KeychainBackend) {
+    let Some(app) = AUDIT_APP.get() else {
+        return;
+    };
+    let vault_secret = if service == SERVICE { VaultSecret::parse_account(account) } else { None };
+    let (vault_id, secret) = vault_secret.map_or((None, None), |(id, secret)| (Some(id), Some(secret)));
+    let change = KeychainChange {
+        at: Utc::now(),
+        operation,
+        service: service.strip_prefix(NAMESPACE).unwrap_or(service).to_string(),
+        vault_id,
+        secret,
+        backend,
+        command: COMMAND.with(Cell::get).map(str::to_string),
+    };
+    if let Err(e) = audit::record_keychain_change(app, &change) {
+        eprintln!("Failed to record keychain change: {}", e);
+    }
+}
+
+fn chunk_account(account: &str, index: usize) -> String {
+    format!("{}#chunk{}", account, index)
+}
+
+/// The chunk count if `value` is the index of a secret split into chunks
+fn chunk_count(value: &str) -> Result<Option<usize>, KeychainError> {
+    let Some(count) = value.strip_prefix(CHUNKS_PREFIX) else {
+        return Ok(None);
+    };
+    match count.parse() {
+        Ok(count) if count <= MAX_CHUNKS => Ok(Some(count)),
+        _ => Err(KeychainError::CorruptSecret),
+    }
+}
+
+/// `secret` cut into pieces of at most `CHUNK_LEN` bytes, on character
+/// boundaries
+fn chunks(secret: &str) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut rest = secret;
+    while rest.len() > CHUNK_LEN {
+        let mut end = CHUNK_LEN;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let (chunk, tail) = rest.split_at(end);
+        chunks.push(chunk);
+        rest = tail;
+    }
+    chunks.push(rest);
+    chunks
+}
+
+fn read(store: &dyn SecretStore, service: &str, account: &str) -> Result<Option<Zeroizing<String>>, KeychainError> {
+    let Some(value) = store.get(service, account)? else {
+        return Ok(None);
+    };
+    let Some(count) = chunk_count(&value)? else {
+        return Ok(Some(value));
+    };
+    // Reserved up front so the secret is never reallocated unscrubbed
+    let mut secret = Zeroizing::new(String::with_capacity(count * CHUNK_LEN));
+    for index in 0..count {
+        let chunk = store
+            .get(service, &chunk_account(account, index))?
+            .ok_or(KeychainError::CorruptSecret)?;
+        secret.push_str(&chunk);
+    }
+    Ok(Some(secret))
+}
+
+/// Write `secret` to one entry, or to chunks and an index when it is too
+/// long, and delete chunks a longer previous secret left over
+fn write(store: &dyn SecretStore, service: &str, account: &str, secret: &str) -> Result<(), KeychainError> {
+    let chunks = chunks(secret);
+    if chunks.len() > MAX_CHUNKS {
+        return Err(KeychainError::PlatformError("The secret is too large for the keychain".to_string()));
+    }
+    let old_count = match store.get(service, account)? {
+        Some(old) => chunk_count(&old).ok().flatten().unwrap_or(0),
+        None => 0,
+    };
+    let count = if chunks.len() == 1 {
+        write_verified(store, service, account, secret)?;
+        0
+    } else {
+        for (index, chunk) in chunks.iter().enumerate() {
+            write_verified(store, service, &chunk_account(account, index), chunk)?;
+        }
+        write_verified(store, service, account, &format!("{}{}", CHUNKS_PREFIX, chunks.len()))?;
+        chunks.len()
+    };
+    for index in count..old_count {
+        store.delete(service, &chunk_account(account, index))?;
+    }
+    Ok(())
+}
+
+/// Write one entry and read it back. An entry that does not match, like
+/// one the platform truncated, is deleted.
+fn write_verified(store: &dyn SecretStore, service: &str, account: &str, secret: &str) -> Result<(), KeychainError> {
+    store.set(service, account, secret)?;
+    let stored = store.get(service, account)?;
+    if stored.as_ref().map(|stored| stored.as_str()) == Some(secret) {
+        return Ok(());
+    }
+    if let Err(e) = store.delete(service, account) {
+        eprintln!("Failed to delete keychain entry that did not verify: {}", e);
+    }
+    Err(KeychainError::VerificationFailed {
+        expected: secret.len(),
+        stored: stored.map_or(0, |stored| stored.len()),
+    })
+}
+
+fn remove(store: &dyn SecretStore, service: &str, account: &str) -> Result<bool, KeychainError> {
+    if let Some(value) = store.get(service, account)? {
+        for index in 0..chunk_count(&value).ok().flatten().unwrap_or(0) {
+            store.delete(service, &chunk_account(account, index))?;
+        }
+    }
+    store.delete(service, account)
+}
+
+/// Delete every secret SafeNode keeps for the vault with `keychain_id`,
+/// continuing past failures. Returns the errors encountered.
+pub fn delete_vault_secrets(keychain_id: &str) -> Vec<KeychainError> {
+    VaultSecret::ALL
+        .iter()
+        .filter_map(|secret| delete(SERVICE, &secret.account(keychain_id)).err())
+        .collect()
+}
+
+/// Move unlock secrets stored under raw names to `VaultSecret::UnlockSecret`
+/// of the default vault, whose `storage::keychain_id` is `default_vault`.
+/// A secret already there is kept and the raw entry is still deleted.
+pub fn migrate_legacy_entries(default_vault: &str) -> Result<(), KeychainError> {
+    for (service, account) in LEGACY_UNLOCK_SECRETS {
+        let Some(secret) = get(service, account)? else {
+            continue;
+        };
+        let target = VaultSecret::UnlockSecret.account(default_vault);
+        if get_secret_bytes(&target)?.is_none() {
+            save_secret_bytes(&target, secret.as_bytes())?;
+        }
+        delete(service, account)?;
+    }
+    Ok(())
+}
+
+/// `Settings::keychain_timeout_seconds`
+static TIMEOUT_SECS: AtomicU64 = AtomicU64::new(DEFAULT_TIMEOUT.as_secs());
+
+pub fn set_timeout(timeout: Duration) {
+    TIMEOUT_SECS.store(timeout.as_secs(), Ordering::Relaxed);
+}
+
+fn timeout() -> Duration {
+    Duration::from_secs(TIMEOUT_SECS.load(Ordering::Relaxed))
+}
+
+/// Run keychain work `op` for `command` on its own thread and wait for it
+/// off the async runtime. After the timeout the caller gets
+/// `KeychainError::Timeout` and the thread is left to finish, since an OS
+/// prompt cannot be withdrawn.
+pub async fn blocking<T, E>(command: &'static str, op: impl FnOnce() -> Result<T, E> + Send + 'static) -> Result<T, E>
+where
+    T: Send + 'static,
+    E: From<KeychainError> + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = sender.send(triggered_by(command, op));
+    });
+    let timeout = timeout();
+    let received = tauri::async_runtime::spawn_blocking(move || receiver.recv_timeout(timeout))
+        .await
+        .map_err(|e| KeychainError::PlatformError(e.to_string()))?;
+    match received {
+        Ok(result) => result,
+        Err(RecvTimeoutError::Timeout) => Err(KeychainError::Timeout.into()),
+        Err(RecvTimeoutError::Disconnected) => {
+            Err(KeychainError::PlatformError("Keychain operation ended without a result".to_string()).into())
+        }
+    }
+}
+
+type ReadResult = Result<Option<Zeroizing<String>>, KeychainError>;
+
+/// A read under way, whose result goes to everyone waiting on it
+#[derive(Default)]
+struct PendingRead {
+    result: Mutex<Option<ReadResult>>,
+    done: Condvar,
+}
+
+impl PendingRead {
+    fn finish(&self, result: ReadResult) {
+        *self.result.lock().unwrap() = Some(result);
+        self.done.notify_all();
+    }
+
+    /// The result, or `None` if it is not in after `timeout`
+    fn wait(&self, timeout: Duration) -> Option<ReadResult> {
+        let result = self.result.lock().unwrap();
+        let (result, _) = self.done.wait_timeout_while(result, timeout, |result| result.is_none()).unwrap();
+        result.clone()
+    }
+}
+
+/// Reads under way, by service and account
+type PendingReads = Mutex<HashMap<(String, String), Arc<PendingRead>>>;
+
+fn pending_reads() -> &'static PendingReads {
+    static PENDING_READS: OnceLock<PendingReads> = OnceLock::new();
+    PENDING_READS.get_or_init(Default::default)
+}
+
+/// `get` without blocking the caller. Reads of an entry that is already
+/// being read wait for that read, so the OS asks for access only once,
+/// even for callers that timed out and retried.
+pub async fn get_async(service: &str, account: &str) -> ReadResult {
+    let entry = (service.to_string(), account.to_string());
+    let (pending, started) = {
+        let mut reads = pending_reads().lock().unwrap();
+        match reads.get(&entry) {
+            Some(pending) => (pending.clone(), false),
+            None => {
+                let pending = Arc::new(PendingRead::default());
+                reads.insert(entry.clone(), pending.clone());
+                (pending, true)
+            }
+        }
+    };
+    if started {
+        let task = pending.clone();
+        std::thread::spawn(move || {
+            let result = get(&entry.0, &entry.1);
+            pending_reads().lock().unwrap().remove(&entry);
+            task.finish(result);
+        });
+    }
+    let timeout = timeout();
+    tauri::async_runtime::spawn_blocking(move || pending.wait(timeout))
+        .await
+        .map_err(|e| KeychainError::PlatformError(e.to_string()))?
+        .unwrap_or(Err(KeychainError::Timeout))
+}
+
+/// `set` for `command` without blocking the caller
+pub async fn set_async(
+    command: &'static str,
+    service: String,
+    account: String,
+    secret: Zeroizing<String>,
+) -> Result<(), KeychainError> {
+    blocking(command, move || set(&service, &account, &secret)).await
+}
+
+/// `delete` for `command` without blocking the caller
+pub async fn delete_async(command: &'static str, service: String, account: String) -> Result<bool, KeychainError> {
+    blocking(command, move || delete(&service, &account)).await
+}
+
+/// Whether a keychain entry exists, without handing out its secret or
+/// blocking the caller
+pub async fn exists_async(service: &str, account: &str) -> Result<bool, KeychainError> {
+    Ok(get_async(service, account).await?.is_some())
+}
+
+/// `get_secret_bytes` for `command` without blocking the caller
+pub async fn get_secret_bytes_async(
+    command: &'static str,
+    account: String,
+) -> Result<Option<Zeroizing<Vec<u8>>>, KeychainError> {
+    let stored = get_async(SERVICE, &account).await?;
+    blocking(command, move || decode_secret_bytes(&account, stored)).await
+}
+
+/// `backend_info` without blocking the caller. A probe that times out is
+/// reported as the OS keychain failing with `KeychainError::Timeout`.
+pub async fn backend_info_async() -> BackendInfo {
+    blocking("keychain_backend_info", || Ok::<_, KeychainError>(backend_info()))
+        .await
+        .unwrap_or_else(|e| BackendInfo::new(OsKeychain.backend(), Some(e)))
+}
+
+/// Rewrite SafeNode's OS keychain entries under the current
+/// `sync_policy`, continuing past failures. Returns the errors
+/// encountered.
+#[cfg(target_os = "macos")]
+pub fn apply_sync_policy() -> Vec<KeychainError> {
+    if FILE_STORE.get().is_some_and(|file| file.in_use()) {
+        return Vec::new();
+    }
+    macos::rewrite(sync_policy() == SyncPolicy::AllowSync)
+}
+
+/// Only the macOS keychain has a sync policy to apply
+#[cfg(not(target_os = "macos"))]
+pub fn apply_sync_policy() -> Vec<KeychainError> {
+    Vec::new()
+}
+
+/// Collections over the Secret Service D-Bus API, which `keyring` does not
+/// expose: unlocking them and creating SafeNode's own
+#[cfg(all(target_os = "linux", feature = "dbus"))]
+mod secret_service {
+    use std::collections::HashMap;
+    use zbus::blocking::{Connection, Proxy};
+    use zbus::zvariant::{OwnedObjectPath, OwnedValue, Value};
+
+    use super::KeychainError;
+
+    const SECRETS: &str = "org.freedesktop.secrets";
+    const SERVICE_PATH: &str = "/org/freedesktop/secrets";
+    const SERVICE_INTERFACE: &str = "org.freedesktop.Secret.Service";
+    const COLLECTION_INTERFACE: &str = "org.freedesktop.Secret.Collection";
+    const PROMPT_INTERFACE: &str = "org.freedesktop.Secret.Prompt";
+    const LABEL_PROPERTY: &str = "org.freedesktop.Secret.Collection.Label";
+    /// Stands for no prompt, and for no collection from `ReadAlias`
+    const NO_OBJECT: &str = "/";
+
+    fn error(error: impl Into<zbus::Error>) -> KeychainError {
+        match error.into() {
+            zbus::Error::FDO(e) if matches!(*e, zbus::fdo::Error::ServiceUnknown(_)) => KeychainError::NoBackend,
+            zbus::Error::MethodError(name, _, _) if name.as_str() == "org.freedesktop.DBus.Error.ServiceUnknown" => {
+                KeychainError::NoBackend
+            }
+            e => KeychainError::PlatformError(e.to_string()),
+        }
+    }
+
+    fn proxy(
+        connection: &Connection,
+        path: OwnedObjectPath,
+        interface: &'static str,
+    ) -> Result<Proxy<'static>, KeychainError> {
+        Proxy::new(connection, SECRETS, path.into_inner(), interface).map_err(error)
+    }
+
+    /// Show `prompt` unless there is none; `false` if the user dismissed it
+    fn run_prompt(connection: &Connection, prompt: OwnedObjectPath) -> Result<bool, KeychainError> {
+        if prompt.as_str() == NO_OBJECT {
+            return Ok(true);
+        }
+        let prompt = proxy(connection, prompt, PROMPT_INTERFACE)?;
+        // Subscribed before prompting, so the answer cannot be missed
+        let mut completed = prompt.receive_signal("Completed").map_err(error)?;
+        prompt.call::<_, _, ()>("Prompt", &("",)).map_err(error)?;
+        let message = completed
+            .next()
+            .ok_or_else(|| KeychainError::PlatformError("The keyring prompt ended without an answer".to_string()))?;
+        let (dismissed, _): (bool, OwnedValue) = message.body().map_err(error)?;
+        Ok(!dismissed)
+    }
+
+    fn find_collection(connection: &Connection, label: &str) -> Result<Option<OwnedObjectPath>, KeychainError> {
+        let service = Proxy::new(connection, SECRETS, SERVICE_PATH, SERVICE_INTERFACE).map_err(error)?;
+        let collections: Vec<OwnedObjectPath> = service.get_property("Collections").map_err(error)?;
+        for path in collections {
+            let collection = proxy(connection, path.clone(), COLLECTION_INTERFACE)?;
+            if collection.get_property::<String>("Label").map_err(error)? == label {
+                return Ok(Some(path));
+            }
+        }
+        Ok(None)
+    }
+
+    /// The collection labelled `label`, created if there is none, or the
+    /// default collection
+    fn collection(connection: &Connection, label: Option<&str>) -> Result<OwnedObjectPath, KeychainError> {
+        let service = Proxy::new(connection, SECRETS, SERVICE_PATH, SERVICE_INTERFACE).map_err(error)?;
+        let Some(label) = label else {
+            let path: OwnedObjectPath = service.call("ReadAlias", &("default",)).map_err(error)?;
+            if path.as_str() == NO_OBJECT {
+                return Err(KeychainError::PlatformError("There is no default keyring".to_string()));
+            }
+            return Ok(path);
+        };
+        if let Some(path) = find_collection(connection, label)? {
+            return Ok(path);
+        }
+        let properties = HashMap::from([(LABEL_PROPERTY, Value::from(label))]);
+        let (path, prompt): (OwnedObjectPath, OwnedObjectPath) =
+            service.call("CreateCollection", &(properties, "")).map_err(error)?;
+        if path.as_str() != NO_OBJECT {
+            return Ok(path);
+        }
+        if !run_prompt(connection, prompt)? {
+            return Err(KeychainError::AccessDenied);
+        }
+        find_collection(connection, label)?
+            .ok_or_else(|| KeychainError::PlatformError("The keyring was not created".to_string()))
+    }
+
+    /// Make sure the collection labelled `label`, or the default one, exists
+    /// and is unlocked, asking the user to unlock it if needed. `false` if
+    /// they declined. Blocks until they answer.
+    pub fn unlock(label: Option<&str>) -> Result<bool, KeychainError> {
+        let connection = Connection::session().map_err(error)?;
+        let path = collection(&connection, label)?;
+        if !proxy(&connection, path.clone(), COLLECTION_INTERFACE)?
+            .get_property::<bool>("Locked")
+            .map_err(error)?
+        {
+            return Ok(true);
+        }
+        let service = Proxy::new(&connection, SECRETS, SERVICE_PATH, SERVICE_INTERFACE).map_err(error)?;
+        let (_, prompt): (Vec<OwnedObjectPath>, OwnedObjectPath) =
+            service.call("Unlock", &(vec![path],)).map_err(error)?;
+        run_prompt(&connection, prompt)
+    }
+}
+
+/// Generic password items through `SecItem*`. Queries match synchronizable
+/// and local items alike; writes create the item in the data protection
+/// keychain with `kSecAttrSynchronizable` set when syncing is allowed, and
+/// in the login keychain, where `keyring` put them, otherwise.
+#[cfg(target_os = "macos")]
+mod macos {
+    use core_foundation::array::{CFArray, CFArrayRef};
+    use core_foundation::base::{CFType, CFTypeRef, TCFType};
+    use core_foundation::boolean::CFBoolean;
+    use core_foundation::data::{CFData, CFDataRef};
+    use core_foundation::dictionary::{CFDictionary, CFDictionaryRef};
+    use core_foundation::string::{CFString, CFStringRef};
+    use security_framework_sys::item::{
+        kSecAttrAccount, kSecAttrService, kSecAttrSynchronizable, kSecAttrSynchronizableAny, kSecClass,
+        kSecClassGenericPassword, kSecMatchLimit, kSecMatchLimitAll, kSecMatchLimitOne, kSecReturnAttributes,
+        kSecReturnData, kSecUseDataProtectionKeychain, kSecValueData,
+    };
+    use security_framework_sys::keychain_item::{SecItemAdd, SecItemCopyMatching, SecItemDelete, SecItemUpdate};
+    use std::ptr;
+    use zeroize::Zeroizing;
+
+    use super::{KeychainBackend, KeychainError, NAMESPACE};
+    use crate::audit::KeychainOperation;
+
+    // `OSStatus` codes
+    const ERR_SEC_SUCCESS: i32 = 0;
+    const ERR_SEC_USER_CANCELED: i32 = -128;
+    const ERR_SEC_AUTH_FAILED: i32 = -25293;
+    const ERR_SEC_NO_SUCH_KEYCHAIN: i32 = -25294;
+    const ERR_SEC_ITEM_NOT_FOUND: i32 = -25300;
+    const ERR_SEC_INTERACTION_NOT_ALLOWED: i32 = -25308;
+
+    fn key(name: CFStringRef) -> CFType {
+        unsafe { CFString::wrap_under_get_rule(name) }.as_CFType()
+    }
+
+    fn error(status: i32) -> KeychainError {
+        match status {
+            ERR_SEC_USER_CANCELED | ERR_SEC_AUTH_FAILED => KeychainError::AccessDenied,
+            ERR_SEC_INTERACTION_NOT_ALLOWED => KeychainError::Locked,
+            ERR_SEC_NO_SUCH_KEYCHAIN => KeychainError::NoBackend,
+            status => KeychainError::PlatformError(security_framework::base::Error::from_code(status).to_string()),
+        }
+    }
+
+    /// Generic password items, further narrowed by `extra`
+    fn query(
+        service: Option<&str>,
+        account: Option<&str>,
+        extra: &[(CFStringRef, CFType)],
+    ) -> CFDictionary<CFType, CFType> {
+        let mut pairs = unsafe { vec![(key(kSecClass), key(kSecClassGenericPassword))] };
+        if let Some(service) = service {
+            pairs.push((unsafe { key(kSecAttrService) }, CFString::new(service).as_CFType()));
+        }
+        if let Some(account) = account {
+            pairs.push((unsafe { key(kSecAttrAccount) }, CFString::new(account).as_CFType()));
+        }
+        pairs.extend(extra.iter().map(|(name, value)| (key(*name), value.clone())));
+        CFDictionary::from_CFType_pairs(&pairs)
+    }
+
+    fn any_sync() -> (CFStringRef, CFType) {
+        unsafe { (kSecAttrSynchronizable, key(kSecAttrSynchronizableAny)) }
+    }
+
+    pub fn get(service: &str, account: &str) -> Result<Option<Zeroizing<String>>, KeychainError> {
+        let query = query(
+            Some(service),
+            Some(account),
+            &[
+                any_sync(),
+                unsafe { (kSecReturnData, CFBoolean::true_value().as_CFType()) },
+                unsafe { (kSecMatchLimit, key(kSecMatchLimitOne)) },
+            ],
+        );
+        let mut result: CFTypeRef = ptr::null();
+        match unsafe { SecItemCopyMatching(query.as_concrete_TypeRef(), &mut result) } {
+            ERR_SEC_SUCCESS => {}
+            ERR_SEC_ITEM_NOT_FOUND => return Ok(None),
+            status => return Err(error(status)),
+        }
+        let data = unsafe { CFData::wrap_under_create_rule(result as CFDataRef) };
+        let secret = std::str::from_utf8(data.bytes()).map_err(|_| KeychainError::CorruptSecret)?;
+        Ok(Some(Zeroizing::new(secret.to_string())))
+    }
+
+    /// Replace the secret of an item with the same sync setting in place;
+    /// otherwise delete any item for the entry and add a new one
+    pub fn set(service: &str, account: &str, secret: &str, allow_sync: bool) -> Result<(), KeychainError> {
+        let sync = unsafe { (kSecAttrSynchronizable, CFBoolean::from(allow_sync).as_CFType()) };
+        let data = CFData::from_buffer(secret.as_bytes()).as_CFType();
+        let existing = query(Some(service), Some(account), &[sync.clone()]);
+        let update = CFDictionary::from_CFType_pairs(&[(unsafe { key(kSecValueData) }, data.clone())]);
+        match unsafe { SecItemUpdate(existing.as_concrete_TypeRef(), update.as_concrete_TypeRef()) } {
+            ERR_SEC_SUCCESS => return Ok(()),
+            ERR_SEC_ITEM_NOT_FOUND => {}
+            status => return Err(error(status)),
+        }
+        delete(service, account)?;
+        let mut attributes = vec![sync, unsafe { (kSecValueData, data) }];
+        if allow_sync {
+            attributes.push(unsafe { (kSecUseDataProtectionKeychain, CFBoolean::true_value().as_CFType()) });
+        }
+        let item = query(Some(service), Some(account), &attributes);
+        match unsafe { SecItemAdd(item.as_concrete_TypeRef(), ptr::null_mut()) } {
+            ERR_SEC_SUCCESS => Ok(()),
+            status => Err(error(status)),
+        }
+    }
+
+    pub fn delete(service: &str, account: &str) -> Result<bool, KeychainError> {
+        let query = query(Some(service), Some(account), &[any_sync()]);
+        match unsafe { SecItemDelete(query.as_concrete_TypeRef()) } {
+            ERR_SEC_SUCCESS => Ok(true),
+            ERR_SEC_ITEM_NOT_FOUND => Ok(false),
+            status => Err(error(status)),
+        }
+    }
+
+    /// Write every item in SafeNode's namespace again with `allow_sync`
+    pub fn rewrite(allow_sync: bool) -> Vec<KeychainError> {
+        let entries = match entries() {
+            Ok(entries) => entries,
+            Err(e) => return vec![e],
+        };
+        entries
+            .iter()
+            .filter(|(service, _)| service.starts_with(NAMESPACE))
+            .filter_map(|(service, account)| match get(service, account) {
+                Ok(Some(secret)) => match set(service, account, &secret, allow_sync) {
+                    Ok(()) => {
+                        super::record_change(KeychainOperation::Write, service, account, KeychainBackend::Keychain);
+                        None
+                    }
+                    Err(e) => Some(e),
+                },
+                Ok(None) => None,
+                Err(e) => Some(e),
+            })
+            .collect()
+    }
+
+    /// Service and account of every generic password item SafeNode can see
+    fn entries() -> Result<Vec<(String, String)>, KeychainError> {
+        let query = query(
+            None,
+            None,
+            &[
+                any_sync(),
+                unsafe { (kSecReturnAttributes, CFBoolean::true_value().as_CFType()) },
+                unsafe { (kSecMatchLimit, key(kSecMatchLimitAll)) },
+            ],
+        );
+        let mut result: CFTypeRef = ptr::null();
+        match unsafe { SecItemCopyMatching(query.as_concrete_TypeRef(), &mut result) } {
+            ERR_SEC_SUCCESS => {}
+            ERR_SEC_ITEM_NOT_FOUND => return Ok(Vec::new()),
+            status => return Err(error(status)),
+        }
+        let items = unsafe { CFArray::<CFType>::wrap_under_create_rule(result as CFArrayRef) };
+        let attribute = |item: &CFDictionary, name: CFStringRef| {
+            item.find(name.cast())
+                .map(|value| unsafe { CFString::wrap_under_get_rule(value.cast()) }.to_string())
+        };
+        Ok(items
+            .iter()
+            .filter_map(|item| {
+                let item = unsafe { CFDictionary::wrap_under_get_rule(item.as_CFTypeRef() as CFDictionaryRef) };
+                Some((attribute(&item, unsafe { kSecAttrService })?, attribute(&item, unsafe { kSecAttrAccount })?))
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Secrets in memory, standing in for the OS keychain
+    #[derive(Default)]
+    struct MemoryStore {
+        entries: Mutex<HashMap<(String, String), String>>,
+    }
+
+    impl SecretStore for MemoryStore {
+        fn get(&self, service: &str, account: &str) -> Result<Option<Zeroizing<String>>, KeychainError> {
+            let entries = self.entries.lock().unwrap();
+            Ok(entries.get(&(service.to_string(), account.to_string())).cloned().map(Zeroizing::new))
+        }
+
+        fn set(&self, service: &str, account: &str, secret: &str) -> Result<(), KeychainError> {
+            let mut entries = self.entries.lock().unwrap();
+            entries.insert((service.to_string(), account.to_string()), secret.to_string());
+            Ok(())
+        }
+
+        fn delete(&self, service: &str, account: &str) -> Result<bool, KeychainError> {
+            let mut entries = self.entries.lock().unwrap();
+            Ok(entries.remove(&(service.to_string(), account.to_string())).is_some())
+        }
+
+        fn backend(&self) -> KeychainBackend {
+            KeychainBackend::None
+        }
+    }
+
+    impl MemoryStore {
+        fn len(&self) -> usize {
+            self.entries.lock().unwrap().len()
+        }
+    }
+
+    #[test]
+    fn deleting_a_missing_entry_is_not_an_error() {
+        let store = MemoryStore::default();
+        assert_eq!(remove(&store, SERVICE, "nothing"), Ok(false));
+
+        write(&store, SERVICE, "account", "secret").unwrap();
+        assert_eq!(remove(&store, SERVICE, "account"), Ok(true));
+        assert_eq!(read(&store, SERVICE, "account"), Ok(None));
+        assert_eq!(remove(&store, SERVICE, "account"), Ok(false));
+    }
+
+    #[test]
+    fn deleting_a_chunked_secret_removes_every_chunk() {
+        let store = MemoryStore::default();
+        let long = "é".repeat(CHUNK_LEN * 2);
+        write(&store, SERVICE, "account", &long).unwrap();
+        assert!(store.len() > 2);
+        assert_eq!(read(&store, SERVICE, "account").unwrap().as_deref().map(String::as_str), Some(long.as_str()));
+
+        assert_eq!(remove(&store, SERVICE, "account"), Ok(true));
+        assert_eq!(store.len(), 0);
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    #[test]
+    fn keyring_no_entry_reads_as_absent() {
+        keyring::set_default_credential_builder(keyring::mock::default_credential_builder());
+        assert_eq!(remove_entry(SERVICE, "nothing", false), Ok(false));
+        assert_eq!(read_entry(SERVICE, "nothing", false), Ok(None));
+        assert_eq!(KeychainError::from(keyring::Error::NoEntry), KeychainError::NotFound);
+    }
+
+    #[test]
+    fn frontend_services_stay_out_of_safenodes_own() {
+        assert_eq!(namespaced("tokens").unwrap(), "com.safenode.tokens");
+        assert!(namespaced("vault").is_err());
+        assert!(namespaced("../escape").is_err());
+        assert!(check_account("bad\naccount").is_err());
+    }
+
+    #[test]
+    fn each_vault_has_its_own_accounts() {
+        let (personal, work) = (uuid::Uuid::new_v4().to_string(), uuid::Uuid::new_v4().to_string());
+        let mut accounts: Vec<String> = [&personal, &work]
+            .iter()
+            .flat_map(|id| VaultSecret::ALL.map(|secret| secret.account(id)))
+            .collect();
+        for account in &accounts {
+            let (id, secret) = VaultSecret::parse_account(account).unwrap();
+            assert_eq!(secret.account(&id), *account);
+        }
+        accounts.sort();
+        accounts.dedup();
+        assert_eq!(accounts.len(), VaultSecret::ALL.len() * 2);
+    }
+
+    #[test]
+    fn deleting_one_vault_leaves_the_other() {
+        let store = MemoryStore::default();
+        let (personal, work) = (uuid::Uuid::new_v4().to_string(), uuid::Uuid::new_v4().to_string());
+        for id in [&personal, &work] {
+            for secret in VaultSecret::ALL {
+                write(&store, SERVICE, &secret.account(id), &format!("{} {:?}", id, secret)).unwrap();
+            }
+        }
+
+        for secret in VaultSecret::ALL {
+            assert_eq!(remove(&store, SERVICE, &secret.account(&personal)), Ok(true));
+        }
+        for secret in VaultSecret::ALL {
+            assert_eq!(read(&store, SERVICE, &secret.account(&personal)), Ok(None));
+            let kept = read(&store, SERVICE, &secret.account(&work)).unwrap().unwrap();
+            assert_eq!(*kept, format!("{} {:?}", work, secret));
+        }
+        assert_eq!(store.len(), VaultSecret::ALL.len());
+    }
+}
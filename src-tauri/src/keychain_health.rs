@@ -0,0 +1,108 @@
+/*!
+ * Keychain Health
+ * Checks SafeNode's keychain secrets against the vaults they are filed under
+ */
+
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::biometric_unlock;
+use crate::crypto::{self, VaultKey};
+use crate::error::VaultError;
+use crate::keychain::{self, KeychainError, VaultSecret};
+use crate::pin;
+use crate::storage;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SecretHealth {
+    Ok,
+    /// Left behind by a quick-unlock method that is no longer set up
+    Orphaned,
+    /// Does not decode
+    Corrupt,
+    /// Decodes, but does not open the vault it is filed under
+    BelongsToOtherVault,
+}
+
+/// One secret found in the keychain
+#[derive(Debug, Clone, Serialize)]
+pub struct SecretReport {
+    pub vault: String,
+    pub secret: VaultSecret,
+    pub health: SecretHealth,
+    /// Keychain account the secret is stored under
+    #[serde(skip)]
+    account: String,
+}
+
+/// Every SafeNode secret present for the vaults on disk. `unlocked` is the
+/// name and data key of an unlocked vault, whose software biometric key is
+/// also checked against that key. `keyring` cannot list entries, so
+/// secrets of vaults whose files are gone are not found.
+pub fn check(app: &AppHandle, unlocked: Option<(&str, &VaultKey)>) -> Result<Vec<SecretReport>, VaultError> {
+    let mut report = Vec::new();
+    for vault in storage::list_vault_names(app)? {
+        let keychain_id = storage::keychain_id(app, &vault)?;
+        for secret in VaultSecret::ALL {
+            let account = secret.account(&keychain_id);
+            let health = match keychain::get_secret_bytes(&account) {
+                Ok(None) => continue,
+                Ok(Some(_)) => health_of(app, &vault, secret, unlocked)?,
+                Err(KeychainError::CorruptSecret) => SecretHealth::Corrupt,
+                Err(e) => return Err(e.into()),
+            };
+            report.push(SecretReport {
+                vault: vault.clone(),
+                secret,
+                health,
+                account,
+            });
+        }
+    }
+    Ok(report)
+}
+
+fn health_of(
+    app: &AppHandle,
+    vault: &str,
+    secret: VaultSecret,
+    unlocked: Option<(&str, &VaultKey)>,
+) -> Result<SecretHealth, VaultError> {
+    let health = match secret {
+        VaultSecret::PinPepper if pin::load(app, vault)?.is_none() => SecretHealth::Orphaned,
+        VaultSecret::BiometricKey
+            if !biometric_unlock::is_enabled(app, vault) || biometric_unlock::is_hardware_backed(app, vault) =>
+        {
+            SecretHealth::Orphaned
+        }
+        VaultSecret::BiometricKey => match unlocked {
+            // A hardware-backed key would prompt, but those were ruled out above
+            Some((name, key)) if name == vault => match biometric_unlock::release(app, vault) {
+                Ok(released) if crypto::bytes_equal(&released[..], &key[..]) => SecretHealth::Ok,
+                Ok(_) | Err(VaultError::BiometricUnlockNotEnabled) => SecretHealth::BelongsToOtherVault,
+                Err(e) => return Err(e),
+            },
+            _ => SecretHealth::Ok,
+        },
+        _ => SecretHealth::Ok,
+    };
+    Ok(health)
+}
+
+/// Delete the secrets `report` found corrupt or filed under the wrong
+/// vault, and orphaned ones when `delete_orphans`. Returns those deleted.
+pub fn repair(report: &[SecretReport], delete_orphans: bool) -> Result<Vec<SecretReport>, VaultError> {
+    let mut deleted = Vec::new();
+    for entry in report {
+        let delete = match entry.health {
+            SecretHealth::Ok => false,
+            SecretHealth::Orphaned => delete_orphans,
+            SecretHealth::Corrupt | SecretHealth::BelongsToOtherVault => true,
+        };
+        if delete && keychain::delete(keychain::SERVICE, &entry.account)? {
+            deleted.push(entry.clone());
+        }
+    }
+    Ok(deleted)
+}
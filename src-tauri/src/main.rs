@@ -1,129 +1,2804 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use std::sync::Mutex;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
 use std::time::Instant;
-use tauri::{command, State, Window, Manager, AppHandle};
-use keyring::Entry;
+use tauri::{command, State, Window, Manager, AppHandle, Runtime};
 
+mod attachments;
+mod audit;
+mod biometric_prompt;
+mod biometric_unlock;
 mod biometrics;
+mod breach;
+mod clipboard;
+mod crypto;
+mod error;
+mod format;
+mod generator;
+mod icons;
+mod items;
+mod keychain;
+mod keychain_health;
+mod migrations;
+mod passphrase;
+mod pin;
+mod power;
+mod reauth;
+mod recovery;
+mod search;
+mod secret_file;
+mod secure_key;
+mod settings;
+mod soft_lock;
+mod storage;
+mod strength;
+mod templates;
+mod throttle;
+mod totp;
+mod unlock_log;
+mod urls;
+mod vault;
 
-// Note: For production biometric authentication on desktop:
-// - macOS: Use LocalAuthentication framework via Objective-C/Swift bridge or a crate like `localauth`
-// - Windows: Use Windows Hello APIs (Windows.Security.Credentials.UI)
-// - Linux: Use fprintd or other biometric services
-// For now, we provide placeholder implementations that return success for demo purposes
+use attachments::AttachmentInfo;
+use audit::{AuditCategory, AuditEvent, SecurityChange};
+use biometric_prompt::{BiometricPromptContext, PromptOperation};
+use biometrics::{AuthMethod, AuthenticatorSource, BiometricError, BiometricResult, CancelToken};
+use breach::BreachCheck;
+use keychain::{KeychainError, VaultSecret};
+use reauth::{ApprovedWith, ExportApproval, ReauthCredential, TokenScope};
+use generator::{GeneratorOptions, GeneratorPolicy};
+use error::{FieldError, VaultError};
+use uuid::Uuid;
+use zeroize::Zeroizing;
+use settings::Settings;
+use strength::PasswordStrength;
+use templates::{EntryTemplate, TemplateFields};
+use unlock_log::UnlockEvent;
+use vault::{
+    BulkAction, EntryFull, EntryInput, EntrySort, EntrySummary, Folder, GeneratorHistoryItem, ItemKind, LockReason,
+    LockState, PasswordHistoryItem, TagCount, UnlockMethod, UnsealError, Vault, Vaults,
+};
 
 // App state for managing vault data
 struct AppState {
-    vault_data: Mutex<Option<String>>, // Encrypted vault data
-    is_unlocked: Mutex<bool>,
+    vaults: Mutex<Vaults>, // Unlocked vaults (with keys) and which one is active
+    settings: Mutex<Settings>,
     last_activity: Mutex<Option<Instant>>, // Track last activity for auto-lock
     auto_lock_timer: Mutex<Option<u64>>, // Auto-lock timeout in seconds (None = disabled)
+    icon_fetches: Mutex<icons::RateLimiter>,
+    unlock_throttle: Mutex<throttle::UnlockThrottle>,
+    reauth_tokens: Mutex<reauth::ReauthTokens>,
+    unlock_history: Mutex<unlock_log::UnlockHistory>,
+    soft_locks: Mutex<soft_lock::SoftLocks>,
+    hidden_since: Mutex<Option<Instant>>, // When the main window was hidden to the tray
+    biometric_prompt: Mutex<Option<CancelToken>>, // The prompt `cancel_biometric_prompt` ends
+    authenticators: Mutex<Arc<dyn AuthenticatorSource>>, // Biometric and system password prompts
+    verified_entries: Mutex<HashMap<Uuid, Instant>>, // When biometrics last cleared each gated entry
+    biometric_availability: Mutex<biometrics::AvailabilityCache>,
+    staged_copy: Mutex<Option<StagedCopy>>, // Password waiting to follow a copied username
+    app: OnceLock<AppHandle>, // Set once the app is built; cleans up after a poisoned vaults lock
+}
+
+/// `Mutex::lock` that carries on with the data of a poisoned lock, so one
+/// command that panicked does not make every later command panic too
+trait LockOrRecover<T> {
+    fn lock_or_recover(&self) -> MutexGuard<'_, T>;
+}
+
+impl<T> LockOrRecover<T> for Mutex<T> {
+    fn lock_or_recover(&self) -> MutexGuard<'_, T> {
+        self.lock().unwrap_or_else(|poisoned| {
+            self.clear_poison();
+            poisoned.into_inner()
+        })
+    }
+}
+
+impl AppState {
+    fn new(authenticators: Arc<dyn AuthenticatorSource>) -> Self {
+        AppState {
+            vaults: Mutex::new(Vaults::default()),
+            settings: Mutex::new(Settings::default()),
+            last_activity: Mutex::new(None),
+            auto_lock_timer: Mutex::new(Some(300)), // Default: 5 minutes
+            icon_fetches: Mutex::new(icons::RateLimiter::default()),
+            unlock_throttle: Mutex::new(throttle::UnlockThrottle::default()),
+            reauth_tokens: Mutex::new(reauth::ReauthTokens::default()),
+            unlock_history: Mutex::new(unlock_log::UnlockHistory::default()),
+            soft_locks: Mutex::new(soft_lock::SoftLocks::default()),
+            hidden_since: Mutex::new(None),
+            biometric_prompt: Mutex::new(None),
+            authenticators: Mutex::new(authenticators),
+            verified_entries: Mutex::new(HashMap::new()),
+            staged_copy: Mutex::new(None),
+            biometric_availability: Mutex::new(biometrics::AvailabilityCache::default()),
+            app: OnceLock::new(),
+        }
+    }
+
+    /// Guard over the vaults. A command that panicked while holding it may
+    /// have left them half updated, so a poisoned lock is recovered by
+    /// locking every vault rather than panicking every later command. The
+    /// rest of `lock_all_vaults` follows once the guard is released.
+    fn vaults(&self) -> MutexGuard<'_, Vaults> {
+        self.vaults.lock().unwrap_or_else(|poisoned| {
+            let mut vaults = poisoned.into_inner();
+            vaults.lock_all(LockReason::InternalError);
+            self.vaults.clear_poison();
+            if let Some(app) = self.app.get().cloned() {
+                tauri::async_runtime::spawn_blocking(move || {
+                    lock_all_vaults(&app.state::<AppState>(), &app, LockReason::InternalError)
+                });
+            }
+            vaults
+        })
+    }
+
+    /// Where biometric and system password prompts come from right now
+    fn authenticators(&self) -> Arc<dyn AuthenticatorSource> {
+        self.authenticators.lock_or_recover().clone()
+    }
+}
+
+const MIN_MASTER_PASSWORD_LEN: usize = 8;
+/// Bounds of `Settings::biometric_timeout_seconds`
+const MIN_BIOMETRIC_TIMEOUT_SECS: u64 = 5;
+const MAX_BIOMETRIC_TIMEOUT_SECS: u64 = 600;
+/// Bounds of `Settings::keychain_timeout_seconds`
+const MIN_KEYCHAIN_TIMEOUT_SECS: u64 = 5;
+const MAX_KEYCHAIN_TIMEOUT_SECS: u64 = 300;
+/// Bounds of `Settings::clipboard_clear_seconds`
+const MIN_CLIPBOARD_CLEAR_SECS: u32 = 5;
+const MAX_CLIPBOARD_CLEAR_SECS: u32 = 600;
+/// Upper bound of `Settings::staged_copy_delay_seconds`
+const MAX_STAGED_COPY_DELAY_SECS: u32 = 120;
+const KEY_FILE_LEN: usize = 64;
+/// Emitted after unlock with the number of expired entries
+const ENTRIES_EXPIRED_EVENT: &str = "entries-expired";
+/// Emitted with the new `LockState` whenever the active vault locks, unlocks
+/// or changes
+const LOCK_STATE_CHANGED_EVENT: &str = "lock-state-changed";
+/// Emitted with the vault name after too many failed unlock attempts wiped it
+const VAULT_WIPED_EVENT: &str = "vault-wiped";
+/// Emitted on unlock with `FailedAttempts` if the vault saw failed attempts
+/// since it was last unlocked
+const UNLOCK_FAILURES_EVENT: &str = "unlock-failures-since-last-unlock";
+/// Emitted when biometrics are locked out by the OS after too many failed
+/// attempts
+const BIOMETRIC_LOCKED_OUT_EVENT: &str = "biometric-locked-out";
+/// Emitted with `check_biometric_available`'s new result when a re-check
+/// finds it changed, e.g. after a reader was unplugged
+const BIOMETRIC_AVAILABILITY_CHANGED_EVENT: &str = "biometric-availability-changed";
+/// Emitted with `keychain_health::check`'s report when an unlock failed on
+/// a keychain error
+const KEYCHAIN_HEALTH_EVENT: &str = "keychain-health";
+/// Emitted every second with a `ClipboardCountdown` while a copied secret
+/// waits to be cleared
+const CLIPBOARD_WILL_CLEAR_EVENT: &str = "clipboard-will-clear";
+/// Emitted with a `ClipboardCleared` when a copied secret's countdown ends,
+/// whether or not it was still on the clipboard
+const CLIPBOARD_CLEARED_EVENT: &str = "clipboard-cleared";
+/// Emitted with a `StagedCopyEvent` as `copy_entry_credentials_staged`
+/// moves from username to password, or is cancelled
+const STAGED_COPY_EVENT: &str = "clipboard-staged-copy";
+/// Emitted with an entry's id when the backend changed it on its own, e.g.
+/// after `rotate_entry_password`
+const ENTRY_UPDATED_EVENT: &str = "entry-updated";
+/// Breached draws `check_breached` throws away before giving up
+const MAX_BREACH_REDRAWS: usize = 5;
+/// Events `get_unlock_history` and `get_audit_log` return by default
+const DEFAULT_HISTORY_LIMIT: usize = 100;
+
+/// Run a read-only query against the active vault
+fn read_vault<T>(state: &AppState, f: impl FnOnce(&Vault) -> Result<T, VaultError>) -> Result<T, VaultError> {
+    let vaults = state.vaults();
+    if vaults.password_reset_required() {
+        return Err(VaultError::PasswordChangeRequired);
+    }
+    f(vaults.active()?)
+}
+
+/// Apply `f` to a copy of the active vault, persist it, and only then
+/// swap it into `AppState`, so a failed save leaves memory and disk in sync.
+///
+/// Trashed entries past the configured retention and expired generator
+/// history are purged on every save.
+fn mutate_vault<T>(
+    state: &AppState,
+    app: &AppHandle,
+    f: impl FnOnce(&mut Vault) -> Result<T, VaultError>,
+) -> Result<T, VaultError> {
+    let trash_retention_days = state.settings.lock_or_recover().trash_retention_days;
+    let mut vaults = state.vaults();
+    if vaults.password_reset_required() {
+        return Err(VaultError::PasswordChangeRequired);
+    }
+    let path = active_vault_file(app, &vaults)?;
+    let (name, vault, key) = vaults.active_mut()?;
+
+    let mut updated = vault.clone();
+    let result = f(&mut updated)?;
+    if let Some(days) = trash_retention_days {
+        updated.purge_trash(chrono::Duration::days(days as i64));
+    }
+    updated.expire_generator_history();
+    updated.metadata.modified_at = chrono::Utc::now();
+
+    let blob = updated.seal(key)?;
+    storage::write_vault_file(&path, &blob)?;
+
+    // Sidecar files of attachments that are no longer referenced
+    let orphaned: Vec<Uuid> = vault
+        .sidecar_attachment_ids()
+        .difference(&updated.sidecar_attachment_ids())
+        .copied()
+        .collect();
+
+    *vault = updated;
+    for id in orphaned {
+        if let Ok(path) = storage::attachment_path(app, &name, id) {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+    Ok(result)
+}
+
+/// File the active vault is saved to: the decoy file if it was opened
+/// with the duress password
+fn active_vault_file(app: &AppHandle, vaults: &Vaults) -> Result<std::path::PathBuf, VaultError> {
+    let name = vaults.active_name().ok_or(VaultError::VaultLocked)?;
+    if vaults.is_decoy(name) {
+        storage::decoy_path(app, name)
+    } else {
+        storage::vault_path(app, name)
+    }
+}
+
+/// Name and data key of the active vault, for work that must happen
+/// outside the state lock (e.g. encrypting files)
+fn active_vault_key(state: &AppState) -> Result<(String, crypto::VaultKey), VaultError> {
+    let mut vaults = state.vaults();
+    if vaults.password_reset_required() {
+        return Err(VaultError::PasswordChangeRequired);
+    }
+    let (name, _, key) = vaults.active_mut()?;
+    Ok((name, key.clone()))
+}
+
+/// Build the KDF input from a password and optional key file on disk
+fn master_secret(password: &str, key_file_path: Option<&str>) -> Result<Zeroizing<Vec<u8>>, VaultError> {
+    let key_file = match key_file_path {
+        Some(path) => Some(Zeroizing::new(std::fs::read(path)?)),
+        None => None,
+    };
+    Ok(crypto::master_secret(password, key_file.as_deref().map(|contents| contents.as_slice())))
+}
+
+/// `InvalidFields` unless a key file is given exactly when `kdf` requires
+/// one, so rekeying can neither drop nor add the second factor
+fn require_key_file_match(kdf: &crypto::KdfParams, key_file_path: Option<&str>) -> Result<(), VaultError> {
+    let message = match (kdf.key_file, key_file_path.is_some()) {
+        (true, false) => "This vault requires its key file",
+        (false, true) => "This vault does not use a key file",
+        _ => return Ok(()),
+    };
+    Err(VaultError::InvalidFields(vec![FieldError::new("key_file_path", message)]))
+}
+
+/// What `rekey_vault` does with the data key
+#[derive(Debug, Clone, Copy)]
+enum DataKey {
+    /// Re-wrap the current key, so the recovery code, PIN and biometric
+    /// unlock keep working
+    Keep,
+    /// Move to a fresh key, so material wrapping the old one stops opening
+    /// the vault. A new recovery code is issued if the vault had one or
+    /// `new_recovery_code` is set.
+    Rotate { new_recovery_code: bool },
+}
+
+/// Check `authorize`, then wrap the vault's data key under `new_secret`
+/// with the KDF parameters produced by `new_kdf`. Returns the new recovery
+/// code, if one was issued.
+///
+/// `authorize` is told whether a password reset is pending after a
+/// recovery-code unlock, when the old password can no longer be checked.
+/// The new key is derived before the vaults lock is taken; the rekey fails
+/// with `VaultChanged` if the vault was switched or rekeyed meanwhile.
+async fn rekey_vault(
+    state: &AppState,
+    app: &AppHandle,
+    authorize: impl FnOnce(&str, &Vault, bool) -> Result<(), VaultError>,
+    new_secret: &[u8],
+    new_kdf: impl FnOnce(&crypto::KdfParams) -> Result<crypto::KdfParams, VaultError>,
+    data_key: DataKey,
+) -> Result<Option<Zeroizing<String>>, VaultError> {
+    let snapshot = KdfSnapshot::take(state)?;
+    let kdf = new_kdf(&snapshot.kdf)?;
+    let new_password_key = derive_key_blocking(new_secret, &kdf).await?;
+
+    let mut vaults = state.vaults();
+    let reset_pending = vaults.password_reset_required();
+    let path = active_vault_file(app, &vaults)?;
+    let (name, vault, key) = vaults.active_mut()?;
+    snapshot.check(&name, vault)?;
+
+    authorize(&name, vault, reset_pending)?;
+
+    let recovery_code = rekey_file(&path, vault, key, &new_password_key, kdf, data_key)?;
+    vaults.clear_password_reset(&name);
+    Ok(recovery_code)
+}
+
+/// Which vault was active, and with what KDF parameters, when a key was
+/// derived for it outside the vaults lock
+struct KdfSnapshot {
+    name: String,
+    id: Option<Uuid>,
+    kdf: crypto::KdfParams,
+}
+
+impl KdfSnapshot {
+    fn take(state: &AppState) -> Result<Self, VaultError> {
+        let vaults = state.vaults();
+        let name = vaults.active_name().ok_or(VaultError::VaultLocked)?.to_string();
+        let vault = vaults.active()?;
+        Ok(KdfSnapshot { name, id: vault.id, kdf: vault.kdf.clone() })
+    }
+
+    /// `VaultChanged` unless `vault` is still the one the snapshot was taken of
+    fn check(&self, name: &str, vault: &Vault) -> Result<(), VaultError> {
+        if name != self.name || vault.id != self.id || vault.kdf != self.kdf {
+            return Err(VaultError::VaultChanged);
+        }
+        Ok(())
+    }
+}
+
+/// Run Argon2 on a blocking thread, so it neither holds the vaults lock
+/// nor stalls the async runtime
+async fn derive_key_blocking(secret: &[u8], kdf: &crypto::KdfParams) -> Result<crypto::VaultKey, VaultError> {
+    let (secret, kdf) = (Zeroizing::new(secret.to_vec()), kdf.clone());
+    tauri::async_runtime::spawn_blocking(move || crypto::derive_key(&secret, &kdf))
+        .await
+        .map_err(|e| VaultError::Io(e.to_string()))?
+        .map_err(VaultError::Crypto)
+}
+
+/// Wrap the data key of `vault` under `new_password_key`, derived with
+/// `kdf`, rotating it first with `DataKey::Rotate`, and save the vault to
+/// `path`.
+///
+/// `vault` and `key` are only updated once the file is fully written, so
+/// any failure leaves the old vault readable with the old password.
+fn rekey_file(
+    path: &std::path::Path,
+    vault: &mut Vault,
+    key: &mut crypto::VaultKey,
+    new_password_key: &crypto::VaultKey,
+    kdf: crypto::KdfParams,
+    data_key: DataKey,
+) -> Result<Option<Zeroizing<String>>, VaultError> {
+    let mut updated = vault.clone();
+    let (new_key, recovery_code) = match data_key {
+        DataKey::Keep => {
+            updated.set_password(kdf, new_password_key, key)?;
+            (key.clone(), None)
+        }
+        DataKey::Rotate { new_recovery_code } => {
+            let new_key = updated.rotate_data_key(kdf, new_password_key, key)?;
+            let recovery_code = if new_recovery_code || vault.key_slots.recovery.is_some() {
+                Some(updated.reset_recovery_code(&new_key)?)
+            } else {
+                None
+            };
+            (new_key, recovery_code)
+        }
+    };
+
+    let blob = updated.seal(&new_key)?;
+    storage::write_vault_file(path, &blob)?;
+
+    *vault = updated;
+    *key = new_key;
+    Ok(recovery_code)
+}
+
+/// `InvalidPassword` unless `password`, with the key file at
+/// `key_file_path`, opens the active vault. Guesses are throttled like
+/// unlocking.
+async fn check_master_password(
+    state: &AppState,
+    app: &AppHandle,
+    password: &str,
+    key_file_path: Option<&str>,
+) -> Result<(), VaultError> {
+    let secret = master_secret(password, key_file_path)?;
+    begin_unlock_attempt(state, app)?;
+    verify_master_secret(state, &secret).await?;
+    reset_unlock_throttle(state, app)
+}
+
+/// `InvalidPassword` unless `secret` opens the active vault. The key is
+/// derived outside the vaults lock.
+async fn verify_master_secret(state: &AppState, secret: &[u8]) -> Result<(), VaultError> {
+    let snapshot = KdfSnapshot::take(state)?;
+    let password_key = derive_key_blocking(secret, &snapshot.kdf).await?;
+    let vaults = state.vaults();
+    if vaults.password_reset_required() {
+        return Err(VaultError::PasswordChangeRequired);
+    }
+    snapshot.check(vaults.active_name().ok_or(VaultError::VaultLocked)?, vaults.active()?)?;
+    if vaults.active()?.verify_password_key(&password_key) {
+        Ok(())
+    } else {
+        Err(VaultError::InvalidPassword)
+    }
+}
+
+/// Bring the unlock material of the active vault in line with a data key
+/// `rekey_vault` rotated. Biometric unlock is re-issued, or turned off if
+/// that fails. PIN unlock is turned off, as only the PIN could wrap the
+/// new key. Failures are logged, since the vault itself is already saved.
+fn reissue_unlock_material(state: &AppState, app: &AppHandle, command: &'static str) {
+    let Ok((name, key)) = active_vault_key(state) else {
+        return;
+    };
+    if matches!(pin::load(app, &name), Ok(Some(_))) {
+        if let Err(e) = keychain::triggered_by(command, || pin::delete(app, &name)) {
+            eprintln!("Failed to turn off PIN unlock after a key change: {}", e);
+        }
+    }
+    if biometric_unlock::is_enabled(app, &name) {
+        let reissued = biometric_unlock::method(app, &name).and_then(|method| {
+            let authenticator = state.authenticators().authenticator(method).ok_or(VaultError::BiometricUnavailable)?;
+            keychain::triggered_by(command, || biometric_unlock::enable(app, &name, &key, &*authenticator))
+        });
+        if let Err(e) = reissued {
+            eprintln!("Failed to re-issue biometric unlock, turning it off: {}", e);
+            if let Err(e) = keychain::triggered_by(command, || biometric_unlock::disable(app, &name)) {
+                eprintln!("Failed to turn off biometric unlock: {}", e);
+            }
+        }
+    }
+}
+
+/// Use up a token from `reauthenticate` for the vault `name`
+fn consume_reauth_token(state: &AppState, token: Option<&str>, name: &str) -> Result<(), VaultError> {
+    state.reauth_tokens.lock_or_recover().consume(token, name, TokenScope::Reauth)
+}
+
+/// `InvalidSession` unless `session` is the token returned when the active
+/// vault was unlocked. Required by every command that returns secrets.
+fn check_session(state: &AppState, session: &str) -> Result<(), VaultError> {
+    state.vaults().check_session(session)
 }
 
 // Commands for Tauri frontend communication
+
+/// Returns the session token, or `None` if the password is wrong
+#[command]
+async fn unlock_vault(
+    name: Option<String>,
+    password: String,
+    key_file_path: Option<String>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<Option<String>, VaultError> {
+    let password = Zeroizing::new(password);
+    let name = name.unwrap_or_else(|| storage::DEFAULT_VAULT_NAME.to_string());
+    let path = storage::vault_path(&app, &name)?;
+    let blob = storage::read_file(&path)?.ok_or(VaultError::NotInitialized)?;
+    let secret = master_secret(&password, key_file_path.as_deref())?;
+    begin_vault_unlock(&state, &app, &name, UnlockMethod::Password)?;
+
+    // Wrong passwords and missing key files both fail the header key check and
+    // are reported identically. Corruption is only reported when the header
+    // is unreadable or the key check passed, so it reveals nothing about the
+    // password.
+    let unsealed = match Vault::unseal(&blob, &secret) {
+        Ok(unsealed) => unsealed,
+        Err(UnsealError::WrongPassword) => match unseal_decoy(&app, &name, &blob, &secret)? {
+            Some(decoy) => {
+                let decoy_path = storage::decoy_path(&app, &name)?;
+                let event = UnlockEvent::new(&name, UnlockMethod::Password, true);
+                let session = finish_unlock(&state, &app, &name, &decoy_path, decoy, event, true)?;
+                return Ok(Some(session));
+            }
+            None => {
+                record_unlock_attempt(&state, &app, UnlockEvent::new(&name, UnlockMethod::Password, false));
+                wipe_if_over_limit(&state, &app, &name, "unlock_vault")?;
+                return Ok(None);
+            }
+        },
+        Err(UnsealError::UnsupportedVersion(found)) => {
+            return Err(VaultError::UnsupportedVersion { found, supported: vault::VAULT_FORMAT_VERSION })
+        }
+        Err(UnsealError::Integrity(detail)) => {
+            eprintln!("Vault {} failed integrity checks: {}", name, detail);
+            return Err(VaultError::VaultCorrupted { candidates: storage::recovery_candidates(&path) })
+        }
+    };
+
+    let event = UnlockEvent::new(&name, UnlockMethod::Password, true);
+    let session = finish_unlock(&state, &app, &name, &path, unsealed, event, false)?;
+    Ok(Some(session))
+}
+
+/// Unlock a vault with the quick-unlock PIN set by `enable_pin_unlock`.
+/// After `pin::MAX_FAILURES` wrong PINs the PIN is destroyed and only the
+/// master password works. Returns the session token.
+#[command]
+async fn unlock_with_pin(name: Option<String>, pin: String, state: State<'_, AppState>, app: AppHandle) -> Result<String, VaultError> {
+    let pin = Zeroizing::new(pin);
+    let name = name.unwrap_or_else(|| storage::DEFAULT_VAULT_NAME.to_string());
+    if !storage::vault_path(&app, &name)?.exists() {
+        return Err(VaultError::NotInitialized);
+    }
+    let mut slot = pin::load(&app, &name)?.ok_or(VaultError::PinUnlockUnavailable)?;
+
+    // Counted before the PIN is checked, so killing the app mid-attempt
+    // cannot dodge the limit
+    slot.failures += 1;
+    pin::save(&app, &name, &slot)?;
+    let keychain_id = storage::keychain_id(&app, &name)?;
+    let task_slot = slot.clone();
+    let opened = keychain::blocking("unlock_with_pin", move || pin::open(&keychain_id, &task_slot, &pin)).await;
+    let Some(key) = opened.map_err(|e| notice_keychain_error(&app, e))? else {
+        record_unlock_attempt(&state, &app, UnlockEvent::new(&name, UnlockMethod::Pin, false));
+        if slot.failures >= pin::MAX_FAILURES {
+            keychain::triggered_by("unlock_with_pin", || pin::delete(&app, &name))?;
+            return Err(VaultError::PinUnlockUnavailable);
+        }
+        return Err(VaultError::InvalidPin { attempts_left: pin::MAX_FAILURES - slot.failures });
+    };
+    slot.failures = 0;
+    pin::save(&app, &name, &slot)?;
+
+    let event = UnlockEvent::new(&name, UnlockMethod::Pin, true);
+    unlock_with_data_key(&state, &app, &name, key, event).map_err(|e| match e {
+        VaultError::InvalidPassword => VaultError::PinUnlockUnavailable,
+        e => e,
+    })
+}
+
+/// Unlock a vault with an unwrapped data key from a quick-unlock method.
+/// The method may have been set up inside the decoy vault, so the decoy is
+/// tried when the key does not open the real vault. `InvalidPassword` if it
+/// opens neither. `event` is the attempt to log if it unlocks. Returns the
+/// session token.
+fn unlock_with_data_key(
+    state: &AppState,
+    app: &AppHandle,
+    name: &str,
+    key: crypto::VaultKey,
+    event: UnlockEvent,
+) -> Result<String, VaultError> {
+    let path = storage::vault_path(app, name)?;
+    let blob = storage::read_file(&path)?.ok_or(VaultError::NotInitialized)?;
+    match Vault::unseal_with_data_key(&blob, key.clone()) {
+        Ok(unsealed) => finish_unlock(state, app, name, &path, unsealed, event, false),
+        Err(UnsealError::UnsupportedVersion(found)) => {
+            Err(VaultError::UnsupportedVersion { found, supported: vault::VAULT_FORMAT_VERSION })
+        }
+        Err(_) => {
+            let decoy_path = storage::decoy_path(app, name)?;
+            let decoy = storage::read_file(&decoy_path)?
+                .and_then(|blob| Vault::unseal_with_data_key(&blob, key).ok())
+                .ok_or(VaultError::InvalidPassword)?;
+            finish_unlock(state, app, name, &decoy_path, decoy, event, true)
+        }
+    }
+}
+
+/// Unlock a vault after a successful biometric or system password check,
+/// using the key kept by a soft lock or else the one stored by
+/// `enable_biometric_unlock`.
+/// Returns the session token.
+#[command]
+async fn unlock_with_biometrics(name: Option<String>, state: State<'_, AppState>, app: AppHandle) -> Result<String, VaultError> {
+    let name = name.unwrap_or_else(|| storage::DEFAULT_VAULT_NAME.to_string());
+    let soft_locked = state.soft_locks.lock_or_recover().is_held(&name);
+    if !soft_locked && !biometric_unlock::is_enabled(&app, &name) {
+        return Err(VaultError::BiometricUnlockNotEnabled);
+    }
+    // A newly enrolled finger or face must not inherit access to the vault
+    if biometric_unlock::is_enabled(&app, &name) {
+        if let Err(e) = biometric_unlock::check_enrollment(&app, &name, &*state.authenticators()) {
+            if let VaultError::BiometricInvalidated = e {
+                state.soft_locks.lock_or_recover().discard(&name);
+                record_security_change(&app, &SecurityChange::new(&name, "biometric_unlock", true, "invalidated"));
+            }
+            return Err(e);
+        }
+    }
+    let method = if biometric_unlock::is_enabled(&app, &name) {
+        biometric_unlock::method(&app, &name)?
+    } else {
+        biometrics::preferred_method(&*state.authenticators()).ok_or(VaultError::BiometricUnavailable)?
+    };
+    let released = if !soft_locked && biometric_unlock::is_hardware_backed(&app, &name) {
+        // The hardware key store shows its own prompt as it releases the key
+        let (task_app, task_name) = (app.clone(), name.clone());
+        let released = tauri::async_runtime::spawn_blocking(move || biometric_unlock::release(&task_app, &task_name))
+            .await
+            .map_err(|e| VaultError::Io(e.to_string()))?;
+        if let Err(VaultError::BiometricFailed(error)) = &released {
+            notice_biometric_error(&app, error);
+        }
+        released
+    } else {
+        let context = BiometricPromptContext::new(PromptOperation::UnlockVault);
+        match require_biometrics(&state, &app, method, &context).await {
+            Ok(()) => {
+                let soft_lock_key = state.soft_locks.lock_or_recover().take(&name);
+                match soft_lock_key {
+                    Some(key) => Ok(key),
+                    None => {
+                        let (task_app, task_name) = (app.clone(), name.clone());
+                        let release = move || biometric_unlock::release(&task_app, &task_name);
+                        keychain::blocking("unlock_with_biometrics", release).await
+                    }
+                }
+            }
+            Err(e) => Err(e),
+        }
+    };
+    let key = match released {
+        Ok(key) => key,
+        Err(e) => {
+            let e = notice_keychain_error(&app, e);
+            if let VaultError::BiometricFailed(error) = &e {
+                record_unlock_attempt(&state, &app, UnlockEvent::biometric(&name, method, Some(error)));
+            }
+            return Err(e);
+        }
+    };
+    let event = UnlockEvent::biometric(&name, method, None);
+    unlock_with_data_key(&state, &app, &name, key, event).map_err(|e| match e {
+        VaultError::InvalidPassword => VaultError::BiometricUnlockNotEnabled,
+        e => e,
+    })
+}
+
+/// Prompt for `method` with the text for `context`; fails unless the user
+/// was verified
+async fn require_biometrics(
+    state: &AppState,
+    app: &AppHandle,
+    method: AuthMethod,
+    context: &BiometricPromptContext,
+) -> Result<(), VaultError> {
+    let availability = state
+        .authenticators()
+        .authenticator(method)
+        .ok_or(VaultError::BiometricUnavailable)?
+        .is_available()
+        .map_err(VaultError::BiometricFailed)?;
+    if !availability.available || !availability.enrolled {
+        return Err(VaultError::BiometricUnavailable);
+    }
+    if availability.locked_out {
+        notice_biometric_error(app, &BiometricError::LockedOut);
+        return Err(VaultError::BiometricFailed(BiometricError::LockedOut));
+    }
+
+    let result = show_biometric_prompt(state, app, method, &context.text(), prompt_timeout(state))
+        .await
+        .map_err(VaultError::BiometricFailed)?;
+    if result.success {
+        Ok(())
+    } else {
+        Err(VaultError::BiometricFailed(result.error.unwrap_or(BiometricError::NotRecognized)))
+    }
+}
+
+/// Try `secret` against the decoy vault of `name`. Without a decoy file an
+/// equivalent key derivation runs anyway, so a wrong password takes as long
+/// and fails the same way whether or not a duress password is configured.
+fn unseal_decoy(app: &AppHandle, name: &str, real_blob: &[u8], secret: &[u8]) -> Result<Option<vault::Unsealed>, VaultError> {
+    let decoy_blob = storage::read_file(&storage::decoy_path(app, name)?).ok().flatten();
+    match decoy_blob {
+        // A damaged decoy is reported as a wrong password, never as corruption
+        Some(blob) => Ok(Vault::unseal(&blob, secret).ok()),
+        None => {
+            if let Ok(file) = format::decode(real_blob) {
+                let _ = crypto::derive_key(secret, &file.header.kdf);
+            }
+            Ok(None)
+        }
+    }
+}
+
+/// Open a vault with its recovery code when the master password is lost.
+///
+/// The vault is unlocked but unusable until `change_master_password` sets a
+/// new password; until then other vault commands fail with
+/// `PasswordChangeRequired`. Returns the session token, or `None` if the
+/// code is wrong.
+#[command]
+async fn unlock_vault_with_recovery_code(
+    name: Option<String>,
+    code: String,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<Option<String>, VaultError> {
+    let code = Zeroizing::new(code);
+    let name = name.unwrap_or_else(|| storage::DEFAULT_VAULT_NAME.to_string());
+    let path = storage::vault_path(&app, &name)?;
+    let blob = storage::read_file(&path)?.ok_or(VaultError::NotInitialized)?;
+    begin_vault_unlock(&state, &app, &name, UnlockMethod::RecoveryCode)?;
+
+    let unsealed = match Vault::unseal_with_recovery_code(&blob, &code) {
+        Ok(unsealed) => unsealed,
+        Err(UnsealError::WrongPassword) => {
+            record_unlock_attempt(&state, &app, UnlockEvent::new(&name, UnlockMethod::RecoveryCode, false));
+            wipe_if_over_limit(&state, &app, &name, "unlock_vault_with_recovery_code")?;
+            return Ok(None);
+        }
+        Err(UnsealError::UnsupportedVersion(found)) => {
+            return Err(VaultError::UnsupportedVersion { found, supported: vault::VAULT_FORMAT_VERSION })
+        }
+        Err(UnsealError::Integrity(detail)) => {
+            eprintln!("Vault {} failed integrity checks: {}", name, detail);
+            return Err(VaultError::VaultCorrupted { candidates: storage::recovery_candidates(&path) })
+        }
+    };
+
+    let event = UnlockEvent::new(&name, UnlockMethod::RecoveryCode, true);
+    let session = finish_unlock(&state, &app, &name, &path, unsealed, event, false)?;
+    Ok(Some(session))
+}
+
+/// Refuse the attempt while a backoff cooldown runs, otherwise count it as
+/// a failure up front. Counting before the key is derived means parallel
+/// calls and killing the app mid-attempt cannot dodge the backoff.
+fn begin_unlock_attempt(state: &AppState, app: &AppHandle) -> Result<(), VaultError> {
+    let throttle = {
+        let mut throttle = state.unlock_throttle.lock_or_recover();
+        throttle.check()?;
+        throttle.record_failure();
+        throttle.clone()
+    };
+    throttle::save(app, &throttle)
+}
+
+/// `begin_unlock_attempt` for unlocking vault `name`, logging attempts the
+/// backoff refuses
+fn begin_vault_unlock(state: &AppState, app: &AppHandle, name: &str, method: UnlockMethod) -> Result<(), VaultError> {
+    begin_unlock_attempt(state, app).map_err(|e| {
+        if let VaultError::TooManyAttempts { .. } = e {
+            let event = UnlockEvent {
+                rate_limited: true,
+                ..UnlockEvent::new(name, method, false)
+            };
+            record_unlock_attempt(state, app, event);
+        }
+        e
+    })
+}
+
+/// Check the keychain in the background after an unlock failed on a
+/// keychain error, and emit the report. Returns `error`. A keychain that
+/// timed out would only keep the check waiting on the same prompt.
+fn notice_keychain_error(app: &AppHandle, error: VaultError) -> VaultError {
+    if matches!(&error, VaultError::Keychain(e) if *e != KeychainError::Timeout) {
+        let app = app.clone();
+        std::thread::spawn(move || match keychain_health::check(&app, None) {
+            Ok(report) => {
+                let _ = app.emit_all(KEYCHAIN_HEALTH_EVENT, report);
+            }
+            Err(e) => eprintln!("Keychain health check failed: {}", e),
+        });
+    }
+    error
+}
+
+/// Add to the unlock history. A history that cannot be written must not
+/// stop the user unlocking, so failures are only logged.
+fn record_unlock_attempt(state: &AppState, app: &AppHandle, event: UnlockEvent) {
+    if let Err(e) = state.unlock_history.lock_or_recover().record(app, event) {
+        eprintln!("Failed to record unlock attempt: {}", e);
+    }
+}
+
+/// Count a wrong password or recovery code for vault `name`, and destroy
+/// the vault once `Settings::wipe_after_failed_attempts` consecutive ones
+/// are reached. `command` is the unlock command that failed, for the audit
+/// log.
+fn wipe_if_over_limit(state: &AppState, app: &AppHandle, name: &str, command: &'static str) -> Result<(), VaultError> {
+    // Read from the vault header, so it has to come before the wipe
+    let keychain_id = storage::keychain_id(app, name)?;
+    let wiped = wipe_after_failure(state, &storage::vaults_dir(app)?, name);
+    save_unlock_throttle(state, app)?;
+    if !wiped? {
+        return Ok(());
+    }
+
+    delete_keychain_material(&keychain_id, command);
+    publish_lock_state(state, app);
+    let _ = app.emit_all(VAULT_WIPED_EVENT, name);
+    Ok(())
+}
+
+/// Delete the keychain secrets and biometric key of a vault whose files
+/// are gone. Failures are logged, since the vault itself no longer exists.
+fn delete_keychain_material(keychain_id: &str, command: &'static str) {
+    keychain::triggered_by(command, || {
+        for error in keychain::delete_vault_secrets(keychain_id) {
+            eprintln!("Failed to delete keychain secret of deleted vault: {}", error);
+        }
+        if let Err(e) = secure_key::delete_all(keychain_id) {
+            eprintln!("Failed to delete biometric key of deleted vault: {}", e);
+        }
+    });
+}
+
+/// The part of `wipe_if_over_limit` that counts the failure and deletes the
+/// files of vault `name` under `vaults_dir`. Returns whether it did, in
+/// which case the caller emits `VAULT_WIPED_EVENT`.
+fn wipe_after_failure(state: &AppState, vaults_dir: &std::path::Path, name: &str) -> Result<bool, VaultError> {
+    let Some(limit) = state.settings.lock_or_recover().wipe_after_failed_attempts else {
+        return Ok(false);
+    };
+    if state.unlock_throttle.lock_or_recover().record_vault_failure(name) < limit {
+        return Ok(false);
+    }
+
+    state.vaults().lock(name, LockReason::Wipe);
+    state.soft_locks.lock_or_recover().discard(name);
+    storage::wipe_vault(vaults_dir, name)?;
+    let mut throttle = state.unlock_throttle.lock_or_recover();
+    throttle.reset();
+    throttle.clear_vault_failures(name);
+    Ok(true)
+}
+
+fn reset_unlock_throttle(state: &AppState, app: &AppHandle) -> Result<(), VaultError> {
+    state.unlock_throttle.lock_or_recover().reset();
+    save_unlock_throttle(state, app)
+}
+
+fn save_unlock_throttle(state: &AppState, app: &AppHandle) -> Result<(), VaultError> {
+    let throttle = state.unlock_throttle.lock_or_recover().clone();
+    throttle::save(app, &throttle)
+}
+
+/// Persist any format upgrade and store a freshly unsealed vault in
+/// `AppState`. `decoy` marks a vault opened with its duress password, whose
+/// saves go to `path` rather than the real vault file. `event` is the
+/// successful attempt for the unlock history. Returns the session token.
+fn finish_unlock(
+    state: &AppState,
+    app: &AppHandle,
+    name: &str,
+    path: &std::path::Path,
+    unsealed: vault::Unsealed,
+    event: UnlockEvent,
+    decoy: bool,
+) -> Result<String, VaultError> {
+    // The attempt succeeded, so it no longer counts towards the backoff or
+    // a wipe
+    state.unlock_throttle.lock_or_recover().clear_vault_failures(name);
+    reset_unlock_throttle(state, app)?;
+    let failed_attempts = state.unlock_history.lock_or_recover().failures_since_unlock(name);
+    let method = event.method;
+    record_unlock_attempt(state, app, event);
+
+    let expired = unsealed.vault.expired_count();
+    let session = open_unsealed(state, name, path, unsealed, method, decoy)?;
+    publish_lock_state(state, app);
+
+    // Let the UI show a banner for passwords due for rotation
+    if expired > 0 {
+        let _ = app.emit_all(ENTRIES_EXPIRED_EVENT, expired);
+    }
+    update_tray_tooltip(app, expired);
+
+    // Let the UI warn that someone may have tried to get in
+    if let Some(failed_attempts) = failed_attempts {
+        let _ = app.emit_all(UNLOCK_FAILURES_EVENT, failed_attempts);
+    }
+
+    Ok(session)
+}
+
+/// The part of `finish_unlock` that only touches `AppState` and the vault
+/// file: persist any format upgrade and store the vault
+fn open_unsealed(
+    state: &AppState,
+    name: &str,
+    path: &std::path::Path,
+    unsealed: vault::Unsealed,
+    method: UnlockMethod,
+    decoy: bool,
+) -> Result<String, VaultError> {
+    let (vault, key) = (unsealed.vault, unsealed.key);
+
+    // Persist the upgraded format, keeping the original file around
+    if unsealed.migrated_from.is_some() {
+        storage::backup_copy(path, storage::MIGRATION_BACKUP_SUFFIX)?;
+        storage::write_vault_file(path, &vault.seal(&key)?)?;
+    }
+
+    let allow_multiple = state.settings.lock_or_recover().allow_multiple_vaults;
+    let session = {
+        let mut vaults = state.vaults();
+        let session = vaults.unlock(name, vault, key, method, allow_multiple);
+        if decoy {
+            vaults.mark_decoy(name);
+        }
+        state.soft_locks.lock_or_recover().discard(name);
+        session
+    };
+    *state.last_activity.lock_or_recover() = Some(Instant::now());
+    Ok(session)
+}
+
+/// Sync the tray menu with the active vault's lock state and tell every
+/// window about it
+fn publish_lock_state(state: &AppState, app: &AppHandle) {
+    let lock_state = state.vaults().lock_state();
+    if let Some(tray) = app.tray_handle_by_id("main") {
+        let _ = tray.set_menu(create_system_tray_menu(lock_state.is_unlocked()));
+    }
+    let _ = app.emit_all(LOCK_STATE_CHANGED_EVENT, &lock_state);
+}
+
+/// Show the number of expired entries in the tray tooltip (0 resets it)
+fn update_tray_tooltip(app: &AppHandle, expired: usize) {
+    if let Some(tray) = app.tray_handle_by_id("main") {
+        let tooltip = match expired {
+            0 => "SafeNode".to_string(),
+            1 => "SafeNode - 1 expired entry".to_string(),
+            n => format!("SafeNode - {} expired entries", n),
+        };
+        let _ = tray.set_tooltip(&tooltip);
+    }
+}
+
+/// `WeakPassword` if `password` is common, or scores below
+/// `Settings::min_master_password_score` and `accept_weak` is not set
+fn require_strong_password(state: &AppState, password: &str, vault_name: &str, accept_weak: bool) -> Result<(), VaultError> {
+    let min_score = state.settings.lock_or_recover().min_master_password_score;
+    let strength = strength::estimate(password, &[vault_name, "safenode"]);
+    if strength.is_rejected(min_score, accept_weak) {
+        Err(VaultError::WeakPassword(Box::new(strength)))
+    } else {
+        Ok(())
+    }
+}
+
+/// Rate a password as the user types or generates it, whether a master
+/// password or an entry's. The password is neither logged nor kept.
+#[command]
+async fn estimate_password_strength(password: String, name: Option<String>) -> Result<PasswordStrength, VaultError> {
+    let password = Zeroizing::new(password);
+    let name = name.unwrap_or_else(|| storage::DEFAULT_VAULT_NAME.to_string());
+    Ok(strength::estimate(&password, &[&name, "safenode"]))
+}
+
+/// Shown to the user once after vault creation
+#[derive(serde::Serialize)]
+struct CreatedVault {
+    recovery_code: String,
+    /// Session token of the new, unlocked vault
+    session: String,
+}
+
+/// Create a vault. Weak master passwords are refused unless `accept_weak`
+/// is set; common ones always are.
+///
+/// Replacing an existing vault needs `overwrite` and a token from
+/// `reauthenticate` for it, so only an unlocked vault can be replaced; one
+/// whose password and recovery code are both lost has to be deleted from
+/// disk first. Its files, PIN and biometric unlock and keychain secrets are
+/// deleted once the new vault is written.
+#[command]
+async fn create_vault(
+    name: Option<String>,
+    master_password: String,
+    overwrite: Option<bool>,
+    token: Option<String>,
+    key_file_path: Option<String>,
+    accept_weak: Option<bool>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<CreatedVault, VaultError> {
+    let master_password = Zeroizing::new(master_password);
+    if master_password.chars().count() < MIN_MASTER_PASSWORD_LEN {
+        return Err(VaultError::PasswordTooShort { min_length: MIN_MASTER_PASSWORD_LEN });
+    }
+
+    let name = name.unwrap_or_else(|| storage::DEFAULT_VAULT_NAME.to_string());
+    require_strong_password(&state, &master_password, &name, accept_weak.unwrap_or(false))?;
+    let path = storage::vault_path(&app, &name)?;
+    let replaces = path.exists();
+    if replaces {
+        if !overwrite.unwrap_or(false) {
+            return Err(VaultError::AlreadyExists);
+        }
+        consume_reauth_token(&state, token.as_deref(), &name)?;
+    }
+
+    let secret = master_secret(&master_password, key_file_path.as_deref())?;
+    let mut kdf = crypto::calibrate(crypto::DEFAULT_KDF_TARGET).map_err(VaultError::Crypto)?;
+    kdf.key_file = key_file_path.is_some();
+    let password_key = crypto::derive_key(&secret, &kdf).map_err(VaultError::Crypto)?;
+    let (mut vault, key) = Vault::new(kdf, &password_key)?;
+    let recovery_code = vault.reset_recovery_code(&key)?;
+
+    // Nothing of the old vault may outlive it: a leftover PIN slot would
+    // be offered for the new vault and never open it
+    let sealed = vault.seal(&key)?;
+    if replaces {
+        // Read from the vault header, so it has to come before the wipe
+        let keychain_id = storage::keychain_id(&app, &name)?;
+        state.vaults().lock(&name, LockReason::Manual);
+        state.soft_locks.lock_or_recover().discard(&name);
+        storage::replace_vault(&storage::vaults_dir(&app)?, &name, &sealed)?;
+        delete_keychain_material(&keychain_id, "create_vault");
+    } else {
+        storage::write_vault_file(&path, &sealed)?;
+    }
+
+    let allow_multiple = state.settings.lock_or_recover().allow_multiple_vaults;
+    let session = state
+        .vaults()
+        .unlock(&name, vault, key, UnlockMethod::Password, allow_multiple);
+    *state.last_activity.lock_or_recover() = Some(Instant::now());
+    publish_lock_state(&state, &app);
+
+    Ok(CreatedVault {
+        recovery_code: String::clone(&recovery_code),
+        session,
+    })
+}
+
+/// What the unlock screen needs to ask for, readable without the password
+#[derive(serde::Serialize)]
+struct UnlockRequirements {
+    initialized: bool,
+    requires_key_file: bool,
+    /// Seconds until the next unlock attempt is allowed (0 if now)
+    retry_after_secs: u64,
+    /// Whether `unlock_with_pin` can be offered
+    pin_unlock: bool,
+    /// Whether `unlock_with_biometrics` can be offered
+    biometric_unlock: bool,
+    /// Whether the vault is in its soft lock grace period, so biometrics
+    /// work even if biometric unlock was never enabled
+    soft_locked: bool,
+}
+
+#[command]
+async fn get_unlock_requirements(
+    name: Option<String>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<UnlockRequirements, VaultError> {
+    let name = name.unwrap_or_else(|| storage::DEFAULT_VAULT_NAME.to_string());
+    let path = storage::vault_path(&app, &name)?;
+    let retry_after_secs = state.unlock_throttle.lock_or_recover().remaining().as_secs_f64().ceil() as u64;
+    let pin_unlock = storage::pin_path(&app, &name)?.exists();
+    let soft_locked = state.soft_locks.lock_or_recover().is_held(&name);
+    let biometric_unlock = soft_locked || biometric_unlock::is_enabled(&app, &name);
+    let blob = match storage::read_file(&path)? {
+        Some(blob) => blob,
+        None => {
+            return Ok(UnlockRequirements {
+                initialized: false,
+                requires_key_file: false,
+                retry_after_secs,
+                pin_unlock,
+                biometric_unlock,
+                soft_locked,
+            })
+        }
+    };
+    let file = format::decode(&blob)
+        .map_err(|_| VaultError::VaultCorrupted { candidates: storage::recovery_candidates(&path) })?;
+
+    Ok(UnlockRequirements {
+        initialized: true,
+        requires_key_file: file.header.kdf.key_file,
+        retry_after_secs,
+        pin_unlock,
+        biometric_unlock,
+        soft_locked,
+    })
+}
+
+/// Restore a damaged vault from one of the candidates reported in
+/// `VaultCorrupted`. The damaged file is moved aside, never overwritten.
+/// Returns the path the damaged file was moved to.
+#[command]
+async fn recover_vault_from_backup(
+    name: Option<String>,
+    path: String,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<String, VaultError> {
+    let name = name.unwrap_or_else(|| storage::DEFAULT_VAULT_NAME.to_string());
+    let vault_file = storage::vault_path(&app, &name)?;
+
+    // Only accept files we offered ourselves, not arbitrary paths from the webview
+    let is_candidate = storage::recovery_candidates(&vault_file)
+        .iter()
+        .any(|candidate| candidate.header_valid && candidate.path == path);
+    if !is_candidate {
+        return Err(VaultError::Io("Not a recovery candidate for this vault".to_string()));
+    }
+
+    let backup = std::fs::read(&path)?;
+    state.vaults().lock(&name, LockReason::Manual);
+    state.soft_locks.lock_or_recover().discard(&name);
+    publish_lock_state(&state, &app);
+
+    let quarantined = if vault_file.exists() {
+        storage::quarantine(&vault_file)?.to_string_lossy().into_owned()
+    } else {
+        String::new()
+    };
+    storage::write_atomic(&vault_file, &backup)?;
+
+    Ok(quarantined)
+}
+
+/// Write a new random key file; refuses to overwrite an existing file
+#[command]
+async fn generate_keyfile(path: String) -> Result<(), VaultError> {
+    let mut contents = Zeroizing::new([0u8; KEY_FILE_LEN]);
+    rand::RngCore::fill_bytes(&mut rand::rngs::OsRng, &mut contents[..]);
+    storage::write_new_private(std::path::Path::new(&path), &contents[..])
+}
+
+/// Confirm the master password (or biometrics) again. Returns a single-use
+/// token that sensitive commands require, valid for `reauth::TOKEN_LIFETIME`.
+/// `context` sets the text of a biometric prompt, e.g. before an export.
+#[command]
+async fn reauthenticate(
+    credential: ReauthCredential,
+    context: Option<BiometricPromptContext>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<String, VaultError> {
+    let name = state
+        .vaults()
+        .active_name()
+        .map(str::to_string)
+        .ok_or(VaultError::VaultLocked)?;
+
+    match &credential {
+        ReauthCredential::Password { password, key_file_path } => {
+            let secret = master_secret(password, key_file_path.as_deref())?;
+            // Guessing the master password here is throttled like unlocking
+            begin_unlock_attempt(&state, &app)?;
+            if let Err(e) = verify_master_secret(&state, &secret).await {
+                // Whoever keeps guessing at an unattended session is locked out
+                // once the backoff kicks in
+                let backing_off = state.unlock_throttle.lock_or_recover().is_backing_off();
+                if matches!(e, VaultError::InvalidPassword) && backing_off {
+                    lock_all_vaults(&state, &app, LockReason::FailedReauth);
+                }
+                return Err(e);
+            }
+            reset_unlock_throttle(&state, &app)?;
+        }
+        ReauthCredential::Biometric => {
+            let method = biometrics::preferred_method(&*state.authenticators()).ok_or(VaultError::BiometricUnavailable)?;
+            let context = context.unwrap_or_else(|| BiometricPromptContext::new(PromptOperation::Reauthenticate));
+            require_biometrics(&state, &app, method, &context).await?
+        }
+    }
+
+    Ok(state.reauth_tokens.lock_or_recover().issue(&name, TokenScope::Reauth))
+}
+
+/// Confirm a plaintext export of the active vault. With
+/// `ExportApproval::Biometric` the user is prompted even if they just
+/// unlocked; when no biometric or system password prompt is usable, `token`
+/// from `reauthenticate` is required instead. The audit log records the
+/// approval asked for and what gave it. Returns a token that only an
+/// export accepts; the commands gated by `reauthenticate` refuse it.
+#[command]
+async fn approve_export(
+    approval: Option<ExportApproval>,
+    token: Option<String>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<String, VaultError> {
+    let name = state
+        .vaults()
+        .active_name()
+        .map(str::to_string)
+        .ok_or(VaultError::VaultLocked)?;
+
+    let approval = approval.unwrap_or_default();
+    let prompt_method = match approval {
+        ExportApproval::Biometric => biometrics::preferred_method(&*state.authenticators()),
+        ExportApproval::Password => None,
+    };
+    let approved_with = match prompt_method {
+        Some(method) => {
+            let context = BiometricPromptContext::new(PromptOperation::ApproveExport);
+            require_biometrics(&state, &app, method, &context).await?;
+            ApprovedWith::from(method)
+        }
+        None => {
+            consume_reauth_token(&state, token.as_deref(), &name)?;
+            ApprovedWith::ReauthToken
+        }
+    };
+    record_security_change(&app, &SecurityChange::new(&name, "export_approval", approval, approved_with));
+
+    Ok(state.reauth_tokens.lock_or_recover().issue(&name, TokenScope::Export))
+}
+
+/// Recent unlock attempts on every vault, newest first, only those made
+/// with `method` when given. Requires an unlocked vault.
+#[command]
+async fn get_unlock_history(
+    limit: Option<usize>,
+    method: Option<UnlockMethod>,
+    state: State<'_, AppState>,
+) -> Result<Vec<UnlockEvent>, VaultError> {
+    if !state.vaults().active_is_unlocked() {
+        return Err(VaultError::VaultLocked);
+    }
+    let limit = limit.unwrap_or(DEFAULT_HISTORY_LIMIT);
+    Ok(state.unlock_history.lock_or_recover().recent(limit, method))
+}
+
+/// Recent security audit events, newest first, only those in `category`
+/// when given. Requires an unlocked vault.
+#[command]
+async fn get_audit_log(
+    category: Option<AuditCategory>,
+    limit: Option<usize>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<Vec<AuditEvent>, VaultError> {
+    if !state.vaults().active_is_unlocked() {
+        return Err(VaultError::VaultLocked);
+    }
+    audit::read(&app, category, limit.unwrap_or(DEFAULT_HISTORY_LIMIT))
+}
+
+/// Erase the unlock history. Requires a token from `reauthenticate`.
+#[command]
+async fn clear_unlock_history(token: String, state: State<'_, AppState>, app: AppHandle) -> Result<(), VaultError> {
+    let name = state
+        .vaults()
+        .active_name()
+        .map(str::to_string)
+        .ok_or(VaultError::VaultLocked)?;
+    consume_reauth_token(&state, Some(&token), &name)?;
+    state.unlock_history.lock_or_recover().clear(&app)
+}
+
+/// Let the active vault be unlocked on this machine with a 4 to 12 digit
+/// PIN, replacing any previous PIN
+#[command]
+async fn enable_pin_unlock(pin: String, state: State<'_, AppState>, app: AppHandle) -> Result<(), VaultError> {
+    let pin = Zeroizing::new(pin);
+    pin::validate(&pin)?;
+    let (name, key) = active_vault_key(&state)?;
+    let kdf = read_vault(&state, |vault| Ok(vault.kdf.clone()))?;
+    let keychain_id = storage::keychain_id(&app, &name)?;
+    let slot = keychain::blocking("enable_pin_unlock", move || pin::create(&keychain_id, &pin, &key, &kdf)).await?;
+    pin::save(&app, &name, &slot)
+}
+
+/// How biometric unlock protects the vault key on this machine
+#[derive(serde::Serialize)]
+struct BiometricUnlockStatus {
+    /// Whether the key is bound to the Secure Enclave or a Windows Hello
+    /// key; `false` means the software fallback in the OS keychain
+    hardware_backed: bool,
+    /// What the user presents to release the key
+    method: AuthMethod,
+}
+
+/// Let the active vault be unlocked with biometrics, or the login password
+/// where there are none, on this machine
+#[command]
+async fn enable_biometric_unlock(state: State<'_, AppState>, app: AppHandle) -> Result<BiometricUnlockStatus, VaultError> {
+    let authenticator = biometrics::get_system_authenticators(&*state.authenticators())
+        .into_iter()
+        .next()
+        .ok_or(VaultError::BiometricUnavailable)?;
+    let method = authenticator.method();
+    let (name, key) = active_vault_key(&state)?;
+    // Creating a Windows Hello key prompts the user
+    let hardware_backed = tauri::async_runtime::spawn_blocking(move || {
+        keychain::triggered_by("enable_biometric_unlock", || {
+            biometric_unlock::enable(&app, &name, &key, &*authenticator)
+        })
+    })
+    .await
+    .map_err(|e| VaultError::Io(e.to_string()))??;
+    Ok(BiometricUnlockStatus { hardware_backed, method })
+}
+
+/// Requires a token from `reauthenticate`
+#[command]
+async fn disable_biometric_unlock(token: String, state: State<'_, AppState>, app: AppHandle) -> Result<(), VaultError> {
+    let (name, _) = active_vault_key(&state)?;
+    consume_reauth_token(&state, Some(&token), &name)?;
+    let was_enabled = biometric_unlock::is_enabled(&app, &name);
+    keychain::triggered_by("disable_biometric_unlock", || biometric_unlock::disable(&app, &name))?;
+    record_security_change(&app, &SecurityChange::new(&name, "biometric_unlock", was_enabled, false));
+    Ok(())
+}
+
+/// Requires a token from `reauthenticate`
+#[command]
+async fn disable_pin_unlock(token: String, state: State<'_, AppState>, app: AppHandle) -> Result<(), VaultError> {
+    let (name, _) = active_vault_key(&state)?;
+    consume_reauth_token(&state, Some(&token), &name)?;
+    let was_enabled = storage::pin_path(&app, &name)?.exists();
+    keychain::triggered_by("disable_pin_unlock", || pin::delete(&app, &name))?;
+    record_security_change(&app, &SecurityChange::new(&name, "pin_unlock", was_enabled, false));
+    Ok(())
+}
+
+/// Create or replace the decoy vault that opens when `duress_password` is
+/// entered at unlock. The decoy starts empty; unlock it with the duress
+/// password to fill it with harmless entries. Requires a token from
+/// `reauthenticate`, and the vault's key file if it uses one: the decoy
+/// asks for the same key file as the real vault.
+#[command]
+async fn configure_duress_vault(
+    duress_password: String,
+    key_file_path: Option<String>,
+    token: String,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<(), VaultError> {
+    let duress_password = Zeroizing::new(duress_password);
+    if duress_password.chars().count() < MIN_MASTER_PASSWORD_LEN {
+        return Err(VaultError::PasswordTooShort { min_length: MIN_MASTER_PASSWORD_LEN });
+    }
+
+    let (name, is_decoy) = {
+        let vaults = state.vaults();
+        let name = vaults.active_name().ok_or(VaultError::VaultLocked)?.to_string();
+        require_key_file_match(&vaults.active()?.kdf, key_file_path.as_deref())?;
+        let is_decoy = vaults.is_decoy(&name);
+        (name, is_decoy)
+    };
+    let secret = master_secret(&duress_password, key_file_path.as_deref())?;
+    consume_reauth_token(&state, Some(&token), &name)?;
+    // Inside the decoy, report success without touching anything, so the
+    // command cannot be used to tell the decoy apart
+    if is_decoy {
+        return Ok(());
+    }
+
+    match verify_master_secret(&state, &secret).await {
+        Err(VaultError::InvalidPassword) => {}
+        Ok(()) => {
+            return Err(VaultError::InvalidFields(vec![FieldError::new(
+                "duress_password",
+                "The duress password must differ from the master password",
+            )]))
+        }
+        Err(e) => return Err(e),
+    }
+    let kdf = read_vault(&state, |vault| Ok(vault.kdf.with_fresh_salt()))?;
+    let password_key = derive_key_blocking(&secret, &kdf).await?;
+    let (decoy, key) = Vault::new(kdf, &password_key)?;
+    storage::write_atomic(&storage::decoy_path(&app, &name)?, &decoy.seal(&key)?)
+}
+
+/// Set a new master password and move the vault to a fresh data key, so
+/// anything holding the old one (a copied biometric file, an old recovery
+/// code) no longer opens it. Requires a token from `reauthenticate` and
+/// `old` as the current password, except right after a recovery-code
+/// unlock. Weak passwords are refused as in `create_vault`.
+///
+/// Returns the new recovery code if the vault had one. Biometric unlock is
+/// re-issued; PIN unlock is turned off and has to be set up again.
+#[command]
+async fn change_master_password(
+    token: Option<String>,
+    old: Option<String>,
+    new: String,
+    key_file_path: Option<String>,
+    accept_weak: Option<bool>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<Option<String>, VaultError> {
+    let old = old.map(Zeroizing::new);
+    let new = Zeroizing::new(new);
+    if new.chars().count() < MIN_MASTER_PASSWORD_LEN {
+        return Err(VaultError::PasswordTooShort { min_length: MIN_MASTER_PASSWORD_LEN });
+    }
+    let (name, reset_pending) = {
+        let vaults = state.vaults();
+        let name = vaults.active_name().map(str::to_string).ok_or(VaultError::VaultLocked)?;
+        require_key_file_match(&vaults.active()?.kdf, key_file_path.as_deref())?;
+        (name, vaults.password_reset_required())
+    };
+    // Checked before the old password, so the user can pick another
+    require_strong_password(&state, &new, &name, accept_weak.unwrap_or(false))?;
+    // The vault must not be rekeyed by someone else between the checks and the rekey
+    let snapshot = KdfSnapshot::take(&state)?;
+    if !reset_pending {
+        consume_reauth_token(&state, token.as_deref(), &name)?;
+        let old = old.as_deref().ok_or(VaultError::InvalidPassword)?;
+        check_master_password(&state, &app, old, key_file_path.as_deref()).await?;
+    }
+
+    // A vault's key file, if any, stays the same across password changes
+    let new_secret = master_secret(&new, key_file_path.as_deref())?;
+    let recovery_code = rekey_vault(
+        &state,
+        &app,
+        |name, vault, _| snapshot.check(name, vault),
+        &new_secret,
+        |kdf| Ok(kdf.with_fresh_salt()),
+        DataKey::Rotate { new_recovery_code: false },
+    )
+    .await?;
+
+    reissue_unlock_material(&state, &app, "change_master_password");
+    Ok(recovery_code.map(|code| String::clone(&code)))
+}
+
+/// Replace the recovery code; requires a token from `reauthenticate` and
+/// the current master password, which wraps the new data key. The vault
+/// moves to a fresh data key as in `change_master_password`, so the
+/// previous code stops working even on copies of the vault taken before.
+/// The new code is returned once.
+#[command]
+async fn regenerate_recovery_code(
+    token: String,
+    password: String,
+    key_file_path: Option<String>,
+    session: String,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<String, VaultError> {
+    let password = Zeroizing::new(password);
+    check_session(&state, &session)?;
+    read_vault(&state, |vault| require_key_file_match(&vault.kdf, key_file_path.as_deref()))?;
+    let name = state.vaults().active_name().map(str::to_string).ok_or(VaultError::VaultLocked)?;
+    consume_reauth_token(&state, Some(&token), &name)?;
+    let snapshot = KdfSnapshot::take(&state)?;
+    check_master_password(&state, &app, &password, key_file_path.as_deref()).await?;
+
+    let secret = master_secret(&password, key_file_path.as_deref())?;
+    let code = rekey_vault(
+        &state,
+        &app,
+        |name, vault, _| snapshot.check(name, vault),
+        &secret,
+        |kdf| Ok(kdf.with_fresh_salt()),
+        DataKey::Rotate { new_recovery_code: true },
+    )
+    .await?
+    .ok_or_else(|| VaultError::Crypto("No recovery code was issued".to_string()))?;
+
+    reissue_unlock_material(&state, &app, "regenerate_recovery_code");
+    Ok(String::clone(&code))
+}
+
+#[derive(serde::Serialize)]
+struct KdfInfo {
+    algorithm: crypto::KdfAlgorithm,
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+}
+
+#[command]
+async fn get_kdf_info(state: State<'_, AppState>) -> Result<KdfInfo, VaultError> {
+    read_vault(&state, |vault| {
+        Ok(KdfInfo {
+            algorithm: vault.kdf.algorithm,
+            memory_kib: vault.kdf.memory_kib,
+            iterations: vault.kdf.iterations,
+            parallelism: vault.kdf.parallelism,
+        })
+    })
+}
+
+/// Re-wrap the data key with KDF parameters calibrated to take about
+/// `target_ms` on this machine; requires the current master password.
+/// `target_ms` must lie between `MIN_KDF_TARGET` and `MAX_KDF_TARGET`.
+/// Refused while a password reset is pending after a recovery-code unlock.
+///
+/// A duress vault is moved to the same parameters, so a wrong password
+/// keeps taking as long with or without one; that needs `duress_password`.
+/// Inside the decoy only the decoy is rekeyed.
+#[command]
+async fn rekey_kdf(
+    password: String,
+    target_ms: u64,
+    key_file_path: Option<String>,
+    duress_password: Option<String>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<KdfInfo, VaultError> {
+    let password = Zeroizing::new(password);
+    let duress_password = duress_password.map(Zeroizing::new);
+    let target = std::time::Duration::from_millis(target_ms);
+    if !(crypto::MIN_KDF_TARGET..=crypto::MAX_KDF_TARGET).contains(&target) {
+        return Err(VaultError::InvalidFields(vec![FieldError::new(
+            "target_ms",
+            format!(
+                "Must be between {} and {} ms",
+                crypto::MIN_KDF_TARGET.as_millis(),
+                crypto::MAX_KDF_TARGET.as_millis()
+            ),
+        )]));
+    }
+    read_vault(&state, |vault| require_key_file_match(&vault.kdf, key_file_path.as_deref()))?;
+    let snapshot = KdfSnapshot::take(&state)?;
+    check_master_password(&state, &app, &password, key_file_path.as_deref()).await?;
+    let duress_password = duress_password.as_deref().map(String::as_str);
+    let decoy = open_decoy(&state, &app, duress_password, key_file_path.as_deref()).await?;
+
+    // Calibration runs Argon2 several times, so it must not hold the vaults lock
+    let mut kdf = tauri::async_runtime::spawn_blocking(move || crypto::calibrate(target))
+        .await
+        .map_err(|e| VaultError::Io(e.to_string()))?
+        .map_err(VaultError::Crypto)?;
+    let secret = master_secret(&password, key_file_path.as_deref())?;
+    rekey_vault(
+        &state,
+        &app,
+        |name, vault, reset_pending| {
+            if reset_pending {
+                return Err(VaultError::PasswordChangeRequired);
+            }
+            snapshot.check(name, vault)
+        },
+        &secret,
+        |current| {
+            kdf.key_file = current.key_file;
+            Ok(kdf.clone())
+        },
+        DataKey::Keep,
+    )
+    .await?;
+
+    if let Some((path, mut decoy, decoy_secret)) = decoy {
+        let decoy_kdf = crypto::KdfParams { key_file: decoy.vault.kdf.key_file, ..kdf.with_fresh_salt() };
+        let password_key = derive_key_blocking(&decoy_secret, &decoy_kdf).await?;
+        rekey_file(&path, &mut decoy.vault, &mut decoy.key, &password_key, decoy_kdf, DataKey::Keep)?;
+    }
+    get_kdf_info(state).await
+}
+
+/// The decoy of the active vault, opened with `duress_password` so
+/// `rekey_kdf` can rekey it too, with its path and secret. `None` if there
+/// is no decoy, or the decoy itself is active.
+async fn open_decoy(
+    state: &AppState,
+    app: &AppHandle,
+    duress_password: Option<&str>,
+    key_file_path: Option<&str>,
+) -> Result<Option<(std::path::PathBuf, vault::Unsealed, Zeroizing<Vec<u8>>)>, VaultError> {
+    let name = {
+        let vaults = state.vaults();
+        let name = vaults.active_name().ok_or(VaultError::VaultLocked)?.to_string();
+        if vaults.is_decoy(&name) {
+            return Ok(None);
+        }
+        name
+    };
+    let path = storage::decoy_path(app, &name)?;
+    let Some(blob) = storage::read_file(&path)? else {
+        return Ok(None);
+    };
+    let invalid = |message: &str| VaultError::InvalidFields(vec![FieldError::new("duress_password", message)]);
+    let duress_password =
+        duress_password.ok_or_else(|| invalid("Also enter the duress password to rekey the duress vault"))?;
+    let secret = master_secret(duress_password, key_file_path)?;
+    let unseal_secret = secret.clone();
+    let unsealed = tauri::async_runtime::spawn_blocking(move || Vault::unseal(&blob, &unseal_secret))
+        .await
+        .map_err(|e| VaultError::Io(e.to_string()))?
+        .map_err(|_| invalid("Incorrect duress password"))?;
+    Ok(Some((path, unsealed, secret)))
+}
+
+/// Summaries of the active vault's entries; trashed entries are only
+/// included when `include_trash` is set. `folder_id` limits the result to
+/// entries directly inside that folder, `tag` to entries carrying that tag
+/// and `kind` to one item kind. Archived entries are left out unless
+/// `include_archived` is set. Without `sort` entries keep their stored order.
+#[command]
+async fn list_entries(
+    include_trash: Option<bool>,
+    include_archived: Option<bool>,
+    folder_id: Option<Uuid>,
+    tag: Option<String>,
+    kind: Option<ItemKind>,
+    sort: Option<EntrySort>,
+    state: State<'_, AppState>,
+) -> Result<Vec<EntrySummary>, VaultError> {
+    read_vault(&state, |vault| {
+        let mut summaries: Vec<EntrySummary> = vault.entries.iter().map(EntrySummary::from).collect();
+        if include_trash.unwrap_or(false) {
+            summaries.extend(vault.trash.iter().map(EntrySummary::from));
+        }
+        if !include_archived.unwrap_or(false) {
+            summaries.retain(|summary| !summary.archived);
+        }
+        if let Some(folder_id) = folder_id {
+            summaries.retain(|summary| summary.folder_id == Some(folder_id));
+        }
+        if let Some(tag) = tag {
+            let tag = tag.trim().to_lowercase();
+            summaries.retain(|summary| summary.tags.iter().any(|t| t.to_lowercase() == tag));
+        }
+        if let Some(kind) = kind {
+            summaries.retain(|summary| summary.kind == kind);
+        }
+        if let Some(sort) = sort {
+            sort.apply(&mut summaries);
+        }
+        Ok(summaries)
+    })
+}
+
+/// Entries of the active vault matching `query`, best match first.
+/// Archived entries are left out unless `include_archived` is set.
+#[command]
+async fn search_entries(
+    query: String,
+    include_archived: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<Vec<EntrySummary>, VaultError> {
+    let include_archived = include_archived.unwrap_or(false);
+    read_vault(&state, |vault| {
+        let entries = vault.entries.iter().filter(|entry| include_archived || !entry.archived);
+        Ok(search::search(entries, &query))
+    })
+}
+
+/// Full entry including secrets; also records it as used. Entries marked
+/// `require_biometric` prompt first.
+#[command]
+async fn get_entry(id: Uuid, session: String, state: State<'_, AppState>, app: AppHandle) -> Result<EntryFull, VaultError> {
+    check_session(&state, &session)?;
+    confirm_gated_entry(&state, &app, id, PromptOperation::RevealPassword).await?;
+    mutate_vault(&state, &app, |vault| {
+        vault.touch_entry(id)?;
+        vault.entry(id).map(EntryFull::from)
+    })
+}
+
+#[command]
+async fn archive_entry(id: Uuid, state: State<'_, AppState>, app: AppHandle) -> Result<(), VaultError> {
+    mutate_vault(&state, &app, |vault| vault.set_archived(id, true))
+}
+
+#[command]
+async fn unarchive_entry(id: Uuid, state: State<'_, AppState>, app: AppHandle) -> Result<(), VaultError> {
+    mutate_vault(&state, &app, |vault| vault.set_archived(id, false))
+}
+
+#[command]
+async fn toggle_favorite(id: Uuid, state: State<'_, AppState>, app: AppHandle) -> Result<bool, VaultError> {
+    mutate_vault(&state, &app, |vault| vault.toggle_favorite(id))
+}
+
+/// Require biometrics to reveal or copy an entry's password. Lifting the
+/// requirement takes a token from `reauthenticate`, so it still works when
+/// biometrics are gone.
+#[command]
+async fn set_entry_require_biometric(
+    id: Uuid,
+    required: bool,
+    token: Option<String>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<(), VaultError> {
+    if required {
+        biometrics::preferred_method(&*state.authenticators()).ok_or(VaultError::BiometricUnavailable)?;
+    } else {
+        let (name, _) = active_vault_key(&state)?;
+        consume_reauth_token(&state, token.as_deref(), &name)?;
+    }
+    mutate_vault(&state, &app, |vault| vault.set_require_biometric(id, required))?;
+    state.verified_entries.lock_or_recover().remove(&id);
+    Ok(())
+}
+
+/// Prompt before an entry marked `require_biometric` is revealed or copied.
+/// A pass is remembered for `Settings::biometric_grace_seconds`, so
+/// revealing and then copying prompts once.
+async fn confirm_gated_entry(
+    state: &AppState,
+    app: &AppHandle,
+    id: Uuid,
+    operation: PromptOperation,
+) -> Result<(), VaultError> {
+    let gated_title = read_vault(state, |vault| {
+        let entry = vault.entry(id)?;
+        Ok(entry.require_biometric.then(|| entry.title.clone()))
+    })?;
+    let Some(title) = gated_title else {
+        return Ok(());
+    };
+    let grace = std::time::Duration::from_secs(u64::from(state.settings.lock_or_recover().biometric_grace_seconds));
+    {
+        let mut verified = state.verified_entries.lock_or_recover();
+        verified.retain(|_, at| at.elapsed() < grace);
+        if verified.contains_key(&id) {
+            return Ok(());
+        }
+    }
+
+    let method = biometrics::preferred_method(&*state.authenticators()).ok_or(VaultError::BiometricRequired)?;
+    let context = BiometricPromptContext::new(operation).with_entry(&title);
+    require_biometrics(state, app, method, &context).await?;
+    state.verified_entries.lock_or_recover().insert(id, Instant::now());
+    Ok(())
+}
+
+#[command]
+async fn add_entry(entry: EntryInput, state: State<'_, AppState>, app: AppHandle) -> Result<Uuid, VaultError> {
+    mutate_vault(&state, &app, |vault| vault.add_entry(entry))
+}
+
+#[command]
+async fn update_entry(id: Uuid, entry: EntryInput, state: State<'_, AppState>, app: AppHandle) -> Result<(), VaultError> {
+    let history_limit = state.settings.lock_or_recover().password_history_limit;
+    mutate_vault(&state, &app, |vault| vault.update_entry(id, entry, history_limit))
+}
+
+/// Previous passwords of an entry, oldest first. Gated like `get_entry`.
+#[command]
+async fn get_password_history(
+    id: Uuid,
+    session: String,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<Vec<PasswordHistoryItem>, VaultError> {
+    check_session(&state, &session)?;
+    confirm_gated_entry(&state, &app, id, PromptOperation::RevealPassword).await?;
+    read_vault(&state, |vault| Ok(vault.entry(id)?.password_history.clone()))
+}
+
+#[command]
+async fn clear_password_history(id: Uuid, state: State<'_, AppState>, app: AppHandle) -> Result<(), VaultError> {
+    mutate_vault(&state, &app, |vault| {
+        vault.editable_entry_mut(id)?.password_history.clear();
+        Ok(())
+    })
+}
+
+/// Generator options last used for an entry's password, if any
+#[command]
+async fn get_entry_generator_prefs(id: Uuid, state: State<'_, AppState>) -> Result<Option<GeneratorOptions>, VaultError> {
+    read_vault(&state, |vault| Ok(vault.entry(id)?.generator_prefs.clone()))
+}
+
+/// `rotate_entry_password` taking plain generator options
+#[command]
+async fn regenerate_entry_password(
+    id: Uuid,
+    options: Option<GeneratorOptions>,
+    session: String,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<GeneratedPassword, VaultError> {
+    check_session(&state, &session)?;
+    rotate_password(&state, &app, id, options.map(PasswordSource::Options)).await
+}
+
+/// What `rotate_entry_password` generates from
+#[derive(serde::Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+enum PasswordSource {
+    Options(GeneratorOptions),
+    /// A saved generator policy, by name
+    Policy(String),
+}
+
+/// Replace an entry's password with one generated here, moving the old one
+/// into its history, and save. The entry is unchanged if the save fails.
+/// Without `generator`, the policy of the entry's folder applies, then the
+/// entry's last options, then the defaults. With `check_breached`, the
+/// password is checked as in `generate_password` before the entry changes.
+/// The new password is only ever returned here; `ENTRY_UPDATED_EVENT` tells
+/// open views to refresh.
+#[command]
+async fn rotate_entry_password(
+    entry_id: Uuid,
+    generator: Option<PasswordSource>,
+    session: String,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<GeneratedPassword, VaultError> {
+    check_session(&state, &session)?;
+    rotate_password(&state, &app, entry_id, generator).await
+}
+
+/// Replace an entry's password with one generated from `source`, falling
+/// back to the entry's folder policy, last options or the defaults
+async fn rotate_password(
+    state: &AppState,
+    app: &AppHandle,
+    entry_id: Uuid,
+    source: Option<PasswordSource>,
+) -> Result<GeneratedPassword, VaultError> {
+    let options = match source {
+        Some(PasswordSource::Options(options)) => options,
+        Some(PasswordSource::Policy(policy)) => {
+            state.settings.lock_or_recover().generator_policy(&policy)?.options.clone()
+        }
+        None => read_vault(state, |vault| match vault.entry_generator_policy(entry_id)? {
+            Some(policy) => Ok(state.settings.lock_or_recover().generator_policy(policy)?.options.clone()),
+            None => Ok(vault.entry(entry_id)?.generator_prefs.clone().unwrap_or_default()),
+        })?,
+    };
+
+    // The lookup may take seconds, so it runs before the vault is locked
+    let (generated, breach_check) = generate_checked(state, &options).await?;
+    let history_limit = state.settings.lock_or_recover().password_history_limit;
+    mutate_vault(state, app, |vault| {
+        vault.set_generated_password(entry_id, &generated.password, options, history_limit)
+    })?;
+    let _ = app.emit_all(ENTRY_UPDATED_EVENT, entry_id);
+    Ok(GeneratedPassword {
+        password: String::clone(&generated.password),
+        entropy_bits: generated.entropy_bits,
+        breach_check,
+    })
+}
+
+/// A generated password for the UI
+#[derive(serde::Serialize)]
+struct GeneratedPassword {
+    password: String,
+    /// log2 of how many passwords `options` allow, all equally likely
+    entropy_bits: f64,
+    breach_check: BreachCheck,
+}
+
+/// Generate a password from `options`. It is only stored in the active
+/// vault's generator history.
+#[command]
+async fn generate_password(
+    options: GeneratorOptions,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<GeneratedPassword, VaultError> {
+    let (generated, breach_check) = generate_checked(&state, &options).await?;
+    record_generated(&state, &app, &generated.password);
+    Ok(GeneratedPassword {
+        password: String::clone(&generated.password),
+        entropy_bits: generated.entropy_bits,
+        breach_check,
+    })
+}
+
+/// Generate from `options`, drawing again while Pwned Passwords knows the
+/// result if `check_breached` is set. A failed lookup, or `offline_mode`,
+/// returns the password unchecked rather than not at all.
+async fn generate_checked(
+    state: &AppState,
+    options: &GeneratorOptions,
+) -> Result<(generator::Generated, BreachCheck), VaultError> {
+    let generate = || generator::generate(options).map_err(|e| VaultError::InvalidFields(vec![e]));
+    if !options.check_breached {
+        return Ok((generate()?, BreachCheck::NotRequested));
+    }
+    if state.settings.lock_or_recover().offline_mode {
+        return Ok((generate()?, BreachCheck::Unchecked));
+    }
+    for _ in 0..=MAX_BREACH_REDRAWS {
+        let generated = generate()?;
+        match breach::is_breached(&generated.password).await {
+            Some(true) => continue,
+            Some(false) => return Ok((generated, BreachCheck::Passed)),
+            None => return Ok((generated, BreachCheck::Unchecked)),
+        }
+    }
+    // Only plausible when the options allow very few passwords
+    Err(VaultError::InvalidFields(vec![FieldError::new(
+        "check_breached",
+        "Every password generated was found in Pwned Passwords; allow longer passwords",
+    )]))
+}
+
+/// Save generator options under `name`, replacing any policy of that name.
+/// Options are checked here so a saved policy can always generate.
+#[command]
+async fn save_generator_policy(
+    name: String,
+    options: GeneratorOptions,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<(), VaultError> {
+    let name = generator::policy_name(&name).map_err(|e| VaultError::InvalidFields(vec![e]))?;
+    generator::validate(&options).map_err(|e| VaultError::InvalidFields(vec![e]))?;
+    update_generator_policies(&state, &app, |policies| {
+        match policies.iter_mut().find(|policy| policy.name == name) {
+            Some(policy) => policy.options = options,
+            None => policies.push(GeneratorPolicy { name, options }),
+        }
+        Ok(())
+    })
+}
+
+#[command]
+async fn list_generator_policies(state: State<'_, AppState>) -> Result<Vec<GeneratorPolicy>, VaultError> {
+    Ok(state.settings.lock_or_recover().generator_policies.clone())
+}
+
+/// Delete a generator policy. Entries in folders still naming it need
+/// explicit options to be regenerated.
+#[command]
+async fn delete_generator_policy(name: String, state: State<'_, AppState>, app: AppHandle) -> Result<(), VaultError> {
+    update_generator_policies(&state, &app, |policies| {
+        let index = policies.iter().position(|policy| policy.name == name.trim()).ok_or_else(|| {
+            VaultError::InvalidFields(vec![FieldError::new("name", "There is no generator policy by this name")])
+        })?;
+        policies.remove(index);
+        Ok(())
+    })
+}
+
+/// Apply `f` to the saved generator policies and persist them
+fn update_generator_policies(
+    state: &AppState,
+    app: &AppHandle,
+    f: impl FnOnce(&mut Vec<GeneratorPolicy>) -> Result<(), VaultError>,
+) -> Result<(), VaultError> {
+    let mut settings = state.settings.lock_or_recover();
+    let mut updated = settings.clone();
+    f(&mut updated.generator_policies)?;
+    settings::save(app, &updated)?;
+    *settings = updated;
+    Ok(())
+}
+
+/// Generate a password from a saved policy, stored only in the generator
+/// history
+#[command]
+async fn generate_with_policy(
+    name: String,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<GeneratedPassword, VaultError> {
+    let options = state.settings.lock_or_recover().generator_policy(&name)?.options.clone();
+    let (generated, breach_check) = generate_checked(&state, &options).await?;
+    record_generated(&state, &app, &generated.password);
+    Ok(GeneratedPassword {
+        password: String::clone(&generated.password),
+        entropy_bits: generated.entropy_bits,
+        breach_check,
+    })
+}
+
+/// Attach a saved generator policy to a folder and its subfolders, or
+/// detach it when `policy` is omitted
+#[command]
+async fn set_folder_generator_policy(
+    folder_id: Uuid,
+    policy: Option<String>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<(), VaultError> {
+    let policy = match policy {
+        Some(name) => Some(state.settings.lock_or_recover().generator_policy(&name)?.name.clone()),
+        None => None,
+    };
+    mutate_vault(&state, &app, |vault| vault.set_folder_generator_policy(folder_id, policy))
+}
+
+/// Keep a password just shown to the user in the active vault's generator
+/// history. Nothing is kept while no vault is unlocked, and a failed save
+/// must not cost the user the password, so it is only logged.
+fn record_generated(state: &AppState, app: &AppHandle, password: &str) {
+    match mutate_vault(state, app, |vault| {
+        vault.record_generated(password);
+        Ok(())
+    }) {
+        Ok(()) | Err(VaultError::VaultLocked) => {}
+        Err(e) => eprintln!("Failed to record generated password: {}", e),
+    }
+}
+
+/// Passwords generated in the last week, oldest first. Gated like
+/// `get_password_history`.
+#[command]
+async fn get_generator_history(
+    session: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<GeneratorHistoryItem>, VaultError> {
+    check_session(&state, &session)?;
+    read_vault(&state, |vault| Ok(vault.generator_history()))
+}
+
+#[command]
+async fn clear_generator_history(state: State<'_, AppState>, app: AppHandle) -> Result<(), VaultError> {
+    mutate_vault(&state, &app, |vault| {
+        vault.generator_history.clear();
+        Ok(())
+    })
+}
+
+/// A generated PIN for the UI
+#[derive(serde::Serialize)]
+struct GeneratedPin {
+    pin: String,
+    /// Whether weak draws, e.g. `1234` or `2580`, were thrown away
+    rerolled: bool,
+}
+
+/// Generate a PIN for a door code or bank card, stored only in the
+/// generator history
+#[command]
+async fn generate_pin(length: usize, state: State<'_, AppState>, app: AppHandle) -> Result<GeneratedPin, VaultError> {
+    let (pin, rerolled) = generator::generate_pin(length).map_err(|e| VaultError::InvalidFields(vec![e]))?;
+    record_generated(&state, &app, &pin);
+    Ok(GeneratedPin {
+        pin: String::clone(&pin),
+        rerolled,
+    })
+}
+
+/// Generate a diceware-style passphrase from `options`, stored only in the
+/// generator history
+#[command]
+async fn generate_passphrase(
+    options: passphrase::PassphraseOptions,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<GeneratedPassword, VaultError> {
+    let generated = passphrase::generate(&options)?;
+    record_generated(&state, &app, &generated.password);
+    Ok(GeneratedPassword {
+        password: String::clone(&generated.password),
+        entropy_bits: generated.entropy_bits,
+        breach_check: BreachCheck::NotRequested,
+    })
+}
+
+/// Wordlists `generate_passphrase` can use without loading a file
+#[command]
+async fn list_wordlists() -> Result<Vec<passphrase::WordlistInfo>, VaultError> {
+    Ok(passphrase::builtin_wordlists())
+}
+
+/// Check a wordlist file before passing it to `generate_passphrase`
+#[command]
+async fn load_wordlist(path: String) -> Result<passphrase::WordlistInfo, VaultError> {
+    passphrase::load_wordlist(std::path::Path::new(&path))
+}
+
+/// Move an entry to the trash
+#[command]
+async fn delete_entry(id: Uuid, state: State<'_, AppState>, app: AppHandle) -> Result<(), VaultError> {
+    mutate_vault(&state, &app, |vault| vault.delete_entry(id))
+}
+
+#[command]
+async fn restore_entry(id: Uuid, state: State<'_, AppState>, app: AppHandle) -> Result<(), VaultError> {
+    mutate_vault(&state, &app, |vault| vault.restore_entry(id))
+}
+
+/// Permanently remove entries trashed more than `older_than_days` ago
+/// (0 empties the trash). Returns how many were removed.
+#[command]
+async fn purge_trash(older_than_days: u32, state: State<'_, AppState>, app: AppHandle) -> Result<usize, VaultError> {
+    mutate_vault(&state, &app, |vault| Ok(vault.purge_trash(chrono::Duration::days(older_than_days as i64))))
+}
+
+#[derive(serde::Serialize)]
+struct UsageStats {
+    most_used: Vec<EntrySummary>,
+    never_used: Vec<EntrySummary>,
+    /// Never used and older than a year; candidates for cleanup
+    stale: Vec<EntrySummary>,
+}
+
+const MOST_USED_LIMIT: usize = 10;
+const STALE_ENTRY_DAYS: i64 = 365;
+
+#[command]
+async fn get_usage_stats(state: State<'_, AppState>) -> Result<UsageStats, VaultError> {
+    let summaries = |entries: Vec<&vault::Entry>| -> Vec<EntrySummary> {
+        entries.into_iter().map(EntrySummary::from).collect()
+    };
+    read_vault(&state, |vault| {
+        Ok(UsageStats {
+            most_used: summaries(vault.most_used_entries(MOST_USED_LIMIT)),
+            never_used: summaries(vault.never_used_entries()),
+            stale: summaries(vault.stale_entries(chrono::Duration::days(STALE_ENTRY_DAYS))),
+        })
+    })
+}
+
+/// Built-in and user-defined templates
+#[command]
+async fn list_templates(state: State<'_, AppState>) -> Result<Vec<EntryTemplate>, VaultError> {
+    read_vault(&state, |vault| Ok(vault.all_templates()))
+}
+
+/// Save a template from an existing entry's layout or from explicit fields
+#[command]
+async fn create_template(
+    name: String,
+    from_entry_id: Option<Uuid>,
+    fields: Option<TemplateFields>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<Uuid, VaultError> {
+    mutate_vault(&state, &app, |vault| vault.create_template(&name, from_entry_id, fields))
+}
+
+#[command]
+async fn delete_template(id: Uuid, state: State<'_, AppState>, app: AppHandle) -> Result<(), VaultError> {
+    mutate_vault(&state, &app, |vault| vault.delete_template(id))
+}
+
+#[command]
+async fn create_entry_from_template(
+    template_id: Uuid,
+    title: Option<String>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<Uuid, VaultError> {
+    mutate_vault(&state, &app, |vault| vault.create_entry_from_template(template_id, title))
+}
+
+/// Apply one action to many entries with a single save. If any id fails,
+/// nothing is saved and the error lists the failing ids.
+#[command]
+async fn bulk_update_entries(
+    ids: Vec<Uuid>,
+    action: BulkAction,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<(), VaultError> {
+    mutate_vault(&state, &app, |vault| vault.bulk_update(&ids, &action))
+}
+
+/// Icon of the entry's site as a data URL, from the cache or downloaded.
+/// `None` when the entry has no URL or the site offers no usable icon.
+#[command]
+async fn fetch_entry_icon(id: Uuid, state: State<'_, AppState>, app: AppHandle) -> Result<Option<String>, VaultError> {
+    let allowed = {
+        let settings = state.settings.lock_or_recover();
+        settings.fetch_icons && !settings.offline_mode
+    };
+    if !allowed {
+        return Err(VaultError::IconFetchDisabled);
+    }
+    let Some(host) = read_vault(&state, |vault| Ok(vault.entry(id)?.site_host()))? else {
+        return Ok(None);
+    };
+    if let Some(icon) = icons::cached(&app, &host)? {
+        return Ok(Some(icon));
+    }
+
+    state
+        .icon_fetches
+        .lock_or_recover()
+        .try_acquire()
+        .map_err(|wait| VaultError::RateLimited {
+            retry_after_secs: wait.as_secs().max(1),
+        })?;
+    let Some(bytes) = icons::fetch(&host).await else {
+        return Ok(None);
+    };
+
+    // The vault may have been locked while the download was in flight
+    read_vault(&state, |_| Ok(()))?;
+    icons::store(&app, &host, &bytes)?;
+    Ok(icons::data_url(&bytes))
+}
+
+#[command]
+async fn clear_icon_cache(app: AppHandle) -> Result<(), VaultError> {
+    icons::clear_cache(&app)
+}
+
+/// Entries whose URL rules match `url`, most specific first
+#[command]
+async fn find_entries_for_url(
+    url: String,
+    include_archived: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<Vec<EntrySummary>, VaultError> {
+    read_vault(&state, |vault| {
+        Ok(vault
+            .entries_for_url(&url, include_archived.unwrap_or(false))
+            .into_iter()
+            .map(EntrySummary::from)
+            .collect())
+    })
+}
+
+/// Groups of likely duplicate entries: same site (ignoring scheme and
+/// `www.`) and username, optionally also the same password
+#[command]
+async fn find_duplicate_entries(
+    include_passwords: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<Vec<Vec<EntrySummary>>, VaultError> {
+    read_vault(&state, |vault| {
+        Ok(vault
+            .duplicate_groups(include_passwords.unwrap_or(false))
+            .into_iter()
+            .map(|group| group.into_iter().map(EntrySummary::from).collect())
+            .collect())
+    })
+}
+
+/// Merge `merge_ids` into `keep_id`; the merged entries go to the trash
+#[command]
+async fn merge_entries(
+    keep_id: Uuid,
+    merge_ids: Vec<Uuid>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<(), VaultError> {
+    let history_limit = state.settings.lock_or_recover().password_history_limit;
+    mutate_vault(&state, &app, |vault| vault.merge_entries(keep_id, &merge_ids, history_limit))
+}
+
+/// Set an entry's expiry date, or clear it when `expires_at` is omitted
+#[command]
+async fn set_entry_expiry(
+    id: Uuid,
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<(), VaultError> {
+    let expired = mutate_vault(&state, &app, |vault| {
+        vault.set_entry_expiry(id, expires_at)?;
+        Ok(vault.expired_count())
+    })?;
+    update_tray_tooltip(&app, expired);
+    Ok(())
+}
+
+/// Entries already expired or expiring within `within_days`, soonest first
+#[command]
+async fn list_expiring_entries(within_days: u32, state: State<'_, AppState>) -> Result<Vec<EntrySummary>, VaultError> {
+    read_vault(&state, |vault| {
+        Ok(vault
+            .expiring_entries(chrono::Duration::days(within_days as i64))
+            .into_iter()
+            .map(EntrySummary::from)
+            .collect())
+    })
+}
+
+#[command]
+async fn set_entry_tags(id: Uuid, tags: Vec<String>, state: State<'_, AppState>, app: AppHandle) -> Result<(), VaultError> {
+    mutate_vault(&state, &app, |vault| vault.set_entry_tags(id, tags))
+}
+
+#[command]
+async fn list_tags(state: State<'_, AppState>) -> Result<Vec<TagCount>, VaultError> {
+    read_vault(&state, |vault| Ok(vault.tag_counts()))
+}
+
+/// Encrypt a file and attach it to an entry
+#[command]
+async fn add_attachment(
+    entry_id: Uuid,
+    file_path: String,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<AttachmentInfo, VaultError> {
+    let max_size = state.settings.lock_or_recover().max_attachment_size;
+    let path = std::path::Path::new(&file_path);
+    if std::fs::metadata(path)?.len() > max_size {
+        return Err(VaultError::AttachmentTooLarge { max_size });
+    }
+    let contents = Zeroizing::new(std::fs::read(path)?);
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "attachment".to_string());
+
+    let (vault_name, key) = active_vault_key(&state)?;
+    let (attachment, sidecar) = attachments::seal(&key, &file_name, &contents).map_err(VaultError::Crypto)?;
+    let info = AttachmentInfo::from(&attachment);
+
+    let sidecar_path = storage::attachment_path(&app, &vault_name, attachment.id)?;
+    if let Some(bytes) = &sidecar {
+        storage::write_atomic(&sidecar_path, bytes)?;
+    }
+
+    let result = mutate_vault(&state, &app, |vault| {
+        vault.editable_entry_mut(entry_id)?.attachments.push(attachment);
+        Ok(())
+    });
+    if result.is_err() && sidecar.is_some() {
+        let _ = std::fs::remove_file(&sidecar_path);
+    }
+    result.map(|_| info)
+}
+
+#[command]
+async fn list_attachments(entry_id: Uuid, state: State<'_, AppState>) -> Result<Vec<AttachmentInfo>, VaultError> {
+    read_vault(&state, |vault| Ok(vault.entry(entry_id)?.attachments.iter().map(AttachmentInfo::from).collect()))
+}
+
+/// Decrypt an attachment and write it to `dest_path`
+#[command]
+async fn save_attachment_to(
+    entry_id: Uuid,
+    attachment_id: Uuid,
+    dest_path: String,
+    session: String,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<(), VaultError> {
+    check_session(&state, &session)?;
+    let (vault_name, key) = active_vault_key(&state)?;
+    let attachment = read_vault(&state, |vault| vault.attachment(entry_id, attachment_id).cloned())?;
+
+    let sidecar = if attachment.is_sidecar() {
+        storage::read_file(&storage::attachment_path(&app, &vault_name, attachment.id)?)?
+    } else {
+        None
+    };
+    let contents = attachments::open(&key, &attachment, sidecar.as_deref()).map_err(VaultError::Crypto)?;
+    storage::write_atomic(std::path::Path::new(&dest_path), &contents)
+}
+
+#[command]
+async fn delete_attachment(
+    entry_id: Uuid,
+    attachment_id: Uuid,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<(), VaultError> {
+    mutate_vault(&state, &app, |vault| vault.delete_attachment(entry_id, attachment_id))
+}
+
+#[command]
+async fn list_folders(state: State<'_, AppState>) -> Result<Vec<Folder>, VaultError> {
+    read_vault(&state, |vault| Ok(vault.folders.clone()))
+}
+
+#[command]
+async fn create_folder(
+    name: String,
+    parent_id: Option<Uuid>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<Uuid, VaultError> {
+    mutate_vault(&state, &app, |vault| vault.create_folder(&name, parent_id))
+}
+
+#[command]
+async fn rename_folder(id: Uuid, name: String, state: State<'_, AppState>, app: AppHandle) -> Result<(), VaultError> {
+    mutate_vault(&state, &app, |vault| vault.rename_folder(id, &name))
+}
+
+/// Delete a folder, moving its entries and subfolders to `move_children_to`
+/// (defaults to the deleted folder's parent)
+#[command]
+async fn delete_folder(
+    id: Uuid,
+    move_children_to: Option<Uuid>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<(), VaultError> {
+    mutate_vault(&state, &app, |vault| vault.delete_folder(id, move_children_to))
+}
+
+/// Move an entry into a folder, or to the top level when `folder_id` is omitted
+#[command]
+async fn move_entry_to_folder(
+    entry_id: Uuid,
+    folder_id: Option<Uuid>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<(), VaultError> {
+    mutate_vault(&state, &app, |vault| vault.move_entry_to_folder(entry_id, folder_id))
+}
+
+#[derive(serde::Serialize)]
+struct VaultProfile {
+    name: String,
+    unlocked: bool,
+    active: bool,
+}
+
+#[command]
+async fn list_vaults(state: State<'_, AppState>, app: AppHandle) -> Result<Vec<VaultProfile>, VaultError> {
+    let names = storage::list_vault_names(&app)?;
+    let vaults = state.vaults();
+
+    Ok(names
+        .into_iter()
+        .map(|name| VaultProfile {
+            unlocked: vaults.is_unlocked(&name),
+            active: vaults.active_name() == Some(name.as_str()),
+            name,
+        })
+        .collect())
+}
+
+/// Switch between vaults that are already unlocked (requires `allow_multiple_vaults`)
+#[command]
+async fn set_active_vault(name: String, state: State<'_, AppState>, app: AppHandle) -> Result<(), VaultError> {
+    state.vaults().set_active(&name)?;
+    publish_lock_state(&state, &app);
+    Ok(())
+}
+
+#[command]
+async fn get_settings(state: State<'_, AppState>) -> Result<Settings, VaultError> {
+    Ok(state.settings.lock_or_recover().clone())
+}
+
+/// Save settings. Changing a security setting (see
+/// `Settings::security_changes`) requires a token from `reauthenticate`,
+/// and each change is written to the audit log.
 #[command]
-async fn unlock_vault(password: String, state: State<'_, AppState>, app: AppHandle) -> Result<bool, String> {
-    // In a real implementation, this would decrypt the vault
-    // For demo purposes, we'll use the same demo password
-    if password == "demo-password" {
-        *state.is_unlocked.lock().unwrap() = true;
-        *state.last_activity.lock().unwrap() = Some(Instant::now());
-        
-        // Update system tray menu to show lock option
-        if let Some(tray) = app.tray_handle_by_id("main") {
-            let is_unlocked = *state.is_unlocked.lock().unwrap();
-            let _ = tray.set_menu(create_system_tray_menu(is_unlocked));
-        }
-        
-        Ok(true)
+async fn update_settings(
+    settings: Settings,
+    token: Option<String>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<(), VaultError> {
+    if settings.wipe_after_failed_attempts == Some(0) {
+        return Err(VaultError::InvalidFields(vec![FieldError::new(
+            "wipe_after_failed_attempts",
+            "Allow at least one failed attempt",
+        )]));
+    }
+    if matches!(settings.soft_lock_minutes, Some(minutes) if minutes == 0 || minutes > soft_lock::MAX_MINUTES) {
+        return Err(VaultError::InvalidFields(vec![FieldError::new(
+            "soft_lock_minutes",
+            &format!("Must be between 1 and {} minutes", soft_lock::MAX_MINUTES),
+        )]));
+    }
+    if settings.lock_when_hidden_minutes == Some(0) {
+        return Err(VaultError::InvalidFields(vec![FieldError::new(
+            "lock_when_hidden_minutes",
+            "Must be at least 1 minute",
+        )]));
+    }
+    if !(MIN_BIOMETRIC_TIMEOUT_SECS..=MAX_BIOMETRIC_TIMEOUT_SECS).contains(&settings.biometric_timeout_seconds) {
+        return Err(VaultError::InvalidFields(vec![FieldError::new(
+            "biometric_timeout_seconds",
+            &format!(
+                "Must be between {} and {} seconds",
+                MIN_BIOMETRIC_TIMEOUT_SECS, MAX_BIOMETRIC_TIMEOUT_SECS
+            ),
+        )]));
+    }
+    if !(MIN_KEYCHAIN_TIMEOUT_SECS..=MAX_KEYCHAIN_TIMEOUT_SECS).contains(&settings.keychain_timeout_seconds) {
+        return Err(VaultError::InvalidFields(vec![FieldError::new(
+            "keychain_timeout_seconds",
+            &format!(
+                "Must be between {} and {} seconds",
+                MIN_KEYCHAIN_TIMEOUT_SECS, MAX_KEYCHAIN_TIMEOUT_SECS
+            ),
+        )]));
+    }
+    if matches!(
+        settings.clipboard_clear_seconds,
+        Some(seconds) if !(MIN_CLIPBOARD_CLEAR_SECS..=MAX_CLIPBOARD_CLEAR_SECS).contains(&seconds)
+    ) {
+        return Err(VaultError::InvalidFields(vec![FieldError::new(
+            "clipboard_clear_seconds",
+            &format!(
+                "Must be between {} and {} seconds",
+                MIN_CLIPBOARD_CLEAR_SECS, MAX_CLIPBOARD_CLEAR_SECS
+            ),
+        )]));
+    }
+    if matches!(
+        settings.staged_copy_delay_seconds,
+        Some(seconds) if seconds == 0 || seconds > MAX_STAGED_COPY_DELAY_SECS
+    ) {
+        return Err(VaultError::InvalidFields(vec![FieldError::new(
+            "staged_copy_delay_seconds",
+            &format!("Must be between 1 and {} seconds", MAX_STAGED_COPY_DELAY_SECS),
+        )]));
+    }
+    settings.validate_generator_policies()?;
+    if settings.min_master_password_score > strength::MAX_SCORE {
+        return Err(VaultError::InvalidFields(vec![FieldError::new(
+            "min_master_password_score",
+            &format!("Score must be between 0 and {}", strength::MAX_SCORE),
+        )]));
+    }
+    let (changes, sync_policy_changed) = {
+        let current = state.settings.lock_or_recover();
+        (current.security_changes(&settings), current.keychain_sync_policy != settings.keychain_sync_policy)
+    };
+    let name = if changes.is_empty() {
+        None
     } else {
-        Ok(false)
+        let name = state
+            .vaults()
+            .active_name()
+            .map(str::to_string)
+            .ok_or(VaultError::VaultLocked)?;
+        consume_reauth_token(&state, token.as_deref(), &name)?;
+        Some(name)
+    };
+
+    settings::save(&app, &settings)?;
+    biometrics::set_device_credential_fallback(settings.allow_device_credential_fallback);
+    keychain::set_timeout(std::time::Duration::from_secs(settings.keychain_timeout_seconds));
+    keychain::set_sync_policy(settings.keychain_sync_policy);
+    keychain::set_dedicated_collection(settings.dedicated_keychain_collection);
+    clipboard::set_primary_selection(settings.also_set_primary_selection);
+    if sync_policy_changed {
+        // Rewriting can wait on keychain prompts
+        std::thread::spawn(|| {
+            for error in keychain::triggered_by("update_settings", keychain::apply_sync_policy) {
+                eprintln!("Failed to apply keychain sync policy: {}", error);
+            }
+        });
+    }
+    state.biometric_availability.lock_or_recover().invalidate();
+    *state.settings.lock_or_recover() = settings;
+    if let Some(name) = name {
+        for (setting, before, after) in changes {
+            record_security_change(&app, &SecurityChange::new(&name, setting, before, after));
+        }
+    }
+    Ok(())
+}
+
+/// Add to the security audit log, which must not block the change itself
+fn record_security_change(app: &AppHandle, change: &SecurityChange) {
+    if let Err(e) = audit::record(app, change) {
+        eprintln!("Failed to record security change: {}", e);
     }
 }
 
+/// Lock the active vault
 #[command]
 async fn lock_vault(state: State<'_, AppState>, app: AppHandle) -> Result<(), String> {
-    *state.is_unlocked.lock().unwrap() = false;
-    *state.vault_data.lock().unwrap() = None;
-    *state.last_activity.lock().unwrap() = None;
+    let still_unlocked = {
+        let mut vaults = state.vaults();
+        vaults.lock_active(LockReason::Manual);
+        // A manual lock is always a hard lock
+        state.soft_locks.lock_or_recover().clear();
+        vaults.active_is_unlocked()
+    };
+    if !still_unlocked {
+        *state.last_activity.lock_or_recover() = None;
+    }
+    state.reauth_tokens.lock_or_recover().clear();
+    state.verified_entries.lock_or_recover().clear();
+    cancel_staged_copy(&state, &app);
+    clear_clipboard(&app, None, ClipboardClearReason::Locked);
+    publish_lock_state(&state, &app);
+    let expired = read_vault(&state, |vault| Ok(vault.expired_count())).unwrap_or(0);
+    update_tray_tooltip(&app, expired);
+    
+    Ok(())
+}
+
+/// Lock every unlocked vault (used by auto-lock and system lock triggers).
+/// An idle lock becomes a soft lock when `Settings::soft_lock_minutes` is set
+/// and biometrics or the system password are available to end it; any other
+/// lock is a hard lock.
+fn lock_all_vaults(state: &AppState, app: &AppHandle, reason: LockReason) {
+    forget_unlocked_vaults(state, reason);
+    cancel_staged_copy(state, app);
+    clear_clipboard(app, None, ClipboardClearReason::Locked);
+    publish_lock_state(state, app);
+    update_tray_tooltip(app, 0);
+}
+
+/// The part of `lock_all_vaults` that only touches `AppState`: lock every
+/// vault, soft-locking them after an idle timeout if enabled, and drop
+/// what was granted while they were unlocked
+fn forget_unlocked_vaults(state: &AppState, reason: LockReason) {
+    let soft_lock_minutes = match reason {
+        LockReason::IdleTimeout => state.settings.lock_or_recover().soft_lock_minutes,
+        _ => None,
+    };
+    let soft_lock_window = soft_lock_minutes
+        .filter(|_| !biometrics::get_system_authenticators(&*state.authenticators()).is_empty())
+        .map(|minutes| std::time::Duration::from_secs(u64::from(minutes) * 60));
+    {
+        let mut vaults = state.vaults();
+        let mut soft_locks = state.soft_locks.lock_or_recover();
+        soft_locks.clear();
+        if let Some(window) = soft_lock_window {
+            for (name, key) in vaults.unlocked_keys() {
+                if let Err(e) = soft_locks.hold(&name, &key, window) {
+                    eprintln!("Failed to soft-lock vault: {}", e);
+                }
+            }
+        }
+        vaults.lock_all(reason);
+    }
+    *state.last_activity.lock_or_recover() = None;
+    state.reauth_tokens.lock_or_recover().clear();
+    state.verified_entries.lock_or_recover().clear();
+}
+
+/// Whether the active vault is unlocked, and how or why not
+#[command]
+async fn get_vault_status(state: State<'_, AppState>) -> Result<LockState, String> {
+    Ok(state.vaults().lock_state())
+}
+
+#[command]
+async fn update_activity(state: State<'_, AppState>) -> Result<(), String> {
+    let mut last_activity = state.last_activity.lock_or_recover();
+    *last_activity = Some(Instant::now());
+    Ok(())
+}
+
+/// Set the idle timeout (`None` turns auto-lock off). While a vault is
+/// unlocked, turning auto-lock off or lengthening it requires a token from
+/// `reauthenticate` and is written to the audit log.
+#[command]
+async fn set_auto_lock_timer(
+    seconds: Option<u64>,
+    token: Option<String>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<(), VaultError> {
+    let current = *state.auto_lock_timer.lock_or_recover();
+    let weakens = match (current, seconds) {
+        (Some(_), None) => true,
+        (Some(current), Some(seconds)) => seconds > current,
+        (None, _) => false,
+    };
+    let name = state.vaults().active_name().map(str::to_string);
+    if let (true, Some(name)) = (weakens, &name) {
+        consume_reauth_token(&state, token.as_deref(), name)?;
+    }
+    *state.auto_lock_timer.lock_or_recover() = seconds;
+    if let (Some(name), true) = (&name, seconds != current) {
+        record_security_change(&app, &SecurityChange::new(name, "auto_lock_timer", current, seconds));
+    }
     
-    // Update system tray menu
+    // Update system tray menu to reflect auto-lock setting
     if let Some(tray) = app.tray_handle_by_id("main") {
-        let _ = tray.set_menu(create_system_tray_menu(false));
+        let is_unlocked = state.vaults().active_is_unlocked();
+        let _ = tray.set_menu(create_system_tray_menu(is_unlocked));
     }
     
     Ok(())
 }
 
 #[command]
-async fn get_vault_status(state: State<'_, AppState>) -> Result<bool, String> {
-    Ok(*state.is_unlocked.lock().unwrap())
+async fn get_auto_lock_timer(state: State<'_, AppState>) -> Result<Option<u64>, String> {
+    Ok(*state.auto_lock_timer.lock_or_recover())
+}
+
+/// Store a keychain entry. `service` is put under `com.safenode.`, so the
+/// frontend cannot reach entries of other applications.
+#[command]
+async fn save_to_keychain(service: String, account: String, password: String) -> Result<(), KeychainError> {
+    let password = Zeroizing::new(password);
+    keychain::check_account(&account)?;
+    keychain::set_async("save_to_keychain", keychain::namespaced(&service)?, account, password).await
+}
+
+/// Keychain entry stored with `save_to_keychain`
+#[command]
+async fn get_from_keychain(service: String, account: String) -> Result<Option<String>, KeychainError> {
+    keychain::check_account(&account)?;
+    let password = keychain::get_async(&keychain::namespaced(&service)?, &account).await?;
+    Ok(password.map(|password| String::clone(&password)))
+}
+
+/// Delete a keychain entry stored with `save_to_keychain`; `false` if there
+/// was none
+#[command]
+async fn delete_from_keychain(service: String, account: String) -> Result<bool, KeychainError> {
+    keychain::check_account(&account)?;
+    keychain::delete_async("delete_from_keychain", keychain::namespaced(&service)?, account).await
+}
+
+/// Whether a keychain entry stored with `save_to_keychain` exists. The
+/// secret itself is not returned.
+#[command]
+async fn keychain_entry_exists(service: String, account: String) -> Result<bool, KeychainError> {
+    keychain::check_account(&account)?;
+    keychain::exists_async(&keychain::namespaced(&service)?, &account).await
 }
 
+/// Keep `secret` in the keychain for unlocking vault `name`, the default
+/// vault when omitted. Deleted with the vault's other keychain secrets.
 #[command]
-async fn update_activity(state: State<'_, AppState>) -> Result<(), String> {
-    let mut last_activity = state.last_activity.lock().unwrap();
-    *last_activity = Some(Instant::now());
+async fn store_unlock_secret(app: AppHandle, name: Option<String>, secret: String) -> Result<(), VaultError> {
+    let secret = Zeroizing::new(secret);
+    let name = name.unwrap_or_else(|| storage::DEFAULT_VAULT_NAME.to_string());
+    storage::validate_vault_name(&name)?;
+    let account = VaultSecret::UnlockSecret.account(&storage::keychain_id(&app, &name)?);
+    keychain::blocking("store_unlock_secret", move || keychain::save_secret_bytes(&account, secret.as_bytes())).await?;
     Ok(())
 }
 
+/// Secret stored with `store_unlock_secret`, released only after a
+/// biometric or system password check like `unlock_with_biometrics`
 #[command]
-async fn set_auto_lock_timer(seconds: Option<u64>, state: State<'_, AppState>, app: AppHandle) -> Result<(), String> {
-    let mut timer = state.auto_lock_timer.lock().unwrap();
-    *timer = seconds;
-    
-    // Update system tray menu to reflect auto-lock setting
-    if let Some(tray) = app.tray_handle_by_id("main") {
-        let is_unlocked = *state.is_unlocked.lock().unwrap();
-        let _ = tray.set_menu(create_system_tray_menu(is_unlocked));
-    }
-    
-    Ok(())
+async fn get_unlock_secret(
+    name: Option<String>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<Option<String>, VaultError> {
+    let name = name.unwrap_or_else(|| storage::DEFAULT_VAULT_NAME.to_string());
+    storage::validate_vault_name(&name)?;
+    let method = biometrics::preferred_method(&*state.authenticators()).ok_or(VaultError::BiometricUnavailable)?;
+    require_biometrics(&state, &app, method, &BiometricPromptContext::new(PromptOperation::UnlockVault)).await?;
+    let account = VaultSecret::UnlockSecret.account(&storage::keychain_id(&app, &name)?);
+    let Some(secret) = keychain::get_secret_bytes_async("get_unlock_secret", account).await? else {
+        return Ok(None);
+    };
+    String::from_utf8(secret.to_vec())
+        .map(Some)
+        .map_err(|_| VaultError::Io("Unlock secret is not valid UTF-8".to_string()))
 }
 
+/// Delete the secret stored with `store_unlock_secret`; `false` if there
+/// was none
 #[command]
-async fn get_auto_lock_timer(state: State<'_, AppState>) -> Result<Option<u64>, String> {
-    Ok(*state.auto_lock_timer.lock().unwrap())
+async fn delete_unlock_secret(app: AppHandle, name: Option<String>) -> Result<bool, VaultError> {
+    let name = name.unwrap_or_else(|| storage::DEFAULT_VAULT_NAME.to_string());
+    storage::validate_vault_name(&name)?;
+    let account = VaultSecret::UnlockSecret.account(&storage::keychain_id(&app, &name)?);
+    Ok(keychain::delete_async("delete_unlock_secret", keychain::SERVICE.to_string(), account).await?)
 }
 
+/// SafeNode's keychain secrets for the vaults on disk and whether each is
+/// still of use. The active vault's biometric key is checked against its
+/// data key when the vault is unlocked.
 #[command]
-async fn save_to_keychain(service: String, account: String, password: String) -> Result<(), String> {
-    let entry = Entry::new(&service, &account)
-        .map_err(|e| format!("Failed to create keychain entry: {}", e))?;
-    
-    entry.set_password(&password)
-        .map_err(|e| format!("Failed to save to keychain: {}", e))?;
-    
-    Ok(())
+async fn keychain_health_check(
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<Vec<keychain_health::SecretReport>, VaultError> {
+    let unlocked = active_vault_key(&state).ok();
+    keychain::blocking("keychain_health_check", move || {
+        keychain_health::check(&app, unlocked.as_ref().map(|(name, key)| (name.as_str(), key)))
+    })
+    .await
 }
 
+/// Delete the keychain secrets `keychain_health_check` finds corrupt or
+/// filed under the wrong vault, and orphaned ones when `delete_orphans`.
+/// Requires a token from `reauthenticate`. Returns the secrets deleted.
 #[command]
-async fn get_from_keychain(service: String, account: String) -> Result<Option<String>, String> {
-    let entry = Entry::new(&service, &account)
-        .map_err(|e| format!("Failed to create keychain entry: {}", e))?;
-    
-    match entry.get_password() {
-        Ok(password) => Ok(Some(password)),
-        Err(keyring::Error::NoEntry) => Ok(None),
-        Err(e) => Err(format!("Failed to get from keychain: {}", e)),
-    }
+async fn repair_keychain(
+    delete_orphans: bool,
+    token: String,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<Vec<keychain_health::SecretReport>, VaultError> {
+    let (name, key) = active_vault_key(&state)?;
+    consume_reauth_token(&state, Some(&token), &name)?;
+    keychain::blocking("repair_keychain", move || {
+        let report = keychain_health::check(&app, Some((&name, &key)))?;
+        keychain_health::repair(&report, delete_orphans)
+    })
+    .await
 }
 
+/// Where SafeNode's secrets go, `file-fallback` when there is no OS
+/// keychain, and whether it can be reached
 #[command]
-async fn delete_from_keychain(service: String, account: String) -> Result<(), String> {
-    let entry = Entry::new(&service, &account)
-        .map_err(|e| format!("Failed to create keychain entry: {}", e))?;
-    
-    entry.delete_password()
-        .map_err(|e| format!("Failed to delete from keychain: {}", e))?;
-    
-    Ok(())
+async fn keychain_backend_info() -> Result<keychain::BackendInfo, String> {
+    Ok(keychain::backend_info_async().await)
 }
 
 #[command]
-async fn list_keychain_accounts(_service: String) -> Result<Vec<String>, String> {
+async fn list_keychain_accounts(_service: String) -> Result<Vec<String>, KeychainError> {
     // Note: The keyring crate doesn't directly support listing accounts
     // This is a limitation - we return an empty vec for now
     // In a production app, you might need platform-specific implementations
@@ -133,27 +2808,473 @@ async fn list_keychain_accounts(_service: String) -> Result<Vec<String>, String>
     Ok(vec![])
 }
 
+/// Availability of the preferred method, cached for
+/// `biometrics::AVAILABILITY_TTL`. A re-check can take a D-Bus round trip,
+/// so it runs on a blocking thread.
+#[command]
+async fn check_biometric_available<R: Runtime>(
+    state: State<'_, AppState>,
+    app: AppHandle<R>,
+) -> Result<serde_json::Value, BiometricError> {
+    if let Some(availability) = state.biometric_availability.lock_or_recover().fresh() {
+        return Ok(availability);
+    }
+    tauri::async_runtime::spawn_blocking(move || recheck_biometric_availability(&app))
+        .await
+        .map_err(|e| BiometricError::platform(e.to_string()))?
+}
+
+/// Check availability now, ignoring the cache
+#[command]
+async fn refresh_biometric_availability(
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<serde_json::Value, BiometricError> {
+    state.biometric_availability.lock_or_recover().invalidate();
+    tauri::async_runtime::spawn_blocking(move || recheck_biometric_availability(&app))
+        .await
+        .map_err(|e| BiometricError::platform(e.to_string()))?
+}
+
+/// Ask the OS for availability, cache the answer and tell the UI if it
+/// changed. Blocks until the OS answers.
+fn recheck_biometric_availability<R: Runtime>(app: &AppHandle<R>) -> Result<serde_json::Value, BiometricError> {
+    let (availability, changed) = store_biometric_availability(&app.state::<AppState>())?;
+    if changed {
+        let _ = app.emit_all(BIOMETRIC_AVAILABILITY_CHANGED_EVENT, &availability);
+    }
+    Ok(availability)
+}
+
+/// Ask the authenticators of `state` for availability and cache the
+/// answer. Returns it and whether it changed.
+fn store_biometric_availability(state: &AppState) -> Result<(serde_json::Value, bool), BiometricError> {
+    let availability = biometrics::check_biometric_available(&*state.authenticators())?;
+    let changed = state.biometric_availability.lock_or_recover().store(availability.clone());
+    Ok((availability, changed))
+}
+
+/// Same as `check_biometric_available`. Both names are registered so
+/// callers of either keep working; new code should use this one.
 #[command]
-async fn check_biometric_available() -> Result<serde_json::Value, String> {
-    biometrics::check_biometric_available()
+async fn biometric_available<R: Runtime>(
+    state: State<'_, AppState>,
+    app: AppHandle<R>,
+) -> Result<serde_json::Value, BiometricError> {
+    check_biometric_available(state, app).await
 }
 
+/// Prompt for `method` (default: the preferred usable one), giving up after
+/// `timeout_seconds` (default `Settings::biometric_timeout_seconds`). The dialog
+/// shows the text for `context`, or else the raw `prompt`.
 #[command]
-async fn authenticate_biometric(prompt: String) -> Result<serde_json::Value, String> {
-    biometrics::authenticate_biometric(&prompt)
+async fn authenticate_biometric<R: Runtime>(
+    prompt: Option<String>,
+    context: Option<BiometricPromptContext>,
+    method: Option<AuthMethod>,
+    timeout_seconds: Option<u64>,
+    state: State<'_, AppState>,
+    app: AppHandle<R>,
+) -> Result<serde_json::Value, BiometricError> {
+    let method = method
+        .or_else(|| biometrics::preferred_method(&*state.authenticators()))
+        .unwrap_or_default();
+    let timeout = timeout_seconds.map_or_else(|| prompt_timeout(&state), std::time::Duration::from_secs);
+    let prompt = match (context, prompt) {
+        (Some(context), _) => context.text(),
+        (None, Some(prompt)) => prompt,
+        (None, None) => BiometricPromptContext::new(PromptOperation::Reauthenticate).text(),
+    };
+    let result = show_biometric_prompt(&state, &app, method, &prompt, timeout).await?;
+    Ok(biometrics::authentication_json(&prompt, result))
 }
 
+/// Prompt for the preferred usable method with the raw `prompt` text,
+/// giving up after `Settings::biometric_timeout_seconds`. The short form
+/// of `authenticate_biometric` for callers with no prompt context; both are
+/// registered.
 #[command]
-async fn copy_to_clipboard(text: String) -> Result<(), String> {
-    // This would use the system clipboard
-    // For now, we'll just return success
-    println!("Copying to clipboard: {}", text);
-    Ok(())
+async fn biometric_authenticate<R: Runtime>(
+    prompt: String,
+    state: State<'_, AppState>,
+    app: AppHandle<R>,
+) -> Result<serde_json::Value, BiometricError> {
+    authenticate_biometric(Some(prompt), None, None, None, state, app).await
+}
+
+/// The real platform, or in debug builds the mock scripted by
+/// `biometrics::mock::ENV_VAR`
+fn platform_authenticators() -> Arc<dyn AuthenticatorSource> {
+    #[cfg(debug_assertions)]
+    if let Some(mock) = biometrics::mock::from_env() {
+        return Arc::new(mock);
+    }
+    Arc::new(biometrics::PlatformAuthenticators)
+}
+
+/// Replace the biometric backend with a mock that plays `behavior`, a
+/// comma-separated script of `succeed`, `fail`, `cancel`, `timeout`,
+/// `unavailable` and `hang`; `None` restores the real platform. Debug
+/// builds only.
+#[command]
+async fn set_biometric_mock(behavior: Option<String>, state: State<'_, AppState>) -> Result<(), String> {
+    #[cfg(debug_assertions)]
+    {
+        let source: Arc<dyn AuthenticatorSource> = match behavior {
+            Some(script) => {
+                let script = biometrics::mock::parse_script(&script)?;
+                Arc::new(biometrics::mock::MockAuthenticators(biometrics::mock::MockBiometricAuthenticator::new(script)))
+            }
+            None => Arc::new(biometrics::PlatformAuthenticators),
+        };
+        *state.authenticators.lock_or_recover() = source;
+        state.biometric_availability.lock_or_recover().invalidate();
+        Ok(())
+    }
+
+    #[cfg(not(debug_assertions))]
+    {
+        let _ = (behavior, state);
+        Err("The biometric mock is only available in debug builds".to_string())
+    }
+}
+
+/// `Settings::biometric_timeout_seconds`
+fn prompt_timeout(state: &AppState) -> std::time::Duration {
+    std::time::Duration::from_secs(state.settings.lock_or_recover().biometric_timeout_seconds)
+}
+
+/// `run_biometric_prompt`, telling the UI when biometrics got locked out
+async fn show_biometric_prompt<R: Runtime>(
+    state: &AppState,
+    app: &AppHandle<R>,
+    method: AuthMethod,
+    prompt: &str,
+    timeout: std::time::Duration,
+) -> Result<BiometricResult, BiometricError> {
+    let result = run_biometric_prompt(state, method, prompt, timeout).await;
+    match &result {
+        Ok(BiometricResult { error: Some(error), .. }) | Err(error) => notice_biometric_error(app, error),
+        _ => {}
+    }
+    result
+}
+
+/// Show a biometric prompt that `cancel_biometric_prompt` can end
+async fn run_biometric_prompt(
+    state: &AppState,
+    method: AuthMethod,
+    prompt: &str,
+    timeout: std::time::Duration,
+) -> Result<BiometricResult, BiometricError> {
+    let authenticator = state.authenticators().authenticator(method).ok_or(BiometricError::NotAvailable)?;
+    let cancel = begin_biometric_prompt(state);
+    let result = biometrics::authenticate(authenticator, prompt, timeout, cancel.clone()).await;
+    end_biometric_prompt(state, &cancel);
+    result
+}
+
+/// Once the OS locks biometrics out, tell the UI to offer only the password
+fn notice_biometric_error<R: Runtime>(app: &AppHandle<R>, error: &BiometricError) {
+    if *error == BiometricError::LockedOut {
+        app.state::<AppState>().biometric_availability.lock_or_recover().invalidate();
+        let _ = app.emit_all(BIOMETRIC_LOCKED_OUT_EVENT, ());
+    }
+}
+
+/// Dismiss the biometric prompt in flight, if any. Returns whether there
+/// was one.
+#[command]
+async fn cancel_biometric_prompt(state: State<'_, AppState>) -> Result<bool, String> {
+    let prompt = state.biometric_prompt.lock_or_recover().take();
+    Ok(prompt.map(|cancel| cancel.cancel()).is_some())
+}
+
+/// Track a new prompt for `cancel_biometric_prompt`. Only one prompt shows
+/// at a time, so any earlier one is cancelled.
+fn begin_biometric_prompt(state: &AppState) -> CancelToken {
+    let cancel = CancelToken::default();
+    let previous = state.biometric_prompt.lock_or_recover().replace(cancel.clone());
+    if let Some(previous) = previous {
+        previous.cancel();
+    }
+    cancel
+}
+
+fn end_biometric_prompt(state: &AppState, cancel: &CancelToken) {
+    let mut current = state.biometric_prompt.lock_or_recover();
+    if current.as_ref().is_some_and(|current| current.same_as(cancel)) {
+        *current = None;
+    }
+}
+
+/// Copy `text`; when it came from an entry, pass `entry_id` so the entry is
+/// recorded as used and, if it is marked `require_biometric`, confirmed first.
+/// Unless `sensitive` is false the copy is handled like any secret: kept out
+/// of clipboard history where possible and cleared again.
+#[command]
+async fn copy_to_clipboard(
+    text: String,
+    entry_id: Option<Uuid>,
+    sensitive: Option<bool>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<clipboard::CopyStatus, VaultError> {
+    let text = Zeroizing::new(text);
+    let Some(id) = entry_id else {
+        return copy_text(&state, &app, &text, sensitive.unwrap_or(true));
+    };
+    confirm_gated_entry(&state, &app, id, PromptOperation::CopyPassword).await?;
+    mutate_vault(&state, &app, |vault| vault.touch_entry(id))?;
+    // Held until the copy is made, like in `copy_secret_to_clipboard`
+    let vaults = state.vaults();
+    if !vaults.active_is_unlocked() {
+        return Err(VaultError::VaultLocked);
+    }
+    copy_text(&state, &app, &text, sensitive.unwrap_or(true))
+}
+
+/// Copy one field of an entry without sending it to the frontend first.
+/// Secret fields of entries marked `require_biometric` are confirmed first.
+#[command]
+async fn copy_secret_to_clipboard(
+    entry_id: Uuid,
+    field: clipboard::EntryField,
+    session: String,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<clipboard::CopyStatus, VaultError> {
+    check_session(&state, &session)?;
+    let (value, secret) = read_vault(&state, |vault| field.read(vault.entry(entry_id)?))?;
+    if secret {
+        confirm_gated_entry(&state, &app, entry_id, PromptOperation::CopyPassword).await?;
+    }
+    mutate_vault(&state, &app, |vault| vault.touch_entry(entry_id))?;
+    // Held until the copy is made: a lock clears the clipboard once it
+    // gets the vaults, so it cannot slip in between and leave this behind
+    let vaults = state.vaults();
+    vaults.check_session(&session)?;
+    copy_text(&state, &app, &value, secret)
+}
+
+/// Copy the entry's current one-time code, or the next one if it is about
+/// to expire. It is cleared when it expires or after
+/// `Settings::clipboard_clear_seconds`, whichever comes first. Returns how
+/// many seconds the copied code stays valid.
+#[command]
+async fn copy_totp_to_clipboard(
+    entry_id: Uuid,
+    session: String,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<u32, VaultError> {
+    check_session(&state, &session)?;
+    let key = read_vault(&state, |vault| {
+        let missing = || VaultError::InvalidFields(vec![FieldError::new("totp_secret", "The entry has no TOTP secret")]);
+        totp::decode_secret(&vault.entry(entry_id)?.totp_secret).ok_or_else(missing)
+    })?;
+    confirm_gated_entry(&state, &app, entry_id, PromptOperation::CopyPassword).await?;
+    mutate_vault(&state, &app, |vault| vault.touch_entry(entry_id))?;
+    let wait = totp::wait_before_copy(chrono::Utc::now().timestamp() as u64);
+    if wait > 0 {
+        let _ = tauri::async_runtime::spawn_blocking(move || std::thread::sleep(std::time::Duration::from_secs(wait)))
+            .await;
+    }
+
+    // Held until the copy is made, like in `copy_secret_to_clipboard`
+    let vaults = state.vaults();
+    vaults.check_session(&session)?;
+    let (code, valid_secs) = totp::code_at(&key, chrono::Utc::now().timestamp() as u64);
+    let valid_secs = valid_secs as u32;
+    let clear_seconds = state.settings.lock_or_recover().clipboard_clear_seconds;
+    copy_secret_text(&state, &app, &code, Some(clear_seconds.map_or(valid_secs, |secs| secs.min(valid_secs))))?;
+    Ok(valid_secs)
+}
+
+/// Copy an entry's username now and its password after
+/// `Settings::staged_copy_delay_seconds` or on `advance_staged_copy`,
+/// whichever comes first. Each step is reported on `STAGED_COPY_EVENT`.
+#[command]
+async fn copy_entry_credentials_staged(
+    entry_id: Uuid,
+    session: String,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<clipboard::CopyStatus, VaultError> {
+    check_session(&state, &session)?;
+    confirm_gated_entry(&state, &app, entry_id, PromptOperation::CopyPassword).await?;
+    let (username, password) = mutate_vault(&state, &app, |vault| {
+        vault.touch_entry(entry_id)?;
+        let entry = vault.entry(entry_id)?;
+        Ok((Zeroizing::new(entry.username.clone()), Zeroizing::new(entry.password.clone())))
+    })?;
+    let status = copy_text(&state, &app, &username, false)?;
+    let staged_at = Instant::now();
+    *state.staged_copy.lock_or_recover() = Some(StagedCopy { staged_at, entry_id, password });
+    emit_staged_copy(&app, entry_id, StagedCopyStep::Username);
+
+    let delay = state.settings.lock_or_recover().staged_copy_delay_seconds;
+    if let Some(delay) = delay {
+        let app = app.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_secs(u64::from(delay)));
+            if let Err(e) = advance_staged(&app.state::<AppState>(), &app, Some(staged_at)) {
+                eprintln!("Failed to copy the staged password: {}", e);
+            }
+        });
+    }
+    Ok(status)
+}
+
+/// Replace a staged username with its password now. `None` when nothing is
+/// staged.
+#[command]
+async fn advance_staged_copy(
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<Option<clipboard::CopyStatus>, VaultError> {
+    advance_staged(&state, &app, None)
+}
+
+/// A password waiting to replace its username on the clipboard
+struct StagedCopy {
+    staged_at: Instant,
+    entry_id: Uuid,
+    password: Zeroizing<String>,
+}
+
+#[derive(Clone, Copy, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+enum StagedCopyStep {
+    Username,
+    Password,
+    /// Locked, or replaced by another copy before the password was copied
+    Cancelled,
+}
+
+/// Payload of `STAGED_COPY_EVENT`
+#[derive(Clone, serde::Serialize)]
+struct StagedCopyEvent {
+    entry_id: Uuid,
+    step: StagedCopyStep,
+}
+
+fn emit_staged_copy(app: &AppHandle, entry_id: Uuid, step: StagedCopyStep) {
+    let _ = app.emit_all(STAGED_COPY_EVENT, StagedCopyEvent { entry_id, step });
+}
+
+/// Copy the staged password; `staged_at` limits this to the sequence
+/// started then, so a late timer cannot advance a newer one
+fn advance_staged(
+    state: &AppState,
+    app: &AppHandle,
+    staged_at: Option<Instant>,
+) -> Result<Option<clipboard::CopyStatus>, VaultError> {
+    // Held until the copy is made, like in `copy_secret_to_clipboard`
+    let vaults = state.vaults();
+    let staged = {
+        let mut staged_copy = state.staged_copy.lock_or_recover();
+        if staged_at.is_some() && staged_copy.as_ref().map(|staged| staged.staged_at) != staged_at {
+            return Ok(None);
+        }
+        staged_copy.take()
+    };
+    let Some(staged) = staged else {
+        return Ok(None);
+    };
+    if !vaults.active_is_unlocked() {
+        emit_staged_copy(app, staged.entry_id, StagedCopyStep::Cancelled);
+        return Err(VaultError::VaultLocked);
+    }
+    let status = copy_text(state, app, &staged.password, true)?;
+    emit_staged_copy(app, staged.entry_id, StagedCopyStep::Password);
+    Ok(Some(status))
+}
+
+/// Drop a staged password before it reaches the clipboard
+fn cancel_staged_copy(state: &AppState, app: &AppHandle) {
+    let cancelled = state.staged_copy.lock_or_recover().take();
+    if let Some(staged) = cancelled {
+        emit_staged_copy(app, staged.entry_id, StagedCopyStep::Cancelled);
+    }
+}
+
+/// Why a copied secret was cleared
+#[derive(Clone, Copy, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+enum ClipboardClearReason {
+    Timeout,
+    Locked,
+}
+
+/// Payload of `CLIPBOARD_WILL_CLEAR_EVENT`
+#[derive(Clone, serde::Serialize)]
+struct ClipboardCountdown {
+    remaining_secs: u32,
+    total_secs: u32,
+}
+
+/// Payload of `CLIPBOARD_CLEARED_EVENT`
+#[derive(Clone, serde::Serialize)]
+struct ClipboardCleared {
+    reason: ClipboardClearReason,
+    outcome: clipboard::ClearOutcome,
+}
+
+/// Copy `text`, and count down to clearing it again when it is a secret.
+/// Any staged copy is cancelled, as it would overwrite this one.
+fn copy_text(state: &AppState, app: &AppHandle, text: &str, secret: bool) -> Result<clipboard::CopyStatus, VaultError> {
+    if !secret {
+        cancel_staged_copy(state, app);
+        return Ok(clipboard::copy(text)?);
+    }
+    let clear_seconds = state.settings.lock_or_recover().clipboard_clear_seconds;
+    copy_secret_text(state, app, text, clear_seconds)
+}
+
+/// Copy the secret `text` and count down `clear_seconds` to clearing it,
+/// or keep it when `None`
+fn copy_secret_text(
+    state: &AppState,
+    app: &AppHandle,
+    text: &str,
+    clear_seconds: Option<u32>,
+) -> Result<clipboard::CopyStatus, VaultError> {
+    cancel_staged_copy(state, app);
+    let (generation, status) = clipboard::copy_secret(text)?;
+    let Some(total_secs) = clear_seconds else {
+        return Ok(status);
+    };
+    let app = app.clone();
+    std::thread::spawn(move || {
+        for remaining_secs in (1..=total_secs).rev() {
+            // Replaced by a later copy, or cleared on lock
+            if !clipboard::is_held(generation) {
+                return;
+            }
+            let _ = app.emit_all(CLIPBOARD_WILL_CLEAR_EVENT, ClipboardCountdown { remaining_secs, total_secs });
+            std::thread::sleep(std::time::Duration::from_secs(1));
+        }
+        clear_clipboard(&app, Some(generation), ClipboardClearReason::Timeout);
+    });
+    Ok(status)
+}
+
+/// Clear the secret copied as `generation`, or any secret SafeNode copied
+fn clear_clipboard(app: &AppHandle, generation: Option<u64>, reason: ClipboardClearReason) {
+    match clipboard::clear_secret(generation) {
+        Ok(clipboard::ClearOutcome::NothingHeld) => {}
+        Ok(outcome) => {
+            let _ = app.emit_all(CLIPBOARD_CLEARED_EVENT, ClipboardCleared { reason, outcome });
+        }
+        Err(e) => eprintln!("Failed to clear the clipboard: {}", e),
+    }
 }
 
 #[command]
 async fn show_system_tray(window: Window, state: State<'_, AppState>) -> Result<(), String> {
     window.hide().map_err(|e| format!("Failed to hide window: {}", e))?;
+    set_window_hidden(&state, true);
     // Update activity on hide
     let _ = update_activity(state).await;
     Ok(())
@@ -163,11 +3284,41 @@ async fn show_system_tray(window: Window, state: State<'_, AppState>) -> Result<
 async fn show_main_window(window: Window, state: State<'_, AppState>) -> Result<(), String> {
     window.show().map_err(|e| format!("Failed to show window: {}", e))?;
     window.set_focus().map_err(|e| format!("Failed to focus window: {}", e))?;
+    set_window_hidden(&state, false);
     // Update activity on show
     let _ = update_activity(state).await;
     Ok(())
 }
 
+/// Start or cancel the `lock_when_hidden_minutes` countdown, and let the
+/// clipboard report copies made while hidden
+fn set_window_hidden(state: &AppState, hidden: bool) {
+    clipboard::set_window_hidden(hidden);
+    let mut hidden_since = state.hidden_since.lock_or_recover();
+    if !hidden {
+        *hidden_since = None;
+    } else if hidden_since.is_none() {
+        *hidden_since = Some(Instant::now());
+    }
+}
+
+/// Change the idle timeout from the tray menu. The tray cannot ask for
+/// the master password, so when the change needs re-authentication the
+/// main window is shown instead for the user to make it in settings.
+fn set_auto_lock_from_tray(app: &AppHandle, seconds: Option<u64>) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let state = app.state::<AppState>();
+        if let Err(VaultError::ReauthenticationRequired) = set_auto_lock_timer(seconds, None, state, app.clone()).await {
+            if let Some(window) = app.get_window("main") {
+                let _ = window.show();
+                let _ = window.set_focus();
+                set_window_hidden(&app.state::<AppState>(), false);
+            }
+        }
+    });
+}
+
 // System tray menu items
 fn create_system_tray_menu(is_unlocked: bool) -> tauri::SystemTrayMenu {
     use tauri::{CustomMenuItem, SystemTrayMenu, SystemTrayMenuItem};
@@ -204,13 +3355,9 @@ fn create_system_tray_menu(is_unlocked: bool) -> tauri::SystemTrayMenu {
 }
 
 fn main() {
+    clipboard::install_panic_scrub();
     tauri::Builder::default()
-        .manage(AppState {
-            vault_data: Mutex::new(None),
-            is_unlocked: Mutex::new(false),
-            last_activity: Mutex::new(None),
-            auto_lock_timer: Mutex::new(Some(300)), // Default: 5 minutes
-        })
+        .manage(AppState::new(platform_authenticators()))
         .system_tray(tauri::SystemTray::new().with_id("main").with_menu(create_system_tray_menu(false)))
         .on_system_tray_event(|app, event| {
             match event {
@@ -223,15 +3370,17 @@ fn main() {
                     if let Some(window) = app.get_window("main") {
                         if window.is_visible().unwrap_or(false) {
                             let _ = window.hide();
+                            set_window_hidden(&app.state::<AppState>(), true);
                         } else {
                             let _ = window.show();
                             let _ = window.set_focus();
+                            set_window_hidden(&app.state::<AppState>(), false);
                             
                             // Update activity on show
                             let app_handle = app.clone();
                             tauri::async_runtime::spawn(async move {
                                 let state = app_handle.state::<AppState>();
-                                let mut last_activity = state.last_activity.lock().unwrap();
+                                let mut last_activity = state.last_activity.lock_or_recover();
                                 *last_activity = Some(Instant::now());
                             });
                         }
@@ -240,18 +3389,21 @@ fn main() {
                 tauri::SystemTrayEvent::MenuItemClick { id, .. } => {
                     match id.as_str() {
                         "quit" => {
-                            std::process::exit(0);
+                            // `AppHandle::exit` skips `RunEvent::Exit`
+                            clipboard::scrub();
+                            app.exit(0);
                         }
                         "show" => {
                             if let Some(window) = app.get_window("main") {
                                 let _ = window.show();
                                 let _ = window.set_focus();
+                                set_window_hidden(&app.state::<AppState>(), false);
                                 
                                 // Update activity on show
                                 let app_handle = app.clone();
                                 tauri::async_runtime::spawn(async move {
                                     let state = app_handle.state::<AppState>();
-                                    let mut last_activity = state.last_activity.lock().unwrap();
+                                    let mut last_activity = state.last_activity.lock_or_recover();
                                     *last_activity = Some(Instant::now());
                                 });
                             }
@@ -263,41 +3415,11 @@ fn main() {
                                 let _ = lock_vault(state, app_clone.clone()).await;
                             });
                         }
-                        "auto_lock_1" => {
-                            let app_clone = app.clone();
-                            tauri::async_runtime::spawn(async move {
-                                let state = app_clone.state::<AppState>();
-                                let _ = set_auto_lock_timer(Some(60), state, app_clone.clone()).await;
-                            });
-                        }
-                        "auto_lock_5" => {
-                            let app_clone = app.clone();
-                            tauri::async_runtime::spawn(async move {
-                                let state = app_clone.state::<AppState>();
-                                let _ = set_auto_lock_timer(Some(300), state, app_clone.clone()).await;
-                            });
-                        }
-                        "auto_lock_15" => {
-                            let app_clone = app.clone();
-                            tauri::async_runtime::spawn(async move {
-                                let state = app_clone.state::<AppState>();
-                                let _ = set_auto_lock_timer(Some(900), state, app_clone.clone()).await;
-                            });
-                        }
-                        "auto_lock_30" => {
-                            let app_clone = app.clone();
-                            tauri::async_runtime::spawn(async move {
-                                let state = app_clone.state::<AppState>();
-                                let _ = set_auto_lock_timer(Some(1800), state, app_clone.clone()).await;
-                            });
-                        }
-                        "auto_lock_off" => {
-                            let app_clone = app.clone();
-                            tauri::async_runtime::spawn(async move {
-                                let state = app_clone.state::<AppState>();
-                                let _ = set_auto_lock_timer(None, state, app_clone.clone()).await;
-                            });
-                        }
+                        "auto_lock_1" => set_auto_lock_from_tray(app, Some(60)),
+                        "auto_lock_5" => set_auto_lock_from_tray(app, Some(300)),
+                        "auto_lock_15" => set_auto_lock_from_tray(app, Some(900)),
+                        "auto_lock_30" => set_auto_lock_from_tray(app, Some(1800)),
+                        "auto_lock_off" => set_auto_lock_from_tray(app, None),
                         _ => {}
                     }
                 }
@@ -306,6 +3428,72 @@ fn main() {
         })
         .setup(|app| {
             let app_handle = app.handle().clone();
+
+            if let Err(e) = storage::migrate_legacy_vault(&app_handle) {
+                eprintln!("Failed to migrate legacy vault: {}", e);
+            }
+            keychain::init_audit(app_handle.clone());
+            let _ = app_handle.state::<AppState>().app.set(app_handle.clone());
+            match storage::data_dir(&app_handle) {
+                Ok(dir) => keychain::init_file_fallback(dir),
+                Err(e) => eprintln!("No place for the keychain fallback file: {}", e),
+            }
+            match storage::keychain_id(&app_handle, storage::DEFAULT_VAULT_NAME) {
+                Ok(id) => {
+                    if let Err(e) = keychain::migrate_legacy_entries(&id) {
+                        eprintln!("Failed to migrate legacy keychain entries: {}", e);
+                    }
+                }
+                Err(e) => eprintln!("Failed to migrate legacy keychain entries: {}", e),
+            }
+            let settings = settings::load(&app_handle);
+            biometrics::set_device_credential_fallback(settings.allow_device_credential_fallback);
+            keychain::set_timeout(std::time::Duration::from_secs(settings.keychain_timeout_seconds));
+            keychain::set_sync_policy(settings.keychain_sync_policy);
+            keychain::set_dedicated_collection(settings.dedicated_keychain_collection);
+            clipboard::set_primary_selection(settings.also_set_primary_selection);
+            *app_handle.state::<AppState>().settings.lock_or_recover() = settings;
+            *app_handle.state::<AppState>().unlock_throttle.lock_or_recover() = throttle::load(&app_handle);
+            *app_handle.state::<AppState>().unlock_history.lock_or_recover() = unlock_log::load(&app_handle);
+
+            // Biometrics may have been locked out or re-enabled while the
+            // window was in the background
+            if let Some(window) = app_handle.get_window("main") {
+                let focus_handle = app_handle.clone();
+                window.on_window_event(move |event| {
+                    if let tauri::WindowEvent::Focused(true) = event {
+                        focus_handle.state::<AppState>().biometric_availability.lock_or_recover().invalidate();
+                        let focus_handle = focus_handle.clone();
+                        std::thread::spawn(move || {
+                            let _ = recheck_biometric_availability(&focus_handle);
+                        });
+                    }
+                });
+            }
+
+            // Lock on system sleep and screen lock
+            let trigger_handle = app_handle.clone();
+            power::watch(move |trigger| {
+                let state = trigger_handle.state::<AppState>();
+                let (enabled, reason) = {
+                    let settings = state.settings.lock_or_recover();
+                    match trigger {
+                        power::LockTrigger::Sleep => (settings.lock_on_sleep, LockReason::Suspend),
+                        power::LockTrigger::ScreenLock => (settings.lock_on_screen_lock, LockReason::ScreenLock),
+                        power::LockTrigger::Wake => {
+                            drop(settings);
+                            // A reader may have been plugged in or out while asleep
+                            state.biometric_availability.lock_or_recover().invalidate();
+                            let _ = recheck_biometric_availability(&trigger_handle);
+                            return;
+                        }
+                    }
+                };
+                let is_unlocked = state.vaults().any_unlocked();
+                if enabled && is_unlocked {
+                    lock_all_vaults(&state, &trigger_handle, reason);
+                }
+            });
             
             // Start auto-lock monitoring task
             std::thread::spawn(move || {
@@ -313,17 +3501,29 @@ fn main() {
                     std::thread::sleep(std::time::Duration::from_secs(5));
                     
                     let state = app_handle.state::<AppState>();
-                    let is_unlocked = *state.is_unlocked.lock().unwrap();
+                    // Fully discard soft-locked keys once their grace period ends
+                    state.soft_locks.lock_or_recover().prune();
+                    let is_unlocked = state.vaults().any_unlocked();
                     if !is_unlocked {
                         continue;
                     }
+
+                    // Lock sooner while the window sits hidden in the tray
+                    let lock_when_hidden = state.settings.lock_or_recover().lock_when_hidden_minutes;
+                    let hidden_since = *state.hidden_since.lock_or_recover();
+                    if let (Some(minutes), Some(since)) = (lock_when_hidden, hidden_since) {
+                        if since.elapsed().as_secs() >= u64::from(minutes) * 60 {
+                            lock_all_vaults(&state, &app_handle, LockReason::HiddenTimeout);
+                            continue;
+                        }
+                    }
                     
-                    let auto_lock_timer = *state.auto_lock_timer.lock().unwrap();
+                    let auto_lock_timer = *state.auto_lock_timer.lock_or_recover();
                     if auto_lock_timer.is_none() {
                         continue; // Auto-lock disabled
                     }
                     
-                    let last_activity = *state.last_activity.lock().unwrap();
+                    let last_activity = *state.last_activity.lock_or_recover();
                     if let Some(last) = last_activity {
                         let elapsed = last.elapsed().as_secs();
                         if elapsed >= auto_lock_timer.unwrap() {
@@ -331,11 +3531,12 @@ fn main() {
                             let app_clone = app_handle.clone();
                             tauri::async_runtime::spawn(async move {
                                 let state = app_clone.state::<AppState>();
-                                let _ = lock_vault(state, app_clone.clone()).await;
+                                lock_all_vaults(&state, &app_clone, LockReason::IdleTimeout);
                                 
                                 // Hide window
                                 if let Some(window) = app_clone.get_window("main") {
                                     let _ = window.hide();
+                                    set_window_hidden(&state, true);
                                 }
                             });
                         }
@@ -347,6 +3548,85 @@ fn main() {
         })
         .invoke_handler(tauri::generate_handler![
             unlock_vault,
+            unlock_vault_with_recovery_code,
+            create_vault,
+            estimate_password_strength,
+            get_unlock_requirements,
+            generate_keyfile,
+            recover_vault_from_backup,
+            unlock_with_pin,
+            enable_pin_unlock,
+            disable_pin_unlock,
+            unlock_with_biometrics,
+            enable_biometric_unlock,
+            disable_biometric_unlock,
+            reauthenticate,
+            approve_export,
+            get_unlock_history,
+            get_audit_log,
+            clear_unlock_history,
+            configure_duress_vault,
+            change_master_password,
+            regenerate_recovery_code,
+            get_kdf_info,
+            rekey_kdf,
+            list_vaults,
+            set_active_vault,
+            get_settings,
+            update_settings,
+            list_entries,
+            search_entries,
+            get_entry,
+            toggle_favorite,
+            set_entry_require_biometric,
+            archive_entry,
+            unarchive_entry,
+            add_entry,
+            update_entry,
+            get_password_history,
+            clear_password_history,
+            get_entry_generator_prefs,
+            regenerate_entry_password,
+            rotate_entry_password,
+            generate_password,
+            save_generator_policy,
+            list_generator_policies,
+            delete_generator_policy,
+            generate_with_policy,
+            set_folder_generator_policy,
+            generate_passphrase,
+            generate_pin,
+            get_generator_history,
+            clear_generator_history,
+            list_wordlists,
+            load_wordlist,
+            delete_entry,
+            restore_entry,
+            purge_trash,
+            get_usage_stats,
+            list_templates,
+            create_template,
+            delete_template,
+            create_entry_from_template,
+            bulk_update_entries,
+            find_entries_for_url,
+            fetch_entry_icon,
+            clear_icon_cache,
+            find_duplicate_entries,
+            merge_entries,
+            set_entry_expiry,
+            list_expiring_entries,
+            set_entry_tags,
+            list_tags,
+            add_attachment,
+            list_attachments,
+            save_attachment_to,
+            delete_attachment,
+            list_folders,
+            create_folder,
+            rename_folder,
+            delete_folder,
+            move_entry_to_folder,
             lock_vault,
             get_vault_status,
             update_activity,
@@ -355,13 +3635,434 @@ fn main() {
             save_to_keychain,
             get_from_keychain,
             delete_from_keychain,
+            keychain_entry_exists,
+            store_unlock_secret,
+            get_unlock_secret,
+            delete_unlock_secret,
             list_keychain_accounts,
+            keychain_backend_info,
+            keychain_health_check,
+            repair_keychain,
             check_biometric_available,
+            biometric_available,
+            refresh_biometric_availability,
             authenticate_biometric,
+            biometric_authenticate,
+            cancel_biometric_prompt,
+            set_biometric_mock,
             copy_to_clipboard,
+            copy_secret_to_clipboard,
+            copy_totp_to_clipboard,
+            copy_entry_credentials_staged,
+            advance_staged_copy,
             show_system_tray,
             show_main_window
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|_, event| {
+            // The clipboard outlives the app
+            if let tauri::RunEvent::Exit = event {
+                clipboard::scrub();
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::{Path, PathBuf};
+
+    /// Argon2 costs low enough to keep tests fast
+    fn test_kdf() -> crypto::KdfParams {
+        crypto::KdfParams {
+            memory_kib: 8,
+            iterations: 1,
+            ..crypto::KdfParams::with_salt(&crypto::generate_salt())
+        }
+    }
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("safenode-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// A vault with a recovery code and one attachment, saved to `path`
+    fn saved_vault(path: &Path, password: &str) -> (Vault, crypto::VaultKey, Zeroizing<String>) {
+        let kdf = test_kdf();
+        let password_key = crypto::derive_key(password.as_bytes(), &kdf).unwrap();
+        let (mut vault, key) = Vault::new(kdf, &password_key).unwrap();
+        let code = vault.reset_recovery_code(&key).unwrap();
+        let id = vault.add_entry(serde_json::from_value(serde_json::json!({ "title": "Bank" })).unwrap()).unwrap();
+        let (attachment, _) = attachments::seal(&key, "note.txt", b"attached").unwrap();
+        vault.entry_mut(id).unwrap().attachments.push(attachment);
+        storage::write_vault_file(path, &vault.seal(&key).unwrap()).unwrap();
+        (vault, key, code)
+    }
+
+    fn rekey(
+        path: &std::path::Path,
+        vault: &mut Vault,
+        key: &mut crypto::VaultKey,
+        secret: &[u8],
+        data_key: DataKey,
+    ) -> Result<Option<Zeroizing<String>>, VaultError> {
+        let kdf = test_kdf();
+        let password_key = crypto::derive_key(secret, &kdf).unwrap();
+        rekey_file(path, vault, key, &password_key, kdf, data_key)
+    }
+
+    #[test]
+    fn failed_write_leaves_the_old_password_working() {
+        let dir = temp_dir();
+        let path = dir.join("vault.safenode");
+        let (mut vault, mut key, _) = saved_vault(&path, "old password");
+        let original_key = key.clone();
+        // A directory in the temp file's place makes the write fail
+        std::fs::create_dir(storage::temp_path(&path)).unwrap();
+
+        let rotate = DataKey::Rotate { new_recovery_code: false };
+        let result = rekey(&path, &mut vault, &mut key, b"new password", rotate);
+
+        assert!(result.is_err());
+        assert_eq!(*key, *original_key);
+        let blob = std::fs::read(&path).unwrap();
+        assert!(Vault::unseal(&blob, b"old password").is_ok());
+        assert!(matches!(Vault::unseal(&blob, b"new password"), Err(UnsealError::WrongPassword)));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn password_change_rotates_the_data_key() {
+        let dir = temp_dir();
+        let path = dir.join("vault.safenode");
+        let (mut vault, mut key, old_code) = saved_vault(&path, "old password");
+        let old_key = key.clone();
+
+        let rotate = DataKey::Rotate { new_recovery_code: false };
+        let new_code = rekey(&path, &mut vault, &mut key, b"new password", rotate)
+            .unwrap()
+            .expect("a vault with a recovery code gets a new one");
+
+        assert_ne!(*key, *old_key);
+        let blob = std::fs::read(&path).unwrap();
+        assert!(matches!(Vault::unseal(&blob, b"old password"), Err(UnsealError::WrongPassword)));
+        assert!(matches!(Vault::unseal_with_data_key(&blob, old_key), Err(UnsealError::Integrity(_))));
+        assert!(matches!(Vault::unseal_with_recovery_code(&blob, &old_code), Err(UnsealError::WrongPassword)));
+        assert!(Vault::unseal_with_recovery_code(&blob, &new_code).is_ok());
+
+        let unsealed = Vault::unseal(&blob, b"new password").unwrap();
+        assert_eq!(*unsealed.key, *key);
+        let attachment = &unsealed.vault.entries[0].attachments[0];
+        assert_eq!(&attachments::open(&unsealed.key, attachment, None).unwrap()[..], b"attached");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn kdf_rekey_keeps_the_data_key() {
+        let dir = temp_dir();
+        let path = dir.join("vault.safenode");
+        let (mut vault, mut key, code) = saved_vault(&path, "password");
+        let original_key = key.clone();
+
+        let new_code = rekey(&path, &mut vault, &mut key, b"password", DataKey::Keep).unwrap();
+
+        assert!(new_code.is_none());
+        assert_eq!(*key, *original_key);
+        let blob = std::fs::read(&path).unwrap();
+        assert!(Vault::unseal(&blob, b"password").is_ok());
+        assert!(Vault::unseal_with_recovery_code(&blob, &code).is_ok());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn new_recovery_code_locks_out_copies_opened_with_the_old_one() {
+        let dir = temp_dir();
+        let path = dir.join("vault.safenode");
+        let (mut vault, mut key, old_code) = saved_vault(&path, "password");
+        let old_blob = std::fs::read(&path).unwrap();
+
+        let rotate = DataKey::Rotate { new_recovery_code: true };
+        let new_code = rekey(&path, &mut vault, &mut key, b"password", rotate).unwrap().unwrap();
+
+        let new_blob = std::fs::read(&path).unwrap();
+        let old_key = Vault::unseal_with_recovery_code(&old_blob, &old_code).unwrap().key;
+        assert!(matches!(Vault::unseal_with_data_key(&new_blob, old_key), Err(UnsealError::Integrity(_))));
+        assert!(matches!(Vault::unseal_with_recovery_code(&new_blob, &old_code), Err(UnsealError::WrongPassword)));
+        assert!(Vault::unseal_with_recovery_code(&new_blob, &new_code).is_ok());
+        assert!(Vault::unseal(&new_blob, b"password").is_ok());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rekeying_keeps_the_key_file_requirement() {
+        let mut kdf = test_kdf();
+        assert!(require_key_file_match(&kdf, None).is_ok());
+        assert!(matches!(require_key_file_match(&kdf, Some("usb/key")), Err(VaultError::InvalidFields(_))));
+
+        kdf.key_file = true;
+        assert!(require_key_file_match(&kdf, Some("usb/key")).is_ok());
+        assert!(matches!(require_key_file_match(&kdf, None), Err(VaultError::InvalidFields(_))));
+    }
+
+    /// Files of vault `name` in `dir`: the vault, its backups and key slots,
+    /// and a sidecar attachment
+    fn vault_files(dir: &Path, name: &str) -> Vec<PathBuf> {
+        let attachments = dir.join(format!("{}.attachments", name));
+        std::fs::create_dir_all(&attachments).unwrap();
+        let files = vec![
+            dir.join(format!("{}.safenode", name)),
+            dir.join(format!("{}.safenode.bak", name)),
+            dir.join(format!("{}.safenode.pre-migration", name)),
+            dir.join(format!("{}.safenode.pin", name)),
+            attachments.join(format!("{}.attach", Uuid::new_v4())),
+        ];
+        for file in &files {
+            std::fs::write(file, b"ciphertext").unwrap();
+        }
+        files
+    }
+
+    fn wiping_state(limit: u32) -> AppState {
+        let state = AppState::new(Arc::new(biometrics::PlatformAuthenticators));
+        state.settings.lock_or_recover().wipe_after_failed_attempts = Some(limit);
+        state
+    }
+
+    #[test]
+    fn vault_is_wiped_on_the_nth_consecutive_failure() {
+        let dir = temp_dir();
+        let travel = vault_files(&dir, "travel");
+        let work = vault_files(&dir, "work");
+        let state = wiping_state(3);
+
+        for _ in 0..2 {
+            assert!(!wipe_after_failure(&state, &dir, "travel").unwrap());
+        }
+        assert!(travel.iter().all(|file| file.exists()));
+
+        // `wipe_if_over_limit` emits `VAULT_WIPED_EVENT` on this result
+        assert!(wipe_after_failure(&state, &dir, "travel").unwrap());
+        assert!(travel.iter().all(|file| !file.exists()));
+        assert!(!dir.join("travel.attachments").exists());
+        assert!(work.iter().all(|file| file.exists()));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn failures_count_per_vault_until_it_unlocks() {
+        let dir = temp_dir();
+        let travel = vault_files(&dir, "travel");
+        let state = wiping_state(3);
+
+        for _ in 0..2 {
+            assert!(!wipe_after_failure(&state, &dir, "travel").unwrap());
+            assert!(!wipe_after_failure(&state, &dir, "work").unwrap());
+        }
+        // What `finish_unlock` does for the vault that opened
+        state.unlock_throttle.lock_or_recover().clear_vault_failures("travel");
+        for _ in 0..2 {
+            assert!(!wipe_after_failure(&state, &dir, "travel").unwrap());
+        }
+        assert!(travel.iter().all(|file| file.exists()));
+        assert!(wipe_after_failure(&state, &dir, "work").unwrap());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn nothing_is_wiped_without_a_limit() {
+        let dir = temp_dir();
+        let travel = vault_files(&dir, "travel");
+        let state = AppState::new(Arc::new(biometrics::PlatformAuthenticators));
+
+        for _ in 0..20 {
+            assert!(!wipe_after_failure(&state, &dir, "travel").unwrap());
+        }
+        assert!(travel.iter().all(|file| file.exists()));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// State whose biometric prompts play `script`
+    fn mock_biometric_state(script: Vec<biometrics::mock::MockBehavior>) -> AppState {
+        let mock = biometrics::mock::MockBiometricAuthenticator::new(script);
+        AppState::new(Arc::new(biometrics::mock::MockAuthenticators(mock)))
+    }
+
+    /// An app on Tauri's mock runtime serving the biometric commands, with
+    /// prompts that play `script`
+    fn mock_biometric_app(script: Vec<biometrics::mock::MockBehavior>) -> tauri::App<tauri::test::MockRuntime> {
+        tauri::test::mock_builder()
+            .manage(mock_biometric_state(script))
+            .invoke_handler(tauri::generate_handler![biometric_available, biometric_authenticate])
+            .build(tauri::test::mock_context(tauri::test::noop_assets()))
+            .unwrap()
+    }
+
+    /// Invoke `cmd` with `args` from the main window, as the frontend does
+    fn invoke(
+        app: &tauri::App<tauri::test::MockRuntime>,
+        cmd: &str,
+        args: serde_json::Value,
+    ) -> Result<serde_json::Value, serde_json::Value> {
+        let payload = tauri::InvokePayload {
+            cmd: cmd.to_string(),
+            tauri_module: None,
+            callback: tauri::api::ipc::CallbackFn(0),
+            error: tauri::api::ipc::CallbackFn(1),
+            inner: args,
+            invoke_key: Some(tauri::test::INVOKE_KEY.to_string()),
+        };
+        tauri::test::get_ipc_response(&app.get_window("main").unwrap(), payload)
+    }
+
+    #[test]
+    fn biometric_available_reports_the_mock_sensor() {
+        use biometrics::mock::MockBehavior;
+        let app = mock_biometric_app(vec![MockBehavior::Succeed]);
+
+        let availability = invoke(&app, "biometric_available", serde_json::json!({})).unwrap();
+        assert_eq!(availability["available"], true);
+        assert_eq!(availability["enrolled"], true);
+        assert_eq!(availability["method"], "biometric");
+        let state = app.state::<AppState>();
+        assert_eq!(state.biometric_availability.lock_or_recover().fresh(), Some(availability.clone()));
+
+        // Answered from the cache the second time
+        assert_eq!(invoke(&app, "biometric_available", serde_json::json!({})), Ok(availability));
+    }
+
+    #[test]
+    fn biometric_available_without_a_sensor() {
+        let app = mock_biometric_app(vec![biometrics::mock::MockBehavior::Unavailable]);
+        let availability = invoke(&app, "biometric_available", serde_json::json!({})).unwrap();
+        assert_eq!(availability["available"], false);
+    }
+
+    #[test]
+    fn biometric_authenticate_plays_the_mock_script() {
+        use biometrics::mock::MockBehavior;
+        let app = mock_biometric_app(vec![MockBehavior::Fail, MockBehavior::Cancel, MockBehavior::Succeed]);
+        let args = serde_json::json!({ "prompt": "Unlock SafeNode" });
+
+        let failed = invoke(&app, "biometric_authenticate", args.clone()).unwrap();
+        assert_eq!(failed["success"], false);
+        assert_eq!(failed["prompt"], "Unlock SafeNode");
+        let cancelled = invoke(&app, "biometric_authenticate", args.clone()).unwrap();
+        assert_eq!(cancelled["success"], false);
+        assert_ne!(cancelled["error"], failed["error"]);
+        let verified = invoke(&app, "biometric_authenticate", args).unwrap();
+        assert_eq!(verified["success"], true);
+
+        // The prompt is no longer in flight for `cancel_biometric_prompt`
+        assert!(app.state::<AppState>().biometric_prompt.lock_or_recover().is_none());
+    }
+
+    #[test]
+    fn biometric_authenticate_without_a_sensor() {
+        let app = mock_biometric_app(vec![biometrics::mock::MockBehavior::Unavailable]);
+        let args = serde_json::json!({ "prompt": "Unlock SafeNode" });
+        let expected = serde_json::to_value(BiometricError::NotAvailable).unwrap();
+        assert_eq!(invoke(&app, "biometric_authenticate", args), Err(expected));
+    }
+
+    #[test]
+    fn concurrent_unlocks_and_locks_stay_consistent() {
+        let dir = temp_dir();
+        let path = dir.join("default.safenode");
+        saved_vault(&path, "password");
+        let state = AppState::new(Arc::new(biometrics::PlatformAuthenticators));
+
+        std::thread::scope(|scope| {
+            for worker in 0..16 {
+                let (state, path) = (&state, &path);
+                scope.spawn(move || {
+                    for _ in 0..20 {
+                        if worker % 2 == 0 {
+                            // What `unlock_vault` does once the password opened the file
+                            let blob = storage::read_file(path).unwrap().unwrap();
+                            let unsealed = Vault::unseal(&blob, b"password").unwrap();
+                            let session =
+                                open_unsealed(state, "default", path, unsealed, UnlockMethod::Password, false).unwrap();
+                            let vaults = state.vaults();
+                            if vaults.check_session(&session).is_ok() {
+                                assert!(vaults.active_is_unlocked());
+                            }
+                        } else {
+                            let name = format!("worker {}", worker);
+                            let scope = TokenScope::Reauth;
+                            let token = state.reauth_tokens.lock_or_recover().issue(&name, scope);
+                            forget_unlocked_vaults(state, LockReason::Manual);
+                            let consumed = state.reauth_tokens.lock_or_recover().consume(Some(&token), &name, scope);
+                            assert!(consumed.is_err());
+                        }
+                        let vaults = state.vaults();
+                        assert_eq!(vaults.lock_state().is_unlocked(), vaults.active_is_unlocked());
+                        assert_eq!(vaults.active().is_ok(), vaults.active_is_unlocked());
+                    }
+                });
+            }
+        });
+
+        forget_unlocked_vaults(&state, LockReason::Manual);
+        assert!(!state.vaults().any_unlocked());
+        assert!(state.last_activity.lock_or_recover().is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn repeated_unlock_keeps_the_session() {
+        let state = AppState::new(Arc::new(biometrics::PlatformAuthenticators));
+        let kdf = test_kdf();
+        let password_key = crypto::derive_key(b"password", &kdf).unwrap();
+        let (vault, key) = Vault::new(kdf, &password_key).unwrap();
+
+        let first = state.vaults().unlock("default", vault.clone(), key.clone(), UnlockMethod::Password, false);
+        let second = state.vaults().unlock("default", vault, key, UnlockMethod::Password, false);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn poisoned_vaults_reset_to_locked() {
+        let state = AppState::new(Arc::new(biometrics::PlatformAuthenticators));
+        let kdf = test_kdf();
+        let password_key = crypto::derive_key(b"password", &kdf).unwrap();
+        let (vault, key) = Vault::new(kdf, &password_key).unwrap();
+        state.vaults().unlock("default", vault, key, UnlockMethod::Password, false);
+
+        let panicked = std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    let _vaults = state.vaults();
+                    panic!("command failed while holding the vaults");
+                })
+                .join()
+        });
+        assert!(panicked.is_err());
+
+        let vaults = state.vaults();
+        assert!(!vaults.any_unlocked());
+        assert!(matches!(vaults.lock_state(), LockState::Locked { reason: LockReason::InternalError }));
+        drop(vaults);
+        assert!(!state.vaults.is_poisoned());
+    }
+
+    #[test]
+    fn poisoned_state_locks_recover() {
+        let state = AppState::new(Arc::new(biometrics::PlatformAuthenticators));
+        let token = state.reauth_tokens.lock_or_recover().issue("default", TokenScope::Reauth);
+
+        let panicked = std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    let _tokens = state.reauth_tokens.lock_or_recover();
+                    panic!("command failed while holding the reauth tokens");
+                })
+                .join()
+        });
+        assert!(panicked.is_err());
+
+        assert!(state.reauth_tokens.lock_or_recover().consume(Some(&token), "default", TokenScope::Reauth).is_ok());
+        assert!(!state.reauth_tokens.is_poisoned());
+    }
 }
@@ -0,0 +1,98 @@
+/*!
+ * Vault Migrations
+ * Ordered upgrades of the decrypted vault JSON envelope between format versions
+ */
+
+use serde_json::Value;
+
+use crate::vault::VAULT_FORMAT_VERSION;
+
+/// Upgrades the envelope in place from one version to the next
+type Migration = fn(&mut Value) -> Result<(), String>;
+
+/// `MIGRATIONS[n]` upgrades version `n + 1` to version `n + 2`.
+///
+/// Append a function here whenever `VAULT_FORMAT_VERSION` is bumped.
+const MIGRATIONS: &[Migration] = &[add_item_kind];
+
+const _: () = assert!(MIGRATIONS.len() + 1 == VAULT_FORMAT_VERSION as usize);
+
+/// v1 -> v2: every entry, including trashed ones, gets an explicit `kind`;
+/// all v1 entries were logins
+fn add_item_kind(envelope: &mut Value) -> Result<(), String> {
+    if let Some(entries) = envelope.get_mut("entries").and_then(Value::as_array_mut) {
+        for entry in entries {
+            set_default_kind(entry)?;
+        }
+    }
+    if let Some(trash) = envelope.get_mut("trash").and_then(Value::as_array_mut) {
+        for entry in trash.iter_mut().filter_map(|item| item.get_mut("entry")) {
+            set_default_kind(entry)?;
+        }
+    }
+    Ok(())
+}
+
+fn set_default_kind(entry: &mut Value) -> Result<(), String> {
+    let entry = entry.as_object_mut().ok_or_else(|| "Entry is not an object".to_string())?;
+    entry.entry("kind").or_insert_with(|| Value::from("login"));
+    Ok(())
+}
+
+/// Upgrade `envelope` from `from_version` to `VAULT_FORMAT_VERSION`
+pub fn migrate(envelope: &mut Value, from_version: u32) -> Result<(), String> {
+    if from_version == 0 || from_version > VAULT_FORMAT_VERSION {
+        return Err(format!("Cannot migrate vault version {}", from_version));
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(from_version as usize - 1) {
+        let target = index as u32 + 2;
+        migration(envelope).map_err(|e| format!("Migration to version {} failed: {}", target, e))?;
+        envelope["version"] = Value::from(target);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn v1() -> Value {
+        json!({
+            "version": 1,
+            "entries": [{ "title": "GitHub" }, { "title": "Visa", "kind": "card" }],
+            "trash": [{ "entry": { "title": "Old" }, "deleted_at": "2024-01-01T00:00:00Z" }]
+        })
+    }
+
+    #[test]
+    fn v1_entries_become_logins() {
+        let mut envelope = v1();
+        migrate(&mut envelope, 1).unwrap();
+        assert_eq!(envelope["version"], 2);
+        assert_eq!(envelope["entries"][0]["kind"], "login");
+        assert_eq!(envelope["entries"][1]["kind"], "card");
+        assert_eq!(envelope["trash"][0]["entry"]["kind"], "login");
+    }
+
+    #[test]
+    fn current_vaults_are_left_alone() {
+        let mut envelope = v1();
+        envelope["version"] = Value::from(VAULT_FORMAT_VERSION);
+        let before = envelope.clone();
+        migrate(&mut envelope, VAULT_FORMAT_VERSION).unwrap();
+        assert_eq!(envelope, before);
+    }
+
+    #[test]
+    fn unknown_versions_and_bad_entries_fail() {
+        assert!(migrate(&mut v1(), 0).is_err());
+        assert!(migrate(&mut v1(), VAULT_FORMAT_VERSION + 1).is_err());
+
+        let mut envelope = json!({ "version": 1, "entries": ["not an entry"] });
+        let error = migrate(&mut envelope, 1).unwrap_err();
+        assert!(error.contains("version 2"), "{}", error);
+    }
+}
@@ -0,0 +1,464 @@
+/*!
+ * Passphrase Generator
+ * Diceware-style passphrases from the EFF wordlists or a user's own list
+ */
+
+use rand::rngs::OsRng;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+use crate::error::{FieldError, VaultError};
+use crate::generator::{self, Generated};
+
+pub const MIN_WORDS: usize = 3;
+pub const MAX_WORDS: usize = 20;
+/// A user's wordlist needs this many distinct words, about 10 bits a word
+pub const MIN_UNIQUE_WORDS: usize = 1024;
+/// Larger files are refused before they are read
+const MAX_WORDLIST_FILE_SIZE: u64 = 4 * 1024 * 1024;
+const MAX_SEPARATOR_LEN: usize = 8;
+
+/// Wordlists built into SafeNode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BuiltinWordlist {
+    /// 7776 words, one per roll of five dice
+    EffLarge,
+    /// 1296 shorter words, one per roll of four dice
+    EffShort,
+}
+
+impl BuiltinWordlist {
+    pub const ALL: [BuiltinWordlist; 2] = [BuiltinWordlist::EffLarge, BuiltinWordlist::EffShort];
+
+    fn list(self) -> &'static [(u32, &'static str)] {
+        match self {
+            BuiltinWordlist::EffLarge => eff_wordlist::large::LIST,
+            BuiltinWordlist::EffShort => eff_wordlist::short::LIST,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            BuiltinWordlist::EffLarge => "EFF large wordlist",
+            BuiltinWordlist::EffShort => "EFF short wordlist",
+        }
+    }
+}
+
+/// Where a passphrase's words come from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Wordlist {
+    Builtin(BuiltinWordlist),
+    /// A text file with one word per line, read each time it is used
+    File(PathBuf),
+}
+
+impl Default for Wordlist {
+    fn default() -> Self {
+        Wordlist::Builtin(BuiltinWordlist::EffLarge)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capitalization {
+    #[default]
+    Lowercase,
+    /// First letter of every word
+    Capitalize,
+    Uppercase,
+    /// Each word capitalized or not at random, for one more bit a word
+    Random,
+}
+
+/// What a generated passphrase looks like
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PassphraseOptions {
+    pub words: usize,
+    pub separator: String,
+    pub capitalization: Capitalization,
+    /// End the passphrase with a random digit
+    pub append_digit: bool,
+    pub wordlist: Wordlist,
+    /// Ignore `words` and use the fewest reaching this many bits
+    pub min_entropy_bits: Option<u32>,
+}
+
+impl Default for PassphraseOptions {
+    fn default() -> Self {
+        PassphraseOptions {
+            words: 6,
+            separator: "-".to_string(),
+            capitalization: Capitalization::default(),
+            append_digit: false,
+            wordlist: Wordlist::default(),
+            min_entropy_bits: None,
+        }
+    }
+}
+
+/// A wordlist as shown to the UI
+#[derive(Debug, Clone, Serialize)]
+pub struct WordlistInfo {
+    pub wordlist: Wordlist,
+    pub name: String,
+    /// Distinct words
+    pub words: usize,
+    pub bits_per_word: f64,
+}
+
+/// Words of a wordlist, lowercase and distinct
+enum Words {
+    Builtin(&'static [(u32, &'static str)]),
+    Loaded(Vec<String>),
+}
+
+impl Words {
+    fn len(&self) -> usize {
+        match self {
+            Words::Builtin(list) => list.len(),
+            Words::Loaded(words) => words.len(),
+        }
+    }
+
+    fn get(&self, index: usize) -> &str {
+        match self {
+            Words::Builtin(list) => list[index].1,
+            Words::Loaded(words) => &words[index],
+        }
+    }
+
+    fn open(wordlist: &Wordlist) -> Result<Words, VaultError> {
+        match wordlist {
+            Wordlist::Builtin(builtin) => Ok(Words::Builtin(builtin.list())),
+            Wordlist::File(path) => read_wordlist(path).map(Words::Loaded),
+        }
+    }
+}
+
+fn invalid(field: &str, message: &str) -> VaultError {
+    VaultError::InvalidFields(vec![FieldError::new(field, message)])
+}
+
+/// Read and check a user's wordlist. Lines may carry a leading dice roll as
+/// in the EFF files; words are lowercased and duplicates dropped.
+fn read_wordlist(path: &Path) -> Result<Vec<String>, VaultError> {
+    if std::fs::metadata(path)?.len() > MAX_WORDLIST_FILE_SIZE {
+        return Err(invalid(
+            "wordlist",
+            &format!("Wordlists may be at most {} MB", MAX_WORDLIST_FILE_SIZE / (1024 * 1024)),
+        ));
+    }
+    let text = std::fs::read_to_string(path)?;
+    let mut words = BTreeSet::new();
+    for line in text.lines() {
+        let mut fields = line.split_whitespace();
+        let word = match (fields.next(), fields.next(), fields.next()) {
+            (None, _, _) => continue,
+            (Some(roll), Some(word), None) if roll.chars().all(|c| c.is_ascii_digit()) => word,
+            (Some(word), None, _) => word,
+            _ => {
+                return Err(invalid("wordlist", &format!("Expected one word per line, found \"{}\"", line.trim())));
+            }
+        };
+        words.insert(word.to_lowercase());
+    }
+    if words.len() < MIN_UNIQUE_WORDS {
+        return Err(invalid(
+            "wordlist",
+            &format!(
+                "The wordlist has {} distinct words; at least {} are needed",
+                words.len(),
+                MIN_UNIQUE_WORDS
+            ),
+        ));
+    }
+    Ok(words.into_iter().collect())
+}
+
+/// The built-in wordlists
+pub fn builtin_wordlists() -> Vec<WordlistInfo> {
+    BuiltinWordlist::ALL
+        .iter()
+        .map(|&builtin| {
+            let words = builtin.list().len();
+            WordlistInfo {
+                wordlist: Wordlist::Builtin(builtin),
+                name: builtin.name().to_string(),
+                words,
+                bits_per_word: (words as f64).log2(),
+            }
+        })
+        .collect()
+}
+
+/// Check a user's wordlist file and describe it
+pub fn load_wordlist(path: &Path) -> Result<WordlistInfo, VaultError> {
+    let words = read_wordlist(path)?.len();
+    Ok(WordlistInfo {
+        wordlist: Wordlist::File(path.to_path_buf()),
+        name: path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default(),
+        words,
+        bits_per_word: (words as f64).log2(),
+    })
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Generate a passphrase of words drawn independently from the wordlist
+pub fn generate(options: &PassphraseOptions) -> Result<Generated, VaultError> {
+    if options.separator.chars().count() > MAX_SEPARATOR_LEN {
+        return Err(invalid(
+            "separator",
+            &format!("The separator may be at most {} characters", MAX_SEPARATOR_LEN),
+        ));
+    }
+    let words = Words::open(&options.wordlist)?;
+
+    let mut bits_per_word = (words.len() as f64).log2();
+    // Only counted when every word's first letter has a capital
+    if options.capitalization == Capitalization::Random
+        && (0..words.len()).all(|i| capitalize(words.get(i)) != words.get(i))
+    {
+        bits_per_word += 1.0;
+    }
+    let digit_bits = if options.append_digit { 10f64.log2() } else { 0.0 };
+
+    let word_count = match options.min_entropy_bits {
+        Some(bits) => {
+            generator::check_entropy_target(bits).map_err(|e| VaultError::InvalidFields(vec![e]))?;
+            // Tolerates rounding when a word count reaches the target exactly
+            let needed = ((bits as f64 - digit_bits - 1e-9) / bits_per_word).ceil().max(0.0) as usize;
+            if needed > MAX_WORDS {
+                return Err(invalid(
+                    "min_entropy_bits",
+                    &format!("No passphrase of up to {} words from this wordlist reaches {} bits", MAX_WORDS, bits),
+                ));
+            }
+            needed.max(MIN_WORDS)
+        }
+        None if (MIN_WORDS..=MAX_WORDS).contains(&options.words) => options.words,
+        None => {
+            return Err(invalid(
+                "words",
+                &format!("Use between {} and {} words", MIN_WORDS, MAX_WORDS),
+            ));
+        }
+    };
+
+    let mut rng = OsRng;
+    let mut phrase = Zeroizing::new(String::new());
+    for i in 0..word_count {
+        if i > 0 {
+            phrase.push_str(&options.separator);
+        }
+        let word = words.get(rng.gen_range(0..words.len()));
+        let word = Zeroizing::new(match options.capitalization {
+            Capitalization::Lowercase => word.to_string(),
+            Capitalization::Capitalize => capitalize(word),
+            Capitalization::Uppercase => word.to_uppercase(),
+            Capitalization::Random if rng.gen() => capitalize(word),
+            Capitalization::Random => word.to_string(),
+        });
+        phrase.push_str(&word);
+    }
+    if options.append_digit {
+        phrase.push(char::from(b'0' + rng.gen_range(0..10u8)));
+    }
+
+    Ok(Generated {
+        password: phrase,
+        entropy_bits: word_count as f64 * bits_per_word + digit_bits,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::MAX_ENTROPY_TARGET_BITS;
+    use uuid::Uuid;
+
+    /// A wordlist file with `lines`, removed by `remove_file` at the end
+    fn wordlist_file(lines: &[String]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("safenode-wordlist-{}.txt", Uuid::new_v4()));
+        std::fs::write(&path, lines.join("\n")).unwrap();
+        path
+    }
+
+    /// `count` distinct words: "word0", "word1", ...
+    fn words(count: usize) -> Vec<String> {
+        (0..count).map(|i| format!("word{}", i)).collect()
+    }
+
+    fn error_field(error: VaultError) -> String {
+        match error {
+            VaultError::InvalidFields(errors) => errors[0].field.clone(),
+            other => panic!("unexpected error: {}", other),
+        }
+    }
+
+    #[test]
+    fn user_wordlists_need_enough_distinct_words() {
+        // Repeats in other cases count once
+        let mut lines = words(MIN_UNIQUE_WORDS - 1);
+        let shouted: Vec<String> = lines.iter().map(|word| word.to_uppercase()).collect();
+        lines.extend(shouted);
+        let short = wordlist_file(&lines);
+        assert_eq!(error_field(load_wordlist(&short).unwrap_err()), "wordlist");
+
+        lines.push(format!("WORD{}", MIN_UNIQUE_WORDS - 1));
+        let enough = wordlist_file(&lines);
+        let info = load_wordlist(&enough).unwrap();
+        assert_eq!(info.words, MIN_UNIQUE_WORDS);
+        assert!((info.bits_per_word - 10.0).abs() < 1e-9);
+        assert_eq!(info.wordlist, Wordlist::File(enough.clone()));
+
+        std::fs::remove_file(short).unwrap();
+        std::fs::remove_file(enough).unwrap();
+    }
+
+    #[test]
+    fn dice_rolls_are_stripped_and_words_lowercased() {
+        let mut lines: Vec<String> = words(MIN_UNIQUE_WORDS)
+            .iter()
+            .enumerate()
+            .map(|(i, word)| format!("{:05}\t{}", i, word.to_uppercase()))
+            .collect();
+        lines.insert(0, String::new());
+        lines.push("   ".to_string());
+        let path = wordlist_file(&lines);
+
+        let loaded = read_wordlist(&path).unwrap();
+        assert_eq!(loaded.len(), MIN_UNIQUE_WORDS);
+        assert!(loaded.iter().all(|word| word.starts_with("word")));
+
+        let options = PassphraseOptions {
+            wordlist: Wordlist::File(path.clone()),
+            ..PassphraseOptions::default()
+        };
+        let phrase = generate(&options).unwrap().password;
+        assert!(phrase.split('-').all(|word| loaded.contains(&word.to_string())), "{}", *phrase);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn malformed_lines_are_rejected() {
+        for bad in ["12345 two words", "roll word", "a b c"] {
+            let mut lines = words(MIN_UNIQUE_WORDS);
+            lines.push(bad.to_string());
+            let path = wordlist_file(&lines);
+            assert_eq!(error_field(read_wordlist(&path).unwrap_err()), "wordlist", "{}", bad);
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn oversized_wordlists_are_refused_unread() {
+        let path = std::env::temp_dir().join(format!("safenode-wordlist-{}.txt", Uuid::new_v4()));
+        let file = std::fs::File::create(&path).unwrap();
+        file.set_len(MAX_WORDLIST_FILE_SIZE + 1).unwrap();
+        // All zero bytes, so it would also fail to parse if it were read
+        assert_eq!(error_field(read_wordlist(&path).unwrap_err()), "wordlist");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn separators_are_limited_in_length() {
+        let longest = PassphraseOptions {
+            separator: "·".repeat(MAX_SEPARATOR_LEN),
+            ..PassphraseOptions::default()
+        };
+        assert_eq!(generate(&longest).unwrap().password.matches('·').count(), 5 * MAX_SEPARATOR_LEN);
+        let too_long = PassphraseOptions {
+            separator: "·".repeat(MAX_SEPARATOR_LEN + 1),
+            ..PassphraseOptions::default()
+        };
+        assert_eq!(error_field(generate(&too_long).unwrap_err()), "separator");
+    }
+
+    #[test]
+    fn builtin_passphrases_have_the_requested_words_and_entropy() {
+        for builtin in BuiltinWordlist::ALL {
+            let list = builtin.list();
+            let options = PassphraseOptions {
+                words: 5,
+                separator: " ".to_string(),
+                wordlist: Wordlist::Builtin(builtin),
+                ..PassphraseOptions::default()
+            };
+            let generated = generate(&options).unwrap();
+            let phrase: Vec<&str> = generated.password.split(' ').collect();
+            assert_eq!(phrase.len(), 5);
+            assert!(phrase.iter().all(|word| list.iter().any(|(_, listed)| listed == word)));
+            assert!((generated.entropy_bits - 5.0 * (list.len() as f64).log2()).abs() < 1e-9);
+        }
+    }
+
+    /// Passphrase options aiming for `bits` from the wordlist at `path`
+    fn aiming_for(bits: u32, path: &Path) -> PassphraseOptions {
+        PassphraseOptions {
+            min_entropy_bits: Some(bits),
+            wordlist: Wordlist::File(path.to_path_buf()),
+            ..PassphraseOptions::default()
+        }
+    }
+
+    #[test]
+    fn entropy_target_picks_the_fewest_words() {
+        // 1024 words, exactly 10 bits each
+        let path = wordlist_file(&words(MIN_UNIQUE_WORDS));
+        for (bits, expected) in [(50, 5), (51, 6), (1, MIN_WORDS), (10 * MAX_WORDS as u32, MAX_WORDS)] {
+            let generated = generate(&aiming_for(bits, &path)).unwrap();
+            assert_eq!(generated.password.split('-').count(), expected, "{} bits", bits);
+            assert!((generated.entropy_bits - 10.0 * expected as f64).abs() < 1e-9);
+        }
+
+        let with_digit = PassphraseOptions {
+            append_digit: true,
+            ..aiming_for(53, &path)
+        };
+        // 50 bits of words and log2(10) of the digit
+        assert_eq!(generate(&with_digit).unwrap().password.split('-').count(), 5);
+
+        for bits in [10 * MAX_WORDS as u32 + 1, MAX_ENTROPY_TARGET_BITS + 1, 0] {
+            assert_eq!(error_field(generate(&aiming_for(bits, &path)).unwrap_err()), "min_entropy_bits");
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn random_capitalization_adds_a_bit_only_when_every_word_has_a_capital() {
+        let random = |bits, path: &Path| PassphraseOptions {
+            capitalization: Capitalization::Random,
+            ..aiming_for(bits, path)
+        };
+        let letters = wordlist_file(&words(MIN_UNIQUE_WORDS));
+        let generated = generate(&random(55, &letters)).unwrap();
+        assert_eq!(generated.password.split('-').count(), 5);
+        assert!((generated.entropy_bits - 55.0).abs() < 1e-9);
+
+        // "42" reads the same capitalized, so no word gains the bit
+        let mut lines = words(MIN_UNIQUE_WORDS - 1);
+        lines.push("42".to_string());
+        let digits = wordlist_file(&lines);
+        let generated = generate(&random(55, &digits)).unwrap();
+        assert_eq!(generated.password.split('-').count(), 6);
+        assert!((generated.entropy_bits - 60.0).abs() < 1e-9);
+
+        std::fs::remove_file(letters).unwrap();
+        std::fs::remove_file(digits).unwrap();
+    }
+}
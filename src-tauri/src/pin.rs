@@ -0,0 +1,117 @@
+/*!
+ * Quick-unlock PIN
+ * The vault data key wrapped under a short PIN plus a pepper kept in the OS keychain
+ */
+
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::ErrorKind;
+use tauri::AppHandle;
+use zeroize::Zeroizing;
+
+use crate::crypto::{self, KdfParams, VaultKey};
+use crate::error::{FieldError, VaultError};
+use crate::format::KeySlot;
+use crate::keychain::{self, VaultSecret};
+use crate::storage;
+
+/// Wrong PINs allowed before the PIN slot is destroyed
+pub const MAX_FAILURES: u32 = 5;
+const MIN_PIN_LEN: usize = 4;
+const MAX_PIN_LEN: usize = 12;
+const PEPPER_LEN: usize = 32;
+
+/// PIN-wrapped data key, stored next to the vault file. Useless without the
+/// pepper, which never leaves the keychain of the machine it was made on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PinSlot {
+    #[serde(flatten)]
+    pub slot: KeySlot,
+    /// Consecutive wrong PINs
+    #[serde(default)]
+    pub failures: u32,
+}
+
+/// Field error unless `pin` is 4 to 12 digits
+pub fn validate(pin: &str) -> Result<(), VaultError> {
+    let valid = (MIN_PIN_LEN..=MAX_PIN_LEN).contains(&pin.len()) && pin.chars().all(|c| c.is_ascii_digit());
+    if valid {
+        Ok(())
+    } else {
+        Err(VaultError::InvalidFields(vec![FieldError::new(
+            "pin",
+            &format!("PIN must be {} to {} digits", MIN_PIN_LEN, MAX_PIN_LEN),
+        )]))
+    }
+}
+
+fn pin_secret(pin: &str, pepper: &[u8]) -> Zeroizing<Vec<u8>> {
+    let mut secret = Zeroizing::new(Vec::with_capacity(pin.len() + pepper.len()));
+    secret.extend_from_slice(pin.as_bytes());
+    secret.extend_from_slice(pepper);
+    secret
+}
+
+/// Wrap `data_key` under `pin` and a fresh pepper. The pepper is stored in
+/// the keychain for the vault with `storage::keychain_id` `keychain_id`
+/// before the slot is returned.
+pub fn create(keychain_id: &str, pin: &str, data_key: &VaultKey, kdf_template: &KdfParams) -> Result<PinSlot, VaultError> {
+    let mut pepper = Zeroizing::new([0u8; PEPPER_LEN]);
+    OsRng.fill_bytes(&mut pepper[..]);
+    keychain::save_secret_bytes(&VaultSecret::PinPepper.account(keychain_id), &pepper[..])?;
+
+    let mut kdf = kdf_template.with_fresh_salt();
+    kdf.key_file = false;
+    let pin_key = crypto::derive_key(&pin_secret(pin, &pepper[..]), &kdf).map_err(VaultError::Crypto)?;
+
+    Ok(PinSlot {
+        slot: KeySlot {
+            key_check: crypto::key_check(&pin_key),
+            wrapped_key: crypto::wrap_key(&pin_key, data_key).map_err(VaultError::Crypto)?,
+            kdf,
+        },
+        failures: 0,
+    })
+}
+
+/// Unwrap the data key; `None` if the PIN is wrong
+pub fn open(keychain_id: &str, slot: &PinSlot, pin: &str) -> Result<Option<VaultKey>, VaultError> {
+    let pepper = keychain::get_secret_bytes(&VaultSecret::PinPepper.account(keychain_id))?
+        .ok_or(VaultError::PinUnlockUnavailable)?;
+    let pin_key = crypto::derive_key(&pin_secret(pin, &pepper), &slot.slot.kdf).map_err(VaultError::Crypto)?;
+    if !crypto::verify_key_check(&pin_key, &slot.slot.key_check) {
+        return Ok(None);
+    }
+    crypto::unwrap_key(&pin_key, &slot.slot.wrapped_key)
+        .map(Some)
+        .map_err(VaultError::Crypto)
+}
+
+pub fn load(app: &AppHandle, vault_name: &str) -> Result<Option<PinSlot>, VaultError> {
+    let Some(bytes) = storage::read_file(&storage::pin_path(app, vault_name)?)? else {
+        return Ok(None);
+    };
+    serde_json::from_slice(&bytes)
+        .map(Some)
+        .map_err(|e| VaultError::Io(format!("PIN file is malformed: {}", e)))
+}
+
+pub fn save(app: &AppHandle, vault_name: &str, slot: &PinSlot) -> Result<(), VaultError> {
+    let json = serde_json::to_vec_pretty(slot).map_err(|e| VaultError::Io(e.to_string()))?;
+    storage::write_atomic(&storage::pin_path(app, vault_name)?, &json)
+}
+
+/// Remove the PIN slot and its pepper, so only the master password opens
+/// the vault
+pub fn delete(app: &AppHandle, vault_name: &str) -> Result<(), VaultError> {
+    if let Err(e) = fs::remove_file(storage::pin_path(app, vault_name)?) {
+        if e.kind() != ErrorKind::NotFound {
+            return Err(e.into());
+        }
+    }
+    let keychain_id = storage::keychain_id(app, vault_name)?;
+    keychain::delete(keychain::SERVICE, &VaultSecret::PinPepper.account(&keychain_id))?;
+    Ok(())
+}
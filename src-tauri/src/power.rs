@@ -0,0 +1,268 @@
+/*!
+ * System Lock Triggers
+ * Watches for system sleep and screen lock so the vault can be locked, and
+ * for wake-up so device state can be checked again
+ */
+
+use std::sync::mpsc;
+
+/// System event that may lock the vault
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockTrigger {
+    Sleep,
+    ScreenLock,
+    /// Back from sleep; never locks, but devices may have come or gone
+    Wake,
+}
+
+/// Start the platform watchers.
+///
+/// Platform callbacks only forward events over a channel; `on_trigger` runs
+/// on a dedicated thread, so it may block on `AppState` mutexes held by an
+/// in-flight command without stalling the OS callback (on macOS, the main
+/// thread).
+pub fn watch(on_trigger: impl Fn(LockTrigger) + Send + 'static) {
+    let (sender, receiver) = mpsc::channel();
+    platform::start(sender);
+    std::thread::spawn(move || {
+        for trigger in receiver {
+            on_trigger(trigger);
+        }
+    });
+}
+
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+mod callback {
+    use std::sync::mpsc::Sender;
+    use std::sync::{Mutex, OnceLock};
+
+    use super::LockTrigger;
+
+    /// OS callbacks cannot capture state, so they reach the channel here
+    static SENDER: OnceLock<Mutex<Sender<LockTrigger>>> = OnceLock::new();
+
+    pub fn install(sender: Sender<LockTrigger>) -> bool {
+        SENDER.set(Mutex::new(sender)).is_ok()
+    }
+
+    pub fn send(trigger: LockTrigger) {
+        if let Some(sender) = SENDER.get() {
+            let _ = sender.lock().unwrap_or_else(|e| e.into_inner()).send(trigger);
+        }
+    }
+}
+
+/// logind: `PrepareForSleep` on the manager and `Lock` on our session
+#[cfg(all(target_os = "linux", feature = "dbus"))]
+mod platform {
+    use std::sync::mpsc::Sender;
+    use zbus::blocking::{Connection, Proxy};
+    use zbus::zvariant::OwnedObjectPath;
+
+    use super::LockTrigger;
+
+    const LOGIND: &str = "org.freedesktop.login1";
+    const MANAGER_PATH: &str = "/org/freedesktop/login1";
+    const MANAGER_INTERFACE: &str = "org.freedesktop.login1.Manager";
+    const SESSION_INTERFACE: &str = "org.freedesktop.login1.Session";
+
+    pub fn start(sender: Sender<LockTrigger>) {
+        let sleep_sender = sender.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = watch_sleep(&sleep_sender) {
+                eprintln!("Sleep watcher stopped: {}", e);
+            }
+        });
+        std::thread::spawn(move || {
+            if let Err(e) = watch_session_lock(&sender) {
+                eprintln!("Screen lock watcher stopped: {}", e);
+            }
+        });
+    }
+
+    fn watch_sleep(sender: &Sender<LockTrigger>) -> zbus::Result<()> {
+        let connection = Connection::system()?;
+        let manager = Proxy::new(&connection, LOGIND, MANAGER_PATH, MANAGER_INTERFACE)?;
+        for message in manager.receive_signal("PrepareForSleep")? {
+            // `true` before suspending, `false` after resuming
+            let trigger = if message.body::<bool>()? { LockTrigger::Sleep } else { LockTrigger::Wake };
+            if sender.send(trigger).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn watch_session_lock(sender: &Sender<LockTrigger>) -> zbus::Result<()> {
+        let connection = Connection::system()?;
+        let manager = Proxy::new(&connection, LOGIND, MANAGER_PATH, MANAGER_INTERFACE)?;
+        // Signals are sent from the session's real path, not `session/auto`
+        let session_path: OwnedObjectPath = manager.call("GetSessionByPID", &(std::process::id()))?;
+        let session = Proxy::new(&connection, LOGIND, session_path.into_inner(), SESSION_INTERFACE)?;
+        for _ in session.receive_signal("Lock")? {
+            if sender.send(LockTrigger::ScreenLock).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A hidden top-level window receiving `WM_POWERBROADCAST` and, after
+/// `WTSRegisterSessionNotification`, `WM_WTSSESSION_CHANGE`. Message-only
+/// windows are not sent broadcasts, so this window has no parent.
+#[cfg(target_os = "windows")]
+mod platform {
+    use std::ptr;
+    use std::sync::mpsc::Sender;
+    use winapi::shared::minwindef::{LPARAM, LRESULT, UINT, WPARAM};
+    use winapi::shared::windef::HWND;
+    use winapi::um::libloaderapi::GetModuleHandleW;
+    use winapi::um::winuser::{
+        CreateWindowExW, DefWindowProcW, DispatchMessageW, GetMessageW, RegisterClassW, TranslateMessage, MSG,
+        PBT_APMRESUMEAUTOMATIC, PBT_APMSUSPEND, WM_POWERBROADCAST, WM_WTSSESSION_CHANGE, WNDCLASSW, WTS_SESSION_LOCK,
+    };
+    use winapi::um::wtsapi32::{WTSRegisterSessionNotification, NOTIFY_FOR_THIS_SESSION};
+
+    use super::{callback, LockTrigger};
+
+    pub fn start(sender: Sender<LockTrigger>) {
+        if !callback::install(sender) {
+            return;
+        }
+        std::thread::spawn(|| unsafe { run_message_loop() });
+    }
+
+    unsafe extern "system" fn window_proc(hwnd: HWND, message: UINT, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+        match message {
+            WM_POWERBROADCAST if wparam == PBT_APMSUSPEND => callback::send(LockTrigger::Sleep),
+            WM_POWERBROADCAST if wparam == PBT_APMRESUMEAUTOMATIC => callback::send(LockTrigger::Wake),
+            WM_WTSSESSION_CHANGE if wparam == WTS_SESSION_LOCK => callback::send(LockTrigger::ScreenLock),
+            _ => {}
+        }
+        DefWindowProcW(hwnd, message, wparam, lparam)
+    }
+
+    unsafe fn run_message_loop() {
+        let class_name: Vec<u16> = "SafeNodeLockTriggers\0".encode_utf16().collect();
+        let instance = GetModuleHandleW(ptr::null());
+        let class = WNDCLASSW {
+            lpfnWndProc: Some(window_proc),
+            hInstance: instance,
+            lpszClassName: class_name.as_ptr(),
+            ..std::mem::zeroed()
+        };
+        if RegisterClassW(&class) == 0 {
+            eprintln!("Failed to register lock trigger window class");
+            return;
+        }
+
+        let hwnd = CreateWindowExW(
+            0,
+            class_name.as_ptr(),
+            class_name.as_ptr(),
+            0,
+            0,
+            0,
+            0,
+            0,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            instance,
+            ptr::null_mut(),
+        );
+        if hwnd.is_null() {
+            eprintln!("Failed to create lock trigger window");
+            return;
+        }
+        if WTSRegisterSessionNotification(hwnd, NOTIFY_FOR_THIS_SESSION) == 0 {
+            eprintln!("Failed to register for session notifications; screen lock will not lock the vault");
+        }
+
+        let mut message: MSG = std::mem::zeroed();
+        while GetMessageW(&mut message, ptr::null_mut(), 0, 0) > 0 {
+            TranslateMessage(&message);
+            DispatchMessageW(&message);
+        }
+    }
+}
+
+/// `NSWorkspaceWillSleepNotification`, `NSWorkspaceDidWakeNotification` and
+/// the distributed `com.apple.screenIsLocked` notification. Must be started
+/// on the main thread, whose run loop delivers them.
+#[cfg(target_os = "macos")]
+mod platform {
+    use objc::declare::ClassDecl;
+    use objc::runtime::{Object, Sel};
+    use objc::{class, msg_send, sel, sel_impl};
+    use std::ffi::CString;
+    use std::ptr;
+    use std::sync::mpsc::Sender;
+
+    use super::{callback, LockTrigger};
+
+    extern "C" fn will_sleep(_: &Object, _: Sel, _: *mut Object) {
+        callback::send(LockTrigger::Sleep);
+    }
+
+    extern "C" fn did_wake(_: &Object, _: Sel, _: *mut Object) {
+        callback::send(LockTrigger::Wake);
+    }
+
+    extern "C" fn screen_locked(_: &Object, _: Sel, _: *mut Object) {
+        callback::send(LockTrigger::ScreenLock);
+    }
+
+    unsafe fn ns_string(value: &str) -> *mut Object {
+        let value = CString::new(value).expect("notification names contain no NUL");
+        msg_send![class!(NSString), stringWithUTF8String: value.as_ptr()]
+    }
+
+    pub fn start(sender: Sender<LockTrigger>) {
+        if !callback::install(sender) {
+            return;
+        }
+
+        let Some(mut decl) = ClassDecl::new("SafeNodeLockObserver", class!(NSObject)) else {
+            return;
+        };
+        unsafe {
+            decl.add_method(sel!(willSleep:), will_sleep as extern "C" fn(&Object, Sel, *mut Object));
+            decl.add_method(sel!(didWake:), did_wake as extern "C" fn(&Object, Sel, *mut Object));
+            decl.add_method(sel!(screenLocked:), screen_locked as extern "C" fn(&Object, Sel, *mut Object));
+            let observer_class = decl.register();
+            // Lives for the rest of the process, so it is never released
+            let observer: *mut Object = msg_send![observer_class, new];
+            let nil = ptr::null_mut::<Object>();
+
+            let workspace: *mut Object = msg_send![class!(NSWorkspace), sharedWorkspace];
+            let workspace_center: *mut Object = msg_send![workspace, notificationCenter];
+            let _: () = msg_send![workspace_center,
+                addObserver: observer
+                selector: sel!(willSleep:)
+                name: ns_string("NSWorkspaceWillSleepNotification")
+                object: nil];
+            let _: () = msg_send![workspace_center,
+                addObserver: observer
+                selector: sel!(didWake:)
+                name: ns_string("NSWorkspaceDidWakeNotification")
+                object: nil];
+
+            let distributed_center: *mut Object = msg_send![class!(NSDistributedNotificationCenter), defaultCenter];
+            let _: () = msg_send![distributed_center,
+                addObserver: observer
+                selector: sel!(screenLocked:)
+                name: ns_string("com.apple.screenIsLocked")
+                object: nil];
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", all(target_os = "linux", feature = "dbus"))))]
+mod platform {
+    use std::sync::mpsc::Sender;
+
+    use super::LockTrigger;
+
+    pub fn start(_sender: Sender<LockTrigger>) {}
+}
@@ -0,0 +1,180 @@
+/*!
+ * Re-authentication Tokens
+ * Short-lived, single-use proof that the user just re-entered their credentials
+ */
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+use crate::biometrics::AuthMethod;
+use crate::crypto;
+use crate::error::VaultError;
+
+/// How long a token from `reauthenticate` stays valid
+pub const TOKEN_LIFETIME: Duration = Duration::from_secs(60);
+
+/// How the user proves their identity again
+#[derive(Debug, Deserialize, Zeroize, ZeroizeOnDrop)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum ReauthCredential {
+    Password {
+        password: String,
+        #[serde(default)]
+        key_file_path: Option<String>,
+    },
+    Biometric,
+}
+
+/// Confirmation asked for before exporting a vault in plaintext
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportApproval {
+    /// A token from `reauthenticate`
+    #[default]
+    Password,
+    /// A fresh biometric or system password prompt, falling back to a
+    /// token when neither is usable
+    Biometric,
+}
+
+/// What approved an export, as written to the audit log
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovedWith {
+    /// A token from `reauthenticate`
+    ReauthToken,
+    Biometric,
+    SystemPassword,
+}
+
+impl From<AuthMethod> for ApprovedWith {
+    fn from(method: AuthMethod) -> Self {
+        match method {
+            AuthMethod::Biometric => ApprovedWith::Biometric,
+            AuthMethod::SystemPassword => ApprovedWith::SystemPassword,
+        }
+    }
+}
+
+/// What a token may be used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenScope {
+    /// From `reauthenticate`, for the commands that require re-entering
+    /// credentials
+    Reauth,
+    /// From `approve_export`, for the export itself and nothing else
+    Export,
+}
+
+/// Outstanding tokens, each bound to the vault and scope it was issued for.
+///
+/// Expiry uses `Instant`, which is monotonic, so setting the wall clock back
+/// cannot extend a token.
+#[derive(Debug, Default)]
+pub struct ReauthTokens {
+    issued: HashMap<String, Issued>,
+}
+
+#[derive(Debug)]
+struct Issued {
+    vault_name: String,
+    scope: TokenScope,
+    expires_at: Instant,
+}
+
+impl ReauthTokens {
+    /// Mint a token for `vault_name`, accepted only for `scope`
+    pub fn issue(&mut self, vault_name: &str, scope: TokenScope) -> String {
+        self.prune();
+        let token = crypto::random_token();
+        let issued = Issued {
+            vault_name: vault_name.to_string(),
+            scope,
+            expires_at: Instant::now() + TOKEN_LIFETIME,
+        };
+        self.issued.insert(token.clone(), issued);
+        token
+    }
+
+    /// Use up `token`; fails unless it was issued for `vault_name` and
+    /// `scope` and has not expired. A token is removed even when the check
+    /// fails.
+    pub fn consume(&mut self, token: Option<&str>, vault_name: &str, scope: TokenScope) -> Result<(), VaultError> {
+        self.prune();
+        let issued = token.and_then(|token| self.issued.remove(token));
+        match issued {
+            Some(issued) if issued.vault_name == vault_name && issued.scope == scope => Ok(()),
+            _ => Err(VaultError::ReauthenticationRequired),
+        }
+    }
+
+    /// Forget every token, e.g. when vaults are locked
+    pub fn clear(&mut self) {
+        self.issued.clear();
+    }
+
+    fn prune(&mut self) {
+        let now = Instant::now();
+        self.issued.retain(|_, issued| issued.expires_at > now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_or_unknown_tokens_are_refused() {
+        let mut tokens = ReauthTokens::default();
+        tokens.issue("default", TokenScope::Reauth);
+        assert!(matches!(
+            tokens.consume(None, "default", TokenScope::Reauth),
+            Err(VaultError::ReauthenticationRequired)
+        ));
+        assert!(matches!(
+            tokens.consume(Some("made-up"), "default", TokenScope::Reauth),
+            Err(VaultError::ReauthenticationRequired)
+        ));
+    }
+
+    #[test]
+    fn tokens_are_single_use_and_bound_to_their_vault() {
+        let mut tokens = ReauthTokens::default();
+        let token = tokens.issue("default", TokenScope::Reauth);
+        assert!(tokens.consume(Some(&token), "default", TokenScope::Reauth).is_ok());
+        assert!(tokens.consume(Some(&token), "default", TokenScope::Reauth).is_err());
+
+        let token = tokens.issue("default", TokenScope::Reauth);
+        assert!(tokens.consume(Some(&token), "work", TokenScope::Reauth).is_err());
+        // Used up by the failed attempt
+        assert!(tokens.consume(Some(&token), "default", TokenScope::Reauth).is_err());
+    }
+
+    #[test]
+    fn expired_and_cleared_tokens_are_refused() {
+        let mut tokens = ReauthTokens::default();
+        let token = tokens.issue("default", TokenScope::Reauth);
+        tokens.issued.get_mut(&token).unwrap().expires_at = Instant::now();
+        assert!(tokens.consume(Some(&token), "default", TokenScope::Reauth).is_err());
+
+        let token = tokens.issue("default", TokenScope::Reauth);
+        tokens.clear();
+        assert!(tokens.consume(Some(&token), "default", TokenScope::Reauth).is_err());
+    }
+
+    #[test]
+    fn export_tokens_only_approve_exports() {
+        let mut tokens = ReauthTokens::default();
+        let token = tokens.issue("default", TokenScope::Export);
+        assert!(tokens.consume(Some(&token), "default", TokenScope::Reauth).is_err());
+        // Used up by the failed attempt
+        assert!(tokens.consume(Some(&token), "default", TokenScope::Export).is_err());
+
+        let token = tokens.issue("default", TokenScope::Export);
+        assert!(tokens.consume(Some(&token), "default", TokenScope::Export).is_ok());
+        let token = tokens.issue("default", TokenScope::Reauth);
+        assert!(tokens.consume(Some(&token), "default", TokenScope::Export).is_err());
+    }
+}
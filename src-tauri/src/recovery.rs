@@ -0,0 +1,95 @@
+/*!
+ * Recovery Codes
+ * Emergency-kit codes that unwrap the vault data key without the master password
+ */
+
+use rand::rngs::OsRng;
+use rand::RngCore;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::crypto::{self, KdfParams, VaultKey};
+use crate::format::KeySlot;
+
+/// 160 bits of entropy, shown as 8 groups of 4 characters
+const CODE_BYTES: usize = 20;
+const GROUP_LEN: usize = 4;
+
+/// Crockford base32 alphabet: no I, L, O or U to avoid misreading
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// Generate a new recovery code, e.g. `7K3M-Q9TB-...`
+pub fn generate_code() -> Zeroizing<String> {
+    let mut bytes = Zeroizing::new([0u8; CODE_BYTES]);
+    OsRng.fill_bytes(&mut bytes[..]);
+
+    let encoded = encode_base32(&bytes[..]);
+    let mut code = Zeroizing::new(String::with_capacity(encoded.len() + encoded.len() / GROUP_LEN));
+    for (i, c) in encoded.chars().enumerate() {
+        if i > 0 && i % GROUP_LEN == 0 {
+            code.push('-');
+        }
+        code.push(c);
+    }
+    code
+}
+
+/// Canonical form used as KDF input: separators and whitespace removed,
+/// uppercased, and commonly confused characters mapped per Crockford
+pub fn normalize(code: &str) -> Zeroizing<String> {
+    let mut out = Zeroizing::new(String::with_capacity(code.len()));
+    for c in code.chars().filter(|c| !c.is_whitespace() && *c != '-') {
+        let c = match c.to_ascii_uppercase() {
+            'O' => '0',
+            'I' | 'L' => '1',
+            other => other,
+        };
+        out.push(c);
+    }
+    out
+}
+
+/// Wrap `data_key` under a key derived from `code`
+pub fn make_slot(code: &str, data_key: &VaultKey, kdf_template: &KdfParams) -> Result<KeySlot, String> {
+    let mut kdf = kdf_template.with_fresh_salt();
+    kdf.key_file = false;
+
+    let normalized = normalize(code);
+    let recovery_key = crypto::derive_key(normalized.as_bytes(), &kdf)?;
+
+    Ok(KeySlot {
+        key_check: crypto::key_check(&recovery_key),
+        wrapped_key: crypto::wrap_key(&recovery_key, data_key)?,
+        kdf,
+    })
+}
+
+/// Unwrap the data key from `slot`; `None` if the code is wrong
+pub fn open_slot(slot: &KeySlot, code: &str) -> Result<Option<VaultKey>, String> {
+    let normalized = normalize(code);
+    let recovery_key = crypto::derive_key(normalized.as_bytes(), &slot.kdf)?;
+    if !crypto::verify_key_check(&recovery_key, &slot.key_check) {
+        return Ok(None);
+    }
+    crypto::unwrap_key(&recovery_key, &slot.wrapped_key).map(Some)
+}
+
+fn encode_base32(bytes: &[u8]) -> Zeroizing<String> {
+    let mut out = Zeroizing::new(String::with_capacity((bytes.len() * 8).div_ceil(5)));
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+
+    for &byte in bytes {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+
+    buffer.zeroize();
+    out
+}
@@ -0,0 +1,184 @@
+/*!
+ * Entry Search
+ * Case-insensitive substring and fuzzy matching over non-secret entry fields
+ */
+
+use crate::vault::{Entry, EntrySummary};
+
+/// Relative importance of a match in each searchable field
+const TITLE_WEIGHT: u32 = 4;
+const USERNAME_WEIGHT: u32 = 3;
+const URL_WEIGHT: u32 = 2;
+const TAG_WEIGHT: u32 = 2;
+const NOTES_WEIGHT: u32 = 1;
+const CUSTOM_FIELD_WEIGHT: u32 = 1;
+
+/// Entries matching `query`, best match first. Passwords and hidden custom
+/// fields are never searched. An empty query matches every entry in its
+/// stored order.
+pub fn search<'a>(entries: impl IntoIterator<Item = &'a Entry>, query: &str) -> Vec<EntrySummary> {
+    let query = query.trim().to_lowercase();
+    if query.is_empty() {
+        return entries.into_iter().map(EntrySummary::from).collect();
+    }
+
+    let mut scored: Vec<(u32, &Entry)> = entries
+        .into_iter()
+        .filter_map(|entry| {
+            let score = score_entry(entry, &query);
+            (score > 0).then_some((score, entry))
+        })
+        .collect();
+
+    // Stable, so equally good matches keep their stored order
+    scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    scored.into_iter().map(|(_, entry)| EntrySummary::from(entry)).collect()
+}
+
+fn score_entry(entry: &Entry, query: &str) -> u32 {
+    let fields = [
+        (entry.title.as_str(), TITLE_WEIGHT),
+        (entry.username.as_str(), USERNAME_WEIGHT),
+        (entry.url.as_str(), URL_WEIGHT),
+        (entry.notes.as_str(), NOTES_WEIGHT),
+    ];
+
+    fields
+        .into_iter()
+        .chain(entry.tags.iter().map(|tag| (tag.as_str(), TAG_WEIGHT)))
+        .chain(
+            entry
+                .custom_fields
+                .iter()
+                .filter(|field| !field.is_hidden())
+                .map(|field| (field.value.as_str(), CUSTOM_FIELD_WEIGHT)),
+        )
+        .map(|(text, weight)| score_text(&text.to_lowercase(), query) * weight)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Score a lowercased `text` against a lowercased, non-empty `query`; 0 means no match
+fn score_text(text: &str, query: &str) -> u32 {
+    if text == query {
+        return 100;
+    }
+    if text.starts_with(query) {
+        return 80;
+    }
+    if let Some(index) = text.find(query) {
+        let at_word_start = text[..index].chars().last().is_none_or(|c| !c.is_alphanumeric());
+        return if at_word_start { 70 } else { 60 };
+    }
+    fuzzy_score(text, query)
+}
+
+/// Match `query` as a subsequence of `text` (so "ghb" finds "github"),
+/// rewarding characters that start words or follow the previous match.
+/// Scores stay below any substring match.
+fn fuzzy_score(text: &str, query: &str) -> u32 {
+    let mut query_chars = query.chars().peekable();
+    let mut previous: Option<char> = None;
+    let mut previous_matched = false;
+    let mut bonus = 0u32;
+    let mut matched = 0u32;
+
+    for c in text.chars() {
+        let Some(&wanted) = query_chars.peek() else {
+            break;
+        };
+        if c == wanted {
+            query_chars.next();
+            matched += 1;
+            if previous.is_none_or(|p| !p.is_alphanumeric()) {
+                bonus += 3;
+            } else if previous_matched {
+                bonus += 2;
+            }
+            previous_matched = true;
+        } else {
+            previous_matched = false;
+        }
+        previous = Some(c);
+    }
+
+    if query_chars.peek().is_some() {
+        return 0;
+    }
+    let density = matched * 20 / text.chars().count().max(1) as u32;
+    (10 + bonus + density).min(50)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entry(title: &str, fields: serde_json::Value) -> Entry {
+        let mut entry = json!({
+            "id": uuid::Uuid::new_v4(),
+            "kind": "login",
+            "title": title,
+            "created_at": "2024-01-01T00:00:00Z",
+            "modified_at": "2024-01-01T00:00:00Z",
+        });
+        entry.as_object_mut().unwrap().extend(fields.as_object().unwrap().clone());
+        serde_json::from_value(entry).unwrap()
+    }
+
+    fn titles(results: &[EntrySummary]) -> Vec<&str> {
+        results.iter().map(|summary| summary.title.as_str()).collect()
+    }
+
+    #[test]
+    fn ranks_title_matches_first() {
+        let entries = [
+            entry("Mail", json!({ "notes": "github recovery codes" })),
+            entry("GitHub", json!({})),
+            entry("Work", json!({ "username": "github-bot" })),
+        ];
+        assert_eq!(titles(&search(&entries, "GITHUB")), ["GitHub", "Work", "Mail"]);
+    }
+
+    #[test]
+    fn fuzzy_matches_rank_below_substrings() {
+        let entries = [entry("GitHub", json!({})), entry("ghb tools", json!({})), entry("Gmail", json!({}))];
+        assert_eq!(titles(&search(&entries, "ghb")), ["ghb tools", "GitHub"]);
+    }
+
+    #[test]
+    fn unicode_titles_match_case_insensitively() {
+        let entries = [
+            entry("Élysée Bank", json!({})),
+            entry("Ünïcode Straße", json!({})),
+            entry("東京 Metro", json!({})),
+        ];
+        assert_eq!(titles(&search(&entries, "élysée")), ["Élysée Bank"]);
+        assert_eq!(titles(&search(&entries, "ÜNÏCODE")), ["Ünïcode Straße"]);
+        assert_eq!(titles(&search(&entries, "東京")), ["東京 Metro"]);
+    }
+
+    #[test]
+    fn secrets_are_never_searched() {
+        let entries = [entry(
+            "Bank",
+            json!({
+                "password": "correcthorse",
+                "custom_fields": [{ "name": "PIN", "value": "correcthorse", "kind": "hidden" }],
+            }),
+        )];
+        assert!(search(&entries, "correcthorse").is_empty());
+    }
+
+    #[test]
+    fn tags_are_searched() {
+        let entries = [entry("Bank", json!({ "tags": ["Finance"] })), entry("Mail", json!({}))];
+        assert_eq!(titles(&search(&entries, "finance")), ["Bank"]);
+    }
+
+    #[test]
+    fn empty_query_returns_everything_in_order() {
+        let entries = [entry("B", json!({})), entry("A", json!({}))];
+        assert_eq!(titles(&search(&entries, "  ")), ["B", "A"]);
+    }
+}
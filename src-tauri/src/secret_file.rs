@@ -0,0 +1,147 @@
+/*!
+ * Secret File
+ * Encrypted stand-in for the OS keychain on systems without one, e.g. a
+ * minimal Linux install with no Secret Service daemon
+ */
+
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
+
+use crate::crypto::{self, VaultKey};
+use crate::keychain::{KeychainBackend, KeychainError, SecretStore};
+use crate::storage;
+
+const FILE_NAME: &str = "secrets.bin";
+/// Random bytes that, with the machine id, make up the file's key
+const PEPPER_FILE_NAME: &str = "secrets.pepper";
+const PEPPER_LEN: usize = 32;
+/// Authenticated with the ciphertext so the file cannot pass as another
+const FILE_AAD: &[u8] = b"safenode-secret-file-v1";
+/// Where Linux keeps its stable machine id; the first that exists is used
+const MACHINE_ID_PATHS: [&str; 2] = ["/etc/machine-id", "/var/lib/dbus/machine-id"];
+
+#[derive(Serialize, Deserialize, Zeroize, ZeroizeOnDrop)]
+struct StoredSecret {
+    service: String,
+    account: String,
+    secret: String,
+}
+
+/// Secrets encrypted under a key derived from a random pepper and the
+/// machine id. Both files are readable only by the user, so this guards
+/// against the file being copied elsewhere, not against other processes
+/// running as the user.
+pub struct FileStore {
+    dir: PathBuf,
+    /// Held across each read-modify-write of the file
+    lock: Mutex<()>,
+}
+
+impl FileStore {
+    pub fn new(dir: PathBuf) -> Self {
+        FileStore { dir, lock: Mutex::new(()) }
+    }
+
+    /// Whether secrets have been written here. Once they have, the file is
+    /// used even if an OS keychain shows up later.
+    pub fn in_use(&self) -> bool {
+        self.dir.join(FILE_NAME).exists()
+    }
+
+    fn key(&self) -> Result<VaultKey, KeychainError> {
+        let path = self.dir.join(PEPPER_FILE_NAME);
+        let pepper = match storage::read_file(&path).map_err(io_error)? {
+            Some(pepper) => Zeroizing::new(pepper),
+            None => {
+                let mut pepper = Zeroizing::new(vec![0u8; PEPPER_LEN]);
+                OsRng.fill_bytes(&mut pepper[..]);
+                std::fs::create_dir_all(&self.dir).map_err(|e| KeychainError::PlatformError(e.to_string()))?;
+                storage::write_new_private(&path, &pepper).map_err(io_error)?;
+                pepper
+            }
+        };
+        Ok(crypto::machine_bound_key(&pepper, &machine_id()))
+    }
+
+    fn load(&self, key: &VaultKey) -> Result<Vec<StoredSecret>, KeychainError> {
+        let Some(blob) = storage::read_file(&self.dir.join(FILE_NAME)).map_err(io_error)? else {
+            return Ok(Vec::new());
+        };
+        let json = crypto::decrypt(key, &blob, FILE_AAD).map_err(|_| {
+            KeychainError::PlatformError("The secret file cannot be decrypted on this machine".to_string())
+        })?;
+        serde_json::from_slice(&json).map_err(|e| KeychainError::PlatformError(e.to_string()))
+    }
+
+    fn save(&self, key: &VaultKey, secrets: &[StoredSecret]) -> Result<(), KeychainError> {
+        let json = serde_json::to_vec(secrets).map_err(|e| KeychainError::PlatformError(e.to_string()))?;
+        let json = Zeroizing::new(json);
+        let blob = crypto::encrypt(key, &json, FILE_AAD).map_err(KeychainError::PlatformError)?;
+        storage::write_atomic(&self.dir.join(FILE_NAME), &blob).map_err(io_error)
+    }
+}
+
+impl SecretStore for FileStore {
+    fn get(&self, service: &str, account: &str) -> Result<Option<Zeroizing<String>>, KeychainError> {
+        let _guard = self.lock.lock().unwrap();
+        if !self.in_use() {
+            return Ok(None);
+        }
+        let secrets = self.load(&self.key()?)?;
+        Ok(secrets
+            .iter()
+            .find(|stored| stored.service == service && stored.account == account)
+            .map(|stored| Zeroizing::new(stored.secret.clone())))
+    }
+
+    fn set(&self, service: &str, account: &str, secret: &str) -> Result<(), KeychainError> {
+        let _guard = self.lock.lock().unwrap();
+        let key = self.key()?;
+        let mut secrets = self.load(&key)?;
+        secrets.retain(|stored| stored.service != service || stored.account != account);
+        secrets.push(StoredSecret {
+            service: service.to_string(),
+            account: account.to_string(),
+            secret: secret.to_string(),
+        });
+        self.save(&key, &secrets)
+    }
+
+    fn delete(&self, service: &str, account: &str) -> Result<bool, KeychainError> {
+        let _guard = self.lock.lock().unwrap();
+        if !self.in_use() {
+            return Ok(false);
+        }
+        let key = self.key()?;
+        let mut secrets = self.load(&key)?;
+        let before = secrets.len();
+        secrets.retain(|stored| stored.service != service || stored.account != account);
+        if secrets.len() == before {
+            return Ok(false);
+        }
+        self.save(&key, &secrets)?;
+        Ok(true)
+    }
+
+    fn backend(&self) -> KeychainBackend {
+        KeychainBackend::FileFallback
+    }
+}
+
+fn io_error(e: crate::error::VaultError) -> KeychainError {
+    KeychainError::PlatformError(e.to_string())
+}
+
+/// The machine id, or nothing where there is none; the pepper alone then
+/// keys the file
+fn machine_id() -> Vec<u8> {
+    MACHINE_ID_PATHS
+        .iter()
+        .find_map(|path| std::fs::read(path).ok())
+        .map(|id| String::from_utf8_lossy(&id).trim().as_bytes().to_vec())
+        .unwrap_or_default()
+}
@@ -0,0 +1,377 @@
+/*!
+ * Secure Key Store
+ * Where the data key for biometric unlock is kept: bound to the Secure
+ * Enclave on macOS or a Windows Hello key on Windows, with a software
+ * fallback in the OS keychain elsewhere
+ */
+
+use zeroize::Zeroizing;
+
+use crate::crypto::{self, VaultKey, KEY_LEN};
+use crate::error::VaultError;
+use crate::keychain::{self, VaultSecret};
+
+/// Protects a vault's data key for biometric unlock
+pub trait SecureKeyStore {
+    /// Whether the protecting key lives in hardware and cannot be copied
+    /// off this machine
+    fn hardware_backed(&self) -> bool;
+
+    /// Protect `data_key` for the vault with `storage::keychain_id`
+    /// `keychain_id`, replacing earlier material.
+    /// Returns an opaque blob to keep next to the vault.
+    fn seal(&self, keychain_id: &str, data_key: &VaultKey) -> Result<Zeroizing<Vec<u8>>, VaultError>;
+
+    /// The data key in a blob from `seal`. Hardware stores show the
+    /// biometric prompt themselves; callers of the software store must have
+    /// verified the user first.
+    fn open(&self, keychain_id: &str, sealed: &[u8]) -> Result<VaultKey, VaultError>;
+
+    /// Remove the protecting key; succeeds if there was none
+    fn delete(&self, keychain_id: &str) -> Result<(), VaultError>;
+}
+
+/// The hardware store of this platform, whether or not it is usable
+fn hardware_store() -> Option<Box<dyn SecureKeyStore>> {
+    #[cfg(target_os = "macos")]
+    {
+        Some(Box::new(macos::SecureEnclaveStore))
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        Some(Box::new(windows::HelloKeyStore))
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    {
+        None
+    }
+}
+
+/// The strongest store usable on this machine
+pub fn platform_store() -> Box<dyn SecureKeyStore> {
+    let supported = {
+        #[cfg(target_os = "macos")]
+        {
+            macos::is_supported()
+        }
+
+        #[cfg(target_os = "windows")]
+        {
+            windows::is_supported()
+        }
+
+        #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+        {
+            false
+        }
+    };
+    match hardware_store() {
+        Some(store) if supported => store,
+        _ => software_store(),
+    }
+}
+
+pub fn software_store() -> Box<dyn SecureKeyStore> {
+    Box::new(SoftwareStore)
+}
+
+/// The kind of store that sealed a blob
+pub fn store_for(hardware_backed: bool) -> Result<Box<dyn SecureKeyStore>, VaultError> {
+    if hardware_backed {
+        hardware_store().ok_or(VaultError::BiometricUnlockNotEnabled)
+    } else {
+        Ok(software_store())
+    }
+}
+
+/// Delete the protecting keys of `keychain_id` from every store
+pub fn delete_all(keychain_id: &str) -> Result<(), VaultError> {
+    software_store().delete(keychain_id)?;
+    match hardware_store() {
+        Some(store) => store.delete(keychain_id),
+        None => Ok(()),
+    }
+}
+
+fn to_vault_key(bytes: &[u8]) -> Result<VaultKey, VaultError> {
+    if bytes.len() != KEY_LEN {
+        return Err(VaultError::BiometricUnlockNotEnabled);
+    }
+    let mut key = Zeroizing::new([0u8; KEY_LEN]);
+    key.copy_from_slice(bytes);
+    Ok(key)
+}
+
+/// The data key wrapped in the OS keychain under a key kept in the blob.
+/// Neither half is useful alone, but any process running as the user can
+/// read both.
+struct SoftwareStore;
+
+impl SecureKeyStore for SoftwareStore {
+    fn hardware_backed(&self) -> bool {
+        false
+    }
+
+    fn seal(&self, keychain_id: &str, data_key: &VaultKey) -> Result<Zeroizing<Vec<u8>>, VaultError> {
+        let wrapping_key = crypto::generate_key();
+        let wrapped = crypto::wrap_key(&wrapping_key, data_key).map_err(VaultError::Crypto)?;
+        keychain::save_secret_bytes(&VaultSecret::BiometricKey.account(keychain_id), &wrapped)?;
+        Ok(Zeroizing::new(wrapping_key.to_vec()))
+    }
+
+    fn open(&self, keychain_id: &str, sealed: &[u8]) -> Result<VaultKey, VaultError> {
+        let wrapping_key = to_vault_key(sealed)?;
+        let wrapped = keychain::get_secret_bytes(&VaultSecret::BiometricKey.account(keychain_id))?
+            .ok_or(VaultError::BiometricUnlockNotEnabled)?;
+        crypto::unwrap_key(&wrapping_key, &wrapped).map_err(|_| VaultError::BiometricUnlockNotEnabled)
+    }
+
+    fn delete(&self, keychain_id: &str) -> Result<(), VaultError> {
+        keychain::delete(keychain::SERVICE, &VaultSecret::BiometricKey.account(keychain_id))?;
+        Ok(())
+    }
+}
+
+/// A P-256 key generated inside the Secure Enclave with
+/// `kSecAccessControlBiometryCurrentSet`. The blob is the data key
+/// encrypted to its public half; decrypting needs the private half, which
+/// the enclave only uses after Touch ID or Face ID.
+#[cfg(target_os = "macos")]
+mod macos {
+    use security_framework::access_control::{ProtectionMode, SecAccessControl};
+    use security_framework::item::{ItemClass, ItemSearchOptions, KeyClass, Location, Reference, SearchResult};
+    use security_framework::key::{Algorithm, GenerateKeyOptions, KeyType, SecKey, Token};
+    use zeroize::Zeroizing;
+
+    use super::{to_vault_key, SecureKeyStore};
+    use crate::biometrics::{AuthMethod, AuthenticatorSource, BiometricError, PlatformAuthenticators};
+    use crate::crypto::VaultKey;
+    use crate::error::VaultError;
+
+    // `SecAccessControlCreateFlags`
+    const BIOMETRY_CURRENT_SET: usize = 1 << 3;
+    const PRIVATE_KEY_USAGE: usize = 1 << 30;
+
+    // `OSStatus` codes
+    const ERR_SEC_USER_CANCELED: isize = -128;
+    const ERR_SEC_AUTH_FAILED: isize = -25293;
+    const ERR_SEC_ITEM_NOT_FOUND: i32 = -25300;
+
+    const ALGORITHM: Algorithm = Algorithm::ECIESEncryptionCofactorVariableIVX963SHA256AESGCM;
+
+    pub struct SecureEnclaveStore;
+
+    /// Every Mac with Touch ID or Face ID has a Secure Enclave
+    pub fn is_supported() -> bool {
+        let availability = PlatformAuthenticators
+            .authenticator(AuthMethod::Biometric)
+            .map(|biometrics| biometrics.is_available());
+        matches!(availability, Some(Ok(availability)) if availability.available && availability.enrolled)
+    }
+
+    fn label(keychain_id: &str) -> String {
+        format!("SafeNode biometric key: {}", keychain_id)
+    }
+
+    fn find_key(keychain_id: &str) -> Result<Option<SecKey>, VaultError> {
+        let results = ItemSearchOptions::new()
+            .class(ItemClass::key())
+            .key_class(KeyClass::private())
+            .label(&label(keychain_id))
+            .ignore_legacy_keychains()
+            .load_refs(true)
+            .search();
+        match results {
+            Ok(results) => Ok(results.into_iter().find_map(|result| match result {
+                SearchResult::Ref(Reference::Key(key)) => Some(key),
+                _ => None,
+            })),
+            Err(e) if e.code() == ERR_SEC_ITEM_NOT_FOUND => Ok(None),
+            Err(e) => Err(VaultError::Io(format!("Keychain error: {}", e))),
+        }
+    }
+
+    impl SecureKeyStore for SecureEnclaveStore {
+        fn hardware_backed(&self) -> bool {
+            true
+        }
+
+        fn seal(&self, keychain_id: &str, data_key: &VaultKey) -> Result<Zeroizing<Vec<u8>>, VaultError> {
+            self.delete(keychain_id)?;
+            let access = SecAccessControl::create_with_protection(
+                Some(ProtectionMode::AccessibleWhenPasscodeSetThisDeviceOnly),
+                (BIOMETRY_CURRENT_SET | PRIVATE_KEY_USAGE) as _,
+            )
+            .map_err(|e| VaultError::Io(format!("Keychain error: {}", e)))?;
+            let mut options = GenerateKeyOptions::default();
+            options
+                .set_key_type(KeyType::ec())
+                .set_size_in_bits(256)
+                .set_label(label(keychain_id))
+                .set_token(Token::SecureEnclave)
+                .set_location(Location::DataProtectionKeychain)
+                .set_access_control(access);
+            let private_key = SecKey::new(&options)
+                .map_err(|e| VaultError::Io(format!("Secure Enclave key could not be created: {}", e)))?;
+            let public_key = private_key
+                .public_key()
+                .ok_or_else(|| VaultError::Io("Secure Enclave key has no public key".to_string()))?;
+            let sealed = public_key
+                .encrypt_data(ALGORITHM, &data_key[..])
+                .map_err(|e| VaultError::Io(format!("Secure Enclave encryption failed: {}", e)))?;
+            Ok(Zeroizing::new(sealed))
+        }
+
+        fn open(&self, keychain_id: &str, sealed: &[u8]) -> Result<VaultKey, VaultError> {
+            let private_key = find_key(keychain_id)?.ok_or(VaultError::BiometricUnlockNotEnabled)?;
+            // Using the private key is what shows the Touch ID / Face ID prompt
+            let data_key = private_key.decrypt_data(ALGORITHM, sealed).map_err(|e| match e.code() {
+                ERR_SEC_USER_CANCELED => VaultError::BiometricFailed(BiometricError::UserCancelled),
+                ERR_SEC_AUTH_FAILED => VaultError::BiometricFailed(BiometricError::NotRecognized),
+                code => VaultError::BiometricFailed(BiometricError::PlatformError {
+                    code: code as i64,
+                    message: format!("Secure Enclave decryption failed: {}", e),
+                }),
+            })?;
+            to_vault_key(&Zeroizing::new(data_key))
+        }
+
+        fn delete(&self, keychain_id: &str) -> Result<(), VaultError> {
+            match find_key(keychain_id)? {
+                Some(key) => key
+                    .delete()
+                    .map_err(|e| VaultError::Io(format!("Failed to delete Secure Enclave key: {}", e))),
+                None => Ok(()),
+            }
+        }
+    }
+}
+
+/// A Windows Hello key credential, whose private half stays in the TPM
+/// and signs only after Hello verifies the user. The blob is a random
+/// challenge followed by the data key wrapped under a hash of the
+/// credential's signature over that challenge.
+#[cfg(target_os = "windows")]
+mod windows {
+    use ::windows::core::{Array, HSTRING};
+    use ::windows::Security::Credentials::{
+        KeyCredential, KeyCredentialCreationOption, KeyCredentialManager, KeyCredentialStatus,
+    };
+    use ::windows::Security::Cryptography::CryptographicBuffer;
+    use rand::rngs::OsRng;
+    use rand::RngCore;
+    use sha2::{Digest, Sha256};
+    use zeroize::{Zeroize, Zeroizing};
+
+    use super::SecureKeyStore;
+    use crate::biometrics::BiometricError;
+    use crate::crypto::{self, VaultKey, KEY_LEN};
+    use crate::error::VaultError;
+
+    const CHALLENGE_LEN: usize = 32;
+
+    pub struct HelloKeyStore;
+
+    pub fn is_supported() -> bool {
+        KeyCredentialManager::IsSupportedAsync()
+            .and_then(|operation| operation.get())
+            .unwrap_or(false)
+    }
+
+    fn credential_name(keychain_id: &str) -> HSTRING {
+        HSTRING::from(format!("SafeNode:{}", keychain_id))
+    }
+
+    fn platform_error(e: ::windows::core::Error) -> VaultError {
+        VaultError::BiometricFailed(BiometricError::PlatformError {
+            code: i64::from(e.code().0),
+            message: format!("Windows Hello error: {}", e.message()),
+        })
+    }
+
+    fn status_error(status: KeyCredentialStatus) -> VaultError {
+        match status {
+            KeyCredentialStatus::NotFound => VaultError::BiometricUnlockNotEnabled,
+            KeyCredentialStatus::UserCanceled => VaultError::BiometricFailed(BiometricError::UserCancelled),
+            KeyCredentialStatus::UserPrefersPassword => VaultError::BiometricFailed(BiometricError::FallbackRequested),
+            KeyCredentialStatus::SecurityDeviceLocked => VaultError::BiometricFailed(BiometricError::LockedOut),
+            _ => VaultError::BiometricFailed(BiometricError::PlatformError {
+                code: i64::from(status.0),
+                message: "Windows Hello could not use its key".to_string(),
+            }),
+        }
+    }
+
+    /// Hello keys are RSA with PKCS#1 v1.5 padding, whose signatures are
+    /// deterministic, so the same challenge always yields the same key
+    fn wrapping_key(credential: &KeyCredential, challenge: &[u8]) -> Result<VaultKey, VaultError> {
+        let data = CryptographicBuffer::CreateFromByteArray(challenge).map_err(platform_error)?;
+        let result = credential
+            .RequestSignAsync(&data)
+            .and_then(|operation| operation.get())
+            .map_err(platform_error)?;
+        let status = result.Status().map_err(platform_error)?;
+        if status != KeyCredentialStatus::Success {
+            return Err(status_error(status));
+        }
+        let mut signature = Array::<u8>::new();
+        CryptographicBuffer::CopyToByteArray(&result.Result().map_err(platform_error)?, &mut signature)
+            .map_err(platform_error)?;
+        let mut key = Zeroizing::new([0u8; KEY_LEN]);
+        key.copy_from_slice(&Sha256::digest(&signature[..]));
+        signature[..].zeroize();
+        Ok(key)
+    }
+
+    impl SecureKeyStore for HelloKeyStore {
+        fn hardware_backed(&self) -> bool {
+            true
+        }
+
+        fn seal(&self, keychain_id: &str, data_key: &VaultKey) -> Result<Zeroizing<Vec<u8>>, VaultError> {
+            let result = KeyCredentialManager::RequestCreateAsync(
+                &credential_name(keychain_id),
+                KeyCredentialCreationOption::ReplaceExisting,
+            )
+            .and_then(|operation| operation.get())
+            .map_err(platform_error)?;
+            let status = result.Status().map_err(platform_error)?;
+            if status != KeyCredentialStatus::Success {
+                return Err(status_error(status));
+            }
+            let credential = result.Credential().map_err(platform_error)?;
+
+            let mut challenge = [0u8; CHALLENGE_LEN];
+            OsRng.fill_bytes(&mut challenge);
+            let wrapping_key = wrapping_key(&credential, &challenge)?;
+            let mut sealed = Zeroizing::new(challenge.to_vec());
+            sealed.extend(crypto::wrap_key(&wrapping_key, data_key).map_err(VaultError::Crypto)?);
+            Ok(sealed)
+        }
+
+        fn open(&self, keychain_id: &str, sealed: &[u8]) -> Result<VaultKey, VaultError> {
+            if sealed.len() <= CHALLENGE_LEN {
+                return Err(VaultError::BiometricUnlockNotEnabled);
+            }
+            let (challenge, wrapped) = sealed.split_at(CHALLENGE_LEN);
+            let result = KeyCredentialManager::OpenAsync(&credential_name(keychain_id))
+                .and_then(|operation| operation.get())
+                .map_err(platform_error)?;
+            let status = result.Status().map_err(platform_error)?;
+            if status != KeyCredentialStatus::Success {
+                return Err(status_error(status));
+            }
+            let credential = result.Credential().map_err(platform_error)?;
+            let wrapping_key = wrapping_key(&credential, challenge)?;
+            crypto::unwrap_key(&wrapping_key, wrapped).map_err(|_| VaultError::BiometricUnlockNotEnabled)
+        }
+
+        fn delete(&self, keychain_id: &str) -> Result<(), VaultError> {
+            // Fails when there is no such credential, which is what we want
+            let _ = KeyCredentialManager::DeleteAsync(&credential_name(keychain_id)).and_then(|action| action.get());
+            Ok(())
+        }
+    }
+}
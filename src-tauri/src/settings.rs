@@ -0,0 +1,246 @@
+/*!
+ * App Settings
+ * Preferences stored in cleartext outside any vault
+ */
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use tauri::AppHandle;
+
+use crate::attachments;
+use crate::biometrics;
+use crate::clipboard;
+use crate::error::{FieldError, VaultError};
+use crate::generator::{self, GeneratorPolicy};
+use crate::keychain;
+use crate::storage;
+
+const SETTINGS_FILE_NAME: &str = "settings.json";
+
+/// User preferences that apply before any vault is unlocked.
+///
+/// Unknown or missing fields fall back to their defaults so older settings
+/// files keep loading as new options are added.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Keep several vaults unlocked at once instead of locking the others
+    pub allow_multiple_vaults: bool,
+    /// Trashed entries older than this many days are purged on save
+    /// (`None` keeps them until purged manually)
+    pub trash_retention_days: Option<u32>,
+    /// How many previous passwords each entry keeps
+    pub password_history_limit: usize,
+    /// Largest file `add_attachment` accepts, in bytes
+    pub max_attachment_size: u64,
+    /// Download site icons for entry URLs. Off by default because each
+    /// fetch tells the site (and the network) that the vault holds it.
+    pub fetch_icons: bool,
+    /// Make no network requests at all, whatever `fetch_icons` or a
+    /// generator's `check_breached` ask for
+    pub offline_mode: bool,
+    /// Lock all vaults when the system goes to sleep
+    pub lock_on_sleep: bool,
+    /// Lock all vaults when the user's session is locked
+    pub lock_on_screen_lock: bool,
+    /// Destroy the vault after this many consecutive failed unlock attempts
+    /// (`None` never wipes)
+    pub wipe_after_failed_attempts: Option<u32>,
+    /// After an idle auto-lock, keep the vault key in memory for this many
+    /// minutes so biometrics alone can reopen the vault (`None` always
+    /// discards it). Convenience at a cost: the key is wrapped under a
+    /// random in-memory key, which only obscures it, so anything able to
+    /// read SafeNode's memory during the window can recover the vault.
+    /// Manual, sleep and screen-lock locks always discard the key.
+    pub soft_lock_minutes: Option<u32>,
+    /// Lowest strength score (0-4) a new master password needs unless the
+    /// caller accepts a weak one
+    pub min_master_password_score: u8,
+    /// Lock all vaults once the main window has been hidden to the tray for
+    /// this many minutes, independent of the idle timeout (`None` disables)
+    pub lock_when_hidden_minutes: Option<u32>,
+    /// After biometrics clear an entry marked `require_biometric`, reveal
+    /// and copy it again without a prompt for this many seconds
+    pub biometric_grace_seconds: u32,
+    /// On macOS, accept an Apple Watch or the login password when Touch ID
+    /// cannot be used, e.g. with the lid closed
+    pub allow_device_credential_fallback: bool,
+    /// How long a biometric or system password prompt waits for the user
+    pub biometric_timeout_seconds: u64,
+    /// How long a keychain operation waits for the OS, which may be showing
+    /// an access prompt
+    pub keychain_timeout_seconds: u64,
+    /// Whether keychain entries may sync to the user's other devices.
+    /// Only the macOS keychain (iCloud Keychain) honours it.
+    pub keychain_sync_policy: keychain::SyncPolicy,
+    /// On Linux, keep SafeNode's secrets in a Secret Service collection of
+    /// their own, which can be locked apart from the login keyring. Entries
+    /// move over as they are next written.
+    pub dedicated_keychain_collection: bool,
+    /// Clear a copied secret from the clipboard after this many seconds,
+    /// unless something else was copied since (`None` disables)
+    pub clipboard_clear_seconds: Option<u32>,
+    /// After `copy_entry_credentials_staged` copies a username, replace it
+    /// with the password after this many seconds (`None` waits for
+    /// `advance_staged_copy`)
+    pub staged_copy_delay_seconds: Option<u32>,
+    /// On Linux, also put copies in the PRIMARY selection so middle-click
+    /// pastes them. Off by default, as any app can read PRIMARY.
+    pub also_set_primary_selection: bool,
+    /// Named generator options; folders may refer to one by name
+    pub generator_policies: Vec<GeneratorPolicy>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            allow_multiple_vaults: false,
+            trash_retention_days: Some(30),
+            password_history_limit: 10,
+            max_attachment_size: attachments::DEFAULT_MAX_SIZE,
+            fetch_icons: false,
+            offline_mode: false,
+            lock_on_sleep: true,
+            lock_on_screen_lock: true,
+            wipe_after_failed_attempts: None,
+            soft_lock_minutes: None,
+            min_master_password_score: 2,
+            lock_when_hidden_minutes: None,
+            biometric_grace_seconds: 10,
+            allow_device_credential_fallback: false,
+            biometric_timeout_seconds: biometrics::PROMPT_TIMEOUT.as_secs(),
+            keychain_timeout_seconds: keychain::DEFAULT_TIMEOUT.as_secs(),
+            keychain_sync_policy: keychain::SyncPolicy::default(),
+            dedicated_keychain_collection: false,
+            clipboard_clear_seconds: Some(clipboard::DEFAULT_CLEAR_AFTER.as_secs() as u32),
+            staged_copy_delay_seconds: Some(10),
+            also_set_primary_selection: false,
+            generator_policies: Vec::new(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn generator_policy(&self, name: &str) -> Result<&GeneratorPolicy, VaultError> {
+        self.generator_policies.iter().find(|policy| policy.name == name.trim()).ok_or_else(|| {
+            VaultError::InvalidFields(vec![FieldError::new(
+                "policy",
+                &format!("There is no generator policy named \"{}\"", name.trim()),
+            )])
+        })
+    }
+
+    /// Check every generator policy as `save_generator_policy` would
+    pub fn validate_generator_policies(&self) -> Result<(), VaultError> {
+        for (i, policy) in self.generator_policies.iter().enumerate() {
+            let name = generator::policy_name(&policy.name).map_err(|e| VaultError::InvalidFields(vec![e]))?;
+            if self.generator_policies[..i].iter().any(|other| other.name.trim() == name) {
+                return Err(VaultError::InvalidFields(vec![FieldError::new(
+                    "generator_policies",
+                    &format!("More than one generator policy is named \"{}\"", name),
+                )]));
+            }
+            generator::validate(&policy.options).map_err(|e| VaultError::InvalidFields(vec![e]))?;
+        }
+        Ok(())
+    }
+
+    /// Security settings that differ in `updated`, as `(field, before,
+    /// after)`. Changing any of them requires re-authentication.
+    pub fn security_changes(&self, updated: &Settings) -> Vec<(&'static str, Value, Value)> {
+        let fields = [
+            ("lock_on_sleep", json!(self.lock_on_sleep), json!(updated.lock_on_sleep)),
+            ("lock_on_screen_lock", json!(self.lock_on_screen_lock), json!(updated.lock_on_screen_lock)),
+            (
+                "wipe_after_failed_attempts",
+                json!(self.wipe_after_failed_attempts),
+                json!(updated.wipe_after_failed_attempts),
+            ),
+            ("soft_lock_minutes", json!(self.soft_lock_minutes), json!(updated.soft_lock_minutes)),
+            (
+                "min_master_password_score",
+                json!(self.min_master_password_score),
+                json!(updated.min_master_password_score),
+            ),
+            (
+                "lock_when_hidden_minutes",
+                json!(self.lock_when_hidden_minutes),
+                json!(updated.lock_when_hidden_minutes),
+            ),
+            (
+                "biometric_grace_seconds",
+                json!(self.biometric_grace_seconds),
+                json!(updated.biometric_grace_seconds),
+            ),
+            (
+                "allow_device_credential_fallback",
+                json!(self.allow_device_credential_fallback),
+                json!(updated.allow_device_credential_fallback),
+            ),
+            (
+                "keychain_sync_policy",
+                json!(self.keychain_sync_policy),
+                json!(updated.keychain_sync_policy),
+            ),
+            (
+                "clipboard_clear_seconds",
+                json!(self.clipboard_clear_seconds),
+                json!(updated.clipboard_clear_seconds),
+            ),
+            (
+                "also_set_primary_selection",
+                json!(self.also_set_primary_selection),
+                json!(updated.also_set_primary_selection),
+            ),
+        ];
+        fields.into_iter().filter(|(_, before, after)| before != after).collect()
+    }
+}
+
+fn settings_path(app: &AppHandle) -> Result<PathBuf, VaultError> {
+    Ok(storage::data_dir(app)?.join(SETTINGS_FILE_NAME))
+}
+
+/// Load settings, falling back to defaults if the file is missing or unreadable
+pub fn load(app: &AppHandle) -> Settings {
+    settings_path(app)
+        .and_then(|path| storage::read_file(&path))
+        .ok()
+        .flatten()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+pub fn save(app: &AppHandle, settings: &Settings) -> Result<(), VaultError> {
+    let json = serde_json::to_vec_pretty(settings).map_err(|e| VaultError::Io(e.to_string()))?;
+    storage::write_atomic(&settings_path(app)?, &json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn security_changes_list_before_and_after() {
+        let current = Settings::default();
+        let mut updated = current.clone();
+        updated.lock_on_sleep = !current.lock_on_sleep;
+        updated.wipe_after_failed_attempts = Some(10);
+
+        let changes = current.security_changes(&updated);
+        let fields: Vec<&str> = changes.iter().map(|(field, _, _)| *field).collect();
+        assert_eq!(fields, ["lock_on_sleep", "wipe_after_failed_attempts"]);
+        assert_eq!(changes[0].1, json!(current.lock_on_sleep));
+        assert_eq!(changes[0].2, json!(updated.lock_on_sleep));
+        assert_eq!(changes[1].2, json!(10));
+    }
+
+    #[test]
+    fn other_settings_need_no_reauthentication() {
+        let current = Settings::default();
+        let mut updated = current.clone();
+        updated.keychain_timeout_seconds += 1;
+        assert!(current.security_changes(&updated).is_empty());
+    }
+}
@@ -0,0 +1,75 @@
+/*!
+ * Soft Lock
+ * Keeps the data key of an idle-locked vault for a short grace period so
+ * biometrics alone can reopen it
+ */
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::crypto::{self, VaultKey};
+use crate::error::VaultError;
+
+/// Longest grace period `Settings::soft_lock_minutes` accepts
+pub const MAX_MINUTES: u32 = 60;
+
+/// A data key wrapped under a random key that exists only for this lock
+struct HeldKey {
+    ephemeral_key: VaultKey,
+    wrapped_key: Vec<u8>,
+    expires_at: Instant,
+}
+
+/// Data keys of soft-locked vaults, keyed by vault name. Dropping an entry
+/// zeroizes its ephemeral key, which leaves the wrapped copy useless.
+#[derive(Default)]
+pub struct SoftLocks {
+    held: HashMap<String, HeldKey>,
+}
+
+impl SoftLocks {
+    /// Keep `data_key` of vault `name` for `window`
+    pub fn hold(&mut self, name: &str, data_key: &VaultKey, window: Duration) -> Result<(), VaultError> {
+        let ephemeral_key = crypto::generate_key();
+        let wrapped_key = crypto::wrap_key(&ephemeral_key, data_key).map_err(VaultError::Crypto)?;
+        self.held.insert(
+            name.to_string(),
+            HeldKey {
+                ephemeral_key,
+                wrapped_key,
+                expires_at: Instant::now() + window,
+            },
+        );
+        Ok(())
+    }
+
+    pub fn is_held(&self, name: &str) -> bool {
+        self.held
+            .get(name)
+            .is_some_and(|held| held.expires_at > Instant::now())
+    }
+
+    /// Remove and unwrap the data key of vault `name`, if its grace period
+    /// has not run out
+    pub fn take(&mut self, name: &str) -> Option<VaultKey> {
+        let held = self.held.remove(name)?;
+        if held.expires_at <= Instant::now() {
+            return None;
+        }
+        crypto::unwrap_key(&held.ephemeral_key, &held.wrapped_key).ok()
+    }
+
+    pub fn discard(&mut self, name: &str) {
+        self.held.remove(name);
+    }
+
+    /// Drop keys whose grace period has run out
+    pub fn prune(&mut self) {
+        let now = Instant::now();
+        self.held.retain(|_, held| held.expires_at > now);
+    }
+
+    pub fn clear(&mut self) {
+        self.held.clear();
+    }
+}
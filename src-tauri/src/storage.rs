@@ -0,0 +1,506 @@
+/*!
+ * Vault Storage Module
+ * Locates the vault file and persists it with crash-safe atomic writes
+ */
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
+use uuid::Uuid;
+
+use crate::error::VaultError;
+use crate::format;
+
+/// Vault file of the single-vault layout, migrated into `vaults/` on startup
+const LEGACY_VAULT_FILE_NAME: &str = "vault.safenode";
+const VAULTS_DIR_NAME: &str = "vaults";
+/// Suffix of the directory holding a vault's sidecar attachment files
+const ATTACHMENTS_DIR_SUFFIX: &str = ".attachments";
+const ATTACHMENT_EXTENSION: &str = "attach";
+pub const VAULT_EXTENSION: &str = "safenode";
+pub const DEFAULT_VAULT_NAME: &str = "default";
+const MAX_VAULT_NAME_LEN: usize = 64;
+const TEMP_SUFFIX: &str = ".tmp";
+/// Previous version of a vault file, refreshed on every save
+const BACKUP_SUFFIX: &str = ".bak";
+pub const MIGRATION_BACKUP_SUFFIX: &str = ".pre-migration";
+/// Decoy vault opened by the duress password
+const DECOY_SUFFIX: &str = ".alt";
+/// PIN-wrapped data key for quick unlock
+const PIN_SUFFIX: &str = ".pin";
+/// Wrapping key for the biometric copy of the data key
+const BIOMETRIC_SUFFIX: &str = ".bio";
+/// Prefix of the timestamped name a corrupted vault is moved aside to
+const CORRUPT_SUFFIX: &str = ".corrupt-";
+/// Suffixes of the new and the old vault file while `replace_vault` runs.
+/// Both files also get a leading '.', so `wipe_vault` leaves them alone.
+const REPLACEMENT_SUFFIX: &str = ".new";
+const REPLACED_SUFFIX: &str = ".old";
+
+/// Directory holding SafeNode's on-disk state
+pub fn data_dir(app: &AppHandle) -> Result<PathBuf, VaultError> {
+    app.path_resolver()
+        .app_data_dir()
+        .ok_or_else(|| VaultError::Io("Could not resolve app data directory".to_string()))
+}
+
+/// Directory holding one encrypted file per named vault
+pub fn vaults_dir(app: &AppHandle) -> Result<PathBuf, VaultError> {
+    Ok(data_dir(app)?.join(VAULTS_DIR_NAME))
+}
+
+/// Path of the encrypted file for the named vault
+pub fn vault_path(app: &AppHandle, name: &str) -> Result<PathBuf, VaultError> {
+    validate_vault_name(name)?;
+    Ok(vaults_dir(app)?.join(format!("{}.{}", name, VAULT_EXTENSION)))
+}
+
+/// Path of the decoy vault file for the named vault
+pub fn decoy_path(app: &AppHandle, name: &str) -> Result<PathBuf, VaultError> {
+    Ok(with_suffix(&vault_path(app, name)?, DECOY_SUFFIX))
+}
+
+/// Path of the quick-unlock PIN slot for the named vault
+pub fn pin_path(app: &AppHandle, name: &str) -> Result<PathBuf, VaultError> {
+    Ok(with_suffix(&vault_path(app, name)?, PIN_SUFFIX))
+}
+
+/// Path of the biometric unlock wrapping key for the named vault
+pub fn biometric_path(app: &AppHandle, name: &str) -> Result<PathBuf, VaultError> {
+    Ok(with_suffix(&vault_path(app, name)?, BIOMETRIC_SUFFIX))
+}
+
+/// Sidecar file holding an attachment too large to store inline
+pub fn attachment_path(app: &AppHandle, vault_name: &str, id: Uuid) -> Result<PathBuf, VaultError> {
+    validate_vault_name(vault_name)?;
+    Ok(vaults_dir(app)?
+        .join(format!("{}{}", vault_name, ATTACHMENTS_DIR_SUFFIX))
+        .join(format!("{}.{}", id, ATTACHMENT_EXTENSION)))
+}
+
+/// Vault names double as file names, so keep them to a safe character set
+pub fn validate_vault_name(name: &str) -> Result<(), VaultError> {
+    let valid = !name.trim().is_empty()
+        && name == name.trim()
+        && name.chars().count() <= MAX_VAULT_NAME_LEN
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == ' ' || c == '-' || c == '_');
+
+    if valid {
+        Ok(())
+    } else {
+        Err(VaultError::InvalidVaultName(name.to_string()))
+    }
+}
+
+/// Names of all vaults present on disk, sorted
+pub fn list_vault_names(app: &AppHandle) -> Result<Vec<String>, VaultError> {
+    let dir = vaults_dir(app)?;
+    let read_dir = match fs::read_dir(&dir) {
+        Ok(read_dir) => read_dir,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut names: Vec<String> = read_dir
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().and_then(|ext| ext.to_str()) == Some(VAULT_EXTENSION))
+        .filter_map(|path| path.file_stem().and_then(|stem| stem.to_str()).map(str::to_string))
+        .filter(|name| validate_vault_name(name).is_ok())
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+/// What names vault `name`'s keychain secrets: the id in its header, or
+/// the name itself for vaults created before ids
+pub fn keychain_id(app: &AppHandle, name: &str) -> Result<String, VaultError> {
+    let id = read_file(&vault_path(app, name)?)?
+        .and_then(|blob| format::decode(&blob).ok().and_then(|file| file.header.vault_id));
+    Ok(id.map_or_else(|| name.to_string(), |id| id.to_string()))
+}
+
+/// Move a vault from the single-vault layout to `vaults/default.safenode`
+pub fn migrate_legacy_vault(app: &AppHandle) -> Result<(), VaultError> {
+    let legacy = data_dir(app)?.join(LEGACY_VAULT_FILE_NAME);
+    let target = vault_path(app, DEFAULT_VAULT_NAME)?;
+    if !legacy.exists() || target.exists() {
+        return Ok(());
+    }
+
+    fs::create_dir_all(vaults_dir(app)?)?;
+    fs::rename(&legacy, &target)?;
+    Ok(())
+}
+
+/// Sibling path used while a new version of `path` is being written
+pub fn temp_path(path: &Path) -> PathBuf {
+    with_suffix(path, TEMP_SUFFIX)
+}
+
+/// Read a file, returning `None` when it does not exist.
+///
+/// Only the committed file is ever read: a leftover temp file from an
+/// interrupted write is ignored and replaced by the next save.
+pub fn read_file(path: &Path) -> Result<Option<Vec<u8>>, VaultError> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Replace `path` with `bytes` so that readers see either the old or the new
+/// contents, never a partial write.
+///
+/// The data is written to a temp file, fsynced, then renamed over the target.
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), VaultError> {
+    let dir = path
+        .parent()
+        .ok_or_else(|| VaultError::Io("Vault path has no parent directory".to_string()))?;
+    fs::create_dir_all(dir)?;
+
+    let tmp = temp_path(path);
+    {
+        let mut file = open_private(&tmp)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+    }
+
+    if let Err(e) = fs::rename(&tmp, path) {
+        let _ = fs::remove_file(&tmp);
+        return Err(e.into());
+    }
+
+    sync_dir(dir);
+    Ok(())
+}
+
+/// Write a brand-new file readable only by the current user, refusing to
+/// replace anything already at `path`
+pub fn write_new_private(path: &Path, bytes: &[u8]) -> Result<(), VaultError> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Append `bytes` to `path`, creating it readable only by the current user
+pub fn append_private(path: &Path, bytes: &[u8]) -> Result<(), VaultError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut options = OpenOptions::new();
+    options.append(true).create(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path)?;
+    file.write_all(bytes)?;
+    file.sync_data()?;
+    Ok(())
+}
+
+/// Replace a vault file, first keeping the current version as a `.bak`
+/// recovery copy. A current file that no longer parses is not backed up, so
+/// a good backup is never replaced by a damaged one.
+pub fn write_vault_file(path: &Path, bytes: &[u8]) -> Result<(), VaultError> {
+    if let Some(current) = read_file(path)? {
+        if format::decode(&current).is_ok() {
+            write_atomic(&with_suffix(path, BACKUP_SUFFIX), &current)?;
+        }
+    }
+    write_atomic(path, bytes)
+}
+
+/// A file that may hold an intact copy of a damaged vault
+#[derive(Debug, Clone, Serialize)]
+pub struct RecoveryCandidate {
+    pub path: String,
+    /// "backup", "pre-migration" or "temp"
+    pub kind: String,
+    pub modified_at: Option<DateTime<Utc>>,
+    pub size: u64,
+    /// Whether the cleartext header parses; the payload can only be checked by unlocking
+    pub header_valid: bool,
+}
+
+/// Backup and leftover temp files for `vault_file`, newest first
+pub fn recovery_candidates(vault_file: &Path) -> Vec<RecoveryCandidate> {
+    let sources = [
+        (BACKUP_SUFFIX, "backup"),
+        (MIGRATION_BACKUP_SUFFIX, "pre-migration"),
+        (TEMP_SUFFIX, "temp"),
+    ];
+
+    let mut candidates: Vec<RecoveryCandidate> = sources
+        .iter()
+        .filter_map(|(suffix, kind)| {
+            let path = with_suffix(vault_file, suffix);
+            let metadata = fs::metadata(&path).ok()?;
+            let header_valid = fs::read(&path)
+                .map(|bytes| format::decode(&bytes).is_ok())
+                .unwrap_or(false);
+
+            Some(RecoveryCandidate {
+                path: path.to_string_lossy().into_owned(),
+                kind: kind.to_string(),
+                modified_at: metadata.modified().ok().map(DateTime::<Utc>::from),
+                size: metadata.len(),
+                header_valid,
+            })
+        })
+        .collect();
+
+    candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.modified_at));
+    candidates
+}
+
+/// Move a damaged file aside under a timestamped name so it is never overwritten
+pub fn quarantine(path: &Path) -> Result<PathBuf, VaultError> {
+    let stamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let target = with_suffix(path, &format!("{}{}", CORRUPT_SUFFIX, stamp));
+    fs::rename(path, &target)?;
+    Ok(target)
+}
+
+/// Delete a vault in `dir` (see `vaults_dir`) and every file derived from
+/// it: backups, leftover temp and quarantined copies, and sidecar
+/// attachments. Each file is overwritten before removal.
+pub fn wipe_vault(dir: &Path, name: &str) -> Result<(), VaultError> {
+    validate_vault_name(name)?;
+    let file_name = format!("{}.{}", name, VAULT_EXTENSION);
+
+    // Vault names cannot contain '.', so only this vault's files share the prefix
+    for entry in list_dir(dir)? {
+        let is_derived = entry.file_name().to_string_lossy().starts_with(&file_name);
+        if is_derived && entry.path().is_file() {
+            shred(&entry.path())?;
+        }
+    }
+
+    let attachments_dir = dir.join(format!("{}{}", name, ATTACHMENTS_DIR_SUFFIX));
+    for entry in list_dir(&attachments_dir)? {
+        shred(&entry.path())?;
+    }
+    if let Err(e) = fs::remove_dir(&attachments_dir) {
+        if e.kind() != ErrorKind::NotFound {
+            return Err(e.into());
+        }
+    }
+
+    sync_dir(dir);
+    Ok(())
+}
+
+/// Replace vault `name` in `dir` with a new vault file holding `bytes`,
+/// wiping the old one as `wipe_vault` does.
+///
+/// The new file is written before anything is wiped, and the old vault
+/// file is only moved aside until the new one is in place, so a failure
+/// leaves the old vault readable. Its backups and attachments may already
+/// be gone by then.
+pub fn replace_vault(dir: &Path, name: &str, bytes: &[u8]) -> Result<(), VaultError> {
+    validate_vault_name(name)?;
+    let file_name = format!("{}.{}", name, VAULT_EXTENSION);
+    let path = dir.join(&file_name);
+    let staged = dir.join(format!(".{}{}", file_name, REPLACEMENT_SUFFIX));
+    let aside = dir.join(format!(".{}{}", file_name, REPLACED_SUFFIX));
+
+    write_atomic(&staged, bytes)?;
+    if let Err(e) = fs::rename(&path, &aside) {
+        let _ = fs::remove_file(&staged);
+        return Err(e.into());
+    }
+    let replaced = wipe_vault(dir, name).and_then(|()| fs::rename(&staged, &path).map_err(VaultError::from));
+    if let Err(e) = replaced {
+        let _ = fs::rename(&aside, &path);
+        let _ = fs::remove_file(&staged);
+        return Err(e);
+    }
+    sync_dir(dir);
+
+    // The new vault is in place, so a leftover copy of the old one is no
+    // reason to report failure
+    if let Err(e) = shred(&aside) {
+        eprintln!("Failed to wipe the replaced vault file: {}", e);
+    }
+    Ok(())
+}
+
+/// Entries of `dir`; empty if it does not exist
+fn list_dir(dir: &Path) -> Result<Vec<fs::DirEntry>, VaultError> {
+    match fs::read_dir(dir) {
+        Ok(read_dir) => Ok(read_dir.filter_map(|entry| entry.ok()).collect()),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Overwrite a file with zeros, then remove it. Best effort: SSDs and
+/// copy-on-write filesystems may still hold the old blocks.
+fn shred(path: &Path) -> Result<(), VaultError> {
+    let len = fs::metadata(path)?.len();
+    {
+        let mut file = OpenOptions::new().write(true).open(path)?;
+        let zeros = [0u8; 64 * 1024];
+        let mut remaining = len;
+        while remaining > 0 {
+            let chunk = remaining.min(zeros.len() as u64) as usize;
+            file.write_all(&zeros[..chunk])?;
+            remaining -= chunk as u64;
+        }
+        file.sync_all()?;
+    }
+    fs::remove_file(path)?;
+    Ok(())
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Copy `path` to a sibling with `suffix` appended to its file name,
+/// replacing any previous copy. Returns the backup path.
+pub fn backup_copy(path: &Path, suffix: &str) -> Result<PathBuf, VaultError> {
+    let backup = with_suffix(path, suffix);
+
+    let bytes = fs::read(path)?;
+    write_atomic(&backup, &bytes)?;
+    Ok(backup)
+}
+
+/// Create (or truncate) a file readable only by the current user
+fn open_private(path: &Path) -> std::io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    options.open(path)
+}
+
+/// Flush the directory entry so the rename itself survives a crash
+fn sync_dir(dir: &Path) {
+    #[cfg(unix)]
+    {
+        if let Ok(handle) = File::open(dir) {
+            let _ = handle.sync_all();
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = dir;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("safenode-test-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn interrupted_write_leaves_the_committed_file() {
+        let path = temp_dir().join("default.safenode");
+        write_atomic(&path, b"first").unwrap();
+
+        // A crash between creating the temp file and the rename
+        fs::write(temp_path(&path), b"sec").unwrap();
+        assert_eq!(read_file(&path).unwrap().as_deref(), Some(&b"first"[..]));
+
+        write_atomic(&path, b"second").unwrap();
+        assert_eq!(read_file(&path).unwrap().as_deref(), Some(&b"second"[..]));
+        assert!(!temp_path(&path).exists());
+    }
+
+    #[test]
+    fn missing_file_reads_as_none() {
+        let path = temp_dir().join("nothing.safenode");
+        assert_eq!(read_file(&path).unwrap(), None);
+    }
+
+    #[test]
+    fn damaged_file_is_not_backed_up() {
+        let path = temp_dir().join("default.safenode");
+        write_atomic(&path, b"not a vault").unwrap();
+        write_vault_file(&path, b"also not a vault").unwrap();
+        assert!(!with_suffix(&path, BACKUP_SUFFIX).exists());
+        assert_eq!(read_file(&path).unwrap().as_deref(), Some(&b"also not a vault"[..]));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn written_files_are_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let path = temp_dir().join("default.safenode");
+        write_atomic(&path, b"secret").unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+
+    #[test]
+    fn replaced_vault_leaves_nothing_of_the_old_one() {
+        let dir = temp_dir();
+        let path = dir.join("default.safenode");
+        write_atomic(&path, b"old").unwrap();
+        write_atomic(&with_suffix(&path, BACKUP_SUFFIX), b"older").unwrap();
+        write_atomic(&dir.join("default.attachments").join("a.attach"), b"attached").unwrap();
+
+        replace_vault(&dir, "default", b"new").unwrap();
+
+        assert_eq!(read_file(&path).unwrap().as_deref(), Some(&b"new"[..]));
+        let left: Vec<_> = list_dir(&dir).unwrap().iter().map(|entry| entry.file_name()).collect();
+        assert_eq!(left, ["default.safenode"]);
+    }
+
+    #[test]
+    fn failed_replacement_keeps_the_old_vault() {
+        let dir = temp_dir();
+        let path = dir.join("default.safenode");
+        write_atomic(&path, b"old").unwrap();
+        // A directory in the staged file's place makes writing it fail
+        fs::create_dir(temp_path(&dir.join(".default.safenode.new"))).unwrap();
+
+        assert!(replace_vault(&dir, "default", b"new").is_err());
+        assert_eq!(read_file(&path).unwrap().as_deref(), Some(&b"old"[..]));
+    }
+
+    #[test]
+    fn vault_names_are_safe_file_names() {
+        for name in ["default", "Work 2", "perso_nel-1", "café"] {
+            assert!(validate_vault_name(name).is_ok(), "{}", name);
+        }
+        for name in ["", " padded", "../escape", "a.b", "back\\slash", &"x".repeat(MAX_VAULT_NAME_LEN + 1)] {
+            assert!(validate_vault_name(name).is_err(), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,170 @@
+/*!
+ * Password Strength
+ * zxcvbn estimate of how guessable a master or entry password is
+ */
+
+use serde::Serialize;
+use zxcvbn::matching::patterns::MatchPattern;
+use zxcvbn::matching::Match;
+use zxcvbn::time_estimates::CrackTimeSeconds;
+use zxcvbn::zxcvbn;
+
+/// Highest zxcvbn score
+pub const MAX_SCORE: u8 = 4;
+/// Rank up to which zxcvbn's bundled password list counts as common
+const COMMON_PASSWORD_RANK: usize = 10_000;
+
+/// Strength estimate returned to the UI
+#[derive(Debug, Clone, Serialize)]
+pub struct PasswordStrength {
+    /// 0 (guessable in seconds) to 4 (very unlikely to be guessed)
+    pub score: u8,
+    /// Time to guess offline at 10k guesses/second, e.g. "3 hours"
+    pub crack_time: String,
+    pub warning: Option<String>,
+    pub suggestions: Vec<String>,
+    /// One of the 10,000 most common passwords
+    pub common: bool,
+    /// log10 of the guesses zxcvbn expects an attacker to need
+    pub guesses_log10: f64,
+    pub crack_times: CrackTimes,
+    /// Parts of the password that follow a known pattern, in order
+    pub patterns: Vec<PatternSpan>,
+}
+
+/// Time to guess under several attacker models
+#[derive(Debug, Clone, Serialize)]
+pub struct CrackTimes {
+    /// Online, rate limited to 100 guesses an hour
+    pub online_throttled: CrackTime,
+    /// Online at 10 guesses a second
+    pub online_unthrottled: CrackTime,
+    /// Offline against a slow hash such as Argon2, 10k guesses a second
+    pub offline_slow_hash: CrackTime,
+    /// Offline against a fast hash, 10 billion guesses a second
+    pub offline_fast_hash: CrackTime,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CrackTime {
+    pub seconds: f64,
+    /// e.g. "3 hours"
+    pub display: String,
+}
+
+impl From<CrackTimeSeconds> for CrackTime {
+    fn from(time: CrackTimeSeconds) -> Self {
+        let seconds = match time {
+            CrackTimeSeconds::Integer(seconds) => seconds as f64,
+            CrackTimeSeconds::Float(seconds) => seconds,
+        };
+        CrackTime {
+            seconds,
+            display: time.to_string(),
+        }
+    }
+}
+
+/// A recognised part of the password, for the UI to underline. Holds no
+/// characters of the password, only where they are.
+#[derive(Debug, Clone, Serialize)]
+pub struct PatternSpan {
+    /// "dictionary", "date", "repeat", "sequence", "spatial" or "regex"
+    pub pattern: &'static str,
+    /// Character offsets, `end` exclusive
+    pub start: usize,
+    pub end: usize,
+}
+
+impl PatternSpan {
+    /// `None` for the stretches zxcvbn could only brute force
+    fn from_match(matched: &Match) -> Option<Self> {
+        let pattern = match &matched.pattern {
+            MatchPattern::Dictionary(_) => "dictionary",
+            MatchPattern::Date(_) => "date",
+            MatchPattern::Repeat(_) => "repeat",
+            MatchPattern::Sequence(_) => "sequence",
+            MatchPattern::Spatial(_) => "spatial",
+            MatchPattern::Regex(_) => "regex",
+            MatchPattern::BruteForce => return None,
+        };
+        Some(PatternSpan {
+            pattern,
+            start: matched.i,
+            end: matched.j + 1,
+        })
+    }
+}
+
+impl PasswordStrength {
+    /// Whether the password should be refused: always when common,
+    /// otherwise when it scores below `min_score` and weak passwords were
+    /// not explicitly accepted
+    pub fn is_rejected(&self, min_score: u8, accept_weak: bool) -> bool {
+        self.common || (self.score < min_score && !accept_weak)
+    }
+}
+
+/// Estimate the strength of `password`. `user_inputs` are words that make
+/// it easier to guess, such as the vault name.
+///
+/// zxcvbn keeps copies of the matched parts in its result, which is dropped
+/// before this returns; it offers no way to zero them.
+pub fn estimate(password: &str, user_inputs: &[&str]) -> PasswordStrength {
+    let Ok(entropy) = zxcvbn(password, user_inputs) else {
+        // Only an empty password fails to estimate
+        let instant = || CrackTime {
+            seconds: 0.0,
+            display: "instant".to_string(),
+        };
+        return PasswordStrength {
+            score: 0,
+            crack_time: "instant".to_string(),
+            warning: None,
+            suggestions: Vec::new(),
+            common: false,
+            guesses_log10: 0.0,
+            crack_times: CrackTimes {
+                online_throttled: instant(),
+                online_unthrottled: instant(),
+                offline_slow_hash: instant(),
+                offline_fast_hash: instant(),
+            },
+            patterns: Vec::new(),
+        };
+    };
+
+    // The whole password is a single, unaltered match in the password list
+    let common = match entropy.sequence() {
+        [only] => matches!(
+            &only.pattern,
+            MatchPattern::Dictionary(dictionary)
+                // zxcvbn does not export the dictionary type, only its name
+                if format!("{:?}", dictionary.dictionary_name) == "Passwords"
+                    && dictionary.rank <= COMMON_PASSWORD_RANK
+                    && !dictionary.l33t
+                    && !dictionary.reversed
+        ),
+        _ => false,
+    };
+    let feedback = entropy.feedback().as_ref();
+    let crack_times = entropy.crack_times();
+
+    PasswordStrength {
+        score: entropy.score(),
+        crack_time: crack_times.offline_slow_hashing_1e4_per_second().to_string(),
+        warning: feedback.and_then(|feedback| feedback.warning()).map(|warning| warning.to_string()),
+        suggestions: feedback
+            .map(|feedback| feedback.suggestions().iter().map(ToString::to_string).collect())
+            .unwrap_or_default(),
+        common,
+        guesses_log10: entropy.guesses_log10(),
+        crack_times: CrackTimes {
+            online_throttled: crack_times.online_throttling_100_per_hour().into(),
+            online_unthrottled: crack_times.online_no_throttling_10_per_second().into(),
+            offline_slow_hash: crack_times.offline_slow_hashing_1e4_per_second().into(),
+            offline_fast_hash: crack_times.offline_fast_hashing_1e10_per_second().into(),
+        },
+        patterns: entropy.sequence().iter().filter_map(PatternSpan::from_match).collect(),
+    }
+}
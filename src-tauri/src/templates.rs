@@ -0,0 +1,106 @@
+/*!
+ * Entry Templates
+ * Reusable layouts for new entries; templates never hold secret values
+ */
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::vault::{CustomFieldKind, Entry, ItemKind};
+
+/// Custom field slot of a template: a name and kind, no value
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateField {
+    pub name: String,
+    #[serde(default)]
+    pub kind: CustomFieldKind,
+}
+
+/// Layout captured by a template
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TemplateFields {
+    pub kind: ItemKind,
+    pub custom_fields: Vec<TemplateField>,
+    pub tags: Vec<String>,
+    pub folder_id: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntryTemplate {
+    pub id: Uuid,
+    pub name: String,
+    #[serde(flatten)]
+    pub fields: TemplateFields,
+    /// Shipped with SafeNode rather than stored in the vault
+    #[serde(default, skip_deserializing)]
+    pub builtin: bool,
+}
+
+impl TemplateFields {
+    /// Layout of an existing entry, with every value left out
+    pub fn from_entry(entry: &Entry) -> Self {
+        TemplateFields {
+            kind: entry.kind,
+            custom_fields: entry
+                .custom_fields
+                .iter()
+                .map(|field| TemplateField {
+                    name: field.name.clone(),
+                    kind: field.kind,
+                })
+                .collect(),
+            tags: entry.tags.clone(),
+            folder_id: entry.folder_id,
+        }
+    }
+}
+
+/// Templates available in every vault. Their ids are fixed so the UI can
+/// refer to them across sessions.
+pub fn builtin() -> Vec<EntryTemplate> {
+    let template = |id: u128, name: &str, fields: &[(&str, CustomFieldKind)]| EntryTemplate {
+        id: Uuid::from_u128(id),
+        name: name.to_string(),
+        fields: TemplateFields {
+            kind: ItemKind::Login,
+            custom_fields: fields
+                .iter()
+                .map(|(name, kind)| TemplateField {
+                    name: name.to_string(),
+                    kind: *kind,
+                })
+                .collect(),
+            tags: Vec::new(),
+            folder_id: None,
+        },
+        builtin: true,
+    };
+
+    vec![
+        template(
+            0x5afe_0001,
+            "Wi-Fi Network",
+            &[("SSID", CustomFieldKind::Text), ("Security", CustomFieldKind::Text)],
+        ),
+        template(
+            0x5afe_0002,
+            "Database Credentials",
+            &[
+                ("Host", CustomFieldKind::Text),
+                ("Port", CustomFieldKind::Text),
+                ("Database", CustomFieldKind::Text),
+                ("Connection String", CustomFieldKind::Hidden),
+            ],
+        ),
+        template(
+            0x5afe_0003,
+            "Server Login",
+            &[
+                ("Hostname", CustomFieldKind::Text),
+                ("Port", CustomFieldKind::Text),
+                ("SSH Private Key", CustomFieldKind::Hidden),
+            ],
+        ),
+    ]
+}
@@ -0,0 +1,173 @@
+/*!
+ * Unlock Throttling
+ * Exponential backoff after failed unlock attempts, persisted across restarts
+ */
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+use tauri::AppHandle;
+
+use crate::error::VaultError;
+use crate::storage;
+
+const THROTTLE_FILE_NAME: &str = "unlock_attempts.json";
+/// Failures allowed before any delay applies
+const FREE_ATTEMPTS: u32 = 3;
+const BASE_DELAY: Duration = Duration::from_secs(1);
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Failed unlock attempts since the last successful one.
+///
+/// Wall-clock time is stored rather than an `Instant` so the cooldown
+/// survives restarting the app.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UnlockThrottle {
+    pub failures: u32,
+    pub last_failure_at: Option<DateTime<Utc>>,
+    /// Consecutive wrong passwords or recovery codes per vault, counted
+    /// towards `Settings::wipe_after_failed_attempts`. Only unlocking that
+    /// same vault clears its count.
+    vault_failures: HashMap<String, u32>,
+}
+
+impl UnlockThrottle {
+    /// Delay imposed after the current number of failures: 1s after the
+    /// third, doubling with each further failure up to `MAX_DELAY`
+    fn delay(&self) -> Duration {
+        if self.failures < FREE_ATTEMPTS {
+            return Duration::ZERO;
+        }
+        let doublings = (self.failures - FREE_ATTEMPTS).min(16);
+        (BASE_DELAY * 2u32.pow(doublings)).min(MAX_DELAY)
+    }
+
+    /// Whether failures have gone past the free attempts
+    pub fn is_backing_off(&self) -> bool {
+        self.failures >= FREE_ATTEMPTS
+    }
+
+    /// Time left before another attempt is allowed
+    pub fn remaining(&self) -> Duration {
+        let Some(last_failure_at) = self.last_failure_at else {
+            return Duration::ZERO;
+        };
+        // A clock set backwards must not shorten the wait, so time before
+        // the last failure counts as no time elapsed
+        let elapsed = (Utc::now() - last_failure_at).to_std().unwrap_or(Duration::ZERO);
+        self.delay().saturating_sub(elapsed)
+    }
+
+    /// `TooManyAttempts` while a cooldown is running
+    pub fn check(&self) -> Result<(), VaultError> {
+        let remaining = self.remaining();
+        if remaining.is_zero() {
+            Ok(())
+        } else {
+            Err(VaultError::TooManyAttempts {
+                retry_after_secs: remaining.as_secs_f64().ceil() as u64,
+            })
+        }
+    }
+
+    pub fn record_failure(&mut self) {
+        self.failures = self.failures.saturating_add(1);
+        self.last_failure_at = Some(Utc::now());
+    }
+
+    /// End the backoff. Counts per vault are kept.
+    pub fn reset(&mut self) {
+        self.failures = 0;
+        self.last_failure_at = None;
+    }
+
+    /// Count a wrong password or recovery code for `vault`. Returns its
+    /// consecutive failures.
+    pub fn record_vault_failure(&mut self, vault: &str) -> u32 {
+        let failures = self.vault_failures.entry(vault.to_string()).or_default();
+        *failures = failures.saturating_add(1);
+        *failures
+    }
+
+    pub fn clear_vault_failures(&mut self, vault: &str) {
+        self.vault_failures.remove(vault);
+    }
+}
+
+fn throttle_path(app: &AppHandle) -> Result<PathBuf, VaultError> {
+    Ok(storage::data_dir(app)?.join(THROTTLE_FILE_NAME))
+}
+
+/// Load the persisted failure count, starting fresh if the file is missing
+/// or unreadable
+pub fn load(app: &AppHandle) -> UnlockThrottle {
+    throttle_path(app)
+        .and_then(|path| storage::read_file(&path))
+        .ok()
+        .flatten()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+pub fn save(app: &AppHandle, throttle: &UnlockThrottle) -> Result<(), VaultError> {
+    let json = serde_json::to_vec_pretty(throttle).map_err(|e| VaultError::Io(e.to_string()))?;
+    storage::write_atomic(&throttle_path(app)?, &json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vault_failures_are_counted_per_vault() {
+        let mut throttle = UnlockThrottle::default();
+        assert_eq!(throttle.record_vault_failure("travel"), 1);
+        assert_eq!(throttle.record_vault_failure("travel"), 2);
+        assert_eq!(throttle.record_vault_failure("work"), 1);
+
+        throttle.clear_vault_failures("work");
+        assert_eq!(throttle.record_vault_failure("travel"), 3);
+        assert_eq!(throttle.record_vault_failure("work"), 1);
+    }
+
+    #[test]
+    fn reset_ends_the_backoff_but_keeps_vault_failures() {
+        let mut throttle = UnlockThrottle::default();
+        for _ in 0..FREE_ATTEMPTS + 1 {
+            throttle.record_failure();
+            throttle.record_vault_failure("travel");
+        }
+        assert!(throttle.is_backing_off());
+        assert!(throttle.check().is_err());
+
+        // e.g. another vault unlocked
+        throttle.reset();
+        assert!(!throttle.is_backing_off());
+        assert!(throttle.check().is_ok());
+        assert_eq!(throttle.record_vault_failure("travel"), FREE_ATTEMPTS + 2);
+    }
+
+    #[test]
+    fn delay_doubles_up_to_the_cap() {
+        let mut throttle = UnlockThrottle::default();
+        let mut delays = Vec::new();
+        for _ in 0..12 {
+            throttle.record_failure();
+            delays.push(throttle.delay());
+        }
+        assert_eq!(delays[..FREE_ATTEMPTS as usize - 1], [Duration::ZERO, Duration::ZERO]);
+        assert_eq!(delays[FREE_ATTEMPTS as usize - 1], BASE_DELAY);
+        assert_eq!(delays[FREE_ATTEMPTS as usize], BASE_DELAY * 2);
+        assert_eq!(*delays.last().unwrap(), MAX_DELAY);
+    }
+
+    #[test]
+    fn files_written_before_vault_counts_still_load() {
+        let throttle: UnlockThrottle = serde_json::from_str(r#"{"failures":2,"last_failure_at":null}"#).unwrap();
+        assert_eq!(throttle.failures, 2);
+        assert!(throttle.vault_failures.is_empty());
+    }
+}
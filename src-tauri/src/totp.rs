@@ -0,0 +1,115 @@
+/*!
+ * TOTP
+ * RFC 6238 one-time codes for entries with a two-factor secret: SHA-1,
+ * 6 digits, 30-second periods, the defaults every authenticator app uses
+ */
+
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use zeroize::Zeroizing;
+
+pub const PERIOD_SECS: u64 = 30;
+const DIGITS: u32 = 6;
+/// A code with less left than this is likely to expire before it is pasted
+const MIN_VALIDITY_SECS: u64 = 5;
+
+/// RFC 4648 alphabet, as used by `otpauth://` secrets
+const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Decode a base32 secret as shown by sites, e.g. `JBSW Y3DP EHPK 3PXP`.
+/// Spaces, dashes, padding and case are ignored. `None` if it is not
+/// base32 or is empty.
+pub fn decode_secret(secret: &str) -> Option<Zeroizing<Vec<u8>>> {
+    let mut out = Zeroizing::new(Vec::with_capacity(secret.len() * 5 / 8));
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+
+    for c in secret.bytes().filter(|c| !matches!(c, b' ' | b'-' | b'=')) {
+        let value = ALPHABET.iter().position(|&a| a == c.to_ascii_uppercase())? as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    (!out.is_empty()).then_some(out)
+}
+
+/// The code for `unix_secs`, and how many seconds it stays valid
+pub fn code_at(key: &[u8], unix_secs: u64) -> (Zeroizing<String>, u64) {
+    let counter = unix_secs / PERIOD_SECS;
+    let mut mac = <Hmac<Sha1> as Mac>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    // Dynamic truncation, RFC 4226 section 5.3
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([hash[offset] & 0x7f, hash[offset + 1], hash[offset + 2], hash[offset + 3]]);
+    let code = Zeroizing::new(format!("{:0width$}", binary % 10u32.pow(DIGITS), width = DIGITS as usize));
+    (code, PERIOD_SECS - unix_secs % PERIOD_SECS)
+}
+
+/// Seconds to wait at `unix_secs` before copying, so the copied code is
+/// not about to expire; `0` to copy the current one
+pub fn wait_before_copy(unix_secs: u64) -> u64 {
+    let left = PERIOD_SECS - unix_secs % PERIOD_SECS;
+    if left < MIN_VALIDITY_SECS {
+        left
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The SHA-1 seed of RFC 6238 appendix B, "12345678901234567890"
+    const RFC_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    #[test]
+    fn matches_the_rfc_test_vectors() {
+        let key = decode_secret(RFC_SECRET).unwrap();
+        assert_eq!(&key[..], b"12345678901234567890");
+        // The RFC lists 8 digits; these are their last 6
+        for (time, expected) in [
+            (59, "287082"),
+            (1111111109, "081804"),
+            (1111111111, "050471"),
+            (1234567890, "005924"),
+            (2000000000, "279037"),
+            (20000000000, "353130"),
+        ] {
+            assert_eq!(code_at(&key, time).0.as_str(), expected, "at {}", time);
+        }
+    }
+
+    #[test]
+    fn reports_the_seconds_left_in_the_period() {
+        let key = decode_secret(RFC_SECRET).unwrap();
+        assert_eq!(code_at(&key, 60).1, 30);
+        assert_eq!(code_at(&key, 59).1, 1);
+        assert_eq!(code_at(&key, 59).0, code_at(&key, 30).0);
+        assert_ne!(code_at(&key, 59).0, code_at(&key, 60).0);
+    }
+
+    #[test]
+    fn codes_about_to_expire_wait_for_the_next_period() {
+        assert_eq!(wait_before_copy(60), 0);
+        assert_eq!(wait_before_copy(84), 0);
+        assert_eq!(wait_before_copy(86), 4);
+        assert_eq!(wait_before_copy(89), 1);
+        let key = decode_secret(RFC_SECRET).unwrap();
+        assert_eq!(code_at(&key, 86 + wait_before_copy(86)).1, PERIOD_SECS);
+    }
+
+    #[test]
+    fn secrets_are_read_as_sites_show_them() {
+        let grouped = decode_secret("gezd gnbv-gy3t qojq gezd gnbv gy3t qojq====").unwrap();
+        assert_eq!(&grouped[..], b"12345678901234567890");
+        assert!(decode_secret("").is_none());
+        assert!(decode_secret("not base32!").is_none());
+        assert!(decode_secret("GEZD1").is_none());
+    }
+}
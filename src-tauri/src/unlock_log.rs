@@ -0,0 +1,340 @@
+/*!
+ * Unlock History
+ * Append-only record of unlock attempts, kept outside the vaults so it can be
+ * shown before unlocking
+ */
+
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
+
+use crate::biometrics::{AuthMethod, BiometricError};
+use crate::error::VaultError;
+use crate::storage;
+use crate::vault::UnlockMethod;
+
+const HISTORY_FILE_NAME: &str = "unlock_history.jsonl";
+/// Events kept; older ones are dropped
+const MAX_EVENTS: usize = 5000;
+/// Events allowed past `MAX_EVENTS` before the file is rewritten, so a
+/// full log is not rewritten on every attempt
+const COMPACT_SLACK: usize = 500;
+/// Longest platform error message kept
+const MAX_DETAIL_CHARS: usize = 200;
+/// Put in place of a user name removed from a platform message
+const USER_PLACEHOLDER: &str = "<user>";
+
+/// One unlock attempt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnlockEvent {
+    pub at: DateTime<Utc>,
+    pub vault: String,
+    pub method: UnlockMethod,
+    pub success: bool,
+    /// Refused unchecked because a backoff cooldown was running
+    #[serde(default)]
+    pub rate_limited: bool,
+    /// How a biometric attempt asked the user, and why it failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub biometric: Option<BiometricAttempt>,
+    /// A password, PIN or recovery code attempt made after biometrics
+    /// failed, before the vault was next unlocked. Set by `record`.
+    #[serde(default)]
+    pub after_biometric_failure: bool,
+}
+
+impl UnlockEvent {
+    pub fn new(vault: &str, method: UnlockMethod, success: bool) -> Self {
+        UnlockEvent {
+            at: Utc::now(),
+            vault: vault.to_string(),
+            method,
+            success,
+            rate_limited: false,
+            biometric: None,
+            after_biometric_failure: false,
+        }
+    }
+
+    /// A biometric attempt with `method`, failed when `error` is given
+    pub fn biometric(vault: &str, method: AuthMethod, error: Option<&BiometricError>) -> Self {
+        UnlockEvent {
+            biometric: Some(BiometricAttempt::new(method, error)),
+            ..UnlockEvent::new(vault, UnlockMethod::Biometric, error.is_none())
+        }
+    }
+
+    fn is_failed_biometric(&self) -> bool {
+        self.method == UnlockMethod::Biometric && !self.success && !self.rate_limited
+    }
+}
+
+/// Details of a biometric unlock attempt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BiometricAttempt {
+    pub method: AuthMethod,
+    /// `BiometricError::kind` of a failed attempt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_kind: Option<String>,
+    /// The platform's own message for a `platform_error`, scrubbed of user
+    /// names
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_message: Option<String>,
+}
+
+impl BiometricAttempt {
+    pub fn new(method: AuthMethod, error: Option<&BiometricError>) -> Self {
+        let error_message = match error {
+            Some(BiometricError::PlatformError { message, .. }) => Some(scrub(message, &current_user_names())),
+            _ => None,
+        };
+        BiometricAttempt {
+            method,
+            error_kind: error.map(|e| e.kind().to_string()),
+            error_message,
+        }
+    }
+}
+
+/// Failed attempts on a vault since it was last unlocked
+#[derive(Debug, Clone, Serialize)]
+pub struct FailedAttempts {
+    pub vault: String,
+    pub count: usize,
+    pub last_failure_at: DateTime<Utc>,
+}
+
+/// The log in memory, oldest event first. The file is only ever appended
+/// to, except when it grows past the cap.
+#[derive(Debug, Default)]
+pub struct UnlockHistory {
+    events: VecDeque<UnlockEvent>,
+}
+
+impl UnlockHistory {
+    /// Up to `limit` events, newest first, only those made with `method`
+    /// when given
+    pub fn recent(&self, limit: usize, method: Option<UnlockMethod>) -> Vec<UnlockEvent> {
+        self.events
+            .iter()
+            .rev()
+            .filter(|event| method.is_none_or(|method| event.method == method))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    /// Failed attempts on `vault` after its last successful unlock
+    pub fn failures_since_unlock(&self, vault: &str) -> Option<FailedAttempts> {
+        let failures: Vec<&UnlockEvent> = self
+            .events
+            .iter()
+            .rev()
+            .filter(|event| event.vault == vault)
+            .take_while(|event| !event.success)
+            .collect();
+        let last = failures.first()?;
+        Some(FailedAttempts {
+            vault: vault.to_string(),
+            count: failures.len(),
+            last_failure_at: last.at,
+        })
+    }
+
+    pub fn record(&mut self, app: &AppHandle, event: UnlockEvent) -> Result<(), VaultError> {
+        self.record_at(&history_path(app)?, event)
+    }
+
+    /// `record` into the log file at `path`
+    fn record_at(&mut self, path: &Path, mut event: UnlockEvent) -> Result<(), VaultError> {
+        if event.method != UnlockMethod::Biometric {
+            event.after_biometric_failure = self
+                .events
+                .iter()
+                .rev()
+                .filter(|earlier| earlier.vault == event.vault)
+                .take_while(|earlier| !earlier.success)
+                .any(UnlockEvent::is_failed_biometric);
+        }
+        let mut line = serde_json::to_vec(&event).map_err(|e| VaultError::Io(e.to_string()))?;
+        line.push(b'\n');
+        self.events.push_back(event);
+
+        if self.events.len() <= MAX_EVENTS + COMPACT_SLACK {
+            return storage::append_private(path, &line);
+        }
+        let excess = self.events.len() - MAX_EVENTS;
+        self.events.drain(..excess);
+        storage::write_atomic(path, &self.to_lines()?)
+    }
+
+    /// Forget every event, in memory and on disk
+    pub fn clear(&mut self, app: &AppHandle) -> Result<(), VaultError> {
+        self.events.clear();
+        storage::write_atomic(&history_path(app)?, &[])
+    }
+
+    fn to_lines(&self) -> Result<Vec<u8>, VaultError> {
+        let mut bytes = Vec::new();
+        for event in &self.events {
+            serde_json::to_writer(&mut bytes, event).map_err(|e| VaultError::Io(e.to_string()))?;
+            bytes.push(b'\n');
+        }
+        Ok(bytes)
+    }
+}
+
+/// Names the current user is known by, for `scrub`
+fn current_user_names() -> Vec<String> {
+    ["USER", "USERNAME", "LOGNAME"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .filter(|name| !name.trim().is_empty())
+        .collect()
+}
+
+/// `message` with user names taken out, so platform errors can be logged
+/// without identifying who was at the machine. Removes `names`, the user
+/// part of home directory paths and polkit `unix-user:` identities, and
+/// cuts the message to `MAX_DETAIL_CHARS`.
+fn scrub(message: &str, names: &[String]) -> String {
+    let identities =
+        Regex::new(r#"(?i)(/home/|/Users/|\\Users\\|unix-user:)[^/\\\s'",;:)]+"#).expect("valid regex");
+    let mut scrubbed = identities
+        .replace_all(message, format!("${{1}}{}", USER_PLACEHOLDER).as_str())
+        .into_owned();
+    for name in names {
+        let name = Regex::new(&format!(r"(?i)\b{}\b", regex::escape(name))).expect("valid regex");
+        scrubbed = name.replace_all(&scrubbed, USER_PLACEHOLDER).into_owned();
+    }
+    let mut chars = scrubbed.chars();
+    let mut short: String = chars.by_ref().take(MAX_DETAIL_CHARS).collect();
+    if chars.next().is_some() {
+        short.push('\u{2026}');
+    }
+    short
+}
+
+fn history_path(app: &AppHandle) -> Result<PathBuf, VaultError> {
+    Ok(storage::data_dir(app)?.join(HISTORY_FILE_NAME))
+}
+
+/// Load the log, skipping lines that do not parse (e.g. one cut short by a
+/// crash mid-append)
+pub fn load(app: &AppHandle) -> UnlockHistory {
+    history_path(app).map(|path| load_from(&path)).unwrap_or_default()
+}
+
+fn load_from(path: &Path) -> UnlockHistory {
+    let bytes = storage::read_file(path).ok().flatten().unwrap_or_default();
+    let mut events: VecDeque<UnlockEvent> = bytes
+        .split(|&b| b == b'\n')
+        .filter_map(|line| serde_json::from_slice(line).ok())
+        .collect();
+    let excess = events.len().saturating_sub(MAX_EVENTS);
+    events.drain(..excess);
+    UnlockHistory { events }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history_file() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("safenode-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join(HISTORY_FILE_NAME)
+    }
+
+    fn password_attempt(vault: &str, success: bool) -> UnlockEvent {
+        UnlockEvent::new(vault, UnlockMethod::Password, success)
+    }
+
+    #[test]
+    fn events_survive_a_reload() {
+        let path = history_file();
+        let mut history = UnlockHistory::default();
+        history.record_at(&path, password_attempt("default", false)).unwrap();
+        history.record_at(&path, password_attempt("default", true)).unwrap();
+
+        // A line cut short by a crash mid-append
+        storage::append_private(&path, b"{\"at\":").unwrap();
+
+        let loaded = load_from(&path);
+        let successes: Vec<bool> = loaded.recent(10, None).iter().map(|event| event.success).collect();
+        assert_eq!(successes, [true, false]);
+    }
+
+    #[test]
+    fn failures_are_counted_until_the_vault_unlocks() {
+        let path = history_file();
+        let mut history = UnlockHistory::default();
+        history.record_at(&path, password_attempt("default", false)).unwrap();
+        history.record_at(&path, password_attempt("default", true)).unwrap();
+        assert!(history.failures_since_unlock("default").is_none());
+
+        history.record_at(&path, password_attempt("default", false)).unwrap();
+        history.record_at(&path, password_attempt("work", false)).unwrap();
+        history.record_at(&path, password_attempt("default", false)).unwrap();
+        assert_eq!(history.failures_since_unlock("default").unwrap().count, 2);
+        assert_eq!(history.failures_since_unlock("work").unwrap().count, 1);
+        assert!(history.failures_since_unlock("other").is_none());
+    }
+
+    #[test]
+    fn log_is_capped() {
+        let path = history_file();
+        let mut history = UnlockHistory {
+            events: (0..MAX_EVENTS + COMPACT_SLACK).map(|_| password_attempt("default", false)).collect(),
+        };
+        history.record_at(&path, password_attempt("default", true)).unwrap();
+
+        assert_eq!(history.events.len(), MAX_EVENTS);
+        assert!(history.recent(1, None)[0].success);
+        assert_eq!(load_from(&path).events.len(), MAX_EVENTS);
+    }
+
+    #[test]
+    fn scrubber_removes_user_names() {
+        let names = ["alice".to_string()];
+        assert_eq!(
+            scrub("Failed to open /home/alice/.local/share/fprint for Alice", &names),
+            "Failed to open /home/<user>/.local/share/fprint for <user>"
+        );
+        assert_eq!(scrub(r"C:\Users\bob\AppData denied", &[]), r"C:\Users\<user>\AppData denied");
+        assert_eq!(scrub("Not authorized: unix-user:carol", &[]), "Not authorized: unix-user:<user>");
+        // Whole words only
+        assert_eq!(scrub("malice aforethought", &names), "malice aforethought");
+    }
+
+    #[test]
+    fn scrubbed_messages_are_cut_short() {
+        let scrubbed = scrub(&"x".repeat(MAX_DETAIL_CHARS + 50), &[]);
+        assert_eq!(scrubbed.chars().count(), MAX_DETAIL_CHARS + 1);
+        assert!(scrubbed.ends_with('\u{2026}'));
+    }
+
+    #[test]
+    fn fallback_after_a_failed_biometric_is_flagged() {
+        let path = history_file();
+        let mut history = UnlockHistory::default();
+        let failed = BiometricError::NotRecognized;
+        history.record_at(&path, UnlockEvent::biometric("default", AuthMethod::Biometric, Some(&failed))).unwrap();
+        history.record_at(&path, password_attempt("default", true)).unwrap();
+        history.record_at(&path, password_attempt("default", true)).unwrap();
+
+        let flags: Vec<bool> = history
+            .recent(10, Some(UnlockMethod::Password))
+            .iter()
+            .map(|event| event.after_biometric_failure)
+            .collect();
+        assert_eq!(flags, [false, true]);
+
+        let biometric = history.recent(10, Some(UnlockMethod::Biometric));
+        assert_eq!(biometric.len(), 1);
+        assert_eq!(biometric[0].biometric.as_ref().unwrap().error_kind.as_deref(), Some("not_recognized"));
+    }
+}
@@ -0,0 +1,189 @@
+/*!
+ * URL Helpers
+ * Parsing of the URLs stored on entries and matching them against pages
+ */
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use url::Url;
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+/// Host of `url` for comparing entries: lowercased, without scheme, port,
+/// credentials, path or a leading `www.`. `None` if there is no host.
+pub fn normalized_host(url: &str) -> Option<String> {
+    let host = host(url)?;
+    let host = host.strip_prefix("www.").unwrap_or(&host);
+    if host.is_empty() {
+        None
+    } else {
+        Some(host.to_string())
+    }
+}
+
+/// Lowercased host of `url`, accepting URLs with or without a scheme
+pub fn host(url: &str) -> Option<String> {
+    let url = url.trim();
+    let rest = match url.find("://") {
+        Some(index) => &url[index + 3..],
+        None => url,
+    };
+
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host_port = authority.rsplit('@').next()?;
+    let host = if let Some(bracketed) = host_port.strip_prefix('[') {
+        // IPv6 literal: [::1]:8080
+        bracketed.split(']').next()?
+    } else {
+        host_port.split(':').next()?
+    };
+
+    let host = host.trim_end_matches('.').to_lowercase();
+    if host.is_empty() {
+        None
+    } else {
+        Some(host)
+    }
+}
+
+/// How an entry URL is compared with the URL of a page
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UriMatchMode {
+    /// Same host or any subdomain of it, ignoring a leading `www.`
+    #[default]
+    Domain,
+    /// Same host and port
+    Host,
+    /// Page URL begins with the rule URL
+    StartsWith,
+    /// Identical URL after normalization
+    Exact,
+    /// Rule is a regular expression matched against the full page URL
+    Regex,
+}
+
+/// A URL on an entry together with its match mode
+#[derive(Debug, Clone, Serialize, Deserialize, Zeroize, ZeroizeOnDrop)]
+pub struct UriMatch {
+    pub url: String,
+    #[zeroize(skip)]
+    #[serde(default)]
+    pub mode: UriMatchMode,
+}
+
+impl UriMatchMode {
+    /// Higher is more specific; used to rank candidates
+    pub fn specificity(self) -> u8 {
+        match self {
+            UriMatchMode::Exact => 5,
+            UriMatchMode::StartsWith => 4,
+            UriMatchMode::Regex => 3,
+            UriMatchMode::Host => 2,
+            UriMatchMode::Domain => 1,
+        }
+    }
+}
+
+impl UriMatch {
+    /// Whether this rule matches `page`. Rules that cannot be parsed,
+    /// including invalid regexes, simply never match.
+    pub fn matches(&self, page: &Url) -> bool {
+        let rule = || parse(&self.url);
+        match self.mode {
+            UriMatchMode::Domain => rule()
+                .and_then(|rule| normalized_url_host(&rule))
+                .zip(normalized_url_host(page))
+                .is_some_and(|(rule_host, page_host)| {
+                    page_host == rule_host || page_host.ends_with(&format!(".{}", rule_host))
+                }),
+            UriMatchMode::Host => rule().is_some_and(|rule| {
+                rule.host_str() == page.host_str() && rule.port_or_known_default() == page.port_or_known_default()
+            }),
+            UriMatchMode::StartsWith => rule().is_some_and(|rule| page.as_str().starts_with(rule.as_str())),
+            UriMatchMode::Exact => rule().is_some_and(|rule| page.as_str() == rule.as_str()),
+            UriMatchMode::Regex => Regex::new(&self.url).is_ok_and(|regex| regex.is_match(page.as_str())),
+        }
+    }
+}
+
+/// Parse a URL as typed by a user, assuming `https://` when no scheme is
+/// given. Hosts are lowercased and IDNs converted to punycode, so rules and
+/// pages compare consistently. `None` if there is no host.
+pub fn parse(url: &str) -> Option<Url> {
+    let url = url.trim();
+    let parsed = if url.contains("://") {
+        Url::parse(url).ok()?
+    } else {
+        Url::parse(&format!("https://{}", url)).ok()?
+    };
+    parsed.host_str()?;
+    Some(parsed)
+}
+
+fn normalized_url_host(url: &Url) -> Option<String> {
+    let host = url.host_str()?.trim_end_matches('.');
+    Some(host.strip_prefix("www.").unwrap_or(host).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(rule: &str, mode: UriMatchMode, page: &str) -> bool {
+        let rule = UriMatch {
+            url: rule.to_string(),
+            mode,
+        };
+        rule.matches(&parse(page).unwrap())
+    }
+
+    #[test]
+    fn domain_rules_cover_subdomains() {
+        assert!(matches("example.com", UriMatchMode::Domain, "https://example.com/login"));
+        assert!(matches("www.example.com", UriMatchMode::Domain, "https://accounts.example.com"));
+        assert!(matches("https://example.com", UriMatchMode::Domain, "http://a.b.example.com:8080"));
+        assert!(!matches("example.com", UriMatchMode::Domain, "https://evil-example.com"));
+        assert!(!matches("example.com", UriMatchMode::Domain, "https://example.com.evil.net"));
+        assert!(!matches("accounts.example.com", UriMatchMode::Domain, "https://example.com"));
+    }
+
+    #[test]
+    fn host_rules_compare_ports() {
+        assert!(matches("https://example.com", UriMatchMode::Host, "https://example.com:443/a"));
+        assert!(matches("example.com:8443", UriMatchMode::Host, "https://example.com:8443"));
+        assert!(!matches("example.com:8443", UriMatchMode::Host, "https://example.com"));
+        assert!(!matches("https://example.com", UriMatchMode::Host, "http://example.com"));
+        assert!(!matches("example.com", UriMatchMode::Host, "https://www.example.com"));
+    }
+
+    #[test]
+    fn idn_hosts_match_their_punycode() {
+        assert_eq!(parse("https://bücher.de").unwrap().host_str(), Some("xn--bcher-kva.de"));
+        assert!(matches("bücher.de", UriMatchMode::Domain, "https://shop.xn--bcher-kva.de"));
+        assert!(matches("https://xn--bcher-kva.de", UriMatchMode::Host, "https://BÜCHER.de"));
+    }
+
+    #[test]
+    fn prefix_and_exact_rules() {
+        assert!(matches("https://example.com/app", UriMatchMode::StartsWith, "https://example.com/app/login"));
+        assert!(!matches("https://example.com/app", UriMatchMode::StartsWith, "https://example.com/"));
+        assert!(matches("https://Example.com/a", UriMatchMode::Exact, "https://example.com/a"));
+        assert!(!matches("https://example.com/a", UriMatchMode::Exact, "https://example.com/a?b"));
+    }
+
+    #[test]
+    fn invalid_regexes_never_match() {
+        assert!(matches(r"^https://[a-z]+\.example\.com/", UriMatchMode::Regex, "https://mail.example.com/"));
+        assert!(!matches("(unclosed", UriMatchMode::Regex, "https://example.com/(unclosed"));
+        assert!(!matches("[", UriMatchMode::Regex, "https://example.com/"));
+    }
+
+    #[test]
+    fn hosts_are_normalized() {
+        assert_eq!(normalized_host("https://user@WWW.Example.com:8080/path"), Some("example.com".to_string()));
+        assert_eq!(host("example.com."), Some("example.com".to_string()));
+        assert_eq!(host("http://[::1]:8080/"), Some("::1".to_string()));
+        assert_eq!(host(""), None);
+        assert_eq!(parse("not a url"), None);
+    }
+}
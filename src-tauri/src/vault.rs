@@ -0,0 +1,2074 @@
+/*!
+ * Vault Model
+ * In-memory representation of the decrypted vault and its lock state
+ */
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+use uuid::Uuid;
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
+
+use crate::attachments::Attachment;
+use crate::crypto::{self, CipherAlgorithm, KdfParams, VaultKey};
+use crate::error::{BulkFailure, FieldError, VaultError};
+use crate::format::{self, KeySlot, VaultFile, VaultHeader, FILE_FORMAT_VERSION};
+use crate::generator::GeneratorOptions;
+use crate::items::{CardDetails, IdentityDetails};
+use crate::migrations;
+use crate::recovery;
+use crate::templates::{self, EntryTemplate, TemplateFields};
+use crate::totp;
+use crate::urls::{self, UriMatch, UriMatchMode};
+
+/// Current version of the serialized vault envelope
+pub const VAULT_FORMAT_VERSION: u32 = 2;
+/// Generated passwords kept in `Vault::generator_history`
+pub const GENERATOR_HISTORY_LIMIT: usize = 20;
+/// Generated passwords older than this many days are dropped on save
+pub const GENERATOR_HISTORY_DAYS: i64 = 7;
+
+/// Decrypted vault contents.
+///
+/// Serialized as a versioned JSON envelope:
+/// `{ "version": 2, "metadata": {...}, "kdf": {...}, "entries": [...] }`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Vault {
+    pub version: u32,
+    #[serde(default)]
+    pub metadata: VaultMetadata,
+    pub kdf: KdfParams,
+    #[serde(default)]
+    pub entries: Vec<Entry>,
+    #[serde(default)]
+    pub folders: Vec<Folder>,
+    /// User-defined templates; built-in ones come from `templates::builtin`
+    #[serde(default)]
+    pub templates: Vec<EntryTemplate>,
+    /// Deleted entries, kept until restored or purged
+    #[serde(default)]
+    pub trash: Vec<TrashedEntry>,
+    /// Passwords recently generated and shown, oldest first, in case one
+    /// was used before it was saved to an entry. Never part of an export.
+    #[serde(default)]
+    pub generator_history: Vec<GeneratorHistoryItem>,
+    /// Wrapped copies of the data key, stored in the cleartext header
+    #[serde(skip)]
+    pub key_slots: KeySlots,
+    /// `VaultHeader::vault_id`, carried between unseal and the next seal
+    #[serde(skip)]
+    pub id: Option<Uuid>,
+}
+
+/// Header key material carried between unseal and the next seal
+#[derive(Debug, Clone, Default)]
+pub struct KeySlots {
+    /// `key_check` of the password-derived key
+    pub password_check: Vec<u8>,
+    /// Data key wrapped by the password-derived key. Empty for vaults created
+    /// before key wrapping, whose password-derived key is the data key.
+    pub password_wrapped_key: Vec<u8>,
+    pub recovery: Option<KeySlot>,
+}
+
+/// Descriptive information about a vault
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VaultMetadata {
+    pub created_at: DateTime<Utc>,
+    pub modified_at: DateTime<Utc>,
+}
+
+/// A named group of entries; folders nest through `parent_id`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Folder {
+    pub id: Uuid,
+    pub name: String,
+    /// `None` for top-level folders
+    pub parent_id: Option<Uuid>,
+    /// Generator policy for passwords regenerated in this folder or its
+    /// subfolders, by name
+    #[serde(default)]
+    pub generator_policy: Option<String>,
+}
+
+/// A stored credential. String fields are scrubbed when the entry is dropped
+/// or overwritten.
+#[derive(Debug, Clone, Serialize, Deserialize, Zeroize, ZeroizeOnDrop)]
+pub struct Entry {
+    #[zeroize(skip)]
+    pub id: Uuid,
+    #[zeroize(skip)]
+    pub kind: ItemKind,
+    pub title: String,
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub password: String,
+    #[serde(default)]
+    pub url: String,
+    /// Additional URLs with explicit match rules, used for autofill
+    #[serde(default)]
+    pub uris: Vec<UriMatch>,
+    /// Free-form notes; the body of a `SecureNote`
+    #[serde(default)]
+    pub notes: String,
+    #[serde(default)]
+    pub custom_fields: Vec<CustomField>,
+    /// Set for `Card` entries
+    #[serde(default)]
+    pub card: Option<CardDetails>,
+    /// Set for `Identity` entries
+    #[serde(default)]
+    pub identity: Option<IdentityDetails>,
+    /// Base32 two-factor secret for `totp::code_at`; empty when unset
+    #[serde(default)]
+    pub totp_secret: String,
+    /// Already encrypted under their own keys
+    #[serde(default)]
+    #[zeroize(skip)]
+    pub attachments: Vec<Attachment>,
+    /// Previous passwords, oldest first
+    #[serde(default)]
+    pub password_history: Vec<PasswordHistoryItem>,
+    /// Generator options last used to regenerate this entry's password
+    #[serde(default)]
+    #[zeroize(skip)]
+    pub generator_prefs: Option<GeneratorOptions>,
+    /// `None` for entries outside any folder
+    #[serde(default)]
+    #[zeroize(skip)]
+    pub folder_id: Option<Uuid>,
+    /// Normalized by `Vault::set_entry_tags`
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    #[zeroize(skip)]
+    pub favorite: bool,
+    /// Kept for reference only: hidden from search and autofill, and read-only
+    #[serde(default)]
+    #[zeroize(skip)]
+    pub archived: bool,
+    /// Revealing or copying the password needs biometrics first
+    #[serde(default)]
+    #[zeroize(skip)]
+    pub require_biometric: bool,
+    #[zeroize(skip)]
+    pub created_at: DateTime<Utc>,
+    #[zeroize(skip)]
+    pub modified_at: DateTime<Utc>,
+    /// Last time the entry was opened or copied from
+    #[serde(default)]
+    #[zeroize(skip)]
+    pub last_used_at: Option<DateTime<Utc>>,
+    /// How many times the entry was opened or copied from
+    #[serde(default)]
+    #[zeroize(skip)]
+    pub use_count: u64,
+    /// When the password should be rotated
+    #[serde(default)]
+    #[zeroize(skip)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// What an entry stores, which decides the fields the UI shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemKind {
+    #[default]
+    Login,
+    /// Free-form text kept in `notes`, without username or password
+    SecureNote,
+    /// Payment card, details in `card`
+    Card,
+    /// Personal details, in `identity`
+    Identity,
+}
+
+/// An extra named value on an entry, e.g. a security answer or API key
+#[derive(Debug, Clone, Serialize, Deserialize, Zeroize, ZeroizeOnDrop)]
+pub struct CustomField {
+    pub name: String,
+    pub value: String,
+    #[zeroize(skip)]
+    #[serde(default)]
+    pub kind: CustomFieldKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CustomFieldKind {
+    #[default]
+    Text,
+    /// Treated like a password: left out of summaries and search
+    Hidden,
+    Url,
+}
+
+impl CustomField {
+    pub fn is_hidden(&self) -> bool {
+        self.kind == CustomFieldKind::Hidden
+    }
+}
+
+/// A password an entry used before it was changed
+#[derive(Debug, Clone, Serialize, Deserialize, Zeroize, ZeroizeOnDrop)]
+pub struct PasswordHistoryItem {
+    pub password: String,
+    /// When this password was replaced
+    #[zeroize(skip)]
+    pub changed_at: DateTime<Utc>,
+}
+
+/// A password the generator produced and showed to the user
+#[derive(Debug, Clone, Serialize, Deserialize, Zeroize, ZeroizeOnDrop)]
+pub struct GeneratorHistoryItem {
+    pub password: String,
+    #[zeroize(skip)]
+    pub generated_at: DateTime<Utc>,
+}
+
+/// Orderings offered by `list_entries`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntrySort {
+    Title,
+    /// Most recently modified first
+    LastModified,
+    /// Most recently used first; never-used entries last
+    LastUsed,
+    /// Favorites first, each group by title
+    FavoritesFirst,
+}
+
+impl EntrySort {
+    /// Sort summaries in place. The sort is stable, so ties keep their order.
+    pub fn apply(self, summaries: &mut [EntrySummary]) {
+        match self {
+            EntrySort::Title => summaries.sort_by_cached_key(|summary| title_key(&summary.title)),
+            EntrySort::LastModified => summaries.sort_by_key(|summary| Reverse(summary.modified_at)),
+            EntrySort::LastUsed => summaries.sort_by_key(|summary| Reverse(summary.last_used_at)),
+            EntrySort::FavoritesFirst => {
+                summaries.sort_by_cached_key(|summary| (!summary.favorite, title_key(&summary.title)))
+            }
+        }
+    }
+}
+
+/// Collation key for titles: accents and case are compared last, so
+/// "Éclair" sorts next to "eclair" and "apple" next to "Apple" rather than
+/// after "Zebra"
+fn title_key(title: &str) -> (String, String) {
+    let folded = title
+        .nfkd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+        .collect();
+    (folded, title.to_string())
+}
+
+/// An action applied to many entries at once by `Vault::bulk_update`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum BulkAction {
+    Trash,
+    Restore,
+    /// `None` moves entries to the top level
+    SetFolder(Option<Uuid>),
+    AddTags(Vec<String>),
+    RemoveTags(Vec<String>),
+    /// `None` clears the expiry
+    SetExpiry(Option<DateTime<Utc>>),
+}
+
+/// A tag and how many entries carry it
+#[derive(Debug, Clone, Serialize)]
+pub struct TagCount {
+    pub name: String,
+    pub count: usize,
+}
+
+/// An entry moved to the trash by `delete_entry`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashedEntry {
+    pub entry: Entry,
+    pub deleted_at: DateTime<Utc>,
+}
+
+/// List-view projection of an entry; deliberately carries no secrets
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntrySummary {
+    pub id: Uuid,
+    pub kind: ItemKind,
+    pub title: String,
+    pub username: String,
+    pub url: String,
+    pub folder_id: Option<Uuid>,
+    pub tags: Vec<String>,
+    /// Custom fields other than `Hidden` ones
+    pub custom_fields: Vec<CustomField>,
+    /// Last four digits of a card number, e.g. `•••• 4242`
+    pub masked_card_number: Option<String>,
+    /// A one-time code can be copied with `copy_totp_to_clipboard`
+    pub has_totp: bool,
+    pub favorite: bool,
+    pub archived: bool,
+    pub require_biometric: bool,
+    pub modified_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub use_count: u64,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Set for entries in the trash
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+/// Complete entry including the password, returned only on explicit request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntryFull {
+    pub id: Uuid,
+    pub kind: ItemKind,
+    pub title: String,
+    pub username: String,
+    pub password: String,
+    pub url: String,
+    pub uris: Vec<UriMatch>,
+    pub notes: String,
+    pub custom_fields: Vec<CustomField>,
+    pub card: Option<CardDetails>,
+    pub identity: Option<IdentityDetails>,
+    pub totp_secret: String,
+    pub created_at: DateTime<Utc>,
+    pub modified_at: DateTime<Utc>,
+}
+
+impl From<&Entry> for EntrySummary {
+    fn from(entry: &Entry) -> Self {
+        EntrySummary {
+            id: entry.id,
+            kind: entry.kind,
+            title: entry.title.clone(),
+            username: entry.username.clone(),
+            url: entry.url.clone(),
+            folder_id: entry.folder_id,
+            tags: entry.tags.clone(),
+            custom_fields: entry
+                .custom_fields
+                .iter()
+                .filter(|field| !field.is_hidden())
+                .cloned()
+                .collect(),
+            masked_card_number: entry.card.as_ref().and_then(CardDetails::masked_number),
+            has_totp: !entry.totp_secret.is_empty(),
+            favorite: entry.favorite,
+            archived: entry.archived,
+            require_biometric: entry.require_biometric,
+            modified_at: entry.modified_at,
+            last_used_at: entry.last_used_at,
+            use_count: entry.use_count,
+            expires_at: entry.expires_at,
+            deleted_at: None,
+        }
+    }
+}
+
+impl From<&TrashedEntry> for EntrySummary {
+    fn from(trashed: &TrashedEntry) -> Self {
+        EntrySummary {
+            deleted_at: Some(trashed.deleted_at),
+            ..EntrySummary::from(&trashed.entry)
+        }
+    }
+}
+
+impl From<&Entry> for EntryFull {
+    fn from(entry: &Entry) -> Self {
+        EntryFull {
+            id: entry.id,
+            kind: entry.kind,
+            title: entry.title.clone(),
+            username: entry.username.clone(),
+            password: entry.password.clone(),
+            url: entry.url.clone(),
+            uris: entry.uris.clone(),
+            notes: entry.notes.clone(),
+            custom_fields: entry.custom_fields.clone(),
+            card: entry.card.clone(),
+            identity: entry.identity.clone(),
+            totp_secret: entry.totp_secret.clone(),
+            created_at: entry.created_at,
+            modified_at: entry.modified_at,
+        }
+    }
+}
+
+/// Editable entry fields supplied by the frontend
+#[derive(Debug, Clone, Deserialize, Zeroize, ZeroizeOnDrop)]
+pub struct EntryInput {
+    #[serde(default)]
+    #[zeroize(skip)]
+    pub kind: ItemKind,
+    pub title: String,
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub password: String,
+    #[serde(default)]
+    pub url: String,
+    #[serde(default)]
+    pub uris: Vec<UriMatch>,
+    #[serde(default)]
+    pub notes: String,
+    #[serde(default)]
+    pub custom_fields: Vec<CustomField>,
+    #[serde(default)]
+    pub card: Option<CardDetails>,
+    #[serde(default)]
+    pub identity: Option<IdentityDetails>,
+    #[serde(default)]
+    pub totp_secret: String,
+}
+
+impl EntryInput {
+    /// Check the typed details required by `kind`, and the TOTP secret
+    fn validate(&self) -> Result<(), VaultError> {
+        let mut errors = match (self.kind, &self.card, &self.identity) {
+            (ItemKind::Card, Some(card), _) => card.validate(),
+            (ItemKind::Card, None, _) => vec![FieldError::new("card", "Card details are required")],
+            (ItemKind::Identity, _, None) => vec![FieldError::new("identity", "Identity details are required")],
+            _ => Vec::new(),
+        };
+        if !self.totp_secret.trim().is_empty() && totp::decode_secret(&self.totp_secret).is_none() {
+            errors.push(FieldError::new("totp_secret", "Not a valid base32 secret"));
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(VaultError::InvalidFields(errors))
+        }
+    }
+
+    /// Typed details that belong to `kind`; others are dropped
+    fn take_details(&mut self) -> (Option<CardDetails>, Option<IdentityDetails>) {
+        let card = self.card.take().filter(|_| self.kind == ItemKind::Card);
+        let identity = self.identity.take().filter(|_| self.kind == ItemKind::Identity);
+        (card, identity)
+    }
+}
+
+impl Entry {
+    /// Host of the entry's main URL, or of its first URL rule with a host
+    pub fn site_host(&self) -> Option<String> {
+        std::iter::once(&self.url)
+            .chain(self.uris.iter().map(|uri| &uri.url))
+            .filter_map(|url| urls::parse(url))
+            .find_map(|url| url.host_str().map(str::to_string))
+    }
+
+    fn from_input(mut input: EntryInput) -> Self {
+        let now = Utc::now();
+        let (card, identity) = input.take_details();
+        Entry {
+            id: Uuid::new_v4(),
+            kind: input.kind,
+            title: std::mem::take(&mut input.title),
+            username: std::mem::take(&mut input.username),
+            password: std::mem::take(&mut input.password),
+            url: std::mem::take(&mut input.url),
+            uris: std::mem::take(&mut input.uris),
+            notes: std::mem::take(&mut input.notes),
+            custom_fields: std::mem::take(&mut input.custom_fields),
+            card,
+            identity,
+            totp_secret: input.totp_secret.trim().to_string(),
+            attachments: Vec::new(),
+            password_history: Vec::new(),
+            generator_prefs: None,
+            folder_id: None,
+            tags: Vec::new(),
+            favorite: false,
+            archived: false,
+            require_biometric: false,
+            created_at: now,
+            modified_at: now,
+            last_used_at: None,
+            use_count: 0,
+            expires_at: None,
+        }
+    }
+
+    /// Move the current password into the history if `new_password`
+    /// differs, keeping at most `history_limit` items
+    fn retire_password(&mut self, new_password: &str, history_limit: usize) {
+        if self.password != new_password && !self.password.is_empty() {
+            self.password_history.push(PasswordHistoryItem {
+                password: std::mem::take(&mut self.password),
+                changed_at: Utc::now(),
+            });
+        }
+        if self.password_history.len() > history_limit {
+            let excess = self.password_history.len() - history_limit;
+            self.password_history.drain(..excess);
+        }
+    }
+
+    /// Replace the password, recording the old one in the history
+    fn replace_password(&mut self, password: String, history_limit: usize) {
+        self.retire_password(&password, history_limit);
+        self.password.zeroize();
+        self.password = password;
+        self.modified_at = Utc::now();
+    }
+
+    /// Replace the editable fields. A changed password is appended to the
+    /// history, keeping at most `history_limit` items.
+    fn apply(&mut self, mut input: EntryInput, history_limit: usize) {
+        let now = Utc::now();
+        self.retire_password(&input.password, history_limit);
+        let tags = std::mem::take(&mut self.tags);
+        let history = std::mem::take(&mut self.password_history);
+
+        // Scrub the previous values before their buffers are released
+        self.zeroize();
+        self.kind = input.kind;
+        self.title = std::mem::take(&mut input.title);
+        self.username = std::mem::take(&mut input.username);
+        self.password = std::mem::take(&mut input.password);
+        self.url = std::mem::take(&mut input.url);
+        self.uris = std::mem::take(&mut input.uris);
+        self.notes = std::mem::take(&mut input.notes);
+        self.custom_fields = std::mem::take(&mut input.custom_fields);
+        (self.card, self.identity) = input.take_details();
+        self.totp_secret = input.totp_secret.trim().to_string();
+        self.password_history = history;
+        self.tags = tags;
+        self.modified_at = now;
+    }
+
+    /// Fold `other` into this entry: notes, tags, custom fields, attachments
+    /// and password history are combined, and `other`'s password joins the
+    /// history if it differs
+    fn absorb(&mut self, other: &mut Entry, history_limit: usize) {
+        let other_notes = other.notes.trim();
+        if !other_notes.is_empty() && !self.notes.contains(other_notes) {
+            if !self.notes.is_empty() {
+                self.notes.push_str("\n\n");
+            }
+            self.notes.push_str(other_notes);
+        }
+
+        for tag in &other.tags {
+            if !self.tags.iter().any(|kept| kept.to_lowercase() == tag.to_lowercase()) {
+                self.tags.push(tag.clone());
+            }
+        }
+
+        for field in &other.custom_fields {
+            if !self.custom_fields.iter().any(|kept| kept.name == field.name && kept.value == field.value) {
+                self.custom_fields.push(field.clone());
+            }
+        }
+
+        self.attachments.append(&mut other.attachments);
+        if self.totp_secret.is_empty() {
+            self.totp_secret = std::mem::take(&mut other.totp_secret);
+        }
+
+        self.password_history.extend(other.password_history.iter().cloned());
+        if !other.password.is_empty() && other.password != self.password {
+            self.password_history.push(PasswordHistoryItem {
+                password: other.password.clone(),
+                changed_at: other.modified_at,
+            });
+        }
+        self.password_history.sort_by_key(|item| item.changed_at);
+        if self.password_history.len() > history_limit {
+            let excess = self.password_history.len() - history_limit;
+            self.password_history.drain(..excess);
+        }
+
+        self.modified_at = Utc::now();
+    }
+}
+
+impl Vault {
+    /// Create an empty vault with a fresh random data key, wrapped by the
+    /// key derived from the master password. Returns the vault and data key.
+    pub fn new(kdf: KdfParams, password_key: &VaultKey) -> Result<(Self, VaultKey), VaultError> {
+        let now = Utc::now();
+        let data_key = crypto::generate_key();
+        let mut vault = Vault {
+            version: VAULT_FORMAT_VERSION,
+            metadata: VaultMetadata {
+                created_at: now,
+                modified_at: now,
+            },
+            kdf: kdf.clone(),
+            entries: Vec::new(),
+            folders: Vec::new(),
+            templates: Vec::new(),
+            trash: Vec::new(),
+            generator_history: Vec::new(),
+            key_slots: KeySlots::default(),
+            id: Some(Uuid::new_v4()),
+        };
+        vault.set_password(kdf, password_key, &data_key)?;
+        Ok((vault, data_key))
+    }
+
+    /// Whether `password_key` was derived from the current master password
+    pub fn verify_password_key(&self, password_key: &VaultKey) -> bool {
+        crypto::verify_key_check(password_key, &self.key_slots.password_check)
+    }
+
+    /// Re-wrap the data key under a new password-derived key
+    pub fn set_password(&mut self, kdf: KdfParams, password_key: &VaultKey, data_key: &VaultKey) -> Result<(), VaultError> {
+        self.key_slots.password_check = crypto::key_check(password_key);
+        self.key_slots.password_wrapped_key = crypto::wrap_key(password_key, data_key).map_err(VaultError::Crypto)?;
+        self.kdf = kdf;
+        Ok(())
+    }
+
+    /// Move the vault to a fresh random data key wrapped under
+    /// `password_key`, re-wrapping every attachment key. The recovery slot
+    /// wraps the old key and is dropped. Returns the new data key.
+    pub fn rotate_data_key(
+        &mut self,
+        kdf: KdfParams,
+        password_key: &VaultKey,
+        old_key: &VaultKey,
+    ) -> Result<VaultKey, VaultError> {
+        let data_key = crypto::generate_key();
+        let entries = self.entries.iter_mut().chain(self.trash.iter_mut().map(|trashed| &mut trashed.entry));
+        for attachment in entries.flat_map(|entry| entry.attachments.iter_mut()) {
+            let key = crypto::unwrap_key(old_key, &attachment.wrapped_key).map_err(VaultError::Crypto)?;
+            attachment.wrapped_key = crypto::wrap_key(&data_key, &key).map_err(VaultError::Crypto)?;
+        }
+        self.key_slots.recovery = None;
+        self.set_password(kdf, password_key, &data_key)?;
+        Ok(data_key)
+    }
+
+    /// Generate a new recovery code and wrap the data key under it,
+    /// replacing any previous code. The code is returned once and never stored.
+    pub fn reset_recovery_code(&mut self, data_key: &VaultKey) -> Result<Zeroizing<String>, VaultError> {
+        let code = recovery::generate_code();
+        let slot = recovery::make_slot(&code, data_key, &self.kdf).map_err(VaultError::Crypto)?;
+        self.key_slots.recovery = Some(slot);
+        Ok(code)
+    }
+
+    pub fn entry(&self, id: Uuid) -> Result<&Entry, VaultError> {
+        self.entries
+            .iter()
+            .find(|entry| entry.id == id)
+            .ok_or(VaultError::EntryNotFound(id))
+    }
+
+    pub fn entry_mut(&mut self, id: Uuid) -> Result<&mut Entry, VaultError> {
+        self.entries
+            .iter_mut()
+            .find(|entry| entry.id == id)
+            .ok_or(VaultError::EntryNotFound(id))
+    }
+
+    /// `entry_mut` for changes an archived entry must refuse; fails with
+    /// `EntryArchived` until it is unarchived
+    pub fn editable_entry_mut(&mut self, id: Uuid) -> Result<&mut Entry, VaultError> {
+        let entry = self.entry_mut(id)?;
+        if entry.archived {
+            return Err(VaultError::EntryArchived(id));
+        }
+        Ok(entry)
+    }
+
+    /// Add a new entry, returning its generated id
+    pub fn add_entry(&mut self, input: EntryInput) -> Result<Uuid, VaultError> {
+        input.validate()?;
+        let entry = Entry::from_input(input);
+        let id = entry.id;
+        self.entries.push(entry);
+        Ok(id)
+    }
+
+    pub fn update_entry(&mut self, id: Uuid, input: EntryInput, history_limit: usize) -> Result<(), VaultError> {
+        let entry = self.editable_entry_mut(id)?;
+        input.validate()?;
+        entry.apply(input, history_limit);
+        Ok(())
+    }
+
+    /// Move an entry to the trash
+    pub fn delete_entry(&mut self, id: Uuid) -> Result<(), VaultError> {
+        let index = self
+            .entries
+            .iter()
+            .position(|entry| entry.id == id)
+            .ok_or(VaultError::EntryNotFound(id))?;
+        let entry = self.entries.remove(index);
+        self.trash.push(TrashedEntry { entry, deleted_at: Utc::now() });
+        Ok(())
+    }
+
+    /// Move an entry from the trash back into the vault
+    pub fn restore_entry(&mut self, id: Uuid) -> Result<(), VaultError> {
+        let index = self
+            .trash
+            .iter()
+            .position(|trashed| trashed.entry.id == id)
+            .ok_or(VaultError::EntryNotFound(id))?;
+        let trashed = self.trash.remove(index);
+        self.entries.push(trashed.entry);
+        Ok(())
+    }
+
+    /// Permanently remove trashed entries deleted more than `older_than` ago,
+    /// returning how many were removed
+    pub fn purge_trash(&mut self, older_than: chrono::Duration) -> usize {
+        let cutoff = Utc::now() - older_than;
+        let before = self.trash.len();
+        self.trash.retain(|trashed| trashed.deleted_at > cutoff);
+        before - self.trash.len()
+    }
+
+    /// Remember a generated password, dropping the oldest beyond
+    /// `GENERATOR_HISTORY_LIMIT`
+    pub fn record_generated(&mut self, password: &str) {
+        self.generator_history.push(GeneratorHistoryItem {
+            password: password.to_string(),
+            generated_at: Utc::now(),
+        });
+        let excess = self.generator_history.len().saturating_sub(GENERATOR_HISTORY_LIMIT);
+        self.generator_history.drain(..excess);
+    }
+
+    /// Generated passwords younger than `GENERATOR_HISTORY_DAYS`, oldest first
+    pub fn generator_history(&self) -> Vec<GeneratorHistoryItem> {
+        let cutoff = Utc::now() - chrono::Duration::days(GENERATOR_HISTORY_DAYS);
+        self.generator_history.iter().filter(|item| item.generated_at > cutoff).cloned().collect()
+    }
+
+    /// Drop generated passwords older than `GENERATOR_HISTORY_DAYS`
+    pub fn expire_generator_history(&mut self) {
+        let cutoff = Utc::now() - chrono::Duration::days(GENERATOR_HISTORY_DAYS);
+        self.generator_history.retain(|item| item.generated_at > cutoff);
+    }
+
+    pub fn folder(&self, id: Uuid) -> Result<&Folder, VaultError> {
+        self.folders
+            .iter()
+            .find(|folder| folder.id == id)
+            .ok_or(VaultError::FolderNotFound(id))
+    }
+
+    fn folder_mut(&mut self, id: Uuid) -> Result<&mut Folder, VaultError> {
+        self.folders
+            .iter_mut()
+            .find(|folder| folder.id == id)
+            .ok_or(VaultError::FolderNotFound(id))
+    }
+
+    /// Whether `id` is `ancestor` or nested anywhere below it
+    fn folder_is_within(&self, id: Uuid, ancestor: Uuid) -> bool {
+        let mut current = Some(id);
+        // Bounded by the folder count in case the stored tree contains a cycle
+        for _ in 0..=self.folders.len() {
+            match current {
+                Some(folder_id) if folder_id == ancestor => return true,
+                Some(folder_id) => current = self.folder(folder_id).ok().and_then(|folder| folder.parent_id),
+                None => return false,
+            }
+        }
+        false
+    }
+
+    /// Add a folder, returning its generated id
+    pub fn create_folder(&mut self, name: &str, parent_id: Option<Uuid>) -> Result<Uuid, VaultError> {
+        let name = folder_name(name)?;
+        if let Some(parent_id) = parent_id {
+            self.folder(parent_id)?;
+        }
+
+        let id = Uuid::new_v4();
+        self.folders.push(Folder {
+            id,
+            name,
+            parent_id,
+            generator_policy: None,
+        });
+        Ok(id)
+    }
+
+    pub fn rename_folder(&mut self, id: Uuid, name: &str) -> Result<(), VaultError> {
+        let name = folder_name(name)?;
+        self.folder_mut(id)?.name = name;
+        Ok(())
+    }
+
+    /// Attach a generator policy to a folder, or detach it with `None`
+    pub fn set_folder_generator_policy(&mut self, id: Uuid, policy: Option<String>) -> Result<(), VaultError> {
+        self.folder_mut(id)?.generator_policy = policy;
+        Ok(())
+    }
+
+    /// The generator policy of an entry's nearest folder that has one
+    pub fn entry_generator_policy(&self, id: Uuid) -> Result<Option<&str>, VaultError> {
+        let mut current = self.entry(id)?.folder_id;
+        // Bounded by the folder count in case the stored tree contains a cycle
+        for _ in 0..=self.folders.len() {
+            let Some(folder) = current.and_then(|folder_id| self.folder(folder_id).ok()) else {
+                break;
+            };
+            if let Some(policy) = &folder.generator_policy {
+                return Ok(Some(policy));
+            }
+            current = folder.parent_id;
+        }
+        Ok(None)
+    }
+
+    /// Delete a folder. Its entries and subfolders move to `move_children_to`,
+    /// or to the deleted folder's own parent when that is `None`.
+    pub fn delete_folder(&mut self, id: Uuid, move_children_to: Option<Uuid>) -> Result<(), VaultError> {
+        let parent_id = self.folder(id)?.parent_id;
+        let target = move_children_to.or(parent_id);
+        if let Some(target) = target {
+            self.folder(target)?;
+            if self.folder_is_within(target, id) {
+                return Err(VaultError::InvalidFolder("Cannot move a folder's contents into itself".to_string()));
+            }
+        }
+
+        let entries = self.entries.iter_mut().chain(self.trash.iter_mut().map(|trashed| &mut trashed.entry));
+        for entry in entries.filter(|entry| entry.folder_id == Some(id)) {
+            entry.folder_id = target;
+        }
+        for folder in self.folders.iter_mut().filter(|folder| folder.parent_id == Some(id)) {
+            folder.parent_id = target;
+        }
+        self.folders.retain(|folder| folder.id != id);
+        Ok(())
+    }
+
+    /// Move an entry into a folder, or to the top level with `None`
+    pub fn move_entry_to_folder(&mut self, entry_id: Uuid, folder_id: Option<Uuid>) -> Result<(), VaultError> {
+        if let Some(folder_id) = folder_id {
+            self.folder(folder_id)?;
+        }
+        self.editable_entry_mut(entry_id)?.folder_id = folder_id;
+        Ok(())
+    }
+
+    pub fn attachment(&self, entry_id: Uuid, attachment_id: Uuid) -> Result<&Attachment, VaultError> {
+        self.entry(entry_id)?
+            .attachments
+            .iter()
+            .find(|attachment| attachment.id == attachment_id)
+            .ok_or(VaultError::AttachmentNotFound(attachment_id))
+    }
+
+    pub fn delete_attachment(&mut self, entry_id: Uuid, attachment_id: Uuid) -> Result<(), VaultError> {
+        let attachments = &mut self.editable_entry_mut(entry_id)?.attachments;
+        let index = attachments
+            .iter()
+            .position(|attachment| attachment.id == attachment_id)
+            .ok_or(VaultError::AttachmentNotFound(attachment_id))?;
+        attachments.remove(index);
+        Ok(())
+    }
+
+    /// Ids of attachments stored in sidecar files, including those of trashed entries
+    pub fn sidecar_attachment_ids(&self) -> HashSet<Uuid> {
+        self.entries
+            .iter()
+            .chain(self.trash.iter().map(|trashed| &trashed.entry))
+            .flat_map(|entry| entry.attachments.iter())
+            .filter(|attachment| attachment.is_sidecar())
+            .map(|attachment| attachment.id)
+            .collect()
+    }
+
+    /// Flip an entry's favorite flag, returning the new value
+    pub fn toggle_favorite(&mut self, id: Uuid) -> Result<bool, VaultError> {
+        let entry = self.entry_mut(id)?;
+        entry.favorite = !entry.favorite;
+        Ok(entry.favorite)
+    }
+
+    /// Replace an entry's password with `password`, generated from
+    /// `options`, which are remembered for next time
+    pub fn set_generated_password(
+        &mut self,
+        id: Uuid,
+        password: &str,
+        options: GeneratorOptions,
+        history_limit: usize,
+    ) -> Result<(), VaultError> {
+        let entry = self.editable_entry_mut(id)?;
+        entry.replace_password(password.to_string(), history_limit);
+        entry.generator_prefs = Some(options);
+        Ok(())
+    }
+
+    pub fn set_archived(&mut self, id: Uuid, archived: bool) -> Result<(), VaultError> {
+        self.entry_mut(id)?.archived = archived;
+        Ok(())
+    }
+
+    pub fn set_require_biometric(&mut self, id: Uuid, required: bool) -> Result<(), VaultError> {
+        self.entry_mut(id)?.require_biometric = required;
+        Ok(())
+    }
+
+    /// Record that an entry's secrets were just revealed or copied
+    pub fn touch_entry(&mut self, id: Uuid) -> Result<(), VaultError> {
+        let entry = self.entry_mut(id)?;
+        entry.last_used_at = Some(Utc::now());
+        entry.use_count += 1;
+        Ok(())
+    }
+
+    /// Up to `limit` used entries, most used first
+    pub fn most_used_entries(&self, limit: usize) -> Vec<&Entry> {
+        let mut used: Vec<&Entry> = self.entries.iter().filter(|entry| entry.use_count > 0).collect();
+        used.sort_by_key(|entry| Reverse(entry.use_count));
+        used.truncate(limit);
+        used
+    }
+
+    /// Entries never revealed or copied, oldest first
+    pub fn never_used_entries(&self) -> Vec<&Entry> {
+        let mut unused: Vec<&Entry> = self.entries.iter().filter(|entry| entry.use_count == 0).collect();
+        unused.sort_by_key(|entry| entry.created_at);
+        unused
+    }
+
+    /// Never-used entries created more than `age` ago: cleanup candidates
+    pub fn stale_entries(&self, age: chrono::Duration) -> Vec<&Entry> {
+        let cutoff = Utc::now() - age;
+        self.never_used_entries()
+            .into_iter()
+            .filter(|entry| entry.created_at < cutoff)
+            .collect()
+    }
+
+    /// Groups of two or more entries with the same normalized URL host and
+    /// username (and, with `include_passwords`, the same password). Entries
+    /// without a URL are never grouped.
+    pub fn duplicate_groups(&self, include_passwords: bool) -> Vec<Vec<&Entry>> {
+        let mut index: HashMap<(String, String), usize> = HashMap::new();
+        let mut groups: Vec<Vec<&Entry>> = Vec::new();
+        for entry in &self.entries {
+            let Some(host) = urls::normalized_host(&entry.url) else {
+                continue;
+            };
+            let key = (host, entry.username.trim().to_lowercase());
+            match index.get(&key) {
+                Some(&i) => groups[i].push(entry),
+                None => {
+                    index.insert(key, groups.len());
+                    groups.push(vec![entry]);
+                }
+            }
+        }
+
+        if include_passwords {
+            groups = groups
+                .into_iter()
+                .flat_map(|group| {
+                    let mut by_password: Vec<Vec<&Entry>> = Vec::new();
+                    for entry in group {
+                        match by_password.iter_mut().find(|sub| sub[0].password == entry.password) {
+                            Some(sub) => sub.push(entry),
+                            None => by_password.push(vec![entry]),
+                        }
+                    }
+                    by_password
+                })
+                .collect();
+        }
+
+        groups.retain(|group| group.len() > 1);
+        groups
+    }
+
+    /// Combine `merge_ids` into `keep_id` (see `Entry::absorb`) and move
+    /// them to the trash
+    pub fn merge_entries(&mut self, keep_id: Uuid, merge_ids: &[Uuid], history_limit: usize) -> Result<(), VaultError> {
+        let mut ids: Vec<Uuid> = Vec::new();
+        for &id in merge_ids {
+            if id != keep_id && !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+        self.editable_entry_mut(keep_id)?;
+        for &id in &ids {
+            self.editable_entry_mut(id)?;
+        }
+
+        let now = Utc::now();
+        for id in ids {
+            let index = self
+                .entries
+                .iter()
+                .position(|entry| entry.id == id)
+                .ok_or(VaultError::EntryNotFound(id))?;
+            let mut merged = self.entries.remove(index);
+            self.entry_mut(keep_id)?.absorb(&mut merged, history_limit);
+            self.trash.push(TrashedEntry { entry: merged, deleted_at: now });
+        }
+        Ok(())
+    }
+
+    /// Entries whose URL rules match `page`, most specific match first.
+    /// An entry's main `url` counts as a `Domain` rule. Archived entries are
+    /// skipped unless `include_archived` is set.
+    pub fn entries_for_url(&self, page: &str, include_archived: bool) -> Vec<&Entry> {
+        let Some(page) = urls::parse(page) else {
+            return Vec::new();
+        };
+
+        let mut candidates: Vec<(u8, &Entry)> = self
+            .entries
+            .iter()
+            .filter(|entry| include_archived || !entry.archived)
+            .filter_map(|entry| {
+                let main = (!entry.url.trim().is_empty()).then(|| UriMatch {
+                    url: entry.url.clone(),
+                    mode: UriMatchMode::Domain,
+                });
+                main.iter()
+                    .chain(entry.uris.iter())
+                    .filter(|rule| rule.matches(&page))
+                    .map(|rule| rule.mode.specificity())
+                    .max()
+                    .map(|specificity| (specificity, entry))
+            })
+            .collect();
+
+        // Stable, so equally specific matches keep their stored order
+        candidates.sort_by_key(|(specificity, _)| Reverse(*specificity));
+        candidates.into_iter().map(|(_, entry)| entry).collect()
+    }
+
+    /// Built-in templates followed by the vault's own
+    pub fn all_templates(&self) -> Vec<EntryTemplate> {
+        let mut all = templates::builtin();
+        all.extend(self.templates.iter().cloned());
+        all
+    }
+
+    /// Save a template, taking the layout from `from_entry_id` if given,
+    /// otherwise from `fields`. Returns the new template's id.
+    pub fn create_template(
+        &mut self,
+        name: &str,
+        from_entry_id: Option<Uuid>,
+        fields: Option<TemplateFields>,
+    ) -> Result<Uuid, VaultError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(VaultError::InvalidFields(vec![FieldError::new("name", "Template name cannot be empty")]));
+        }
+        let fields = match (from_entry_id, fields) {
+            (Some(entry_id), _) => TemplateFields::from_entry(self.entry(entry_id)?),
+            (None, Some(fields)) => fields,
+            (None, None) => {
+                return Err(VaultError::InvalidFields(vec![FieldError::new(
+                    "fields",
+                    "Provide an entry or fields to create the template from",
+                )]))
+            }
+        };
+
+        let id = Uuid::new_v4();
+        self.templates.push(EntryTemplate {
+            id,
+            name: name.to_string(),
+            fields,
+            builtin: false,
+        });
+        Ok(id)
+    }
+
+    /// Delete a user-defined template
+    pub fn delete_template(&mut self, id: Uuid) -> Result<(), VaultError> {
+        let index = self
+            .templates
+            .iter()
+            .position(|template| template.id == id)
+            .ok_or(VaultError::TemplateNotFound(id))?;
+        self.templates.remove(index);
+        Ok(())
+    }
+
+    /// Add an empty entry laid out like the template, returning its id.
+    /// The entry is a draft: typed details start blank and are validated
+    /// when the user saves it with `update_entry`.
+    pub fn create_entry_from_template(&mut self, template_id: Uuid, title: Option<String>) -> Result<Uuid, VaultError> {
+        let template = self
+            .all_templates()
+            .into_iter()
+            .find(|template| template.id == template_id)
+            .ok_or(VaultError::TemplateNotFound(template_id))?;
+        let fields = &template.fields;
+
+        let input = EntryInput {
+            kind: fields.kind,
+            title: title.unwrap_or_else(|| template.name.clone()),
+            username: String::new(),
+            password: String::new(),
+            url: String::new(),
+            uris: Vec::new(),
+            notes: String::new(),
+            custom_fields: fields
+                .custom_fields
+                .iter()
+                .map(|field| CustomField {
+                    name: field.name.clone(),
+                    value: String::new(),
+                    kind: field.kind,
+                })
+                .collect(),
+            card: (fields.kind == ItemKind::Card).then(CardDetails::default),
+            identity: (fields.kind == ItemKind::Identity).then(IdentityDetails::default),
+            totp_secret: String::new(),
+        };
+        let mut entry = Entry::from_input(input);
+        entry.folder_id = fields.folder_id.filter(|folder_id| self.folder(*folder_id).is_ok());
+        let id = entry.id;
+        self.entries.push(entry);
+
+        self.set_entry_tags(id, fields.tags.clone())?;
+        Ok(id)
+    }
+
+    /// Apply `action` to every id. All ids are attempted; if any fail, the
+    /// error lists each failing id and the caller must discard this vault.
+    pub fn bulk_update(&mut self, ids: &[Uuid], action: &BulkAction) -> Result<(), VaultError> {
+        let mut failures = Vec::new();
+        for &id in ids {
+            let result = match action {
+                BulkAction::Trash => self.delete_entry(id),
+                BulkAction::Restore => self.restore_entry(id),
+                BulkAction::SetFolder(folder_id) => self.move_entry_to_folder(id, *folder_id),
+                BulkAction::AddTags(tags) => self.entry(id).map(|entry| entry.tags.clone()).and_then(|mut current| {
+                    current.extend(tags.iter().cloned());
+                    self.set_entry_tags(id, current)
+                }),
+                BulkAction::RemoveTags(tags) => {
+                    let removed: Vec<String> = tags.iter().map(|tag| tag.trim().to_lowercase()).collect();
+                    self.entry(id).map(|entry| entry.tags.clone()).and_then(|mut current| {
+                        current.retain(|tag| !removed.contains(&tag.to_lowercase()));
+                        self.set_entry_tags(id, current)
+                    })
+                }
+                BulkAction::SetExpiry(expires_at) => self.set_entry_expiry(id, *expires_at),
+            };
+            if let Err(error) = result {
+                failures.push(BulkFailure {
+                    id,
+                    kind: error.kind().to_string(),
+                    message: error.to_string(),
+                });
+            }
+        }
+
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(VaultError::BulkUpdateFailed(failures))
+        }
+    }
+
+    /// Set or clear (`None`) an entry's expiry date
+    pub fn set_entry_expiry(&mut self, id: Uuid, expires_at: Option<DateTime<Utc>>) -> Result<(), VaultError> {
+        self.editable_entry_mut(id)?.expires_at = expires_at;
+        Ok(())
+    }
+
+    /// Entries that have expired or expire within `within`, soonest first
+    pub fn expiring_entries(&self, within: chrono::Duration) -> Vec<&Entry> {
+        let cutoff = Utc::now() + within;
+        let mut expiring: Vec<&Entry> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.expires_at.is_some_and(|expires_at| expires_at <= cutoff))
+            .collect();
+        expiring.sort_by_key(|entry| entry.expires_at);
+        expiring
+    }
+
+    pub fn expired_count(&self) -> usize {
+        self.expiring_entries(chrono::Duration::zero()).len()
+    }
+
+    /// Replace an entry's tags. Tags are trimmed, empty ones dropped and
+    /// duplicates removed case-insensitively; a tag already used elsewhere in
+    /// the vault keeps its existing spelling.
+    pub fn set_entry_tags(&mut self, id: Uuid, tags: Vec<String>) -> Result<(), VaultError> {
+        let existing: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.id != id)
+            .flat_map(|entry| entry.tags.iter().cloned())
+            .collect();
+
+        let mut normalized: Vec<String> = Vec::new();
+        for tag in tags.iter().map(|tag| tag.trim()).filter(|tag| !tag.is_empty()) {
+            let key = tag.to_lowercase();
+            if normalized.iter().any(|kept| kept.to_lowercase() == key) {
+                continue;
+            }
+            let spelling = existing
+                .iter()
+                .find(|other| other.to_lowercase() == key)
+                .cloned()
+                .unwrap_or_else(|| tag.to_string());
+            normalized.push(spelling);
+        }
+
+        self.editable_entry_mut(id)?.tags = normalized;
+        Ok(())
+    }
+
+    /// Tags carried by entries outside the trash, with counts, sorted by name
+    pub fn tag_counts(&self) -> Vec<TagCount> {
+        let mut counts: Vec<TagCount> = Vec::new();
+        for tag in self.entries.iter().flat_map(|entry| entry.tags.iter()) {
+            let key = tag.to_lowercase();
+            match counts.iter_mut().find(|count| count.name.to_lowercase() == key) {
+                Some(count) => count.count += 1,
+                None => counts.push(TagCount { name: tag.clone(), count: 1 }),
+            }
+        }
+        counts.sort_by_key(|count| count.name.to_lowercase());
+        counts
+    }
+
+    /// Encrypt the vault into its on-disk form (see `format`)
+    pub fn seal(&self, key: &VaultKey) -> Result<Vec<u8>, VaultError> {
+        let json = Zeroizing::new(serde_json::to_vec(self).map_err(|e| VaultError::Io(e.to_string()))?);
+        let compression = format::compression_for(json.len());
+        let payload = format::compress(&json, compression).map_err(VaultError::Io)?;
+
+        let header = VaultHeader {
+            format_version: FILE_FORMAT_VERSION,
+            cipher: CipherAlgorithm::Xchacha20poly1305,
+            kdf: self.kdf.clone(),
+            key_check: self.key_slots.password_check.clone(),
+            compression,
+            wrapped_key: self.key_slots.password_wrapped_key.clone(),
+            recovery: self.key_slots.recovery.clone(),
+            vault_id: self.id,
+        };
+        let mut blob = format::encode_header(&header).map_err(VaultError::Io)?;
+        let sealed = crypto::encrypt(key, &payload, &blob).map_err(VaultError::Crypto)?;
+
+        blob.extend_from_slice(&sealed);
+        Ok(blob)
+    }
+
+    /// Decrypt an on-disk vault with the master secret, upgrading older
+    /// formats in memory (see `Unsealed::migrated_from`)
+    pub fn unseal(blob: &[u8], secret: &[u8]) -> Result<Unsealed, UnsealError> {
+        let file = decode_supported(blob)?;
+
+        let password_key = crypto::derive_key(secret, &file.header.kdf).map_err(UnsealError::Integrity)?;
+        if !crypto::verify_key_check(&password_key, &file.header.key_check) {
+            return Err(UnsealError::WrongPassword);
+        }
+
+        let data_key = if file.header.wrapped_key.is_empty() {
+            password_key
+        } else {
+            crypto::unwrap_key(&password_key, &file.header.wrapped_key).map_err(UnsealError::Integrity)?
+        };
+
+        Self::open_payload(&file, data_key)
+    }
+
+    /// Decrypt an on-disk vault with an already unwrapped data key
+    pub fn unseal_with_data_key(blob: &[u8], data_key: VaultKey) -> Result<Unsealed, UnsealError> {
+        let file = decode_supported(blob)?;
+        Self::open_payload(&file, data_key)
+    }
+
+    /// Decrypt an on-disk vault with its recovery code instead of the password
+    pub fn unseal_with_recovery_code(blob: &[u8], code: &str) -> Result<Unsealed, UnsealError> {
+        let file = decode_supported(blob)?;
+        let slot = file.header.recovery.as_ref().ok_or(UnsealError::WrongPassword)?;
+
+        let data_key = recovery::open_slot(slot, code)
+            .map_err(UnsealError::Integrity)?
+            .ok_or(UnsealError::WrongPassword)?;
+
+        Self::open_payload(&file, data_key)
+    }
+
+    fn open_payload(file: &VaultFile<'_>, data_key: VaultKey) -> Result<Unsealed, UnsealError> {
+        let decrypted = crypto::decrypt(&data_key, file.payload, file.aad).map_err(UnsealError::Integrity)?;
+        let plaintext = format::decompress(&decrypted, file.header.compression).map_err(UnsealError::Integrity)?;
+        let malformed = |e: serde_json::Error| UnsealError::Integrity(format!("Vault contents are malformed: {}", e));
+
+        #[derive(Deserialize)]
+        struct Envelope {
+            version: u32,
+        }
+        let version = serde_json::from_slice::<Envelope>(&plaintext).map_err(malformed)?.version;
+        if version > VAULT_FORMAT_VERSION {
+            return Err(UnsealError::UnsupportedVersion(version));
+        }
+
+        let (mut vault, migrated_from) = if version == VAULT_FORMAT_VERSION {
+            (serde_json::from_slice::<Vault>(&plaintext).map_err(malformed)?, None)
+        } else {
+            let mut envelope: serde_json::Value = serde_json::from_slice(&plaintext).map_err(malformed)?;
+            migrations::migrate(&mut envelope, version).map_err(UnsealError::Integrity)?;
+            (serde_json::from_value::<Vault>(envelope).map_err(malformed)?, Some(version))
+        };
+        vault.kdf = file.header.kdf.clone();
+        vault.key_slots = KeySlots {
+            password_check: file.header.key_check.clone(),
+            password_wrapped_key: file.header.wrapped_key.clone(),
+            recovery: file.header.recovery.clone(),
+        };
+        vault.id = file.header.vault_id;
+
+        Ok(Unsealed { vault, key: data_key, migrated_from })
+    }
+}
+
+fn folder_name(name: &str) -> Result<String, VaultError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(VaultError::InvalidFolder("Folder name cannot be empty".to_string()));
+    }
+    Ok(name.to_string())
+}
+
+fn decode_supported(blob: &[u8]) -> Result<VaultFile<'_>, UnsealError> {
+    let file = format::decode(blob).map_err(UnsealError::Integrity)?;
+    if file.header.format_version > FILE_FORMAT_VERSION {
+        return Err(UnsealError::UnsupportedVersion(file.header.format_version));
+    }
+    Ok(file)
+}
+
+/// Result of a successful `Vault::unseal`
+pub struct Unsealed {
+    pub vault: Vault,
+    /// Data key the payload is encrypted with
+    pub key: VaultKey,
+    /// Envelope version the vault was upgraded from, if a migration ran
+    pub migrated_from: Option<u32>,
+}
+
+/// Why a vault file could not be opened.
+///
+/// `WrongPassword` and `Integrity` are kept distinct for internal diagnostics;
+/// commands report both the same way so the caller cannot probe the file with
+/// guessed passwords.
+#[derive(Debug)]
+pub enum UnsealError {
+    WrongPassword,
+    /// Header or ciphertext failed to parse or authenticate
+    Integrity(String),
+    /// Written by a newer SafeNode than this one
+    UnsupportedVersion(u32),
+}
+
+/// Why the active vault is locked
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LockReason {
+    /// Not unlocked since the app started
+    #[default]
+    Startup,
+    Manual,
+    IdleTimeout,
+    Suspend,
+    ScreenLock,
+    /// The main window stayed hidden past `Settings::lock_when_hidden_minutes`
+    HiddenTimeout,
+    /// Too many wrong passwords at a re-authentication prompt
+    FailedReauth,
+    /// Destroyed after too many failed unlock attempts
+    Wipe,
+    /// Reset after a command failed while changing vault state
+    InternalError,
+}
+
+/// Credential a vault was unlocked with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnlockMethod {
+    Password,
+    RecoveryCode,
+    Pin,
+    Biometric,
+}
+
+/// Lock state of the active vault as reported to the UI
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum LockState {
+    Locked {
+        reason: LockReason,
+    },
+    Unlocked {
+        vault: String,
+        since: DateTime<Utc>,
+        method: UnlockMethod,
+    },
+}
+
+impl LockState {
+    pub fn is_unlocked(&self) -> bool {
+        matches!(self, LockState::Unlocked { .. })
+    }
+}
+
+/// Lock state of the vault held in `AppState`.
+///
+/// Dropping the `Unlocked` payload scrubs both the key and every entry.
+pub enum VaultState {
+    Locked,
+    Unlocked {
+        vault: Box<Vault>,
+        key: VaultKey,
+        since: DateTime<Utc>,
+        method: UnlockMethod,
+        /// Token from the unlock that secret-returning commands must present
+        session: Zeroizing<String>,
+    },
+}
+
+impl VaultState {
+    /// Discard the decrypted vault and derived key
+    pub fn lock(&mut self) {
+        if let VaultState::Unlocked { vault, key, .. } = std::mem::replace(self, VaultState::Locked) {
+            drop(key);
+            drop(vault);
+        }
+    }
+
+    pub fn is_unlocked(&self) -> bool {
+        matches!(self, VaultState::Unlocked { .. })
+    }
+}
+
+/// Every vault the running app has opened, keyed by vault name.
+///
+/// Vaults that are absent from the map are locked. Commands that act on
+/// "the vault" use the active one.
+#[derive(Default)]
+pub struct Vaults {
+    states: HashMap<String, VaultState>,
+    active: Option<String>,
+    /// Vaults opened with a recovery code that must set a new master password
+    /// before anything else
+    password_reset_required: HashSet<String>,
+    /// Vaults opened with their duress password, which are saved to the
+    /// decoy file instead of the real one
+    decoys: HashSet<String>,
+    /// Why the most recent lock happened
+    lock_reason: LockReason,
+}
+
+impl Vaults {
+    pub fn active_name(&self) -> Option<&str> {
+        self.active.as_deref()
+    }
+
+    pub fn is_unlocked(&self, name: &str) -> bool {
+        self.states.get(name).is_some_and(VaultState::is_unlocked)
+    }
+
+    /// Whether the active vault is unlocked
+    pub fn active_is_unlocked(&self) -> bool {
+        self.active.as_deref().is_some_and(|name| self.is_unlocked(name))
+    }
+
+    pub fn any_unlocked(&self) -> bool {
+        self.states.values().any(VaultState::is_unlocked)
+    }
+
+    /// Whether the active vault was opened with a recovery code and still
+    /// needs a new master password
+    pub fn password_reset_required(&self) -> bool {
+        self.active
+            .as_deref()
+            .is_some_and(|name| self.password_reset_required.contains(name))
+    }
+
+    pub fn clear_password_reset(&mut self, name: &str) {
+        self.password_reset_required.remove(name);
+    }
+
+    pub fn is_decoy(&self, name: &str) -> bool {
+        self.decoys.contains(name)
+    }
+
+    pub fn mark_decoy(&mut self, name: &str) {
+        self.decoys.insert(name.to_string());
+    }
+
+    pub fn active(&self) -> Result<&Vault, VaultError> {
+        let name = self.active.as_deref().ok_or(VaultError::VaultLocked)?;
+        match self.states.get(name) {
+            Some(VaultState::Unlocked { vault, .. }) => Ok(vault),
+            _ => Err(VaultError::VaultLocked),
+        }
+    }
+
+    pub fn lock_state(&self) -> LockState {
+        let active = self.active.as_deref().and_then(|name| Some((name, self.states.get(name)?)));
+        match active {
+            Some((name, VaultState::Unlocked { since, method, .. })) => LockState::Unlocked {
+                vault: name.to_string(),
+                since: *since,
+                method: *method,
+            },
+            _ => LockState::Locked { reason: self.lock_reason },
+        }
+    }
+
+    /// Name and data key of every unlocked vault
+    pub fn unlocked_keys(&self) -> Vec<(String, VaultKey)> {
+        self.states
+            .iter()
+            .filter_map(|(name, state)| match state {
+                VaultState::Unlocked { key, .. } => Some((name.clone(), key.clone())),
+                VaultState::Locked => None,
+            })
+            .collect()
+    }
+
+    /// Name, vault and key of the active vault
+    pub fn active_mut(&mut self) -> Result<(String, &mut Vault, &mut VaultKey), VaultError> {
+        let name = self.active.clone().ok_or(VaultError::VaultLocked)?;
+        match self.states.get_mut(&name) {
+            Some(VaultState::Unlocked { vault, key, .. }) => Ok((name, vault, key)),
+            _ => Err(VaultError::VaultLocked),
+        }
+    }
+
+    /// Store a freshly unlocked vault and make it active. Unless
+    /// `allow_multiple` is set, every other vault is locked first. Returns
+    /// the session token, which stops working when the vault locks.
+    ///
+    /// Unlocking a vault that is already open with the same key only makes
+    /// it active again and returns its current token. A vault opened with
+    /// its recovery code needs a new master password before it can be used,
+    /// from the moment it is unlocked.
+    pub fn unlock(
+        &mut self,
+        name: &str,
+        vault: Vault,
+        key: VaultKey,
+        method: UnlockMethod,
+        allow_multiple: bool,
+    ) -> String {
+        if method == UnlockMethod::RecoveryCode {
+            self.password_reset_required.insert(name.to_string());
+        }
+        // A repeated unlock, e.g. from a double-click, keeps the open
+        // session instead of invalidating the token the first call returned
+        if let Some(VaultState::Unlocked { key: current, session, .. }) = self.states.get(name) {
+            if crypto::bytes_equal(&current[..], &key[..]) {
+                let session = session.to_string();
+                self.active = Some(name.to_string());
+                return session;
+            }
+        }
+        if !allow_multiple {
+            self.discard_all();
+        }
+        let session = crypto::random_token();
+        self.states.insert(
+            name.to_string(),
+            VaultState::Unlocked {
+                vault: Box::new(vault),
+                key,
+                since: Utc::now(),
+                method,
+                session: Zeroizing::new(session.clone()),
+            },
+        );
+        self.decoys.remove(name);
+        self.active = Some(name.to_string());
+        session
+    }
+
+    /// `InvalidSession` unless `token` is the session token of the active vault
+    pub fn check_session(&self, token: &str) -> Result<(), VaultError> {
+        let name = self.active.as_deref().ok_or(VaultError::VaultLocked)?;
+        match self.states.get(name) {
+            Some(VaultState::Unlocked { session, .. }) if crypto::tokens_equal(session, token) => Ok(()),
+            Some(VaultState::Unlocked { .. }) => Err(VaultError::InvalidSession),
+            _ => Err(VaultError::VaultLocked),
+        }
+    }
+
+    /// Make an already unlocked vault the active one
+    pub fn set_active(&mut self, name: &str) -> Result<(), VaultError> {
+        if !self.is_unlocked(name) {
+            return Err(VaultError::VaultLocked);
+        }
+        self.active = Some(name.to_string());
+        Ok(())
+    }
+
+    /// Lock one vault. If it was active, another unlocked vault (if any) takes over.
+    pub fn lock(&mut self, name: &str, reason: LockReason) {
+        if let Some(mut state) = self.states.remove(name) {
+            state.lock();
+        }
+        self.lock_reason = reason;
+        self.password_reset_required.remove(name);
+        self.decoys.remove(name);
+        if self.active.as_deref() == Some(name) {
+            self.active = self
+                .states
+                .iter()
+                .find(|(_, state)| state.is_unlocked())
+                .map(|(name, _)| name.clone());
+        }
+    }
+
+    pub fn lock_active(&mut self, reason: LockReason) {
+        if let Some(name) = self.active.clone() {
+            self.lock(&name, reason);
+        }
+    }
+
+    pub fn lock_all(&mut self, reason: LockReason) {
+        self.discard_all();
+        self.lock_reason = reason;
+    }
+
+    fn discard_all(&mut self) {
+        for (_, mut state) in self.states.drain() {
+            state.lock();
+        }
+        self.active = None;
+        self.password_reset_required.clear();
+        self.decoys.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn test_kdf() -> KdfParams {
+        KdfParams {
+            memory_kib: 8,
+            iterations: 1,
+            ..KdfParams::with_salt(&crypto::generate_salt())
+        }
+    }
+
+    /// An empty vault whose master password is "password"
+    fn new_vault() -> (Vault, VaultKey) {
+        let kdf = test_kdf();
+        let password_key = crypto::derive_key(b"password", &kdf).unwrap();
+        Vault::new(kdf, &password_key).unwrap()
+    }
+
+    fn input(fields: serde_json::Value) -> EntryInput {
+        serde_json::from_value(fields).unwrap()
+    }
+
+    /// One entry of every item kind
+    fn every_kind() -> Vec<EntryInput> {
+        vec![
+            input(json!({
+                "title": "GitHub",
+                "username": "octocat",
+                "password": "hunter2",
+                "url": "https://github.com"
+            })),
+            input(json!({ "kind": "secure_note", "title": "Safe combination", "notes": "12-34-56" })),
+            input(json!({
+                "kind": "card",
+                "title": "Visa",
+                "card": { "cardholder": "A N Other", "number": "4242 4242 4242 4242", "expiry": "12/30", "cvv": "123" }
+            })),
+            input(json!({
+                "kind": "identity",
+                "title": "Me",
+                "identity": { "name": "A N Other", "email": "me@example.com" }
+            })),
+        ]
+    }
+
+    #[test]
+    fn every_item_kind_round_trips() {
+        let (mut vault, _) = new_vault();
+        for input in every_kind() {
+            vault.add_entry(input).unwrap();
+        }
+
+        let json = serde_json::to_string(&vault).unwrap();
+        let restored: Vault = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&restored).unwrap(), json);
+        assert_eq!(restored.version, VAULT_FORMAT_VERSION);
+
+        let kinds: Vec<ItemKind> = restored.entries.iter().map(|entry| entry.kind).collect();
+        assert_eq!(kinds, [ItemKind::Login, ItemKind::SecureNote, ItemKind::Card, ItemKind::Identity]);
+        assert_eq!(restored.entries[0].password, "hunter2");
+        assert_eq!(restored.entries[2].card.as_ref().unwrap().cvv, "123");
+        assert_eq!(restored.entries[3].identity.as_ref().unwrap().email, "me@example.com");
+    }
+
+    #[test]
+    fn sealed_vault_opens_with_its_password_only() {
+        let (mut vault, key) = new_vault();
+        vault.add_entry(every_kind().remove(0)).unwrap();
+        let blob = vault.seal(&key).unwrap();
+
+        let unsealed = Vault::unseal(&blob, b"password").unwrap();
+        assert_eq!(unsealed.vault.entries[0].password, "hunter2");
+        assert_eq!(unsealed.key, key);
+        assert!(unsealed.migrated_from.is_none());
+
+        assert!(matches!(Vault::unseal(&blob, b"wrong"), Err(UnsealError::WrongPassword)));
+    }
+
+    #[test]
+    fn tampered_vault_is_rejected() {
+        let (vault, key) = new_vault();
+        let blob = vault.seal(&key).unwrap();
+
+        let mut payload = blob.clone();
+        *payload.last_mut().unwrap() ^= 1;
+        assert!(matches!(Vault::unseal(&payload, b"password"), Err(UnsealError::Integrity(_))));
+
+        // The header is authenticated along with the payload
+        let file = format::decode(&blob).unwrap();
+        let mut header = file.header.clone();
+        header.compression = match header.compression {
+            format::Compression::None => format::Compression::Deflate,
+            format::Compression::Deflate => format::Compression::None,
+        };
+        let mut swapped = format::encode_header(&header).unwrap();
+        swapped.extend_from_slice(file.payload);
+        assert!(matches!(Vault::unseal(&swapped, b"password"), Err(UnsealError::Integrity(_))));
+    }
+
+    #[test]
+    fn older_envelope_is_migrated_on_open() {
+        let (mut vault, key) = new_vault();
+        vault.add_entry(every_kind().remove(0)).unwrap();
+        vault.version = 1;
+        let blob = vault.seal(&key).unwrap();
+
+        let unsealed = Vault::unseal(&blob, b"password").unwrap();
+        assert_eq!(unsealed.migrated_from, Some(1));
+        assert_eq!(unsealed.vault.version, VAULT_FORMAT_VERSION);
+        assert_eq!(unsealed.vault.entries[0].kind, ItemKind::Login);
+    }
+
+    #[test]
+    fn newer_envelope_is_refused() {
+        let (mut vault, key) = new_vault();
+        vault.version = VAULT_FORMAT_VERSION + 1;
+        let blob = vault.seal(&key).unwrap();
+        assert!(matches!(
+            Vault::unseal(&blob, b"password"),
+            Err(UnsealError::UnsupportedVersion(version)) if version == VAULT_FORMAT_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn titles_sort_ignoring_accents_and_case() {
+        let (mut vault, _) = new_vault();
+        for title in ["Zebra", "Éclair", "apple", "Ångström", "Apple", "eclair", "Office"] {
+            vault.add_entry(input(json!({ "title": title }))).unwrap();
+        }
+
+        let mut summaries: Vec<_> = vault.entries.iter().map(EntrySummary::from).collect();
+        EntrySort::Title.apply(&mut summaries);
+        let titles: Vec<_> = summaries.iter().map(|summary| summary.title.as_str()).collect();
+        assert_eq!(titles, ["Ångström", "Apple", "apple", "eclair", "Éclair", "Office", "Zebra"]);
+    }
+
+    #[test]
+    fn summaries_carry_no_secrets() {
+        let (mut vault, _) = new_vault();
+        let mut login = every_kind().remove(0);
+        login.custom_fields = vec![
+            CustomField { name: "PIN".into(), value: "9876".into(), kind: CustomFieldKind::Hidden },
+            CustomField { name: "Team".into(), value: "Core".into(), kind: CustomFieldKind::Text },
+        ];
+        let login = vault.add_entry(login).unwrap();
+        let card = vault.add_entry(every_kind().remove(2)).unwrap();
+
+        let summary = serde_json::to_value(EntrySummary::from(vault.entry(login).unwrap())).unwrap();
+        assert!(summary.get("password").is_none());
+        assert_eq!(summary["username"], "octocat");
+        assert_eq!(summary["custom_fields"], json!([{ "name": "Team", "value": "Core", "kind": "text" }]));
+
+        let summary = serde_json::to_string(&EntrySummary::from(vault.entry(card).unwrap())).unwrap();
+        assert!(!summary.contains("4242 4242") && !summary.contains("cvv"));
+        assert!(summary.contains("•••• 4242"));
+    }
+
+    #[test]
+    fn full_entry_includes_the_secrets() {
+        let (mut vault, _) = new_vault();
+        let id = vault.add_entry(every_kind().remove(0)).unwrap();
+        let full = EntryFull::from(vault.entry(id).unwrap());
+        assert_eq!(full.id, id);
+        assert_eq!(full.password, "hunter2");
+        assert_eq!(full.url, "https://github.com");
+    }
+
+    #[test]
+    fn large_vaults_are_compressed_and_save_quickly() {
+        let (mut vault, key) = new_vault();
+        for i in 0..10_000 {
+            vault
+                .add_entry(input(json!({
+                    "title": format!("Site {}", i),
+                    "username": format!("user{}@example.com", i),
+                    "password": crypto::random_token(),
+                    "url": format!("https://site{}.example.com/login", i),
+                })))
+                .unwrap();
+        }
+
+        let started = std::time::Instant::now();
+        let blob = vault.seal(&key).unwrap();
+        // Generous for debug builds on slow CI machines
+        assert!(started.elapsed() < std::time::Duration::from_secs(10), "{:?}", started.elapsed());
+
+        let json_len = serde_json::to_vec(&vault).unwrap().len();
+        assert_eq!(format::decode(&blob).unwrap().header.compression, format::Compression::Deflate);
+        assert!(blob.len() < json_len, "{} of {}", blob.len(), json_len);
+        assert_eq!(Vault::unseal_with_data_key(&blob, key).unwrap().vault.entries.len(), 10_000);
+    }
+
+    #[test]
+    fn small_vaults_are_stored_uncompressed() {
+        let (vault, key) = new_vault();
+        let blob = vault.seal(&key).unwrap();
+        assert_eq!(format::decode(&blob).unwrap().header.compression, format::Compression::None);
+        assert!(Vault::unseal(&blob, b"password").is_ok());
+    }
+
+    #[test]
+    fn url_candidates_are_ordered_by_specificity() {
+        let (mut vault, _) = new_vault();
+        let domain = vault.add_entry(input(json!({ "title": "Domain", "url": "example.com" }))).unwrap();
+        let exact = vault
+            .add_entry(input(json!({
+                "title": "Exact",
+                "uris": [{ "url": "https://mail.example.com/login", "mode": "exact" }]
+            })))
+            .unwrap();
+        vault.add_entry(input(json!({ "title": "Other", "url": "example.org" }))).unwrap();
+        vault
+            .add_entry(input(json!({ "title": "Broken", "uris": [{ "url": "(", "mode": "regex" }] })))
+            .unwrap();
+
+        let ids: Vec<Uuid> = vault
+            .entries_for_url("https://mail.example.com/login", false)
+            .iter()
+            .map(|entry| entry.id)
+            .collect();
+        assert_eq!(ids, [exact, domain]);
+        assert!(vault.entries_for_url("not a url", false).is_empty());
+    }
+
+    #[test]
+    fn session_ends_when_the_vault_locks() {
+        let mut vaults = Vaults::default();
+        let (vault, key) = new_vault();
+        let first = vaults.unlock("default", vault.clone(), key.clone(), UnlockMethod::Password, false);
+        assert!(vaults.check_session(&first).is_ok());
+        assert!(matches!(vaults.check_session("guess"), Err(VaultError::InvalidSession)));
+
+        vaults.lock_active(LockReason::Manual);
+        assert!(matches!(vaults.check_session(&first), Err(VaultError::VaultLocked)));
+
+        let second = vaults.unlock("default", vault, key, UnlockMethod::Password, false);
+        assert_ne!(first, second);
+        assert!(matches!(vaults.check_session(&first), Err(VaultError::InvalidSession)));
+        assert!(vaults.check_session(&second).is_ok());
+    }
+
+    #[test]
+    fn recovery_code_unlock_requires_a_new_password() {
+        let mut vaults = Vaults::default();
+        let (vault, key) = new_vault();
+        vaults.unlock("default", vault.clone(), key.clone(), UnlockMethod::Password, false);
+        assert!(!vaults.password_reset_required());
+
+        // Even a vault that is already open needs a new password once its
+        // recovery code was used
+        vaults.unlock("default", vault, key, UnlockMethod::RecoveryCode, false);
+        assert!(vaults.password_reset_required());
+        vaults.clear_password_reset("default");
+        assert!(!vaults.password_reset_required());
+    }
+
+    #[test]
+    fn session_only_opens_its_own_vault() {
+        let mut vaults = Vaults::default();
+        let (personal, personal_key) = new_vault();
+        let (work, work_key) = new_vault();
+        let personal_session = vaults.unlock("personal", personal, personal_key, UnlockMethod::Password, true);
+        let work_session = vaults.unlock("work", work, work_key, UnlockMethod::Password, true);
+
+        assert!(matches!(vaults.check_session(&personal_session), Err(VaultError::InvalidSession)));
+        assert!(vaults.check_session(&work_session).is_ok());
+        vaults.set_active("personal").unwrap();
+        assert!(vaults.check_session(&personal_session).is_ok());
+    }
+
+    #[test]
+    fn folders_nest_and_hand_on_their_contents() {
+        let (mut vault, _) = new_vault();
+        let work = vault.create_folder("  Work ", None).unwrap();
+        let servers = vault.create_folder("Servers", Some(work)).unwrap();
+        let db = vault.create_folder("Databases", Some(servers)).unwrap();
+        let entry = vault.add_entry(every_kind().remove(0)).unwrap();
+        vault.move_entry_to_folder(entry, Some(servers)).unwrap();
+        assert_eq!(vault.folder(work).unwrap().name, "Work");
+
+        assert!(matches!(vault.create_folder(" ", None), Err(VaultError::InvalidFolder(_))));
+        assert!(matches!(vault.create_folder("Lost", Some(Uuid::new_v4())), Err(VaultError::FolderNotFound(_))));
+        assert!(matches!(vault.move_entry_to_folder(entry, Some(Uuid::new_v4())), Err(VaultError::FolderNotFound(_))));
+        assert!(matches!(vault.delete_folder(servers, Some(db)), Err(VaultError::InvalidFolder(_))));
+
+        // Contents go to the parent unless told otherwise
+        vault.delete_folder(servers, None).unwrap();
+        assert_eq!(vault.entry(entry).unwrap().folder_id, Some(work));
+        assert_eq!(vault.folder(db).unwrap().parent_id, Some(work));
+        assert!(matches!(vault.folder(servers), Err(VaultError::FolderNotFound(_))));
+
+        vault.delete_folder(work, None).unwrap();
+        assert_eq!(vault.entry(entry).unwrap().folder_id, None);
+        assert_eq!(vault.folder(db).unwrap().parent_id, None);
+    }
+
+    #[test]
+    fn tags_are_normalized_and_counted() {
+        let (mut vault, _) = new_vault();
+        let ids: Vec<Uuid> = every_kind().into_iter().map(|input| vault.add_entry(input).unwrap()).collect();
+        let tags = |list: &[&str]| list.iter().map(|tag| tag.to_string()).collect::<Vec<_>>();
+
+        vault.set_entry_tags(ids[0], tags(&[" Work ", "work", "", "2FA"])).unwrap();
+        assert_eq!(vault.entry(ids[0]).unwrap().tags, ["Work", "2FA"]);
+        // Spelled as the vault already has it
+        vault.set_entry_tags(ids[1], tags(&["WORK", "personal"])).unwrap();
+        assert_eq!(vault.entry(ids[1]).unwrap().tags, ["Work", "personal"]);
+
+        let counts: Vec<(String, usize)> =
+            vault.tag_counts().into_iter().map(|count| (count.name, count.count)).collect();
+        assert_eq!(counts, [("2FA".to_string(), 1), ("personal".to_string(), 1), ("Work".to_string(), 2)]);
+
+        vault.delete_entry(ids[0]).unwrap();
+        let counts: Vec<(String, usize)> =
+            vault.tag_counts().into_iter().map(|count| (count.name, count.count)).collect();
+        assert_eq!(counts, [("personal".to_string(), 1), ("Work".to_string(), 1)]);
+        assert!(matches!(vault.set_entry_tags(Uuid::new_v4(), Vec::new()), Err(VaultError::EntryNotFound(_))));
+    }
+
+    fn github_logins(vault: &mut Vault) -> Vec<Uuid> {
+        [
+            json!({ "title": "GitHub", "username": "octocat", "password": "hunter2", "url": "https://github.com" }),
+            json!({
+                "title": "GitHub (old)",
+                "username": "OctoCat ",
+                "password": "letmein",
+                "url": "http://GitHub.com/login",
+                "notes": "Recovery codes in the safe",
+                "custom_fields": [{ "name": "PIN", "value": "4821" }],
+                "totp_secret": "JBSWY3DPEHPK3PXP"
+            }),
+            json!({ "title": "GitHub", "username": "octocat", "password": "hunter2", "url": "github.com" }),
+            json!({ "title": "GitHub", "username": "hubot", "password": "hunter2", "url": "https://github.com" }),
+        ]
+        .into_iter()
+        .map(|fields| vault.add_entry(input(fields)).unwrap())
+        .collect()
+    }
+
+    #[test]
+    fn duplicates_are_grouped_by_host_and_username() {
+        let (mut vault, _) = new_vault();
+        let ids = github_logins(&mut vault);
+        let group_ids = |groups: Vec<Vec<&Entry>>| -> Vec<Vec<Uuid>> {
+            groups.iter().map(|group| group.iter().map(|entry| entry.id).collect()).collect()
+        };
+        assert_eq!(group_ids(vault.duplicate_groups(false)), [vec![ids[0], ids[1], ids[2]]]);
+        assert_eq!(group_ids(vault.duplicate_groups(true)), [vec![ids[0], ids[2]]]);
+    }
+
+    #[test]
+    fn merged_entries_fold_into_the_kept_one() {
+        let (mut vault, _) = new_vault();
+        let ids = github_logins(&mut vault);
+        vault.set_entry_tags(ids[0], vec!["work".to_string()]).unwrap();
+        vault.set_entry_tags(ids[1], vec!["Work".to_string(), "2fa".to_string()]).unwrap();
+
+        assert!(matches!(
+            vault.merge_entries(ids[0], &[ids[1], Uuid::new_v4()], 10),
+            Err(VaultError::EntryNotFound(_))
+        ));
+        assert_eq!(vault.entries.len(), 4);
+
+        vault.merge_entries(ids[0], &[ids[1], ids[2], ids[1], ids[0]], 10).unwrap();
+        assert_eq!(vault.entries.len(), 2);
+        assert_eq!(vault.trash.len(), 2);
+        let kept = vault.entry(ids[0]).unwrap();
+        assert_eq!(kept.password, "hunter2");
+        assert_eq!(kept.notes, "Recovery codes in the safe");
+        assert_eq!(kept.tags, ["work", "2fa"]);
+        assert_eq!(kept.custom_fields.len(), 1);
+        assert_eq!(kept.totp_secret, "JBSWY3DPEHPK3PXP");
+        // Only the password that differed joins the history
+        let history: Vec<&str> = kept.password_history.iter().map(|item| item.password.as_str()).collect();
+        assert_eq!(history, ["letmein"]);
+    }
+
+    #[test]
+    fn merged_history_is_capped() {
+        let (mut vault, _) = new_vault();
+        let keep = vault.add_entry(input(json!({ "title": "Kept", "password": "current" }))).unwrap();
+        let others: Vec<Uuid> = (0..5)
+            .map(|n| vault.add_entry(input(json!({ "title": "Other", "password": format!("old{}", n) }))).unwrap())
+            .collect();
+        vault.merge_entries(keep, &others, 3).unwrap();
+        let history: Vec<&str> =
+            vault.entry(keep).unwrap().password_history.iter().map(|item| item.password.as_str()).collect();
+        assert_eq!(history, ["old2", "old3", "old4"]);
+    }
+
+    #[test]
+    fn archived_entries_refuse_changes_until_unarchived() {
+        let (mut vault, _) = new_vault();
+        let archived = vault.add_entry(input(json!({ "title": "Old", "password": "old" }))).unwrap();
+        let live = vault.add_entry(input(json!({ "title": "New", "password": "new" }))).unwrap();
+        vault.set_archived(archived, true).unwrap();
+
+        let tags = BulkAction::AddTags(vec!["work".to_string()]);
+        let Err(VaultError::BulkUpdateFailed(failures)) = vault.bulk_update(&[archived, live], &tags) else {
+            panic!("archived entry was tagged");
+        };
+        assert_eq!(failures.len(), 1);
+        assert_eq!((failures[0].id, failures[0].kind.as_str()), (archived, "entry_archived"));
+        let merged = vault.merge_entries(live, &[archived], 10);
+        assert!(matches!(merged, Err(VaultError::EntryArchived(id)) if id == archived));
+        assert!(vault.entry(archived).unwrap().tags.is_empty());
+
+        vault.set_archived(archived, false).unwrap();
+        vault.set_entry_tags(archived, vec!["work".to_string()]).unwrap();
+        assert_eq!(vault.entry(archived).unwrap().tags, ["work"]);
+    }
+}